@@ -0,0 +1,5 @@
+//! Print the protocol spec as Markdown: `cargo run -p protocol --bin protocol_spec`
+
+fn main() {
+    print!("{}", protocol::protocol_spec());
+}
@@ -0,0 +1,1442 @@
+//! Wire schema shared by the GDExtension, the dedicated server and any bot or tool
+//! that talks to them: the messages, their codec and the channels they travel on.
+
+use std::fmt;
+
+pub use bevy_quinnet::shared::ClientId;
+use bincode::Options;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Bumped whenever ClientMessage/ServerMessage change
+pub const PROTOCOL_VERSION: u32 = 22;
+/// Oldest version peers of this one understand. Versions that only added messages stay
+/// compatible, since unknown ones are skipped; any change to an existing message's
+/// fields raises this too, see `Envelope`.
+pub const MIN_COMPATIBLE_VERSION: u32 = 22;
+
+/// What every message travels in: its variant's tag and the sender's protocol version,
+/// in front of the variant's fields. The tag is the variant's position in the enum, so
+/// new messages go at the end. Receivers skip tags they don't know, so a newer peer
+/// can add messages without breaking older ones (e.g. clients lagging behind a server
+/// upgrade). Fields are not that forgiving: an older peer ignores fields past the ones
+/// it knows, but bincode has no defaults, so a newer peer fails to read a message
+/// without the fields it added.
+#[derive(Serialize, Deserialize)]
+struct Envelope<'a> {
+    /// Index of the variant in `MessageKind::KINDS`
+    kind: u16,
+    version: u32,
+    /// bincode encoding of the variant's fields, without the enum's own tag
+    fields: &'a [u8],
+}
+
+/// Encode a message in its `Envelope`, as it goes on the wire for every transport
+pub fn encode<T: Serialize + MessageKind>(message: &T) -> Vec<u8> {
+    let encoded = bincode::serialize(message).expect("protocol messages are always serializable");
+    // The derived encoding starts with the variant's index as a u32, the envelope's tag
+    // is shorter
+    let (index, fields) = encoded.split_at(4);
+    let index = u32::from_le_bytes(index.try_into().expect("split at 4 bytes"));
+    let envelope = Envelope {
+        kind: index as u16,
+        version: PROTOCOL_VERSION,
+        fields,
+    };
+    bincode::serialize(&envelope).expect("envelopes are always serializable")
+}
+
+/// Why a message was not decoded
+#[derive(Debug)]
+pub enum DecodeError {
+    /// A message this version doesn't have, from a newer peer: skip it
+    UnknownKind { kind: u16, version: u32 },
+    /// From a peer older than `MIN_COMPATIBLE_VERSION`, whose fields can't be trusted to
+    /// line up with ours
+    Incompatible { version: u32 },
+    /// Not a valid encoding of the message
+    Invalid(bincode::Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownKind { kind, version } => write!(
+                f,
+                "unknown message #{} from protocol version {}",
+                kind, version
+            ),
+            DecodeError::Incompatible { version } => write!(
+                f,
+                "protocol version {} is older than the oldest compatible one, {}",
+                version, MIN_COMPATIBLE_VERSION
+            ),
+            DecodeError::Invalid(err) => err.fmt(f),
+        }
+    }
+}
+
+/// The message in `envelope`, read with `options`. Fields past the ones we know were
+/// added by a newer version and are left unread.
+fn open_envelope<T: DeserializeOwned + MessageKind>(
+    envelope: Envelope,
+    options: impl Options,
+) -> Result<T, DecodeError> {
+    if envelope.version < MIN_COMPATIBLE_VERSION {
+        return Err(DecodeError::Incompatible {
+            version: envelope.version,
+        });
+    }
+    if usize::from(envelope.kind) >= T::KINDS.len() {
+        return Err(DecodeError::UnknownKind {
+            kind: envelope.kind,
+            version: envelope.version,
+        });
+    }
+    let mut bytes = Vec::with_capacity(4 + envelope.fields.len());
+    bytes.extend_from_slice(&u32::from(envelope.kind).to_le_bytes());
+    bytes.extend_from_slice(envelope.fields);
+    options.deserialize(&bytes).map_err(DecodeError::Invalid)
+}
+
+/// The channels both ends open. Everything travels on quinnet's default
+/// ordered, reliable channel.
+pub mod channels {
+    use bevy_quinnet::shared::channels::ChannelsConfiguration;
+
+    /// Name of the default channel, for traces and logs
+    pub const DEFAULT: &str = "default";
+
+    pub fn configuration() -> ChannelsConfiguration {
+        ChannelsConfiguration::default()
+    }
+}
+
+/// Server info without joining: a UDP datagram starting with `REQUEST`, padded to at
+/// least `REQUEST_LEN` bytes, sent to the server's query port is answered with its
+/// `ServerInfo`, bincode-encoded. Fields may be added at the end, older readers skip them.
+pub mod query {
+    use bincode::Options;
+    use serde::{Deserialize, Serialize};
+
+    pub const REQUEST: &[u8] = b"GBQ-INFO";
+    /// Answers are never larger than the request, so spoofed queries can't turn the
+    /// query port into an amplifier
+    pub const REQUEST_LEN: usize = 512;
+    /// Next to the game's 6000 and RCON's 6001
+    pub const DEFAULT_PORT: u16 = 6002;
+
+    /// A request ready to send
+    pub fn request() -> Vec<u8> {
+        let mut request = REQUEST.to_vec();
+        request.resize(REQUEST_LEN, 0);
+        request
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct ServerInfo {
+        pub name: String,
+        /// Level scene being played, None on servers without levels
+        pub level: Option<String>,
+        pub players: usize,
+        pub max_players: usize,
+        /// Whether joining takes a password
+        pub password: bool,
+        /// The server's `PROTOCOL_VERSION`
+        pub protocol_version: u32,
+        /// Port of the game endpoint
+        pub port: u16,
+    }
+
+    impl ServerInfo {
+        pub fn encode(&self) -> Vec<u8> {
+            bincode::serialize(self).expect("server info is always serializable")
+        }
+
+        pub fn decode(bytes: &[u8]) -> Result<Self, bincode::Error> {
+            super::untrusted_options().deserialize(bytes)
+        }
+    }
+}
+
+/// Default maximum chat message length, in grapheme clusters
+pub const DEFAULT_MAX_CHAT_LENGTH: usize = 256;
+
+pub fn chat_length(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Cut `text` to at most `max_len` grapheme clusters, never splitting a character
+/// (or a multi-codepoint emoji) in half
+pub fn truncate_chat(text: &str, max_len: usize) -> &str {
+    match text.grapheme_indices(true).nth(max_len) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Wrapping comparison for movement sequence numbers: true if `seq` is more recent than `last`
+pub fn is_newer_seq(seq: u32, last: u32) -> bool {
+    (seq.wrapping_sub(last) as i32) > 0
+}
+
+/// Options of `bincode::deserialize`, the format `encode` writes
+fn trusted_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+pub fn decode<T: DeserializeOwned + MessageKind>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let envelope = trusted_options()
+        .deserialize(bytes)
+        .map_err(DecodeError::Invalid)?;
+    open_envelope(envelope, trusted_options())
+}
+
+/// No legitimate client message comes close, chat is capped far below this
+pub const MAX_CLIENT_MESSAGE_LEN: u64 = 64 * 1024;
+
+fn untrusted_options() -> impl Options {
+    trusted_options().with_limit(MAX_CLIENT_MESSAGE_LEN)
+}
+
+/// `decode` for bytes from an untrusted peer: same wire format, but length
+/// prefixes claiming more than `MAX_CLIENT_MESSAGE_LEN` fail instead of allocating
+pub fn decode_untrusted<T: DeserializeOwned + MessageKind>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let envelope = untrusted_options()
+        .deserialize(bytes)
+        .map_err(DecodeError::Invalid)?;
+    open_envelope(envelope, untrusted_options())
+}
+
+/// Encoded messages larger than this are split into InitBegin/InitChunk/InitEnd
+pub const CHUNK_SIZE: usize = 1024;
+
+/// `message` as-is when small enough, otherwise its encoding cut into chunks
+pub fn split_chunks(message: ServerMessage) -> Vec<ServerMessage> {
+    let bytes = encode(&message);
+    if bytes.len() <= CHUNK_SIZE {
+        return vec![message];
+    }
+    let chunks: Vec<_> = bytes.chunks(CHUNK_SIZE).collect();
+    let mut messages = Vec::with_capacity(chunks.len() + 2);
+    messages.push(ServerMessage::InitBegin {
+        total_len: bytes.len() as u32,
+        chunk_count: chunks.len() as u32,
+    });
+    messages.extend(
+        chunks
+            .iter()
+            .zip(0..)
+            .map(|(data, index)| ServerMessage::InitChunk {
+                index,
+                data: data.to_vec(),
+            }),
+    );
+    messages.push(ServerMessage::InitEnd {});
+    messages
+}
+
+/// Collects InitChunks until InitEnd, then decodes the original message
+#[derive(Debug, Default)]
+pub struct ChunkAssembly {
+    buffer: Vec<u8>,
+    total_len: usize,
+    chunk_count: u32,
+    next_index: u32,
+}
+
+impl ChunkAssembly {
+    pub fn begin(&mut self, total_len: u32, chunk_count: u32) {
+        self.buffer = Vec::with_capacity(total_len as usize);
+        self.total_len = total_len as usize;
+        self.chunk_count = chunk_count;
+        self.next_index = 0;
+    }
+
+    pub fn push(&mut self, index: u32, data: &[u8]) -> Result<(), String> {
+        if index != self.next_index || index >= self.chunk_count {
+            return Err(format!(
+                "chunk {} out of sequence (expected {} of {})",
+                index, self.next_index, self.chunk_count
+            ));
+        }
+        if self.buffer.len() + data.len() > self.total_len {
+            return Err("chunks exceed the announced length".to_string());
+        }
+        self.buffer.extend_from_slice(data);
+        self.next_index += 1;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<ServerMessage, String> {
+        let buffer = std::mem::take(&mut self.buffer);
+        if self.next_index != self.chunk_count || buffer.len() != self.total_len {
+            return Err(format!(
+                "incomplete transfer: {} of {} chunks, {} of {} bytes",
+                self.next_index,
+                self.chunk_count,
+                buffer.len(),
+                self.total_len
+            ));
+        }
+        decode(&buffer).map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRecord {
+    pub username: String,
+    pub message: String,
+    /// Unix timestamp assigned by the server, seconds (0 in saves predating it)
+    #[serde(default)]
+    pub sent_at: u64,
+}
+
+/// Sound ids for `ServerMessage::PlaySoundAt`, mapped to streams by the client
+pub mod sounds {
+    pub const FOOTSTEP: u32 = 1;
+    pub const CHAT_PING: u32 = 2;
+    pub const ITEM_PICKUP: u32 = 3;
+}
+
+/// What a `ServerMessage::FeedbackEvent` was caused by, the client picks its effects
+/// (camera shake, hit flash, rumble) from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeedbackKind {
+    /// A player took damage at the position
+    Hit,
+    Explosion,
+    /// Two players ran into each other
+    Bump,
+}
+
+/// A player's round trip time as measured by the server, for the scoreboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerPing {
+    pub client_id: ClientId,
+    pub rtt_ms: u32,
+}
+
+/// Fingerprint of the state a client can't recover on its own once it missed a message:
+/// who is in its room (ids and names) and which props exist. Positions are left out, the
+/// next update corrects them anyway. Computed the same way on both ends, so a
+/// `ServerMessage::StateChecksum` that doesn't match means the client drifted.
+pub fn state_checksum<'a>(
+    players: impl IntoIterator<Item = (ClientId, &'a str)>,
+    props: impl IntoIterator<Item = u32>,
+) -> u64 {
+    // FNV-1a, stable across builds unlike std's hasher
+    fn feed(hash: u64, bytes: &[u8]) -> u64 {
+        bytes.iter().fold(hash, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+        })
+    }
+    let mut players: Vec<_> = players.into_iter().collect();
+    players.sort_unstable();
+    let mut props: Vec<_> = props.into_iter().collect();
+    props.sort_unstable();
+
+    let mut hash = 0xcbf2_9ce4_8422_2325;
+    hash = feed(hash, &(players.len() as u64).to_le_bytes());
+    for (client_id, name) in players {
+        hash = feed(hash, &client_id.to_le_bytes());
+        hash = feed(hash, &(name.len() as u64).to_le_bytes());
+        hash = feed(hash, name.as_bytes());
+    }
+    hash = feed(hash, &(props.len() as u64).to_le_bytes());
+    for prop_id in props {
+        hash = feed(hash, &prop_id.to_le_bytes());
+    }
+    hash
+}
+
+/// Emote ids for `ClientMessage::Emote`, mapped to effects by the client
+pub mod emotes {
+    pub const WAVE: u32 = 1;
+    pub const DANCE: u32 = 2;
+    pub const LAUGH: u32 = 3;
+    pub const HEART: u32 = 4;
+}
+
+/// Shared ambience, advanced by the server and mirrored by clients between syncs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentState {
+    /// Fraction of the day: 0.0 midnight, 0.5 noon
+    pub time_of_day: f32,
+    pub day_length_secs: f32,
+    /// Picked by the server each day, clients derive the weather from it
+    pub weather_seed: u32,
+}
+
+impl Default for EnvironmentState {
+    fn default() -> Self {
+        Self {
+            time_of_day: 0.3,
+            day_length_secs: 1200.0,
+            weather_seed: 0,
+        }
+    }
+}
+
+impl EnvironmentState {
+    /// Move the clock forward, true when a new day started
+    pub fn advance(&mut self, secs: f32) -> bool {
+        if self.day_length_secs <= 0.0 {
+            return false;
+        }
+        self.time_of_day += secs / self.day_length_secs;
+        let new_day = self.time_of_day >= 1.0;
+        self.time_of_day = self.time_of_day.fract();
+        new_day
+    }
+}
+
+/// A moving platform or elevator, going from its first point to its last and back. Its
+/// position is a function of the server clock alone, so every client computes it on
+/// its own and they all agree without the server sending it every frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlatformPath {
+    /// Stops along the way, at least one
+    pub points: Vec<(f32, f32)>,
+    /// Time for the way there and back, ms; 0 keeps it on the first point
+    pub period_ms: u32,
+    /// Offset into the period, to spread out platforms sharing a path
+    #[serde(default)]
+    pub phase_ms: u32,
+    /// Width and height of the area carrying players, centered on its position
+    pub size: (f32, f32),
+}
+
+impl PlatformPath {
+    /// Where the platform is at `server_time_ms`, easing in and out at both ends
+    pub fn position_at(&self, server_time_ms: u64) -> (f32, f32) {
+        let points = match self.points.as_slice() {
+            [] => return (0.0, 0.0),
+            [point] => return *point,
+            points => points,
+        };
+        if self.period_ms == 0 {
+            return points[0];
+        }
+        let period = u64::from(self.period_ms);
+        let t = ((server_time_ms + u64::from(self.phase_ms)) % period) as f32 / period as f32;
+        // There in the first half of the period, back in the second
+        let along = if t < 0.5 { t * 2.0 } else { 2.0 - t * 2.0 };
+        let along = along * along * (3.0 - 2.0 * along);
+
+        let segment_length =
+            |pair: &[(f32, f32)]| (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1);
+        let total: f32 = points.windows(2).map(segment_length).sum();
+        let mut remaining = along * total;
+        for pair in points.windows(2) {
+            let length = segment_length(pair);
+            if remaining <= length && length > 0.0 {
+                let f = remaining / length;
+                return (
+                    pair[0].0 + (pair[1].0 - pair[0].0) * f,
+                    pair[0].1 + (pair[1].1 - pair[0].1) * f,
+                );
+            }
+            remaining -= length;
+        }
+        points[points.len() - 1]
+    }
+
+    /// Whether a player at `point` stands on the platform when it is at `position`
+    pub fn carries(&self, position: (f32, f32), point: (f32, f32)) -> bool {
+        (point.0 - position.0).abs() <= self.size.0 / 2.0
+            && (point.1 - position.1).abs() <= self.size.1 / 2.0
+    }
+}
+
+/// How close a player must be to a prop to push it (and be granted authority over it)
+pub const PROP_INTERACT_RADIUS: f32 = 64.0;
+
+/// A pushable physics prop, as simulated by whoever has authority over it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PropState {
+    pub prop_id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub rotation: f32,
+    pub vx: f32,
+    pub vy: f32,
+}
+
+impl PropState {
+    /// No NaN or infinite position, rotation or velocity
+    pub fn is_finite(&self) -> bool {
+        [self.x, self.y, self.rotation, self.vx, self.vy]
+            .iter()
+            .all(|value| value.is_finite())
+    }
+}
+
+/// Gameplay rules the server can change at any time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GameRules {
+    /// Players block each other instead of walking through
+    pub player_collision: bool,
+    /// Where players can go, unbounded when None
+    pub bounds: Option<WorldBounds>,
+}
+
+/// The playable area: the server clamps every position it accepts to it, and clients
+/// clamp their own movement the same way
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WorldBounds {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+impl WorldBounds {
+    /// The closest point inside; NaN goes to the minimum
+    pub fn clamp(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            x.max(self.min_x).min(self.max_x),
+            y.max(self.min_y).min(self.max_y),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub client_id: ClientId,
+    pub username: String,
+    pub x: f32,
+    pub y: f32,
+    pub cosmetics: Cosmetics,
+}
+
+/// A player's place at the end of a match; players with the same score share it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchRanking {
+    pub client_id: ClientId,
+    pub name: String,
+    /// 1 for the winners
+    pub rank: u32,
+}
+
+/// What a player did during a match
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchStats {
+    pub client_id: ClientId,
+    /// Points awarded by gameplay, what players are ranked by
+    pub score: u32,
+    /// Pixels walked, breaks ties in score
+    pub distance: f32,
+    /// Chat messages sent
+    pub messages: u32,
+}
+
+/// One account's totals over every session, see `ServerMessage::Leaderboard`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub wins: u32,
+    pub playtime_secs: u64,
+    /// Chat messages sent
+    pub messages: u64,
+}
+
+/// What a player wears, from its account profile. Each id names a scene the client
+/// instances as a child of the player, e.g. `res://cosmetics/top_hat.tscn`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cosmetics {
+    pub hat: Option<String>,
+    pub trail: Option<String>,
+}
+
+impl Cosmetics {
+    /// Every slot with what is equipped in it; the slot names are also the names of
+    /// the child nodes
+    pub fn slots(&self) -> [(&'static str, Option<&str>); 2] {
+        [
+            ("Hat", self.hat.as_deref()),
+            ("Trail", self.trail.as_deref()),
+        ]
+    }
+
+    /// Ids become file names, so only letters, digits and underscores are allowed
+    pub fn valid_id(id: &str) -> bool {
+        !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+}
+
+/// Proof that a Join comes from the holder of an ed25519 identity key, see `identity`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedJoin {
+    pub public_key: [u8; 32],
+    /// Unix seconds, the server refuses signatures too far from its clock
+    pub issued_at: u64,
+    /// Random per Join, the server refuses a (key, nonce) pair it has already seen
+    pub nonce: u64,
+    /// Over the requested name, `issued_at` and `nonce`
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendStatus {
+    pub friend_id: String,
+    pub online: bool,
+}
+
+/// Server text shown in the player's language: `key` picks the message (see `texts`)
+/// and `args` fill the `{0}`, `{1}`... placeholders of its translation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalizedText {
+    pub key: String,
+    pub args: Vec<String>,
+}
+
+impl LocalizedText {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Text that isn't translated, e.g. the MOTD written by the operator
+    pub fn raw(text: impl Into<String>) -> Self {
+        Self::new(texts::RAW).arg(text.into())
+    }
+
+    /// Add the next placeholder's value
+    pub fn arg(mut self, value: impl ToString) -> Self {
+        self.args.push(value.to_string());
+        self
+    }
+
+    /// `template` with the placeholders replaced by the args. Placeholders without
+    /// an arg are kept, and args are never searched for placeholders themselves.
+    pub fn fill(&self, template: &str) -> String {
+        let mut filled = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            filled.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let arg = after.find('}').and_then(|end| {
+                let index: usize = after[..end].parse().ok()?;
+                Some((self.args.get(index)?, end))
+            });
+            match arg {
+                Some((arg, end)) => {
+                    filled.push_str(arg);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    filled.push('{');
+                    rest = after;
+                }
+            }
+        }
+        filled.push_str(rest);
+        filled
+    }
+
+    /// The English text, for logs and clients without a translation
+    pub fn english(&self) -> String {
+        self.fill(texts::english(&self.key).unwrap_or(&self.key))
+    }
+}
+
+impl std::fmt::Display for LocalizedText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.english())
+    }
+}
+
+/// Keys of `LocalizedText`. Translations (e.g. a CSV imported into the Godot project)
+/// use the same keys and placeholders as the English in `ENGLISH`.
+pub mod texts {
+    /// Shown as is, `{0}` is the whole text
+    pub const RAW: &str = "RAW";
+    pub const KICKED: &str = "KICKED";
+    pub const KICKED_REASON: &str = "KICKED_REASON";
+    pub const PLAYER_KICKED: &str = "PLAYER_KICKED";
+    pub const TOO_MANY_MALFORMED: &str = "TOO_MANY_MALFORMED";
+    pub const CHAT_TOO_FAST: &str = "CHAT_TOO_FAST";
+    pub const ROOM_PLAYER_JOINED: &str = "ROOM_PLAYER_JOINED";
+    pub const UNKNOWN_INVITE_CODE: &str = "UNKNOWN_INVITE_CODE";
+    pub const FRIEND_NOT_ONLINE: &str = "FRIEND_NOT_ONLINE";
+    pub const WHISPER_TOO_LONG: &str = "WHISPER_TOO_LONG";
+    pub const WHISPER_TOO_FAST: &str = "WHISPER_TOO_FAST";
+    pub const WHISPER_NOT_ONLINE: &str = "WHISPER_NOT_ONLINE";
+    pub const WHISPER_SELF: &str = "WHISPER_SELF";
+    pub const SERVER_FULL: &str = "SERVER_FULL";
+    pub const IDENTITY_REQUIRED: &str = "IDENTITY_REQUIRED";
+    pub const INCOMPATIBLE_VERSION: &str = "INCOMPATIBLE_VERSION";
+    pub const WRONG_PASSWORD: &str = "WRONG_PASSWORD";
+    pub const IDENTITY_BAD_KEY: &str = "IDENTITY_BAD_KEY";
+    pub const IDENTITY_BAD_SIGNATURE: &str = "IDENTITY_BAD_SIGNATURE";
+    pub const IDENTITY_EXPIRED: &str = "IDENTITY_EXPIRED";
+    pub const IDENTITY_REPLAYED: &str = "IDENTITY_REPLAYED";
+    pub const NAME_REGISTERED: &str = "NAME_REGISTERED";
+    pub const NAME_TOO_SHORT: &str = "NAME_TOO_SHORT";
+    pub const NAME_TOO_LONG: &str = "NAME_TOO_LONG";
+    pub const NAME_NO_LETTERS: &str = "NAME_NO_LETTERS";
+    pub const NAME_CONFUSABLE: &str = "NAME_CONFUSABLE";
+    pub const NAME_LOOKS_LIKE: &str = "NAME_LOOKS_LIKE";
+    pub const VOTE_RUNNING: &str = "VOTE_RUNNING";
+    pub const VOTE_COOLDOWN: &str = "VOTE_COOLDOWN";
+    pub const VOTE_KICK_SELF: &str = "VOTE_KICK_SELF";
+    pub const VOTE_PLAY_AGAIN_ONLY: &str = "VOTE_PLAY_AGAIN_ONLY";
+    pub const VOTE_TOO_FEW: &str = "VOTE_TOO_FEW";
+    pub const VOTE_LEVEL_IN_LOBBY: &str = "VOTE_LEVEL_IN_LOBBY";
+    pub const NO_SUCH_PLAYER: &str = "NO_SUCH_PLAYER";
+    pub const NO_SUCH_CLIENT: &str = "NO_SUCH_CLIENT";
+    pub const NO_LEVELS: &str = "NO_LEVELS";
+    pub const UNKNOWN_LEVEL: &str = "UNKNOWN_LEVEL";
+    pub const NOT_HOST: &str = "NOT_HOST";
+    pub const KICK_SELF: &str = "KICK_SELF";
+    pub const GAME_PAUSED: &str = "GAME_PAUSED";
+    pub const GAME_RESUMED: &str = "GAME_RESUMED";
+    pub const WORLD_RESET: &str = "WORLD_RESET";
+    pub const REPORT_SENT: &str = "REPORT_SENT";
+    pub const REPORT_SELF: &str = "REPORT_SELF";
+    pub const REPORT_TOO_FAST: &str = "REPORT_TOO_FAST";
+    // Built by clients from other server messages
+    pub const PLAYER_JOINED: &str = "PLAYER_JOINED";
+    pub const PLAYER_LEFT: &str = "PLAYER_LEFT";
+    pub const SHUTTING_DOWN: &str = "SHUTTING_DOWN";
+    pub const SHUTTING_DOWN_IN: &str = "SHUTTING_DOWN_IN";
+    pub const STATE_RESTORED: &str = "STATE_RESTORED";
+    pub const FRIEND_ONLINE: &str = "FRIEND_ONLINE";
+    pub const FRIEND_OFFLINE: &str = "FRIEND_OFFLINE";
+    pub const FRIEND_IN_ROOM: &str = "FRIEND_IN_ROOM";
+    pub const FRIEND_IN_LOBBY: &str = "FRIEND_IN_LOBBY";
+    pub const FRIEND_IN_PRIVATE_ROOM: &str = "FRIEND_IN_PRIVATE_ROOM";
+    pub const ROOM_JOINED: &str = "ROOM_JOINED";
+    pub const ROOM_LEFT: &str = "ROOM_LEFT";
+    pub const WHISPER_FAILED: &str = "WHISPER_FAILED";
+    pub const VOTE_KICK: &str = "VOTE_KICK";
+    pub const VOTE_CHANGE_LEVEL: &str = "VOTE_CHANGE_LEVEL";
+    pub const VOTE_PLAY_AGAIN: &str = "VOTE_PLAY_AGAIN";
+    pub const VOTE_STATUS: &str = "VOTE_STATUS";
+    pub const VOTE_PASSED: &str = "VOTE_PASSED";
+    pub const VOTE_FAILED: &str = "VOTE_FAILED";
+    pub const HOST_CHANGED: &str = "HOST_CHANGED";
+
+    pub const ENGLISH: &[(&str, &str)] = &[
+        (RAW, "{0}"),
+        (KICKED, "You were kicked"),
+        (KICKED_REASON, "You were kicked: {0}"),
+        (PLAYER_KICKED, "{0} was kicked"),
+        (
+            TOO_MANY_MALFORMED,
+            "Disconnected: too many malformed messages",
+        ),
+        (
+            CHAT_TOO_FAST,
+            "You are sending messages too fast, slow down",
+        ),
+        (ROOM_PLAYER_JOINED, "{0} joined the room"),
+        (UNKNOWN_INVITE_CODE, "Unknown invite code {0}"),
+        (FRIEND_NOT_ONLINE, "{0} is not online"),
+        (WHISPER_TOO_LONG, "message too long"),
+        (WHISPER_TOO_FAST, "sending too fast"),
+        (WHISPER_NOT_ONLINE, "no such player online"),
+        (WHISPER_SELF, "that's you"),
+        (SERVER_FULL, "server is full"),
+        (IDENTITY_REQUIRED, "this server requires a signed identity"),
+        (
+            INCOMPATIBLE_VERSION,
+            "your game speaks protocol version {0}, this server needs at least {1}: please update",
+        ),
+        (WRONG_PASSWORD, "wrong server password"),
+        (IDENTITY_BAD_KEY, "invalid identity key"),
+        (IDENTITY_BAD_SIGNATURE, "invalid join signature"),
+        (IDENTITY_EXPIRED, "join signature expired, check your clock"),
+        (IDENTITY_REPLAYED, "join signature already used"),
+        (NAME_REGISTERED, "name is registered to another identity"),
+        (NAME_TOO_SHORT, "name must be at least {0} characters"),
+        (NAME_TOO_LONG, "name must be at most {0} characters"),
+        (NAME_NO_LETTERS, "name must contain a letter or digit"),
+        (
+            NAME_CONFUSABLE,
+            "name mixes letters from different alphabets",
+        ),
+        (NAME_LOOKS_LIKE, "name looks too much like {0}'s"),
+        (VOTE_RUNNING, "Another vote is already running"),
+        (VOTE_COOLDOWN, "You can start another vote in {0} seconds"),
+        (VOTE_KICK_SELF, "You can't vote to kick yourself"),
+        (
+            VOTE_PLAY_AGAIN_ONLY,
+            "Playing again is voted on from the results screen",
+        ),
+        (
+            VOTE_TOO_FEW,
+            "A kick needs at least {0} other players here to vote",
+        ),
+        (VOTE_LEVEL_IN_LOBBY, "Levels are voted on from the lobby"),
+        (NO_SUCH_PLAYER, "No player named {0}"),
+        (NO_SUCH_CLIENT, "That player is no longer connected"),
+        (NO_LEVELS, "This server has no levels to vote for"),
+        (UNKNOWN_LEVEL, "Unknown level {0}, pick one of: {1}"),
+        (NOT_HOST, "Only the host can do that"),
+        (KICK_SELF, "You can't kick yourself"),
+        (GAME_PAUSED, "{0} paused the game"),
+        (GAME_RESUMED, "{0} resumed the game"),
+        (WORLD_RESET, "{0} reset the world"),
+        (REPORT_SENT, "Your report about {0} was sent to the admins"),
+        (REPORT_SELF, "You can't report yourself"),
+        (
+            REPORT_TOO_FAST,
+            "You can send another report in {0} seconds",
+        ),
+        (PLAYER_JOINED, "{0} joined"),
+        (PLAYER_LEFT, "{0} left"),
+        (SHUTTING_DOWN, "Server is shutting down"),
+        (SHUTTING_DOWN_IN, "Server shutting down in {0} seconds"),
+        (
+            STATE_RESTORED,
+            "Server restarted, session restored from a save {0} min ago",
+        ),
+        (FRIEND_ONLINE, "{0} is now online"),
+        (FRIEND_OFFLINE, "{0} went offline"),
+        (FRIEND_IN_ROOM, "Joining {0} in their room"),
+        (FRIEND_IN_LOBBY, "{0} is in the lobby"),
+        (FRIEND_IN_PRIVATE_ROOM, "{0} is in a private room"),
+        (
+            ROOM_JOINED,
+            "You are in private room {0}, share this code to invite others",
+        ),
+        (ROOM_LEFT, "You are back in the lobby"),
+        (WHISPER_FAILED, "Whisper to {0} failed: {1}"),
+        (VOTE_KICK, "kick {0}"),
+        (VOTE_CHANGE_LEVEL, "change level to {0}"),
+        (VOTE_PLAY_AGAIN, "play again"),
+        (
+            VOTE_STATUS,
+            "{0} wants to {1} ({2}s)\nYes {3} / No {4}, {5} of {6} votes needed",
+        ),
+        (VOTE_PASSED, "Vote to {0} passed"),
+        (VOTE_FAILED, "Vote to {0} failed"),
+        (HOST_CHANGED, "{0} is now the host"),
+    ];
+
+    pub fn english(key: &str) -> Option<&'static str> {
+        ENGLISH
+            .iter()
+            .find(|(known, _)| *known == key)
+            .map(|(_, text)| *text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoticeLevel {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// What a vote started with `ClientMessage::StartVote` decides
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoteKind {
+    /// Disconnect the player with this name
+    Kick { name: String },
+    /// Load another level scene, one of the server's `levels`
+    ChangeLevel { level: String },
+    /// Start the next match right away instead of after the results. Opened by the
+    /// first `ClientMessage::PlayAgain`, not by StartVote.
+    PlayAgain,
+}
+
+impl VoteKind {
+    /// What the vote is about, e.g. "kick Bob"
+    pub fn text(&self) -> LocalizedText {
+        match self {
+            VoteKind::Kick { name } => LocalizedText::new(texts::VOTE_KICK).arg(name),
+            VoteKind::ChangeLevel { level } => {
+                LocalizedText::new(texts::VOTE_CHANGE_LEVEL).arg(level)
+            }
+            VoteKind::PlayAgain => LocalizedText::new(texts::VOTE_PLAY_AGAIN),
+        }
+    }
+}
+
+impl std::fmt::Display for VoteKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.text().fmt(f)
+    }
+}
+
+/// Privileged actions, only taken from the match host (see `ServerMessage::HostChanged`).
+/// Refused with a SystemNotice from anyone else.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdminCommand {
+    /// Disconnect the player with this name
+    Kick { name: String },
+    /// Load another level scene, one of the server's `levels`
+    ChangeLevel { level: String },
+    /// Freeze (or unfreeze) every player's movement, see `ServerMessage::Paused`
+    Pause { paused: bool },
+    /// Hand the host role to another player
+    TransferHost { to: ClientId },
+    /// Props back where they started and everyone to a spawn point, see
+    /// `ServerMessage::WorldReset`
+    ResetWorld,
+}
+
+/// Variant names of a message enum, for logs, traces and handler registration
+pub trait MessageKind {
+    /// Every variant name, in declaration order: a variant's position is its tag on
+    /// the wire
+    const KINDS: &'static [&'static str];
+
+    fn kind(&self) -> &'static str;
+}
+
+/// Defines a message enum from `client_messages!`/`server_messages!` and its `MessageKind`
+macro_rules! message_enum {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident { $($(#[$field_meta:meta])* $field:ident: $ty:ty),* $(,)? }
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant { $($(#[$field_meta])* $field: $ty),* },
+            )*
+        }
+
+        impl MessageKind for $name {
+            const KINDS: &'static [&'static str] = &[$(stringify!($variant)),*];
+
+            fn kind(&self) -> &'static str {
+                match self {
+                    $(Self::$variant { .. } => stringify!($variant),)*
+                }
+            }
+        }
+    };
+}
+
+/// Hands the `ClientMessage` variants to the macro `$callback`, e.g. to generate a type
+/// per variant; `ClientMessage` itself is defined this way. Types are `$crate`-qualified
+/// so they resolve wherever the callback expands.
+#[macro_export]
+macro_rules! client_messages {
+    ($callback:ident) => {
+        $callback! {
+            /// Messages from clients
+            ClientMessage {
+                Join {
+                    name: String,
+                    /// Optional; once a name joined with a key, only that key may use it
+                    identity: Option<$crate::SignedJoin>,
+                    /// Required when the server has a join password
+                    password: Option<String>,
+                },
+                Disconnect {},
+                ChatMessage {
+                    message: String,
+                    /// Picked by the sender and echoed back, so it can match the echo to its local entry
+                    local_id: u32,
+                },
+                PlayerUpdate {
+                    /// Incremented by the client for every update, used to drop stale/duplicate updates
+                    seq: u32,
+                    x: f32,
+                    y: f32,
+                    horizontal: f32,
+                    vertical: f32,
+                    /// Client clock in milliseconds (any origin, wrapping) when the input was
+                    /// read. Fast clients send several updates per server tick, which are
+                    /// weighed by how long each input was held.
+                    sent_at_ms: u32,
+                },
+                /// Private message to the player named `to`, answered with WhisperDelivered or WhisperFailed
+                Whisper {
+                    to: String,
+                    text: String,
+                    local_id: u32,
+                },
+                /// `friend_id` is the friend's account id
+                AddFriend {
+                    friend_id: String,
+                },
+                RemoveFriend {
+                    friend_id: String,
+                },
+                /// Ask where a friend is, answered with FriendLocation
+                JoinFriend {
+                    friend_id: String,
+                },
+                /// Open a private room, answered with RoomJoined and its invite code
+                CreateRoom {},
+                /// Join a private room by invite code
+                JoinRoom {
+                    code: String,
+                },
+                /// Back to the lobby
+                LeaveRoom {},
+                /// Play an emote on our player for everyone in the room, see `emotes`
+                Emote {
+                    id: u32,
+                },
+                /// Ask to simulate a prop we're touching, answered with AuthorityGranted if we're
+                /// the nearest player. Repeated while touching it to keep the authority.
+                RequestAuthority {
+                    prop_id: u32,
+                },
+                /// New state of a prop we have authority over
+                PropUpdate {
+                    state: $crate::PropState,
+                },
+                /// Connectivity probe, answered with a Pong even before Join
+                Ping {},
+                /// The app went to the background (or came back), shown to peers with PlayerAway
+                SetAway {
+                    away: bool,
+                },
+                /// We dropped our world: answered like a Join (InitClient, game rules,
+                /// environment), then the world state is streamed again without the chat
+                /// backlog. Sent after a checksum mismatch, a long stall or being backgrounded.
+                RequestFullSync {},
+                /// Sent instead of Join after a dropped connection, answered with Resumed or ResumeFailed
+                Resume {
+                    token: u64,
+                },
+                /// Start a vote, which we vote yes in. Refused with a SystemNotice while another
+                /// vote runs or during the cooldown after our last one.
+                StartVote {
+                    kind: $crate::VoteKind,
+                },
+                CastVote {
+                    yes: bool,
+                },
+                /// While the results of a match are shown: whether we want the next one
+                /// to start now. Counted like a vote (`VoteKind::PlayAgain`).
+                PlayAgain {
+                    yes: bool,
+                },
+                /// Only obeyed from the host
+                Admin {
+                    command: $crate::AdminCommand,
+                },
+                /// Answered with a Leaderboard
+                RequestLeaderboard {},
+                /// Report a misbehaving player to the server's operators, answered with a
+                /// SystemNotice. Limited to one every few minutes.
+                Report {
+                    name: String,
+                    reason: String,
+                },
+            }
+        }
+    };
+}
+
+client_messages!(message_enum);
+
+/// Same as `client_messages!`, for `ServerMessage`
+#[macro_export]
+macro_rules! server_messages {
+    ($callback:ident) => {
+        $callback! {
+            /// Messages from the server
+            ServerMessage {
+                ClientConnected {
+                    client_id: $crate::ClientId,
+                    username: String,
+                    /// Spawn point picked by the server
+                    x: f32,
+                    y: f32,
+                    cosmetics: $crate::Cosmetics,
+                },
+                ClientDisconnected {
+                    client_id: $crate::ClientId,
+                },
+                ChatMessage {
+                    client_id: $crate::ClientId,
+                    message: String,
+                    /// The sender's `local_id`, only meaningful to the sender
+                    local_id: u32,
+                    /// Unix timestamp assigned by the server, seconds
+                    sent_at: u64,
+                },
+                /// First reply to Join; the world state is then streamed in batches
+                /// (WorldPlayers, ChatBacklog, PropSnapshot) with SyncProgress after each,
+                /// until SyncComplete
+                InitClient {
+                    client_id: $crate::ClientId,
+                    /// Seed for randomness that must match on every client, see `SharedRng`
+                    rng_seed: u64,
+                },
+                /// Players already in the world (in our room), this client included; also sent
+                /// with the players of a room we enter and to its players with ours
+                WorldPlayers {
+                    players: Vec<$crate::PlayerInfo>,
+                },
+                ChatBacklog {
+                    records: Vec<$crate::ChatRecord>,
+                },
+                /// `done` of `total` world state batches sent
+                SyncProgress {
+                    done: u32,
+                    total: u32,
+                },
+                SyncComplete {},
+                /// A large message (e.g. a stream batch) follows in `chunk_count` InitChunks, see `split_chunks`
+                InitBegin {
+                    total_len: u32,
+                    chunk_count: u32,
+                },
+                InitChunk {
+                    index: u32,
+                    data: Vec<u8>,
+                },
+                /// All chunks sent, the client decodes and handles the reassembled message
+                InitEnd {},
+                Whisper {
+                    from: String,
+                    text: String,
+                    sent_at: u64,
+                },
+                WhisperDelivered {
+                    local_id: u32,
+                    to: String,
+                },
+                WhisperFailed {
+                    local_id: u32,
+                    to: String,
+                    reason: $crate::LocalizedText,
+                },
+                /// Sent after InitClient and whenever the list changes
+                FriendList {
+                    friends: Vec<$crate::FriendStatus>,
+                },
+                /// A friend connected or left
+                FriendPresence {
+                    friend_id: String,
+                    online: bool,
+                },
+                /// Where an online friend is; `room` is None for the shared lobby
+                FriendLocation {
+                    friend_id: String,
+                    room: Option<String>,
+                },
+                /// The client moved to the private room with this invite code, or the lobby (None).
+                /// Chat and player updates only reach clients in the same room.
+                RoomJoined {
+                    code: Option<String>,
+                },
+                /// `client_id` left our room (or we left its room) but is still connected:
+                /// despawn its player without the disconnect handling
+                PlayerLeftRoom {
+                    client_id: $crate::ClientId,
+                },
+                /// `client_id` played an emote, sent to everyone in its room including itself
+                Emote {
+                    client_id: $crate::ClientId,
+                    id: u32,
+                },
+                /// Sent on join and whenever a rule changes
+                GameRules {
+                    rules: $crate::GameRules,
+                },
+                /// Server state of every prop, skip the ones we have authority over
+                PropSnapshot {
+                    props: Vec<$crate::PropState>,
+                },
+                /// We may simulate this prop locally and report it with PropUpdate
+                AuthorityGranted {
+                    prop_id: u32,
+                },
+                /// Authority expired, went to a nearer player, or an update was rejected;
+                /// `state` is the server's, to snap back to
+                AuthorityRevoked {
+                    state: $crate::PropState,
+                },
+                /// Time of day and weather, sent on join and every few seconds
+                Environment {
+                    state: $crate::EnvironmentState,
+                },
+                /// The level's moving platforms and the server clock they follow, sent on
+                /// join, when they change and every few seconds to correct clock drift
+                Platforms {
+                    /// Milliseconds since the server started
+                    server_time_ms: u64,
+                    platforms: Vec<$crate::PlatformPath>,
+                },
+                /// Gameplay sound at a world position, see `sounds`
+                PlaySoundAt {
+                    sound_id: u32,
+                    x: f32,
+                    y: f32,
+                },
+                /// Announcements, moderation and warnings from the server itself
+                /// (MOTD, kicks, rule reminders, rate limiting)
+                SystemNotice {
+                    level: $crate::NoticeLevel,
+                    text: $crate::LocalizedText,
+                },
+                /// Sent after a successful Join or Resume; Resume with it after a dropped connection
+                /// to keep the session. Single use, a new one comes with Resumed.
+                SessionToken {
+                    token: u64,
+                },
+                /// The session survived the dropped connection; we now have a new client id.
+                /// What changed while we were away is streamed like on join (without chat backlog).
+                Resumed {
+                    client_id: $crate::ClientId,
+                    previous_client_id: $crate::ClientId,
+                },
+                /// The session expired, Join instead
+                ResumeFailed {},
+                /// A player in our room backgrounded its app, or came back
+                PlayerAway {
+                    client_id: $crate::ClientId,
+                    away: bool,
+                },
+                /// A peer resumed its session after a dropped connection and got a new client id
+                ClientMigrated {
+                    from: $crate::ClientId,
+                    to: $crate::ClientId,
+                },
+                /// Join refused for a reason other than the name (e.g. server full)
+                JoinRejected {
+                    reason: $crate::LocalizedText,
+                },
+                /// The server is going down in `seconds` (0: immediately, e.g. after a crash)
+                ShuttingDown {
+                    seconds: u32,
+                },
+                /// The server restored its state from a save made at `saved_at` (unix seconds)
+                StateRestored {
+                    saved_at: u64,
+                },
+                PlayerUpdate {
+                    client_id: $crate::ClientId,
+                    seq: u32,
+                    x: f32,
+                    y: f32,
+                    horizontal: f32,
+                    vertical: f32,
+                },
+                Pong {
+                    protocol_version: u32,
+                    players: usize,
+                },
+                /// The sanitized name the server accepted for this client
+                NameAssigned {
+                    name: String,
+                },
+                /// Join refused because of the requested name, the client may retry
+                NameRejected {
+                    reason: $crate::LocalizedText,
+                },
+                /// Sent when a vote starts and after every ballot
+                VoteProgress {
+                    kind: $crate::VoteKind,
+                    started_by: String,
+                    yes: u32,
+                    no: u32,
+                    /// Players who may vote
+                    eligible: u32,
+                    /// Ballots needed for the vote to count
+                    quorum: u32,
+                    remaining_secs: u32,
+                },
+                VoteEnded {
+                    kind: $crate::VoteKind,
+                    passed: bool,
+                },
+                /// Load this level scene, sent on join and when a level vote passes
+                ChangeLevel {
+                    level: String,
+                },
+                /// The player who may send `Admin` commands, None while nobody is connected.
+                /// Sent on join and whenever it changes, e.g. to move a crown icon.
+                HostChanged {
+                    host: Option<$crate::ClientId>,
+                },
+                /// The host paused the game: movement is ignored until it resumes.
+                /// Sent on join while paused and whenever it changes.
+                Paused {
+                    paused: bool,
+                },
+                /// The world was reset, e.g. to restart a match: props are back where they
+                /// started and every player at a spawn point. Clients drop their players and
+                /// props and ask for a full sync to rebuild them.
+                WorldReset {},
+                /// Something in our room players should feel, at a world position; `intensity`
+                /// from 0 to 1, scaled down by the client with the distance to its player
+                FeedbackEvent {
+                    kind: $crate::FeedbackKind,
+                    intensity: f32,
+                    x: f32,
+                    y: f32,
+                },
+                /// Round trip times of the players in our room, ourselves included,
+                /// sent every few seconds
+                PlayerPings {
+                    pings: Vec<$crate::PlayerPing>,
+                },
+                /// Put this player (possibly us) there right away, e.g. on respawn or to undo
+                /// an impossible move. Unlike PlayerUpdate it applies to our own player too.
+                Teleport {
+                    client_id: $crate::ClientId,
+                    x: f32,
+                    y: f32,
+                },
+                /// `state_checksum` of our room as the server sees it, sent every few
+                /// seconds; the client asks for a resync when it keeps disagreeing
+                StateChecksum {
+                    checksum: u64,
+                },
+                /// The best accounts on this server, best first
+                Leaderboard {
+                    entries: Vec<$crate::LeaderboardEntry>,
+                },
+                /// The match is over, rankings best first. The next one starts with
+                /// MatchStarted.
+                MatchResults {
+                    rankings: Vec<$crate::MatchRanking>,
+                    stats: Vec<$crate::MatchStats>,
+                },
+                /// A new match began, scores are back to zero
+                MatchStarted {
+                    duration_secs: u32,
+                },
+                /// A player in our room (possibly us) now wears something else
+                CosmeticsChanged {
+                    client_id: $crate::ClientId,
+                    cosmetics: $crate::Cosmetics,
+                },
+            }
+        }
+    };
+}
+
+server_messages!(message_enum);
+
+/// A message variant as written in `client_messages!`/`server_messages!`, doc comments
+/// included, for tools that document the protocol
+#[derive(Debug, Clone, Copy)]
+pub struct VariantSpec {
+    pub name: &'static str,
+    /// One entry per doc comment line
+    pub docs: &'static [&'static str],
+    pub fields: &'static [FieldSpec],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FieldSpec {
+    pub name: &'static str,
+    /// As written in the macro, e.g. `Option<$crate::SignedJoin>`, see `type_name`
+    pub ty: &'static str,
+    pub docs: &'static [&'static str],
+}
+
+/// Layout of a message enum, see `protocol_spec`
+pub trait MessageSpec {
+    /// Doc comment of the enum
+    const DOCS: &'static [&'static str];
+    /// Every variant, in declaration order
+    const VARIANTS: &'static [VariantSpec];
+}
+
+/// Implements `MessageSpec` from `client_messages!`/`server_messages!`. Only doc
+/// comments are expected on the enum, its variants and their fields.
+macro_rules! message_spec {
+    (
+        $(#[doc = $doc:literal])*
+        $name:ident {
+            $(
+                $(#[doc = $variant_doc:literal])*
+                $variant:ident {
+                    $($(#[doc = $field_doc:literal])* $field:ident: $ty:ty),* $(,)?
+                }
+            ),* $(,)?
+        }
+    ) => {
+        impl MessageSpec for $name {
+            const DOCS: &'static [&'static str] = &[$($doc),*];
+            const VARIANTS: &'static [VariantSpec] = &[$(
+                VariantSpec {
+                    name: stringify!($variant),
+                    docs: &[$($variant_doc),*],
+                    fields: &[$(
+                        FieldSpec {
+                            name: stringify!($field),
+                            ty: stringify!($ty),
+                            docs: &[$($field_doc),*],
+                        }
+                    ),*],
+                }
+            ),*];
+        }
+    };
+}
+
+client_messages!(message_spec);
+server_messages!(message_spec);
+
+/// A field type as the `protocol` crate names it: `$crate::` and spacing removed
+pub fn type_name(ty: &str) -> String {
+    ty.replace(' ', "").replace("$crate::", "")
+}
+
+/// Doc comment lines joined into one, for a table cell
+fn doc_text(docs: &[&str]) -> String {
+    let lines: Vec<&str> = docs.iter().map(|line| line.trim()).collect();
+    lines.join(" ").replace('|', "\\|")
+}
+
+fn push_messages<T: MessageSpec>(spec: &mut String, name: &str, direction: &str) {
+    spec.push_str(&format!("\n## {} ({})\n\n", name, direction));
+    spec.push_str(&format!("{}\n", doc_text(T::DOCS)));
+    for (tag, variant) in T::VARIANTS.iter().enumerate() {
+        spec.push_str(&format!("\n### `{}` (tag {})\n\n", variant.name, tag));
+        if !variant.docs.is_empty() {
+            spec.push_str(&format!("{}\n\n", doc_text(variant.docs)));
+        }
+        spec.push_str(&format!("Channel: `{}`\n\n", channels::DEFAULT));
+        if variant.fields.is_empty() {
+            spec.push_str("No fields.\n");
+            continue;
+        }
+        spec.push_str("| Field | Type | Description |\n|---|---|---|\n");
+        for field in variant.fields {
+            spec.push_str(&format!(
+                "| `{}` | `{}` | {} |\n",
+                field.name,
+                type_name(field.ty),
+                doc_text(field.docs)
+            ));
+        }
+    }
+}
+
+/// Markdown description of every message, its fields and how it is encoded, for
+/// people writing other clients and to diff the protocol between versions
+pub fn protocol_spec() -> String {
+    let mut spec = format!("# Protocol version {}\n\n", PROTOCOL_VERSION);
+    spec.push_str(
+        "Messages are encoded with bincode 1 defaults: little-endian fixed-width \
+         integers, `u64` lengths before strings and sequences, and a `u8` 0 or 1 before \
+         an `Option`'s value. Each one travels in an envelope: the message's tag as a \
+         `u16`, the sender's protocol version as a `u32`, then the message's fields in \
+         order, as a byte sequence. Skip messages with an unknown tag: newer versions \
+         that only add messages stay compatible, and any other change raises the oldest \
+         compatible version. Every message travels on the ordered, reliable `default` channel. \
+         Types other than standard ones are defined in the `protocol` crate.\n",
+    );
+    push_messages::<ClientMessage>(&mut spec, "ClientMessage", "client to server");
+    push_messages::<ServerMessage>(&mut spec, "ServerMessage", "server to client");
+    spec
+}
@@ -0,0 +1,176 @@
+use std::collections::{BTreeSet, HashMap};
+
+use bevy::prelude::*;
+use protocol::{Cosmetics, LeaderboardEntry};
+use serde::{Deserialize, Serialize};
+
+/// An account is identified by its identity key when the player signs its joins, so it
+/// keeps its profile under another name, and by its normalized username otherwise. Name
+/// ids are lowercase, they never start with `Key:`.
+pub fn account_id(username: &str, identity_key: Option<&[u8; 32]>) -> String {
+    match identity_key {
+        Some(key) => {
+            let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("Key:{}", hex)
+        }
+        None => username.to_lowercase(),
+    }
+}
+
+/// Per-account data the server keeps across sessions, saved with the server state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountProfile {
+    /// Account ids this player follows; one-way, like a contact list
+    #[serde(default)]
+    pub friends: BTreeSet<String>,
+    /// Public key of the account's signed joins; later joins with its name must be
+    /// signed by it too
+    #[serde(default)]
+    pub identity_key: Option<[u8; 32]>,
+    /// Where the player was when it last left, restored on its next join
+    #[serde(default)]
+    pub last_position: Option<LastPosition>,
+    /// Shown to every player, set by the operator with the RCON `cosmetic` command
+    #[serde(default)]
+    pub cosmetics: Cosmetics,
+    #[serde(default)]
+    pub stats: AccountStats,
+}
+
+/// Totals over every session, for the leaderboard
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountStats {
+    /// Name as last joined with, shown instead of the account id
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub wins: u32,
+    #[serde(default)]
+    pub playtime_secs: u64,
+    #[serde(default)]
+    pub messages: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastPosition {
+    pub x: f32,
+    pub y: f32,
+    /// Invite code of the private room it was in, None for the lobby. Only restored
+    /// if the room is still open.
+    pub room: Option<String>,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Accounts(pub HashMap<String, AccountProfile>);
+
+impl Accounts {
+    pub fn profile_mut(&mut self, id: &str) -> &mut AccountProfile {
+        self.0.entry(id.to_string()).or_default()
+    }
+
+    /// Key of the account whose player last joined as `username`, if it signs its joins:
+    /// nobody else may use that name
+    pub fn name_owner(&self, username: &str) -> Option<[u8; 32]> {
+        let name = username.to_lowercase();
+        self.0
+            .values()
+            .filter(|profile| profile.stats.name.to_lowercase() == name)
+            .find_map(|profile| profile.identity_key)
+    }
+
+    /// Account id of a name typed by a player, e.g. for a friend request
+    pub fn resolve(&self, username: &str) -> String {
+        match self.name_owner(username) {
+            Some(key) => account_id(username, Some(&key)),
+            None => account_id(username, None),
+        }
+    }
+
+    /// How players see an account: the name it last joined with
+    pub fn display_name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.0
+            .get(id)
+            .map(|profile| profile.stats.name.as_str())
+            .filter(|name| !name.is_empty())
+            .unwrap_or(id)
+    }
+
+    /// Saves from before accounts were keyed by identity key have bound profiles under
+    /// their name: move them (and the friend lists naming them) to their key
+    pub fn rekey_bound_profiles(&mut self) {
+        let moved: Vec<(String, String)> = self
+            .0
+            .iter()
+            .filter_map(|(id, profile)| {
+                let key = profile.identity_key?;
+                let new_id = account_id(id, Some(&key));
+                (new_id != *id).then(|| (id.clone(), new_id))
+            })
+            .collect();
+        for (old_id, new_id) in &moved {
+            self.move_profile(old_id, new_id);
+        }
+    }
+
+    /// A signed join with a name no key owns: bind it, taking over the profile kept
+    /// under the name if the key has none yet
+    pub fn bind(&mut self, username: &str, key: [u8; 32]) {
+        let id = account_id(username, Some(&key));
+        if !self.0.contains_key(&id) {
+            self.move_profile(&account_id(username, None), &id);
+        }
+        self.profile_mut(&id).identity_key = Some(key);
+    }
+
+    fn move_profile(&mut self, old_id: &str, new_id: &str) {
+        let Some(mut profile) = self.0.remove(old_id) else {
+            return;
+        };
+        if profile.stats.name.is_empty() {
+            profile.stats.name = old_id.to_string();
+        }
+        self.0.insert(new_id.to_string(), profile);
+        for profile in self.0.values_mut() {
+            if profile.friends.remove(old_id) {
+                profile.friends.insert(new_id.to_string());
+            }
+        }
+    }
+
+    pub fn friends_of(&self, id: &str) -> impl Iterator<Item = &String> {
+        self.0
+            .get(id)
+            .into_iter()
+            .flat_map(|profile| &profile.friends)
+    }
+
+    /// The `count` best accounts: most wins, then most playtime, then most messages
+    pub fn leaderboard(&self, count: usize) -> Vec<LeaderboardEntry> {
+        let mut stats: Vec<&AccountStats> = self
+            .0
+            .values()
+            .map(|profile| &profile.stats)
+            .filter(|stats| !stats.name.is_empty())
+            .collect();
+        stats.sort_by(|a, b| {
+            (b.wins, b.playtime_secs, b.messages).cmp(&(a.wins, a.playtime_secs, a.messages))
+        });
+        stats
+            .into_iter()
+            .take(count)
+            .map(|stats| LeaderboardEntry {
+                name: stats.name.clone(),
+                wins: stats.wins,
+                playtime_secs: stats.playtime_secs,
+                messages: stats.messages,
+            })
+            .collect()
+    }
+
+    /// Whether `follower` has `id` in their friend list
+    pub fn follows(&self, follower: &str, id: &str) -> bool {
+        self.0
+            .get(follower)
+            .is_some_and(|profile| profile.friends.contains(id))
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+#[cfg(feature = "server-movement")]
+use std::time::Duration;
+use std::time::Instant;
+
+use bevy_quinnet::shared::ClientId;
+
+/// Movement over a gap can't be judged exactly (jitter, bursts after a stall):
+/// this much distance is always allowed on top of `max_speed * elapsed`
+#[cfg(feature = "server-movement")]
+const MOVE_SLACK: f32 = 32.0;
+#[cfg(feature = "server-movement")]
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// What one client did that an honest client wouldn't
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientAnomalies {
+    /// Moves faster than the configured max speed
+    pub speed_violations: u32,
+    /// Fastest move seen, pixels per second
+    pub max_speed: f32,
+    /// Seconds in which it sent more movement updates than allowed
+    pub rate_violations: u32,
+    /// Most movement updates seen in one second
+    pub peak_update_rate: u32,
+    /// Messages rejected at the decode boundary
+    pub malformed: u32,
+}
+
+impl ClientAnomalies {
+    pub fn is_clean(&self) -> bool {
+        self.speed_violations == 0 && self.rate_violations == 0 && self.malformed == 0
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} speed violations (max {:.0} px/s), {} rate violations (peak {}/s), {} malformed",
+            self.speed_violations,
+            self.max_speed,
+            self.rate_violations,
+            self.peak_update_rate,
+            self.malformed
+        )
+    }
+}
+
+/// Per-client anomaly statistics, reported periodically and over RCON
+#[derive(Debug, Clone, Default)]
+pub struct Anomalies {
+    clients: HashMap<ClientId, ClientAnomalies>,
+    last_move: HashMap<ClientId, (f32, f32, Instant)>,
+    /// Updates in the current window and when it started
+    update_window: HashMap<ClientId, (Instant, u32)>,
+}
+
+impl Anomalies {
+    /// A movement update reached `(x, y)`
+    #[cfg(feature = "server-movement")]
+    pub fn record_move(&mut self, client_id: ClientId, x: f32, y: f32, max_speed: f32) {
+        let now = Instant::now();
+        let Some((last_x, last_y, at)) = self.last_move.insert(client_id, (x, y, now)) else {
+            return;
+        };
+        let elapsed = now.duration_since(at).as_secs_f32();
+        let distance = ((x - last_x).powi(2) + (y - last_y).powi(2)).sqrt();
+        let stats = self.clients.entry(client_id).or_default();
+        if elapsed > 0.0 {
+            stats.max_speed = stats.max_speed.max(distance / elapsed);
+        }
+        if distance > max_speed * elapsed + MOVE_SLACK {
+            stats.speed_violations += 1;
+        }
+    }
+
+    /// Count a movement update against `max_per_sec`
+    #[cfg(feature = "server-movement")]
+    pub fn record_update(&mut self, client_id: ClientId, max_per_sec: u32) {
+        let now = Instant::now();
+        let (started, count) = self.update_window.entry(client_id).or_insert((now, 0));
+        if now.duration_since(*started) >= RATE_WINDOW {
+            *started = now;
+            *count = 0;
+        }
+        *count += 1;
+        let stats = self.clients.entry(client_id).or_default();
+        stats.peak_update_rate = stats.peak_update_rate.max(*count);
+        // Counted once per window, when it goes over
+        if *count == max_per_sec + 1 {
+            stats.rate_violations += 1;
+        }
+    }
+
+    pub fn record_malformed(&mut self, client_id: ClientId, count: u32) {
+        self.clients.entry(client_id).or_default().malformed += count;
+    }
+
+    /// A spawn or resync moved the player without it walking there
+    pub fn reset_position(&mut self, client_id: ClientId) {
+        self.last_move.remove(&client_id);
+    }
+
+    pub fn migrate(&mut self, from: ClientId, to: ClientId) {
+        if let Some(stats) = self.clients.remove(&from) {
+            self.clients.insert(to, stats);
+        }
+        if let Some(last) = self.last_move.remove(&from) {
+            self.last_move.insert(to, last);
+        }
+        self.update_window.remove(&from);
+    }
+
+    /// Drop a client, returning what it did if anything
+    pub fn forget(&mut self, client_id: ClientId) -> Option<ClientAnomalies> {
+        self.last_move.remove(&client_id);
+        self.update_window.remove(&client_id);
+        self.clients
+            .remove(&client_id)
+            .filter(|stats| !stats.is_clean())
+    }
+
+    /// Clients with anomalies, worst first
+    pub fn flagged(&self) -> Vec<(ClientId, ClientAnomalies)> {
+        let mut flagged: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, stats)| !stats.is_clean())
+            .map(|(client_id, stats)| (*client_id, *stats))
+            .collect();
+        flagged.sort_by_key(|(_, stats)| {
+            std::cmp::Reverse(stats.speed_violations + stats.rate_violations + stats.malformed)
+        });
+        flagged
+    }
+}
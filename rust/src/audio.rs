@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use godot::{
+    classes::{AudioStream, AudioStreamPlayer2D, Engine, ResourceLoader, SceneTree},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::sounds;
+
+use crate::frame_budget;
+
+/// Upper bound on simultaneous positional sounds; extra requests are dropped
+const MAX_PLAYERS: usize = 16;
+
+/// The server asked for `sound_id` to be played at `position`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlaySoundEvent {
+    pub sound_id: u32,
+    pub position: Vector2,
+}
+
+/// Which AudioStream each protocol sound id plays
+#[derive(Resource, Debug, Clone)]
+pub struct SoundLibrary(pub HashMap<u32, String>);
+
+impl Default for SoundLibrary {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (sounds::FOOTSTEP, "res://sounds/footstep.ogg".to_string()),
+            (sounds::CHAT_PING, "res://sounds/chat_ping.ogg".to_string()),
+            (
+                sounds::ITEM_PICKUP,
+                "res://sounds/item_pickup.ogg".to_string(),
+            ),
+        ]))
+    }
+}
+
+/// Reused AudioStreamPlayer2D nodes and loaded streams (None: failed to load, don't retry).
+/// Godot objects aren't Send, so this lives in a non-send resource.
+#[derive(Default)]
+pub struct AudioPool {
+    players: Vec<Gd<AudioStreamPlayer2D>>,
+    streams: HashMap<u32, Option<Gd<AudioStream>>>,
+}
+
+impl AudioPool {
+    fn stream(&mut self, sound_id: u32, library: &SoundLibrary) -> Option<Gd<AudioStream>> {
+        self.streams
+            .entry(sound_id)
+            .or_insert_with(|| {
+                let path = library.0.get(&sound_id)?;
+                let stream = ResourceLoader::singleton()
+                    .load(path)
+                    .and_then(|resource| resource.try_cast::<AudioStream>().ok());
+                if stream.is_none() {
+                    godot_print!("Could not load sound {} from {}", sound_id, path);
+                }
+                stream
+            })
+            .clone()
+    }
+
+    /// An idle player, creating one while under the cap
+    fn idle_player(&mut self) -> Option<Gd<AudioStreamPlayer2D>> {
+        self.players.retain(|player| player.is_instance_valid());
+        if let Some(player) = self.players.iter().find(|player| !player.is_playing()) {
+            return Some(player.clone());
+        }
+        if self.players.len() >= MAX_PLAYERS {
+            return None;
+        }
+        let mut scene = Engine::singleton()
+            .get_main_loop()
+            .and_then(|main_loop| main_loop.try_cast::<SceneTree>().ok())
+            .and_then(|tree| tree.get_current_scene())?;
+        let player = AudioStreamPlayer2D::new_alloc();
+        scene.add_child(&player);
+        self.players.push(player.clone());
+        Some(player)
+    }
+}
+
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlaySoundEvent>()
+            .init_resource::<SoundLibrary>()
+            .insert_non_send_resource(AudioPool::default())
+            .add_systems(Update, play_sounds);
+    }
+}
+
+#[main_thread_system]
+fn play_sounds(
+    mut events: EventReader<PlaySoundEvent>,
+    library: Res<SoundLibrary>,
+    mut pool: NonSendMut<AudioPool>,
+) {
+    let _timing = frame_budget::scope("play_sounds");
+    for ev in events.read() {
+        let Some(stream) = pool.stream(ev.sound_id, &library) else {
+            continue;
+        };
+        let Some(mut player) = pool.idle_player() else {
+            continue;
+        };
+        player.set_stream(&stream);
+        player.set_global_position(ev.position);
+        player.play();
+    }
+}
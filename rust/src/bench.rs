@@ -0,0 +1,293 @@
+//! `server --bench n_clients=64 duration=60`: run the dedicated server against bot
+//! clients on loopback for a while, then print tick times, bandwidth and drops, to
+//! compare builds.
+
+use std::{
+    thread::{self, JoinHandle, sleep},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_quinnet::client::{
+    QuinnetClient, QuinnetClientPlugin, certificate::CertificateVerificationMode,
+    connection::ClientEndpointConfiguration,
+};
+use protocol::ClientMessage;
+
+/// Time for the server endpoint to come up before the bots connect
+const STARTUP_DELAY: Duration = Duration::from_secs(1);
+/// How often each bot sends a movement update and a chat message
+const MOVE_INTERVAL: Duration = Duration::from_millis(50);
+const CHAT_INTERVAL: Duration = Duration::from_secs(2);
+/// Server frame times are counted in buckets this wide, up to `MAX_FRAME_TIME`
+const BUCKET: Duration = Duration::from_micros(10);
+const MAX_FRAME_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct BenchOptions {
+    pub n_clients: usize,
+    pub duration: Duration,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            n_clients: 64,
+            duration: Duration::from_secs(60),
+        }
+    }
+}
+
+impl BenchOptions {
+    /// None without `--bench`; the `key=value` arguments after it override the defaults
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut args = args.skip_while(|arg| arg != "--bench");
+        if args.next().is_none() {
+            return Ok(None);
+        }
+        let mut options = Self::default();
+        for arg in args {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got {}", arg))?;
+            let number: u64 = value
+                .parse()
+                .map_err(|_| format!("invalid number for {}: {}", key, value))?;
+            match key {
+                "n_clients" => options.n_clients = number as usize,
+                "duration" => options.duration = Duration::from_secs(number),
+                _ => return Err(format!("unknown bench option: {}", key)),
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+/// What the bots saw, sent back when they are done
+#[derive(Debug, Clone, Default)]
+struct BotReport {
+    connected: usize,
+    messages_sent: u64,
+    messages_received: u64,
+    send_errors: u64,
+    /// QUIC counters of every bot connection, so server in and out
+    bytes_sent: u64,
+    bytes_received: u64,
+    lost_packets: u64,
+    elapsed: Duration,
+}
+
+/// A running benchmark: the server's frame times so far, and the bot thread
+#[derive(Resource)]
+pub struct Bench {
+    options: BenchOptions,
+    frame_counts: Vec<u64>,
+    bots: Option<JoinHandle<BotReport>>,
+}
+
+impl Bench {
+    /// Start the bots, connecting to the server's `port` on loopback
+    pub fn start(options: BenchOptions, port: u16) -> Self {
+        info!(
+            "Benchmark: {} bots for {} s",
+            options.n_clients,
+            options.duration.as_secs()
+        );
+        let buckets = (MAX_FRAME_TIME.as_micros() / BUCKET.as_micros()) as usize + 1;
+        Self {
+            options,
+            frame_counts: vec![0; buckets],
+            bots: Some(thread::spawn(move || run_bots(options, port))),
+        }
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        let bucket = (frame_time.as_micros() / BUCKET.as_micros()) as usize;
+        let last = self.frame_counts.len() - 1;
+        self.frame_counts[bucket.min(last)] += 1;
+    }
+
+    /// Upper bound of the bucket holding the `p` quantile of frame times
+    fn percentile(&self, p: f64) -> Duration {
+        let total: u64 = self.frame_counts.iter().sum();
+        let target = (total as f64 * p).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.frame_counts.iter().enumerate() {
+            seen += count;
+            if seen >= target.max(1) {
+                return BUCKET * (bucket as u32 + 1);
+            }
+        }
+        MAX_FRAME_TIME
+    }
+}
+
+/// Print the results and stop the server once the bots are done
+pub fn finish_bench(mut bench: ResMut<Bench>, mut exit: EventWriter<AppExit>) {
+    if !bench.bots.as_ref().is_some_and(JoinHandle::is_finished) {
+        return;
+    }
+    let Some(Ok(report)) = bench.bots.take().map(JoinHandle::join) else {
+        error!("Benchmark bots panicked");
+        exit.write(AppExit::error());
+        return;
+    };
+    let secs = report.elapsed.as_secs_f64().max(f64::EPSILON);
+    let frames: u64 = bench.frame_counts.iter().sum();
+    println!(
+        "bench: {} of {} bots connected, {} s",
+        report.connected,
+        bench.options.n_clients,
+        report.elapsed.as_secs()
+    );
+    println!(
+        "tick: p50 {:?}, p99 {:?} over {} frames",
+        bench.percentile(0.5),
+        bench.percentile(0.99),
+        frames
+    );
+    println!(
+        "bytes/sec: server in {:.0}, server out {:.0}",
+        report.bytes_sent as f64 / secs,
+        report.bytes_received as f64 / secs
+    );
+    println!(
+        "messages: {} sent, {} received",
+        report.messages_sent, report.messages_received
+    );
+    println!(
+        "dropped: {} send errors, {} lost packets",
+        report.send_errors, report.lost_packets
+    );
+    exit.write(AppExit::Success);
+}
+
+struct Bot {
+    connection: u64,
+    index: usize,
+    joined: bool,
+    seq: u32,
+}
+
+/// Connect the bots, have them join, walk in circles and chat until the time is up.
+/// Blocking, run on its own thread.
+fn run_bots(options: BenchOptions, port: u16) -> BotReport {
+    sleep(STARTUP_DELAY);
+    let mut app = App::new();
+    app.add_plugins(QuinnetClientPlugin::default());
+    let mut report = BotReport::default();
+
+    let mut bots = Vec::new();
+    let address = format!("127.0.0.1:{}", port);
+    let mut client = app.world_mut().resource_mut::<QuinnetClient>();
+    for index in 0..options.n_clients {
+        let config = ClientEndpointConfiguration::from_strings(&address, "0.0.0.0:0")
+            .expect("valid loopback address");
+        match client.open_connection(
+            config,
+            CertificateVerificationMode::SkipVerification,
+            protocol::channels::configuration(),
+        ) {
+            Ok(connection) => bots.push(Bot {
+                connection,
+                index,
+                joined: false,
+                seq: 0,
+            }),
+            Err(err) => {
+                warn!("Bench bot {} could not connect: {}", index, err);
+                report.send_errors += 1;
+            }
+        }
+    }
+
+    let start = Instant::now();
+    let mut last_move = start;
+    let mut last_chat = start;
+    while start.elapsed() < options.duration {
+        app.update();
+        let moving = last_move.elapsed() >= MOVE_INTERVAL;
+        if moving {
+            last_move = Instant::now();
+        }
+        let chatting = last_chat.elapsed() >= CHAT_INTERVAL;
+        if chatting {
+            last_chat = Instant::now();
+        }
+        let secs = start.elapsed().as_secs_f32();
+
+        let mut client = app.world_mut().resource_mut::<QuinnetClient>();
+        for bot in bots.iter_mut() {
+            let Some(connection) = client.get_connection_mut_by_id(bot.connection) else {
+                continue;
+            };
+            if !connection.is_connected() {
+                continue;
+            }
+            while connection.try_receive_payload().is_some() {
+                report.messages_received += 1;
+            }
+
+            let mut messages = Vec::new();
+            if !bot.joined {
+                bot.joined = true;
+                messages.push(ClientMessage::Join {
+                    name: format!("bench{}", bot.index),
+                    identity: None,
+                    password: None,
+                });
+            }
+            if moving {
+                // A circle of 100 px at 1 rad/s, well under the speed limit
+                let angle = secs + bot.index as f32;
+                bot.seq = bot.seq.wrapping_add(1);
+                messages.push(ClientMessage::PlayerUpdate {
+                    seq: bot.seq,
+                    x: 400.0 + 100.0 * angle.cos(),
+                    y: 300.0 + 100.0 * angle.sin(),
+                    horizontal: -angle.sin(),
+                    vertical: angle.cos(),
+                    sent_at_ms: start.elapsed().as_millis() as u32,
+                });
+            }
+            if chatting {
+                messages.push(ClientMessage::ChatMessage {
+                    message: format!("bench message {}", bot.seq),
+                    local_id: bot.seq,
+                });
+            }
+            for message in messages {
+                match connection.send_payload(protocol::encode(&message)) {
+                    Ok(()) => report.messages_sent += 1,
+                    Err(_) => report.send_errors += 1,
+                }
+            }
+        }
+        sleep(Duration::from_millis(5));
+    }
+    report.elapsed = start.elapsed();
+
+    let mut client = app.world_mut().resource_mut::<QuinnetClient>();
+    for bot in &bots {
+        let Some(connection) = client.get_connection_mut_by_id(bot.connection) else {
+            continue;
+        };
+        if bot.joined {
+            report.connected += 1;
+        }
+        if let Some(stats) = connection.connection_stats() {
+            report.bytes_sent += stats.udp_tx.bytes;
+            report.bytes_received += stats.udp_rx.bytes;
+            report.lost_packets += stats.path.lost_packets;
+        }
+        let _ = connection.send_payload(protocol::encode(&ClientMessage::Disconnect {}));
+    }
+    // Let the disconnects go out
+    app.update();
+    sleep(Duration::from_millis(100));
+    let _ = app
+        .world_mut()
+        .resource_mut::<QuinnetClient>()
+        .close_all_connections();
+    report
+}
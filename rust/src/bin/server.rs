@@ -0,0 +1,3 @@
+fn main() {
+    rust::server::create_server();
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use godot::{
+    classes::{Label, control::GrowDirection},
+    global::{AutowrapMode, HorizontalAlignment, VerticalAlignment},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::ChatMessage;
+use crate::frame_budget;
+use crate::player::{PlayerIndex, PlayerNode};
+
+/// Name of the bubble label under the PlayerNode, so a newer message replaces it
+const BUBBLE_NAME: &str = "ChatBubble";
+/// Longer messages are cut, the chat log has the full text
+const MAX_BUBBLE_LENGTH: usize = 80;
+
+/// How long speech bubbles stay up before fading out
+#[derive(Resource, Debug, Clone)]
+pub struct BubbleTiming {
+    pub visible_secs: f64,
+    pub fade_secs: f64,
+}
+
+impl Default for BubbleTiming {
+    fn default() -> Self {
+        Self {
+            visible_secs: 4.0,
+            fade_secs: 1.0,
+        }
+    }
+}
+
+pub struct BubblePlugin;
+
+impl Plugin for BubblePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BubbleTiming>()
+            .add_systems(Update, show_chat_bubbles);
+    }
+}
+
+#[main_thread_system]
+fn show_chat_bubbles(
+    mut events: EventReader<ChatMessage>,
+    timing: Res<BubbleTiming>,
+    index: Res<PlayerIndex>,
+    mut query: Query<&mut GodotNodeHandle>,
+) {
+    let _timing = frame_budget::scope("show_chat_bubbles");
+    for ev in events.read() {
+        let Some(client_id) = ev.client_id else {
+            continue;
+        };
+        let Some(mut handle) = index
+            .0
+            .get(&client_id)
+            .and_then(|entity| query.get_mut(*entity).ok())
+        else {
+            continue;
+        };
+        let Some(mut player) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+
+        if let Some(mut previous) = player.get_node_or_null(BUBBLE_NAME) {
+            // Rename first, queue_free only happens at the end of the frame
+            previous.set_name("ChatBubbleOld");
+            previous.queue_free();
+        }
+
+        let mut text = protocol::truncate_chat(&ev.message, MAX_BUBBLE_LENGTH).to_string();
+        if text.len() < ev.message.len() {
+            text.push('…');
+        }
+        let mut bubble = Label::new_alloc();
+        bubble.set_name(BUBBLE_NAME);
+        bubble.set_text(&text);
+        bubble.set_autowrap_mode(AutowrapMode::WORD_SMART);
+        bubble.set_custom_minimum_size(Vector2::new(120.0, 0.0));
+        bubble.set_horizontal_alignment(HorizontalAlignment::CENTER);
+        bubble.set_vertical_alignment(VerticalAlignment::BOTTOM);
+        bubble.set_grow_vertical(GrowDirection::BEGIN);
+        bubble.set_position(Vector2::new(-60.0, -64.0));
+        player.add_child(&bubble);
+
+        // Hold, fade out, then free the label
+        let Some(mut tween) = bubble.create_tween() else {
+            continue;
+        };
+        tween.tween_interval(timing.visible_secs);
+        tween.tween_property(
+            &bubble,
+            "modulate:a",
+            &0.0f32.to_variant(),
+            timing.fade_secs,
+        );
+        tween.tween_callback(&Callable::from_object_method(&bubble, "queue_free"));
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use godot::{
+    classes::{Camera2D, ICamera2D},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::flow::in_game;
+use crate::player::{Player, PlayerNode};
+use crate::{Users, frame_budget};
+
+#[derive(Component, Default)]
+pub struct FollowCameraComp;
+
+/// Camera tracking our own player, moved by `follow_local_player`
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Camera2D)]
+#[bevy_bundle((FollowCameraComp))]
+pub struct FollowCameraNode {
+    base: Base<Camera2D>,
+    /// How fast the camera catches up, higher is snappier; 0 sticks to the player
+    #[export]
+    smoothing: f32,
+    /// Half size of the box around the view center the player moves in freely
+    #[export]
+    deadzone: Vector2,
+    /// Keep the view inside `bounds`, e.g. the level's extent
+    #[export]
+    clamp_to_bounds: bool,
+    #[export]
+    bounds: Rect2,
+    /// Whether the player was found last frame; the camera jumps to it when it appears
+    tracking: bool,
+}
+
+#[godot_api]
+impl ICamera2D for FollowCameraNode {
+    fn init(base: Base<Camera2D>) -> Self {
+        Self {
+            base,
+            smoothing: 8.0,
+            deadzone: Vector2::new(32.0, 24.0),
+            clamp_to_bounds: false,
+            bounds: Rect2::new(Vector2::ZERO, Vector2::new(1920.0, 1080.0)),
+            tracking: false,
+        }
+    }
+}
+
+impl FollowCameraNode {
+    /// Where the camera should be, starting from `from`, for a player at `target`
+    fn next_position(&self, from: Vector2, target: Vector2, secs: f32) -> Vector2 {
+        // Only what the player is beyond the deadzone moves the camera
+        let offset = target - from;
+        let deadzone = self.deadzone.abs();
+        let excess = offset - offset.clamp(-deadzone, deadzone);
+        let goal = from + excess;
+        let position = if !self.tracking || self.smoothing <= 0.0 {
+            goal
+        } else {
+            // Frame-rate independent exponential ease
+            from.lerp(goal, 1.0 - (-self.smoothing * secs).exp())
+        };
+        if self.clamp_to_bounds {
+            self.clamp(position)
+        } else {
+            position
+        }
+    }
+
+    /// Keep the whole view inside `bounds`, centered on an axis the bounds are too small for
+    fn clamp(&self, position: Vector2) -> Vector2 {
+        let zoom = self.base().get_zoom();
+        let view = self.base().get_viewport_rect().size / zoom;
+        let half = view / 2.0;
+        let min = self.bounds.position + half;
+        let max = self.bounds.end() - half;
+        let center = self.bounds.center();
+        Vector2::new(
+            if min.x <= max.x {
+                position.x.clamp(min.x, max.x)
+            } else {
+                center.x
+            },
+            if min.y <= max.y {
+                position.y.clamp(min.y, max.y)
+            } else {
+                center.y
+            },
+        )
+    }
+}
+
+pub struct CameraFollowPlugin;
+
+impl Plugin for CameraFollowPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, follow_local_player.run_if(in_game));
+    }
+}
+
+#[main_thread_system]
+fn follow_local_player(
+    users: Res<Users>,
+    mut cameras: Query<&mut GodotNodeHandle, With<FollowCameraComp>>,
+    mut players: Query<(&Player, &mut GodotNodeHandle), Without<FollowCameraComp>>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    let _timing = frame_budget::scope("follow_local_player");
+    let now = Instant::now();
+    let elapsed = now.duration_since(last_frame.replace(now).unwrap_or(now));
+    let target = players
+        .iter_mut()
+        .find(|(player, _)| player.0 == users.self_id)
+        .and_then(|(_, mut handle)| handle.try_get::<PlayerNode>())
+        .map(|player| player.get_global_position());
+
+    for mut handle in cameras.iter_mut() {
+        let Some(mut camera) = handle.try_get::<FollowCameraNode>() else {
+            continue;
+        };
+        let Some(target) = target else {
+            camera.bind_mut().tracking = false;
+            continue;
+        };
+        let from = camera.get_global_position();
+        let position = camera
+            .bind()
+            .next_position(from, target, elapsed.as_secs_f32());
+        camera.set_global_position(position);
+        camera.bind_mut().tracking = true;
+    }
+}
@@ -1,16 +1,442 @@
-use bevy::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
 use godot::{
-    classes::{IRichTextLabel, ITextEdit, RichTextLabel, TextEdit},
+    classes::{Button, IRichTextLabel, ITextEdit, RichTextLabel, TextEdit},
     prelude::*,
 };
 use godot_bevy::prelude::*;
+use protocol::{self, AdminCommand, NoticeLevel, VoteKind};
 use tokio::sync::mpsc::Sender;
 
+use crate::frame_budget;
+use crate::localization::Translations;
+use crate::node_safety::{NodeHandleExt, NodeInvalidated};
+use crate::transport::TransportEvent;
+
+pub const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Colour of private messages in the chat log
+const WHISPER_COLOR: &str = "violet";
+
+/// Chat lines are BBCode: anything coming from players must go through this
+pub fn escape_bbcode(text: &str) -> String {
+    text.replace('[', "[lb]")
+}
+
+/// Plain text of a chat line: tags dropped and `escape_bbcode` undone
+pub fn strip_bbcode(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        text.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        if &rest[start + 1..start + len] == "lb" {
+            text.push('[');
+        }
+        rest = &rest[start + len + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+pub fn chat_line(username: &str, message: &str) -> String {
+    format!("{}: {}", escape_bbcode(username), escape_bbcode(message))
+}
+
+/// `direction` is "To" or "From"
+pub fn whisper_line(direction: &str, username: &str, message: &str) -> String {
+    format!(
+        "[color={}]{} {}: {}[/color]",
+        WHISPER_COLOR,
+        direction,
+        escape_bbcode(username),
+        escape_bbcode(message)
+    )
+}
+
+pub fn notice_line(level: NoticeLevel, text: &str) -> String {
+    let color = match level {
+        NoticeLevel::Info => "lightblue",
+        NoticeLevel::Warning => "yellow",
+        NoticeLevel::Critical => "red",
+    };
+    format!("[color={}]* {}[/color]", color, escape_bbcode(text))
+}
+
+/// What the player typed in a chat input
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatCommand {
+    Say(String),
+    Whisper {
+        to: String,
+        text: String,
+    },
+    /// Whisper back to whoever whispered last
+    Reply(String),
+    AddFriend(String),
+    RemoveFriend(String),
+    ListFriends,
+    JoinFriend(String),
+    /// Open a private room and get its invite code
+    CreateRoom,
+    JoinRoom(String),
+    LeaveRoom,
+    StartVote(VoteKind),
+    CastVote(bool),
+    /// Only obeyed by the server when we're the host
+    Admin(AdminCommand),
+    /// Hand the host role to the player with this name
+    TransferHost(String),
+    /// Append the chat history to today's file in `user://chatlogs`
+    SaveChat,
+    Report {
+        name: String,
+        reason: String,
+    },
+}
+
+impl ChatCommand {
+    /// Input not starting with a known `/command` is said as is.
+    /// The error is a usage line for the player.
+    pub fn parse(input: &str) -> Result<Self, &'static str> {
+        let Some(command) = input.strip_prefix('/') else {
+            return Ok(ChatCommand::Say(input.to_string()));
+        };
+        let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+        let rest = rest.trim();
+        match name {
+            "w" | "msg" => rest
+                .split_once(' ')
+                .map(|(to, text)| ChatCommand::Whisper {
+                    to: to.to_string(),
+                    text: text.trim().to_string(),
+                })
+                .ok_or("Usage: /w <name> <message>"),
+            "r" if !rest.is_empty() => Ok(ChatCommand::Reply(rest.to_string())),
+            "r" => Err("Usage: /r <message>"),
+            "friend" => match rest.split_once(' ') {
+                Some(("add", friend)) => Ok(ChatCommand::AddFriend(friend.trim().to_string())),
+                Some(("remove", friend)) => {
+                    Ok(ChatCommand::RemoveFriend(friend.trim().to_string()))
+                }
+                _ => Err("Usage: /friend add|remove <name>"),
+            },
+            "friends" => Ok(ChatCommand::ListFriends),
+            "join" if !rest.is_empty() => Ok(ChatCommand::JoinFriend(rest.to_string())),
+            "join" => Err("Usage: /join <friend>"),
+            "invite" => Ok(ChatCommand::CreateRoom),
+            "room" if !rest.is_empty() => Ok(ChatCommand::JoinRoom(rest.to_string())),
+            "room" => Err("Usage: /room <invite code>"),
+            "leave" => Ok(ChatCommand::LeaveRoom),
+            "votekick" if !rest.is_empty() => Ok(ChatCommand::StartVote(VoteKind::Kick {
+                name: rest.to_string(),
+            })),
+            "votekick" => Err("Usage: /votekick <name>"),
+            "votelevel" if !rest.is_empty() => Ok(ChatCommand::StartVote(VoteKind::ChangeLevel {
+                level: rest.to_string(),
+            })),
+            "votelevel" => Err("Usage: /votelevel <level>"),
+            "vote" => match rest {
+                "yes" => Ok(ChatCommand::CastVote(true)),
+                "no" => Ok(ChatCommand::CastVote(false)),
+                _ => Err("Usage: /vote yes|no"),
+            },
+            "kick" if !rest.is_empty() => Ok(ChatCommand::Admin(AdminCommand::Kick {
+                name: rest.to_string(),
+            })),
+            "kick" => Err("Usage: /kick <name>"),
+            "level" if !rest.is_empty() => Ok(ChatCommand::Admin(AdminCommand::ChangeLevel {
+                level: rest.to_string(),
+            })),
+            "level" => Err("Usage: /level <level>"),
+            "pause" => Ok(ChatCommand::Admin(AdminCommand::Pause { paused: true })),
+            "resume" => Ok(ChatCommand::Admin(AdminCommand::Pause { paused: false })),
+            "reset" => Ok(ChatCommand::Admin(AdminCommand::ResetWorld)),
+            "host" if !rest.is_empty() => Ok(ChatCommand::TransferHost(rest.to_string())),
+            "host" => Err("Usage: /host <name>"),
+            "savechat" => Ok(ChatCommand::SaveChat),
+            "report" => split_name(rest)
+                .filter(|(name, reason)| !name.is_empty() && !reason.is_empty())
+                .map(|(name, reason)| ChatCommand::Report {
+                    name: name.to_string(),
+                    reason: reason.to_string(),
+                })
+                .ok_or("Usage: /report <name|\"name\"> <reason>"),
+            _ => Ok(ChatCommand::Say(input.to_string())),
+        }
+    }
+}
+
+/// A leading name, in quotes when it has spaces, and the trimmed rest
+fn split_name(rest: &str) -> Option<(&str, &str)> {
+    let (name, rest) = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"')?,
+        None => rest.split_once(' ')?,
+    };
+    Some((name, rest.trim()))
+}
+
+/// A sent message not echoed back by then is shown as failed
+const PENDING_CHAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client-side chat limits, messages are truncated before being sent
+#[derive(Resource, Debug, Clone)]
+pub struct ChatLimits {
+    pub max_message_length: usize,
+}
+
+impl Default for ChatLimits {
+    fn default() -> Self {
+        Self {
+            max_message_length: protocol::DEFAULT_MAX_CHAT_LENGTH,
+        }
+    }
+}
+
+impl ChatLimits {
+    pub fn apply(&self, text: &str) -> String {
+        protocol::truncate_chat(text, self.max_message_length).to_string()
+    }
+}
+
+/// What server messages need to become chat lines: timestamps and the player's language
+#[derive(SystemParam)]
+pub struct ChatFormat<'w> {
+    pub display: ResMut<'w, ChatDisplayOptions>,
+    pub translations: Res<'w, Translations>,
+}
+
+/// How chat lines with a server timestamp are decorated when added to the log
+#[derive(Resource, Debug, Clone)]
+pub struct ChatDisplayOptions {
+    /// Prefix lines with `[HH:MM]`
+    pub show_timestamps: bool,
+    /// Insert a date line whenever the day changes between two messages
+    pub day_separators: bool,
+    /// Applied to server timestamps, read from the OS at startup
+    pub utc_offset_minutes: i64,
+    /// Day of the last decorated line, to know when to insert a separator
+    last_day: Option<i64>,
+}
+
+impl Default for ChatDisplayOptions {
+    fn default() -> Self {
+        Self {
+            show_timestamps: true,
+            day_separators: true,
+            utc_offset_minutes: 0,
+            last_day: None,
+        }
+    }
+}
+
+impl ChatDisplayOptions {
+    /// Lines to append for `line` sent at `sent_at` (unix seconds):
+    /// a day separator if needed, then the line itself
+    pub fn decorate(&mut self, sent_at: u64, line: &str) -> Vec<String> {
+        // Old saves have no timestamps
+        if sent_at == 0 {
+            return vec![line.to_string()];
+        }
+        let local = sent_at as i64 + self.utc_offset_minutes * 60;
+        let day = local.div_euclid(SECS_PER_DAY);
+
+        let mut lines = Vec::new();
+        if self.day_separators && self.last_day.is_some_and(|last| last != day) {
+            let (year, month, day) = civil_from_days(day);
+            lines.push(format!("-- {:04}-{:02}-{:02} --", year, month, day));
+        }
+        self.last_day = Some(day);
+
+        if self.show_timestamps {
+            let secs = local.rem_euclid(SECS_PER_DAY);
+            lines.push(format!(
+                "[lb]{:02}:{:02}] {}",
+                secs / 3600,
+                secs % 3600 / 60,
+                line
+            ));
+        } else {
+            lines.push(line.to_string());
+        }
+        lines
+    }
+
+    /// `line` without the timestamp `decorate` may have added, None for a day separator
+    pub fn undecorate(line: &str) -> Option<&str> {
+        if line.len() == "-- YYYY-MM-DD --".len()
+            && line.starts_with("-- ")
+            && line.ends_with(" --")
+        {
+            return None;
+        }
+        let time = line
+            .strip_prefix("[lb]")
+            .and_then(|rest| rest.get(..5).zip(rest.get(5..)))
+            .filter(|(time, rest)| time.as_bytes()[2] == b':' && rest.starts_with("] "));
+        Some(time.map_or(line, |(_, rest)| &rest[2..]))
+    }
+}
+
+/// Days since 1970-01-01 to (year, month, day), proleptic Gregorian calendar
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[main_thread_system]
+pub fn init_chat_display_options(mut options: ResMut<ChatDisplayOptions>) {
+    let time_zone = godot::classes::Time::singleton().get_time_zone_from_system();
+    if let Some(bias) = time_zone
+        .get("bias")
+        .and_then(|bias| bias.try_to::<i64>().ok())
+    {
+        options.utc_offset_minutes = bias;
+    }
+}
+
 #[derive(Component, Default)]
+#[require(ChatScrollback)]
 pub struct Chat {
     pub messages: Vec<String>,
 }
 
+/// New lines only snap the log to the bottom when the player is already there,
+/// otherwise they are counted as unread until the player scrolls down or jumps
+#[derive(Component, Debug, Clone, Default)]
+pub struct ChatScrollback {
+    pub unread: usize,
+    /// `Chat::messages` length at the last sync
+    pub seen_len: usize,
+    /// Last text pushed to the label, re-setting it would reset the scroll position
+    pub rendered: String,
+}
+
+impl Chat {
+    /// Replace the most recent `old` line, or append when it isn't there
+    pub fn replace_last(&mut self, old: &str, new: String) {
+        match self.messages.iter().rposition(|line| line == old) {
+            Some(index) => self.messages[index] = new,
+            None => self.messages.push(new),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingLine {
+    line: String,
+    sent_at: Instant,
+    failed: bool,
+}
+
+/// Our own chat lines shown before the server echoed them, by local id
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PendingChat {
+    next_id: u32,
+    lines: HashMap<u32, PendingLine>,
+}
+
+impl PendingChat {
+    pub fn insert(&mut self, line: String) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.lines.insert(
+            id,
+            PendingLine {
+                line,
+                sent_at: Instant::now(),
+                failed: false,
+            },
+        );
+        id
+    }
+
+    /// The echo arrived: forget the entry (even if it was marked failed) and return its line
+    pub fn take(&mut self, local_id: u32) -> Option<String> {
+        self.lines.remove(&local_id).map(|pending| pending.line)
+    }
+
+    /// The server refused the message
+    pub fn fail(&mut self, local_id: u32) {
+        if let Some(entry) = self.lines.get_mut(&local_id) {
+            entry.failed = true;
+        }
+    }
+
+    /// Whether `line` is one of ours still waiting for its echo
+    pub fn is_pending(&self, line: &str) -> bool {
+        self.lines
+            .values()
+            .any(|entry| !entry.failed && entry.line == line)
+    }
+
+    /// Whether `line` is one of ours that didn't get through
+    pub fn has_failed(&self, line: &str) -> bool {
+        self.lines
+            .values()
+            .any(|entry| entry.failed && entry.line == line)
+    }
+
+    /// Pending lines greyed out, failed ones in red
+    pub fn render(&self, messages: &[String]) -> String {
+        // Only the most recent occurrence of a line is the pending one
+        let mut pending: HashSet<&str> = HashSet::new();
+        let mut failed: HashSet<&str> = HashSet::new();
+        for entry in self.lines.values() {
+            if entry.failed {
+                failed.insert(&entry.line);
+            } else {
+                pending.insert(&entry.line);
+            }
+        }
+
+        let mut rendered: Vec<String> = messages
+            .iter()
+            .rev()
+            .map(|line| {
+                if pending.remove(line.as_str()) {
+                    format!("[color=gray]{}[/color]", line)
+                } else if failed.remove(line.as_str()) {
+                    format!("[color=red]{} (not delivered)[/color]", line)
+                } else {
+                    line.clone()
+                }
+            })
+            .collect();
+        rendered.reverse();
+        rendered.join("\n")
+    }
+}
+
+/// Mark messages as failed once they time out, or when the connection drops
+pub fn expire_pending_chat(
+    mut pending: ResMut<PendingChat>,
+    mut transport_events: EventReader<TransportEvent>,
+) {
+    let disconnected = transport_events
+        .read()
+        .any(|ev| matches!(ev, TransportEvent::Disconnected));
+    for entry in pending.lines.values_mut() {
+        if disconnected || entry.sent_at.elapsed() > PENDING_CHAT_TIMEOUT {
+            entry.failed = true;
+        }
+    }
+}
+
 #[derive(Component, Default)]
 pub struct ChatInput {
     pub sender: Option<Sender<String>>,
@@ -28,6 +454,8 @@ pub struct ChatNode {
     #[export]
     #[bevy_bundle(transform_with = "gd_arr_to_rust")]
     messages: PackedStringArray,
+    /// "N new" badge, created as a child in `ready`
+    jump_button: Option<Gd<Button>>,
 }
 
 #[derive(GodotClass, BevyBundle)]
@@ -45,6 +473,60 @@ impl IRichTextLabel for ChatNode {
         Self {
             base,
             messages: PackedStringArray::new(),
+            jump_button: None,
+        }
+    }
+
+    fn ready(&mut self) {
+        // Lines are BBCode, player text is escaped when the line is built
+        self.base_mut().set_use_bbcode(true);
+
+        let mut button = Button::new_alloc();
+        button.set_visible(false);
+        button.set_anchors_and_offsets_preset(godot::classes::control::LayoutPreset::BOTTOM_RIGHT);
+        button.connect(
+            "pressed",
+            &Callable::from_object_method(&self.to_gd(), "jump_to_latest"),
+        );
+        self.base_mut().add_child(&button);
+        self.jump_button = Some(button);
+    }
+}
+
+#[godot_api]
+impl ChatNode {
+    #[func]
+    fn jump_to_latest(&mut self) {
+        let last_line = self.base().get_line_count() - 1;
+        self.base_mut().scroll_to_line(last_line.max(0));
+    }
+
+    pub fn is_at_bottom(&self) -> bool {
+        self.base()
+            .get_v_scroll_bar()
+            .is_none_or(|bar| bar.get_value() + bar.get_page() >= bar.get_max() - 1.0)
+    }
+
+    /// Push new text while keeping the reader where they were, or at the bottom
+    pub fn sync_text(&mut self, text: &str, follow: bool) {
+        let scroll = self.base().get_v_scroll_bar().map(|bar| bar.get_value());
+        self.base_mut().set_text(text);
+        if follow {
+            self.jump_to_latest();
+        } else if let (Some(mut bar), Some(scroll)) = (self.base().get_v_scroll_bar(), scroll) {
+            bar.set_value(scroll);
+        }
+    }
+
+    pub fn show_unread(&mut self, unread: usize) {
+        let Some(button) = self.jump_button.as_mut() else {
+            return;
+        };
+        button.set_visible(unread > 0);
+        if unread > 0 {
+            button.set_text(&format!("{} new - jump to latest", unread));
+            // Keep the badge in the bottom-right corner as the text grows
+            button.reset_size();
         }
     }
 }
@@ -60,9 +542,15 @@ impl ITextEdit for ChatInputNode {
 pub fn read_chat_messages(
     mut query: Query<(Entity, &mut GodotNodeHandle, &mut ChatInput), With<TextEditMarker>>,
     mut events: EventReader<ActionInput>,
+    mut invalidated: EventWriter<NodeInvalidated>,
 ) {
-    for (_, mut handle, chat_input) in query.iter_mut() {
-        let mut chat_input_node = handle.get::<ChatInputNode>();
+    let _timing = frame_budget::scope("read_chat_messages");
+    for (entity, mut handle, chat_input) in query.iter_mut() {
+        let Some(mut chat_input_node) =
+            handle.get_or_invalidate::<ChatInputNode>(entity, &mut invalidated)
+        else {
+            continue;
+        };
         for event in events.read() {
             if event.action.as_str() == "ui_text_submit" {
                 let text = chat_input_node.get_text().to_string();
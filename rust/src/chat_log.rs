@@ -0,0 +1,204 @@
+//! Chat logs on disk: every line added to the chat is kept with the local time it
+//! arrived, and appended to `user://chatlogs/<date>.txt` by `/savechat`, or every few
+//! seconds with the `autosave_chat` setting. Files only ever grow, so a day's file holds
+//! its whole history however often it's saved.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use godot::{
+    classes::{DirAccess, FileAccess, file_access::ModeFlags},
+    global::Error,
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::chat::{self, Chat, ChatDisplayOptions, PendingChat, SECS_PER_DAY};
+use crate::frame_budget;
+use crate::settings::ClientSettings;
+
+const CHATLOG_DIR: &str = "user://chatlogs";
+
+/// How often new lines are written with `autosave_chat` on
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Append the lines not saved yet to the chat logs, written by `/savechat`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SaveChatLog;
+
+/// Chat lines not written to a file yet, as plain text with their local time in
+/// seconds since the epoch
+#[derive(Resource, Debug, Default)]
+pub struct ChatTranscript {
+    unsaved: Vec<(i64, String)>,
+    /// `Chat::messages` lines already taken
+    seen: usize,
+}
+
+impl ChatTranscript {
+    /// Append the unsaved lines to the file of the day each was received on
+    fn flush(&mut self) -> Result<(), String> {
+        if self.unsaved.is_empty() {
+            return Ok(());
+        }
+        let err = DirAccess::make_dir_recursive_absolute(CHATLOG_DIR);
+        if err != Error::OK {
+            return Err(format!("could not create {}: {:?}", CHATLOG_DIR, err));
+        }
+        while let Some(&(first, _)) = self.unsaved.first() {
+            let day = first.div_euclid(SECS_PER_DAY);
+            let end = self
+                .unsaved
+                .iter()
+                .position(|(time, _)| time.div_euclid(SECS_PER_DAY) != day)
+                .unwrap_or(self.unsaved.len());
+            let text: String = self.unsaved[..end]
+                .iter()
+                .map(|(time, line)| {
+                    let secs = time.rem_euclid(SECS_PER_DAY);
+                    format!(
+                        "[{:02}:{:02}:{:02}] {}\n",
+                        secs / 3600,
+                        secs % 3600 / 60,
+                        secs % 60,
+                        line
+                    )
+                })
+                .collect();
+            append(&log_path(day), &text)?;
+            // Saved lines aren't written again if a later day fails
+            self.unsaved.drain(..end);
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the epoch in the player's time zone
+fn local_now(display: &ChatDisplayOptions) -> i64 {
+    let utc = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+    utc + display.utc_offset_minutes * 60
+}
+
+/// `user://chatlogs/YYYY-MM-DD.txt` for this many days since the epoch
+fn log_path(day: i64) -> String {
+    let (year, month, day) = chat::civil_from_days(day);
+    format!("{}/{:04}-{:02}-{:02}.txt", CHATLOG_DIR, year, month, day)
+}
+
+fn append(path: &str, text: &str) -> Result<(), String> {
+    let file = if FileAccess::file_exists(path) {
+        FileAccess::open(path, ModeFlags::READ_WRITE).map(|mut file| {
+            file.seek_end();
+            file
+        })
+    } else {
+        FileAccess::open(path, ModeFlags::WRITE)
+    };
+    let Some(mut file) = file else {
+        return Err(format!(
+            "could not open {}: {:?}",
+            path,
+            FileAccess::get_open_error()
+        ));
+    };
+    file.store_string(text);
+    file.close();
+    Ok(())
+}
+
+pub struct ChatLogPlugin;
+
+impl Plugin for ChatLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatTranscript>()
+            .add_event::<SaveChatLog>()
+            .add_systems(Update, (record_chat_lines, write_chat_logs).chain());
+    }
+}
+
+/// Take the lines added to the chat since the last frame, up to the first of ours still
+/// waiting for its echo: it is replaced by the echoed line, or shown as not delivered
+fn record_chat_lines(
+    chats: Query<&Chat>,
+    display: Res<ChatDisplayOptions>,
+    pending: Res<PendingChat>,
+    mut transcript: ResMut<ChatTranscript>,
+) {
+    // Every chat node holds the same lines
+    let Some(chat) = chats.iter().next() else {
+        return;
+    };
+    // Cleared when leaving a game
+    if chat.messages.len() < transcript.seen {
+        transcript.seen = 0;
+    }
+    if chat.messages.len() == transcript.seen {
+        return;
+    }
+    let now = local_now(&display);
+    let mut seen = transcript.seen;
+    let mut lines = Vec::new();
+    for line in &chat.messages[seen..] {
+        if pending.is_pending(line) {
+            break;
+        }
+        seen += 1;
+        let Some(text) = ChatDisplayOptions::undecorate(line) else {
+            continue;
+        };
+        let mut text = chat::strip_bbcode(text);
+        if pending.has_failed(line) {
+            text.push_str(" (not delivered)");
+        }
+        lines.push((now, text));
+    }
+    transcript.unsaved.extend(lines);
+    transcript.seen = seen;
+}
+
+#[main_thread_system]
+fn write_chat_logs(
+    mut requests: EventReader<SaveChatLog>,
+    settings: Res<ClientSettings>,
+    display: Res<ChatDisplayOptions>,
+    mut transcript: ResMut<ChatTranscript>,
+    mut chats: Query<&mut Chat>,
+    mut last_autosave: Local<Option<Instant>>,
+) {
+    let _timing = frame_budget::scope("write_chat_logs");
+    let requested = requests.read().count() > 0;
+    let autosave_due = settings.autosave_chat
+        && last_autosave.is_none_or(|last| last.elapsed() >= AUTOSAVE_INTERVAL);
+    if !requested && !autosave_due {
+        return;
+    }
+    if autosave_due {
+        *last_autosave = Some(Instant::now());
+    }
+    let line = match transcript.flush() {
+        Ok(()) if requested => format!(
+            "* Chat saved to {}",
+            log_path(local_now(&display).div_euclid(SECS_PER_DAY))
+        ),
+        Err(err) if requested => format!("* Could not save the chat: {}", err),
+        Ok(()) => return,
+        Err(err) => {
+            godot_print!("Could not save the chat log: {}", err);
+            return;
+        }
+    };
+    // Feedback for us, not part of the conversation; only skipped when nothing is left
+    // to record before it
+    let caught_up = chats
+        .iter()
+        .next()
+        .is_some_and(|chat| chat.messages.len() == transcript.seen);
+    for mut chat in chats.iter_mut() {
+        chat.messages.push(chat::escape_bbcode(&line));
+        if caught_up {
+            transcript.seen = chat.messages.len();
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    sync::mpsc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_quinnet::client::{
+    QuinnetClient, QuinnetClientPlugin,
+    certificate::CertificateVerificationMode,
+    connection::{ClientEndpointConfiguration, ConnectionFailedEvent},
+};
+use godot::{
+    classes::{Button, IPanelContainer, Label, PanelContainer, VBoxContainer},
+    prelude::*,
+};
+use protocol::{ClientMessage, MIN_COMPATIBLE_VERSION, PROTOCOL_VERSION, ServerMessage};
+
+#[derive(Debug, Clone)]
+pub struct ProbeReport {
+    pub certificate_trusted: bool,
+    pub rtt: Duration,
+    pub protocol_version: u32,
+    pub players: usize,
+}
+
+impl ProbeReport {
+    fn describe(&self) -> String {
+        let compat = if self.protocol_version == PROTOCOL_VERSION {
+            "compatible"
+        } else if self.protocol_version >= MIN_COMPATIBLE_VERSION {
+            "compatible, messages only one side knows are skipped"
+        } else {
+            "INCOMPATIBLE"
+        };
+        format!(
+            "Reachable, rtt {} ms\nCertificate: {}\nProtocol v{} ({}, client is v{})\nPlayers online: {}",
+            self.rtt.as_millis(),
+            if self.certificate_trusted {
+                "trusted"
+            } else {
+                "self-signed / untrusted"
+            },
+            self.protocol_version,
+            compat,
+            PROTOCOL_VERSION,
+            self.players
+        )
+    }
+}
+
+/// Connect to `address`, first verifying the certificate against the system CAs and
+/// falling back to skipping verification, then exchange a Ping/Pong.
+/// Blocking: run it off the main thread.
+pub fn probe_server(address: &str, timeout: Duration) -> Result<ProbeReport, String> {
+    match run_probe(
+        address,
+        CertificateVerificationMode::SignedByCertificateAuthority,
+        timeout,
+    ) {
+        Ok(report) => Ok(report),
+        Err(_) => run_probe(
+            address,
+            CertificateVerificationMode::SkipVerification,
+            timeout,
+        )
+        .map(|report| ProbeReport {
+            certificate_trusted: false,
+            ..report
+        }),
+    }
+}
+
+fn run_probe(
+    address: &str,
+    verification: CertificateVerificationMode,
+    timeout: Duration,
+) -> Result<ProbeReport, String> {
+    let mut app = App::new();
+    app.add_plugins(QuinnetClientPlugin::default());
+
+    let config = ClientEndpointConfiguration::from_strings(address, "0.0.0.0:0")
+        .map_err(|err| format!("Invalid address {}: {}", address, err))?;
+    app.world_mut()
+        .resource_mut::<QuinnetClient>()
+        .open_connection(config, verification, protocol::channels::configuration())
+        .map_err(|err| err.to_string())?;
+
+    let start = Instant::now();
+    let mut ping_sent_at = None;
+    while start.elapsed() < timeout {
+        app.update();
+        let world = app.world_mut();
+
+        if let Some(ev) = world
+            .resource_mut::<Events<ConnectionFailedEvent>>()
+            .drain()
+            .next()
+        {
+            return Err(format!("{:?}", ev.err));
+        }
+
+        let mut client = world.resource_mut::<QuinnetClient>();
+        if ping_sent_at.is_none() && client.is_connected() {
+            client
+                .connection_mut()
+                .send_payload(protocol::encode(&ClientMessage::Ping {}))
+                .map_err(|err| err.to_string())?;
+            ping_sent_at = Some(Instant::now());
+        }
+        if let Some(sent_at) = ping_sent_at {
+            while let Some((_, payload)) = client.connection_mut().try_receive_payload() {
+                // Newer servers may send messages we don't know, only the Pong matters
+                if let Ok(ServerMessage::Pong {
+                    protocol_version,
+                    players,
+                }) = protocol::decode(&payload)
+                {
+                    let _ = client.close_all_connections();
+                    return Ok(ProbeReport {
+                        certificate_trusted: true,
+                        rtt: sent_at.elapsed(),
+                        protocol_version,
+                        players,
+                    });
+                }
+            }
+        }
+        sleep(Duration::from_millis(10));
+    }
+    Err("Timed out".to_string())
+}
+
+/// Editor tool panel to check a server without running the game:
+/// pings `address`, validates its certificate and shows its protocol version.
+#[derive(GodotClass)]
+#[class(tool, base=PanelContainer)]
+pub struct ConnectionTesterNode {
+    base: Base<PanelContainer>,
+    #[export]
+    address: GString,
+    #[export]
+    timeout_secs: f32,
+    #[var]
+    status: GString,
+    status_label: Option<Gd<Label>>,
+    result_rx: Option<mpsc::Receiver<Result<ProbeReport, String>>>,
+}
+
+#[godot_api]
+impl ConnectionTesterNode {
+    #[signal]
+    fn probe_finished(success: bool, status: GString);
+
+    #[func]
+    fn test_connection(&mut self) {
+        if self.result_rx.is_some() {
+            return;
+        }
+        let address = self.address.to_string();
+        let timeout = Duration::from_secs_f32(self.timeout_secs.max(0.1));
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(probe_server(&address, timeout));
+        });
+        self.result_rx = Some(rx);
+        self.set_status(format!("Probing {}...", self.address));
+    }
+
+    fn set_status(&mut self, status: String) {
+        if let Some(label) = self.status_label.as_mut() {
+            label.set_text(&status);
+        }
+        self.status = GString::from(status);
+    }
+}
+
+#[godot_api]
+impl IPanelContainer for ConnectionTesterNode {
+    fn init(base: Base<PanelContainer>) -> Self {
+        Self {
+            base,
+            address: GString::from("127.0.0.1:6000"),
+            timeout_secs: 3.0,
+            status: GString::new(),
+            status_label: None,
+            result_rx: None,
+        }
+    }
+
+    fn ready(&mut self) {
+        let mut layout = VBoxContainer::new_alloc();
+        let mut button = Button::new_alloc();
+        button.set_text("Test connection");
+        button.connect(
+            "pressed",
+            &Callable::from_object_method(&self.to_gd(), "test_connection"),
+        );
+        let label = Label::new_alloc();
+        layout.add_child(&button);
+        layout.add_child(&label);
+        self.base_mut().add_child(&layout);
+        self.status_label = Some(label);
+    }
+
+    fn process(&mut self, _delta: f64) {
+        let Some(result) = self.result_rx.as_ref().and_then(|rx| rx.try_recv().ok()) else {
+            return;
+        };
+        self.result_rx = None;
+        let (success, status) = match result {
+            Ok(report) => (true, report.describe()),
+            Err(err) => (false, format!("Unreachable: {}", err)),
+        };
+        self.set_status(status.clone());
+        self.base_mut().emit_signal(
+            "probe_finished",
+            &[success.to_variant(), GString::from(status).to_variant()],
+        );
+    }
+}
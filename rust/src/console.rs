@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use bevy::ecs::system::SystemId;
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::{
+    classes::{
+        IVBoxContainer, InputEvent, InputEventKey, LineEdit, RichTextLabel, VBoxContainer,
+        control::SizeFlags,
+    },
+    global::Key,
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::ClientMessage;
+use rand::Rng;
+use tokio::sync::mpsc::{self, Sender};
+
+use crate::frame_budget;
+use crate::migration::SessionResume;
+use crate::net_stats::NetworkStats;
+use crate::player::{PlayerInputEvent, SpawnPlayerEvent};
+use crate::presence::CurrentServer;
+use crate::transport::{ClientTransport, ConnectionState, Outbox, Transport};
+
+/// Bots get ids from here up, far from anything the server hands out
+const FIRST_BOT_ID: ClientId = 1 << 62;
+/// How long a bot keeps walking in one direction
+const BOT_WANDER_INTERVAL: Duration = Duration::from_millis(1500);
+
+#[derive(Component, Default)]
+pub struct DebugConsoleComp;
+
+/// Developer console, toggled with `~`. Type `help` for the commands.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=VBoxContainer)]
+#[bevy_bundle((DebugConsoleComp))]
+pub struct DebugConsoleNode {
+    base: Base<VBoxContainer>,
+    #[bevy_bundle]
+    pub sender: Option<Sender<String>>,
+    log: Option<Gd<RichTextLabel>>,
+    input: Option<Gd<LineEdit>>,
+}
+
+#[godot_api]
+impl IVBoxContainer for DebugConsoleNode {
+    fn init(base: Base<VBoxContainer>) -> Self {
+        Self {
+            base,
+            sender: None,
+            log: None,
+            input: None,
+        }
+    }
+
+    fn ready(&mut self) {
+        let mut log = RichTextLabel::new_alloc();
+        log.set_scroll_follow(true);
+        log.set_v_size_flags(SizeFlags::EXPAND_FILL);
+        let mut input = LineEdit::new_alloc();
+        input.set_placeholder("help");
+        input.connect(
+            "text_submitted",
+            &Callable::from_object_method(&self.to_gd(), "submit_line"),
+        );
+        self.base_mut().add_child(&log);
+        self.base_mut().add_child(&input);
+        self.log = Some(log);
+        self.input = Some(input);
+        self.base_mut().set_visible(false);
+    }
+
+    /// `input` rather than `unhandled_input`: the line edit would swallow the key
+    fn input(&mut self, event: Gd<InputEvent>) {
+        let Ok(key) = event.try_cast::<InputEventKey>() else {
+            return;
+        };
+        if !key.is_pressed() || key.is_echo() || key.get_keycode() != Key::QUOTELEFT {
+            return;
+        }
+        let visible = !self.base().is_visible();
+        self.base_mut().set_visible(visible);
+        if let Some(input) = &mut self.input {
+            if visible {
+                input.grab_focus();
+            } else {
+                input.release_focus();
+            }
+        }
+        if let Some(mut viewport) = self.base().get_viewport() {
+            viewport.set_input_as_handled();
+        }
+    }
+}
+
+#[godot_api]
+impl DebugConsoleNode {
+    #[func]
+    fn submit_line(&mut self, line: GString) {
+        let line = line.to_string();
+        if let Some(input) = &mut self.input {
+            input.clear();
+        }
+        if line.trim().is_empty() {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(line);
+        }
+    }
+
+    pub fn append(&mut self, line: &str) {
+        if let Some(log) = &mut self.log {
+            log.add_text(format!("{}\n", line).as_str());
+        }
+    }
+}
+
+/// A line for the console log
+#[derive(Event, Debug, Clone)]
+pub struct ConsoleOutput(pub String);
+
+pub struct ConsoleCommand {
+    pub help: String,
+    system: SystemId<In<Vec<String>>>,
+}
+
+/// Console commands by name; each runs a one-shot system given the arguments,
+/// which reports back with `ConsoleOutput`
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(BTreeMap<String, ConsoleCommand>);
+
+pub trait ConsoleAppExt {
+    fn add_console_command<M>(
+        &mut self,
+        name: &str,
+        help: &str,
+        system: impl IntoSystem<In<Vec<String>>, (), M> + 'static,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command<M>(
+        &mut self,
+        name: &str,
+        help: &str,
+        system: impl IntoSystem<In<Vec<String>>, (), M> + 'static,
+    ) -> &mut Self {
+        let system = self.world_mut().register_system(system);
+        self.init_resource::<ConsoleCommands>();
+        self.world_mut().resource_mut::<ConsoleCommands>().0.insert(
+            name.to_string(),
+            ConsoleCommand {
+                help: help.to_string(),
+                system,
+            },
+        );
+        self
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct ConsoleReceiver(pub mpsc::Receiver<String>);
+
+/// Local players that wander around, to test rendering and sync without real clients
+#[derive(Resource, Debug, Clone, Default)]
+pub struct DebugBots {
+    bots: Vec<(ClientId, f32, f32)>,
+    last_wander: Option<Instant>,
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConsoleOutput>()
+            .init_resource::<ConsoleCommands>()
+            .init_resource::<DebugBots>()
+            .add_systems(Startup, start_console_listener)
+            .add_systems(
+                Update,
+                (
+                    run_console_commands.run_if(resource_exists::<ConsoleReceiver>),
+                    show_console_output,
+                    drive_bots,
+                ),
+            )
+            .add_console_command("connect", "connect <addr>: connect to a server", connect)
+            .add_console_command("disconnect", "disconnect: leave the server", disconnect)
+            .add_console_command(
+                "netstats",
+                "netstats: round trip, loss and send rate",
+                netstats,
+            )
+            .add_console_command(
+                "simulate_lag",
+                "simulate_lag <ms>: delay everything we send, 0 to stop",
+                simulate_lag,
+            )
+            .add_console_command(
+                "spawn_bot",
+                "spawn_bot <count>: add local players that wander around",
+                spawn_bot,
+            );
+    }
+}
+
+fn start_console_listener(mut commands: Commands) {
+    let (tx, rx) = mpsc::channel::<String>(100);
+
+    commands.queue(move |world: &mut World| {
+        let mut query = world.query::<&mut GodotNodeHandle>();
+        for mut handle in query.iter_mut(world) {
+            if let Some(mut node) = handle.try_get::<DebugConsoleNode>() {
+                node.bind_mut().sender = Some(tx.clone());
+            }
+        }
+    });
+
+    commands.insert_resource(ConsoleReceiver(rx));
+}
+
+fn run_console_commands(
+    mut receiver: ResMut<ConsoleReceiver>,
+    registry: Res<ConsoleCommands>,
+    mut output: EventWriter<ConsoleOutput>,
+    mut commands: Commands,
+) {
+    while let Ok(line) = receiver.try_recv() {
+        output.write(ConsoleOutput(format!("> {}", line)));
+        let mut words = line.split_whitespace().map(str::to_string);
+        let Some(name) = words.next() else {
+            continue;
+        };
+        if name == "help" {
+            for command in registry.0.values() {
+                output.write(ConsoleOutput(command.help.clone()));
+            }
+            continue;
+        }
+        match registry.0.get(&name) {
+            Some(command) => commands.run_system_with(command.system, words.collect()),
+            None => {
+                output.write(ConsoleOutput(format!("unknown command: {}", name)));
+            }
+        }
+    }
+}
+
+#[main_thread_system]
+fn show_console_output(
+    mut events: EventReader<ConsoleOutput>,
+    mut query: Query<&mut GodotNodeHandle, With<DebugConsoleComp>>,
+) {
+    let _timing = frame_budget::scope("show_console_output");
+    let lines: Vec<_> = events.read().map(|ev| ev.0.clone()).collect();
+    if lines.is_empty() {
+        return;
+    }
+    for mut handle in query.iter_mut() {
+        if let Some(mut node) = handle.try_get::<DebugConsoleNode>() {
+            for line in &lines {
+                node.bind_mut().append(line);
+            }
+        }
+    }
+}
+
+fn connect(
+    In(args): In<Vec<String>>,
+    mut client: Transport,
+    mut current_server: ResMut<CurrentServer>,
+    mut connection: ResMut<ConnectionState>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    let Some(address) = args.first() else {
+        output.write(ConsoleOutput("usage: connect <addr>".to_string()));
+        return;
+    };
+    match client.open(address) {
+        Ok(()) => {
+            current_server.address = Some(address.clone());
+            *connection = ConnectionState::Connecting;
+            output.write(ConsoleOutput(format!("connecting to {}", address)));
+        }
+        Err(err) => {
+            output.write(ConsoleOutput(format!("error: {}", err)));
+        }
+    }
+}
+
+fn disconnect(
+    In(_): In<Vec<String>>,
+    mut client: Transport,
+    mut resume: ResMut<SessionResume>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    resume.clear();
+    client.try_send(ClientMessage::Disconnect {});
+    client.close();
+    output.write(ConsoleOutput("disconnected".to_string()));
+}
+
+fn netstats(
+    In(_): In<Vec<String>>,
+    stats: Res<NetworkStats>,
+    outbox: Res<Outbox>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    output.write(ConsoleOutput(format!(
+        "rtt {} ms, loss {:.1}%, send rate x{:.2}, simulated lag {} ms",
+        stats.rtt.as_millis(),
+        stats.loss * 100.0,
+        stats.rate.scale,
+        outbox.simulated_lag.as_millis()
+    )));
+}
+
+fn simulate_lag(
+    In(args): In<Vec<String>>,
+    mut outbox: ResMut<Outbox>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    let Some(ms) = args.first().and_then(|ms| ms.parse().ok()) else {
+        output.write(ConsoleOutput("usage: simulate_lag <ms>".to_string()));
+        return;
+    };
+    outbox.simulated_lag = Duration::from_millis(ms);
+    output.write(ConsoleOutput(format!(
+        "outgoing messages delayed by {} ms",
+        ms
+    )));
+}
+
+fn spawn_bot(
+    In(args): In<Vec<String>>,
+    mut bots: ResMut<DebugBots>,
+    mut spawn_events: EventWriter<SpawnPlayerEvent>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    let count: usize = args.first().and_then(|n| n.parse().ok()).unwrap_or(1);
+    for _ in 0..count {
+        let client_id = FIRST_BOT_ID + bots.bots.len() as ClientId;
+        bots.bots.push((client_id, 0.0, 0.0));
+        spawn_events.write(SpawnPlayerEvent {
+            client_id,
+            position: None,
+            cosmetics: default(),
+        });
+    }
+    output.write(ConsoleOutput(format!(
+        "{} bots ({} total)",
+        count,
+        bots.bots.len()
+    )));
+}
+
+/// Bots pick a new direction now and then, and keep reporting it so they don't
+/// look stalled
+fn drive_bots(mut bots: ResMut<DebugBots>, mut input_events: EventWriter<PlayerInputEvent>) {
+    if bots.bots.is_empty() {
+        return;
+    }
+    if bots
+        .last_wander
+        .is_none_or(|at| at.elapsed() >= BOT_WANDER_INTERVAL)
+    {
+        bots.last_wander = Some(Instant::now());
+        let mut rng = rand::thread_rng();
+        for (_, horizontal, vertical) in &mut bots.bots {
+            *horizontal = rng.gen_range(-1..=1) as f32;
+            *vertical = rng.gen_range(-1..=1) as f32;
+        }
+    }
+    for &(client_id, horizontal, vertical) in &bots.bots {
+        input_events.write(PlayerInputEvent {
+            client_id,
+            horizontal,
+            vertical,
+            received: None,
+        });
+    }
+}
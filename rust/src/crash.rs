@@ -0,0 +1,69 @@
+use std::{
+    panic,
+    sync::{
+        Arc, Mutex, Once,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, ThreadId},
+};
+
+use bevy::prelude::*;
+
+/// Every app watching for panics, by the thread running it. The game and a server
+/// hosted in it share the process, so a panic is only the business of its own app.
+static APPS: Mutex<Vec<(ThreadId, Arc<AtomicBool>)>> = Mutex::new(Vec::new());
+static INSTALL: Once = Once::new();
+
+/// Whether the app that installed it panicked, see `install_panic_hook`. Stops
+/// watching when dropped.
+#[derive(Resource)]
+pub struct PanicWatch {
+    thread: ThreadId,
+    panicked: Arc<AtomicBool>,
+}
+
+impl PanicWatch {
+    pub fn has_panicked(&self) -> bool {
+        self.panicked.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for PanicWatch {
+    fn drop(&mut self) {
+        apps().retain(|(thread, _)| *thread != self.thread);
+    }
+}
+
+fn apps() -> std::sync::MutexGuard<'static, Vec<(ThreadId, Arc<AtomicBool>)>> {
+    APPS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Chain a panic hook that records panics of the app running on this thread, so it can
+/// tell its peers before going down instead of leaving them to time out. Panics on the
+/// shared task pool threads can't be told apart: they count for the app only while it
+/// is the only one in the process.
+pub fn install_panic_hook() -> PanicWatch {
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let current = thread::current().id();
+            let apps = apps();
+            let app = match apps.iter().find(|(thread, _)| *thread == current) {
+                Some((_, panicked)) => Some(panicked),
+                None if apps.len() == 1 => Some(&apps[0].1),
+                None => None,
+            };
+            if let Some(panicked) = app {
+                panicked.store(true, Ordering::SeqCst);
+            }
+            drop(apps);
+            previous(info);
+        }));
+    });
+    let watch = PanicWatch {
+        thread: thread::current().id(),
+        panicked: Arc::new(AtomicBool::new(false)),
+    };
+    apps().push((watch.thread, watch.panicked.clone()));
+    watch
+}
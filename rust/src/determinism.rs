@@ -0,0 +1,431 @@
+//! `server --determinism steps=600 tolerance=0.5`: two headless clients join an offline
+//! server through the loopback, predict their own movement from the same scripted inputs
+//! with the game's own prediction step, then the worlds they ended up with are compared
+//! with the server's. Exits with an error when a client is more than `tolerance` px off,
+//! so CI catches changes to prediction or reconciliation that make players see
+//! different things.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    thread::{self, JoinHandle, sleep},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::builtin::Vector2;
+use protocol::{ChunkAssembly, ClientMessage, GameRules, ServerMessage};
+
+use crate::loopback::{LOOPBACK_ADDRESS, LoopbackTransport};
+use crate::player::{QueuedInput, predict_tick};
+use crate::server::Users;
+use crate::transport::ClientTransport;
+
+/// One scripted step, like a physics tick of the game
+const STEP: Duration = Duration::from_micros(16_667);
+/// How long the clients wait to join, and to see the last moves after the script
+const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+const SETTLE: Duration = Duration::from_secs(1);
+/// Each scripted input is held for this many steps
+const INPUT_STEPS: u32 = 45;
+/// Inputs the clients cycle through, each starting at a different one
+const SCRIPT: [(f32, f32); 8] = [
+    (1.0, 0.0),
+    (1.0, 1.0),
+    (0.0, 1.0),
+    (-1.0, 0.0),
+    (0.0, 0.0),
+    (-1.0, -1.0),
+    (0.0, -1.0),
+    (1.0, -0.5),
+];
+const N_CLIENTS: usize = 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeterminismOptions {
+    pub steps: u32,
+    /// Largest difference allowed between two views of a position, px
+    pub tolerance: f32,
+}
+
+impl Default for DeterminismOptions {
+    fn default() -> Self {
+        Self {
+            steps: 600,
+            tolerance: 0.5,
+        }
+    }
+}
+
+impl DeterminismOptions {
+    /// None without `--determinism`; the `key=value` arguments after it override the
+    /// defaults
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut args = args.skip_while(|arg| arg != "--determinism");
+        if args.next().is_none() {
+            return Ok(None);
+        }
+        let mut options = Self::default();
+        for arg in args {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got {}", arg))?;
+            match key {
+                "steps" => {
+                    options.steps = value
+                        .parse()
+                        .map_err(|_| format!("invalid number for {}: {}", key, value))?;
+                }
+                "tolerance" => {
+                    options.tolerance = value
+                        .parse()
+                        .map_err(|_| format!("invalid number for {}: {}", key, value))?;
+                }
+                _ => return Err(format!("unknown determinism option: {}", key)),
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+/// The world as one client ended up seeing it
+#[derive(Debug, Clone, Default)]
+struct ClientReport {
+    name: String,
+    client_id: Option<ClientId>,
+    /// Where its own prediction put it, after the server's corrections
+    own: (f32, f32),
+    /// Every player it knows of, itself included as the server last sent it
+    players: HashMap<ClientId, (f32, f32)>,
+    props: HashMap<u32, (f32, f32)>,
+    errors: Vec<String>,
+}
+
+/// A running determinism check: the thread running the clients
+#[derive(Resource)]
+pub struct Determinism {
+    options: DeterminismOptions,
+    clients: Option<JoinHandle<Vec<ClientReport>>>,
+}
+
+impl Determinism {
+    pub fn start(options: DeterminismOptions) -> Self {
+        info!(
+            "Determinism check: {} clients, {} steps",
+            N_CLIENTS, options.steps
+        );
+        Self {
+            options,
+            clients: Some(thread::spawn(move || run_clients(options))),
+        }
+    }
+}
+
+/// Compare the clients' worlds with the server's and stop it once they are done
+pub fn finish_determinism(
+    mut check: ResMut<Determinism>,
+    users: Res<Users>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !check.clients.as_ref().is_some_and(JoinHandle::is_finished) {
+        return;
+    }
+    let Some(Ok(reports)) = check.clients.take().map(JoinHandle::join) else {
+        error!("Determinism clients panicked");
+        exit.write(AppExit::error());
+        return;
+    };
+    let mut errors: Vec<String> = reports
+        .iter()
+        .flat_map(|report| {
+            report
+                .errors
+                .iter()
+                .map(move |err| format!("{}: {}", report.name, err))
+        })
+        .collect();
+    errors.extend(compare(&reports, &users, check.options.tolerance));
+
+    println!(
+        "determinism: {} clients, {} steps, tolerance {} px",
+        reports.len(),
+        check.options.steps,
+        check.options.tolerance
+    );
+    for report in &reports {
+        println!(
+            "{}: at ({:.2}, {:.2}), sees {} players and {} props",
+            report.name,
+            report.own.0,
+            report.own.1,
+            report.players.len(),
+            report.props.len()
+        );
+    }
+    if errors.is_empty() {
+        println!("determinism: ok");
+        exit.write(AppExit::Success);
+    } else {
+        for err in &errors {
+            println!("mismatch: {}", err);
+        }
+        exit.write(AppExit::error());
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Every disagreement with the server: where each client ended up after reconciling
+/// its prediction and where the others see it, against where the server has it; and
+/// between the clients, where they see the props
+fn compare(reports: &[ClientReport], users: &Users, tolerance: f32) -> Vec<String> {
+    let mut errors = Vec::new();
+    for report in reports {
+        let Some(client_id) = report.client_id else {
+            continue;
+        };
+        let Some(authoritative) = users.position(client_id) else {
+            errors.push(format!("the server has no position for {}", report.name));
+            continue;
+        };
+        if distance(report.own, authoritative) > tolerance {
+            errors.push(format!(
+                "{} ended at ({:.2}, {:.2}), the server has it at ({:.2}, {:.2})",
+                report.name, report.own.0, report.own.1, authoritative.0, authoritative.1
+            ));
+        }
+        for other in reports
+            .iter()
+            .filter(|other| other.client_id != Some(client_id))
+        {
+            match other.players.get(&client_id) {
+                Some(&seen) if distance(seen, authoritative) > tolerance => errors.push(format!(
+                    "{} sees {} at ({:.2}, {:.2}), the server has it at ({:.2}, {:.2})",
+                    other.name, report.name, seen.0, seen.1, authoritative.0, authoritative.1
+                )),
+                Some(_) => {}
+                None => errors.push(format!("{} doesn't see {}", other.name, report.name)),
+            }
+        }
+    }
+    for pair in reports.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if a.props.len() != b.props.len() {
+            errors.push(format!(
+                "{} sees {} props, {} sees {}",
+                a.name,
+                a.props.len(),
+                b.name,
+                b.props.len()
+            ));
+        }
+        for (prop_id, &seen) in &a.props {
+            match b.props.get(prop_id) {
+                Some(&other) if distance(seen, other) > tolerance => errors.push(format!(
+                    "prop {} is at ({:.2}, {:.2}) for {}, ({:.2}, {:.2}) for {}",
+                    prop_id, seen.0, seen.1, a.name, other.0, other.1, b.name
+                )),
+                _ => {}
+            }
+        }
+    }
+    errors
+}
+
+/// A client without Godot: the transport, and the part of the world the check compares
+struct HeadlessClient {
+    transport: LoopbackTransport,
+    chunks: ChunkAssembly,
+    rules: GameRules,
+    synced: bool,
+    seq: u32,
+    /// The input held since the last step
+    held: (f32, f32),
+    report: ClientReport,
+}
+
+impl HeadlessClient {
+    fn join(index: usize) -> Self {
+        let name = format!("determinism{}", index);
+        let mut client = Self {
+            transport: LoopbackTransport::default(),
+            chunks: ChunkAssembly::default(),
+            rules: GameRules::default(),
+            synced: false,
+            seq: 0,
+            held: (0.0, 0.0),
+            report: ClientReport {
+                name: name.clone(),
+                ..Default::default()
+            },
+        };
+        let joined = client.transport.open(LOOPBACK_ADDRESS).and_then(|()| {
+            client.transport.send(ClientMessage::Join {
+                name,
+                identity: None,
+                password: None,
+            })
+        });
+        if let Err(err) = joined {
+            client
+                .report
+                .errors
+                .push(format!("could not join: {}", err));
+        }
+        client
+    }
+
+    fn receive_all(&mut self) {
+        while let Some(message) = self.transport.receive() {
+            self.handle(message);
+        }
+    }
+
+    fn handle(&mut self, message: ServerMessage) {
+        let players = &mut self.report.players;
+        match message {
+            ServerMessage::InitClient { client_id, .. } => self.report.client_id = Some(client_id),
+            ServerMessage::WorldPlayers { players: list } => {
+                for player in list {
+                    players.insert(player.client_id, (player.x, player.y));
+                    if Some(player.client_id) == self.report.client_id {
+                        self.report.own = (player.x, player.y);
+                    }
+                }
+            }
+            ServerMessage::ClientConnected {
+                client_id, x, y, ..
+            } => {
+                players.insert(client_id, (x, y));
+            }
+            ServerMessage::ClientDisconnected { client_id }
+            | ServerMessage::PlayerLeftRoom { client_id } => {
+                players.remove(&client_id);
+            }
+            // Relayed moves of our own player are ignored like in the game, we predict it
+            ServerMessage::PlayerUpdate {
+                client_id, x, y, ..
+            } if Some(client_id) != self.report.client_id => {
+                players.insert(client_id, (x, y));
+            }
+            ServerMessage::Teleport { client_id, x, y } => {
+                players.insert(client_id, (x, y));
+                if Some(client_id) == self.report.client_id {
+                    self.report.own = (x, y);
+                }
+            }
+            ServerMessage::PropSnapshot { props } => {
+                for state in props {
+                    self.report.props.insert(state.prop_id, (state.x, state.y));
+                }
+            }
+            ServerMessage::AuthorityRevoked { state } => {
+                self.report.props.insert(state.prop_id, (state.x, state.y));
+            }
+            ServerMessage::GameRules { rules } => self.rules = rules,
+            ServerMessage::SyncComplete {} => self.synced = true,
+            ServerMessage::InitBegin {
+                total_len,
+                chunk_count,
+            } => self.chunks.begin(total_len, chunk_count),
+            ServerMessage::InitChunk { index, data } => {
+                if let Err(err) = self.chunks.push(index, &data) {
+                    self.report.errors.push(err);
+                }
+            }
+            ServerMessage::InitEnd {} => match self.chunks.finish() {
+                Ok(message) => self.handle(message),
+                Err(err) => self.report.errors.push(err),
+            },
+            ServerMessage::JoinRejected { reason } | ServerMessage::NameRejected { reason } => {
+                self.report
+                    .errors
+                    .push(format!("join rejected: {:?}", reason));
+            }
+            _ => {}
+        }
+    }
+
+    /// Move by one step of `input`, as the game predicts it in a world without obstacles,
+    /// and send the update
+    fn step(&mut self, input: (f32, f32), sent_at_ms: u32) {
+        let own = Vector2::new(self.report.own.0, self.report.own.1);
+        let mut queue = VecDeque::from([QueuedInput {
+            offset: 0.0,
+            horizontal: input.0,
+            vertical: input.1,
+        }]);
+        let (position, held) = predict_tick(self.held, &mut queue, self.rules.bounds, |velocity| {
+            own + velocity * STEP.as_secs_f32()
+        });
+        self.held = held;
+        let (x, y) = (position.x, position.y);
+        self.report.own = (x, y);
+        self.seq = self.seq.wrapping_add(1);
+        let sent = self.transport.send(ClientMessage::PlayerUpdate {
+            seq: self.seq,
+            x,
+            y,
+            horizontal: input.0,
+            vertical: input.1,
+            sent_at_ms,
+        });
+        if let Err(err) = sent {
+            self.report
+                .errors
+                .push(format!("update {}: {}", self.seq, err));
+        }
+    }
+}
+
+/// The input client `index` holds at `step`
+fn scripted_input(index: usize, step: u32) -> (f32, f32) {
+    SCRIPT[(index + (step / INPUT_STEPS) as usize) % SCRIPT.len()]
+}
+
+/// Join both clients, play the script at the game's tick rate, let the last moves
+/// arrive, and report what each saw. Blocking, run on its own thread.
+fn run_clients(options: DeterminismOptions) -> Vec<ClientReport> {
+    let mut clients: Vec<HeadlessClient> = (0..N_CLIENTS).map(HeadlessClient::join).collect();
+
+    let start = Instant::now();
+    while !clients.iter().all(|client| client.synced) {
+        if start.elapsed() > JOIN_TIMEOUT {
+            for client in clients.iter_mut().filter(|client| !client.synced) {
+                client.report.errors.push("never synced".to_string());
+            }
+            return clients.into_iter().map(|client| client.report).collect();
+        }
+        for client in clients.iter_mut() {
+            client.receive_all();
+        }
+        sleep(STEP);
+    }
+
+    let start = Instant::now();
+    for step in 0..options.steps {
+        let sent_at_ms = start.elapsed().as_millis() as u32;
+        for (index, client) in clients.iter_mut().enumerate() {
+            client.receive_all();
+            client.step(scripted_input(index, step), sent_at_ms);
+        }
+        // Steps keep the game's pace, the server checks movement speed against real time
+        sleep((STEP * (step + 1)).saturating_sub(start.elapsed()));
+    }
+    // Standing still, so the last relayed position is the final one
+    let sent_at_ms = start.elapsed().as_millis() as u32;
+    for client in clients.iter_mut() {
+        client.step((0.0, 0.0), sent_at_ms);
+    }
+
+    let settled = Instant::now();
+    while settled.elapsed() < SETTLE {
+        for client in clients.iter_mut() {
+            client.receive_all();
+        }
+        sleep(STEP);
+    }
+    clients.into_iter().map(|client| client.report).collect()
+}
@@ -0,0 +1,224 @@
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::{
+    classes::{Button, Control, IControl, Input, InputMap, Label, PackedScene, ResourceLoader},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::{ClientMessage, emotes};
+use tokio::sync::mpsc::{self, Sender};
+
+use crate::frame_budget;
+use crate::player::{PlayerIndex, PlayerNode};
+use crate::transport::SendToServer;
+
+/// Input action toggling the wheel, used only if the project defines it
+const WHEEL_ACTION: &str = "emote_wheel";
+const WHEEL_RADIUS: f32 = 80.0;
+/// How long the symbol floats above the player
+const EMOTE_SECS: f64 = 1.5;
+
+#[derive(Debug, Clone)]
+pub struct EmoteDef {
+    pub id: u32,
+    pub label: String,
+    /// Shown floating above the player
+    pub symbol: String,
+    /// Particles/animation scene instanced on the player, when it exists
+    pub scene_path: String,
+}
+
+/// Emotes offered by the wheel, by protocol id
+#[derive(Resource, Debug, Clone)]
+pub struct EmoteLibrary(pub Vec<EmoteDef>);
+
+impl Default for EmoteLibrary {
+    fn default() -> Self {
+        let emote = |id, label: &str, symbol: &str| EmoteDef {
+            id,
+            label: label.to_string(),
+            symbol: symbol.to_string(),
+            scene_path: format!("res://emotes/{}.tscn", label.to_lowercase()),
+        };
+        Self(vec![
+            emote(emotes::WAVE, "Wave", "o/"),
+            emote(emotes::DANCE, "Dance", "~♪~"),
+            emote(emotes::LAUGH, "Laugh", "xD"),
+            emote(emotes::HEART, "Heart", "<3"),
+        ])
+    }
+}
+
+impl EmoteLibrary {
+    pub fn get(&self, id: u32) -> Option<&EmoteDef> {
+        self.0.iter().find(|emote| emote.id == id)
+    }
+}
+
+/// `client_id` played emote `id`; the sender receives its own emote too
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EmoteEvent {
+    pub client_id: ClientId,
+    pub id: u32,
+}
+
+#[derive(Component, Default)]
+pub struct EmoteWheelComp;
+
+/// Radial menu of emotes, toggled with the `emote_wheel` action or `toggle()`
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Control)]
+#[bevy_bundle((EmoteWheelComp))]
+pub struct EmoteWheelNode {
+    base: Base<Control>,
+    #[bevy_bundle]
+    pub sender: Option<Sender<u32>>,
+}
+
+#[godot_api]
+impl IControl for EmoteWheelNode {
+    fn init(base: Base<Control>) -> Self {
+        Self { base, sender: None }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_visible(false);
+    }
+
+    fn process(&mut self, _delta: f64) {
+        if InputMap::singleton().has_action(WHEEL_ACTION)
+            && Input::singleton().is_action_just_pressed(WHEEL_ACTION)
+        {
+            self.toggle();
+        }
+    }
+}
+
+#[godot_api]
+impl EmoteWheelNode {
+    #[func]
+    pub fn toggle(&mut self) {
+        let visible = self.base().is_visible();
+        self.base_mut().set_visible(!visible);
+    }
+
+    #[func]
+    fn choose(&mut self, id: u32) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(id);
+        } else {
+            godot_print!("Emote chosen, but sender not set yet");
+        }
+        self.base_mut().set_visible(false);
+    }
+
+    /// One button per emote, laid out in a circle around the node's origin
+    fn build_buttons(&mut self, library: &EmoteLibrary) {
+        let count = library.0.len().max(1) as f32;
+        for (i, emote) in library.0.iter().enumerate() {
+            let angle = i as f32 / count * std::f32::consts::TAU;
+            let mut button = Button::new_alloc();
+            button.set_text(&format!("{} {}", emote.symbol, emote.label));
+            button.set_position(Vector2::new(angle.cos(), angle.sin()) * WHEEL_RADIUS);
+            let callable = Callable::from_object_method(&self.to_gd(), "choose")
+                .bind(&[emote.id.to_variant()]);
+            button.connect("pressed", &callable);
+            self.base_mut().add_child(&button);
+        }
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct EmoteReceiver(pub mpsc::Receiver<u32>);
+
+pub struct EmotePlugin;
+
+impl Plugin for EmotePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EmoteEvent>()
+            .init_resource::<EmoteLibrary>()
+            .add_systems(Startup, start_emote_wheels)
+            .add_systems(
+                Update,
+                (
+                    send_emotes.run_if(resource_exists::<EmoteReceiver>),
+                    play_emotes,
+                ),
+            );
+    }
+}
+
+#[main_thread_system]
+fn start_emote_wheels(mut commands: Commands, library: Res<EmoteLibrary>) {
+    let (tx, rx) = mpsc::channel::<u32>(16);
+    let library = library.clone();
+
+    commands.queue(move |world: &mut World| {
+        let mut query = world.query::<&mut GodotNodeHandle>();
+        for mut handle in query.iter_mut(world) {
+            if let Some(mut wheel) = handle.try_get::<EmoteWheelNode>() {
+                let mut wheel = wheel.bind_mut();
+                wheel.sender = Some(tx.clone());
+                wheel.build_buttons(&library);
+            }
+        }
+    });
+
+    commands.insert_resource(EmoteReceiver(rx));
+}
+
+fn send_emotes(mut receiver: ResMut<EmoteReceiver>, mut to_server: EventWriter<SendToServer>) {
+    while let Ok(id) = receiver.try_recv() {
+        to_server.write(SendToServer(ClientMessage::Emote { id }));
+    }
+}
+
+#[main_thread_system]
+fn play_emotes(
+    mut events: EventReader<EmoteEvent>,
+    library: Res<EmoteLibrary>,
+    index: Res<PlayerIndex>,
+    mut query: Query<&mut GodotNodeHandle>,
+) {
+    let _timing = frame_budget::scope("play_emotes");
+    for ev in events.read() {
+        let Some(emote) = library.get(ev.id) else {
+            continue;
+        };
+        let Some(mut handle) = index
+            .0
+            .get(&ev.client_id)
+            .and_then(|entity| query.get_mut(*entity).ok())
+        else {
+            continue;
+        };
+        let Some(mut player) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+
+        let mut loader = ResourceLoader::singleton();
+        if loader.exists(&emote.scene_path) {
+            if let Some(effect) = loader
+                .load(&emote.scene_path)
+                .and_then(|resource| resource.try_cast::<PackedScene>().ok())
+                .and_then(|scene| scene.instantiate())
+            {
+                player.add_child(&effect);
+            }
+        }
+
+        // The symbol rises and fades above the player's head, then frees itself
+        let mut label = Label::new_alloc();
+        label.set_text(&emote.symbol);
+        label.set_position(Vector2::new(-12.0, -48.0));
+        player.add_child(&label);
+        let Some(mut tween) = label.create_tween() else {
+            continue;
+        };
+        tween.set_parallel();
+        tween.tween_property(&label, "position:y", &(-80.0f32).to_variant(), EMOTE_SECS);
+        tween.tween_property(&label, "modulate:a", &0.0f32.to_variant(), EMOTE_SECS);
+        tween.chain();
+        tween.tween_callback(&Callable::from_object_method(&label, "queue_free"));
+    }
+}
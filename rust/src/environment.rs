@@ -0,0 +1,131 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use godot::{
+    classes::{CanvasModulate, ICanvasModulate},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::EnvironmentState;
+
+use crate::frame_budget;
+
+/// The server sent its environment state
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EnvironmentSynced {
+    pub state: EnvironmentState,
+}
+
+/// Local copy of the server's environment, advanced between syncs.
+/// None until the first sync, scene lighting is left alone until then.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Environment(pub Option<EnvironmentState>);
+
+#[derive(Component, Default)]
+pub struct DayNightComp;
+
+/// Tints the canvas by the replicated time of day and weather.
+/// `time_of_day` and `weather_seed` are readable from scripts, e.g. to drive a WorldEnvironment.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=CanvasModulate)]
+#[bevy_bundle((DayNightComp))]
+pub struct DayNightNode {
+    base: Base<CanvasModulate>,
+    #[export]
+    day_color: Color,
+    #[export]
+    dusk_color: Color,
+    #[export]
+    night_color: Color,
+    /// How much an overcast day darkens the tint, 0 disables weather
+    #[export]
+    overcast_strength: f32,
+    #[var]
+    time_of_day: f32,
+    #[var]
+    weather_seed: u32,
+}
+
+#[godot_api]
+impl ICanvasModulate for DayNightNode {
+    fn init(base: Base<CanvasModulate>) -> Self {
+        Self {
+            base,
+            day_color: Color::WHITE,
+            dusk_color: Color::from_rgb(1.0, 0.75, 0.6),
+            night_color: Color::from_rgb(0.25, 0.3, 0.5),
+            overcast_strength: 0.3,
+            time_of_day: 0.0,
+            weather_seed: 0,
+        }
+    }
+}
+
+impl DayNightNode {
+    fn apply(&mut self, state: &EnvironmentState) {
+        self.time_of_day = state.time_of_day;
+        self.weather_seed = state.weather_seed;
+
+        // -1 at midnight, 1 at noon
+        let sun = -(state.time_of_day * std::f32::consts::TAU).cos();
+        let daylight = ((sun + 0.2) / 0.6).clamp(0.0, 1.0);
+        let dusk = (1.0 - sun.abs() / 0.3).clamp(0.0, 1.0) * 0.5;
+        let overcast = (state.weather_seed % 100) as f32 / 100.0 * self.overcast_strength;
+
+        let color = mix(self.night_color, self.day_color, daylight);
+        let color = mix(color, self.dusk_color, dusk);
+        let color = mix(color, Color::from_rgb(0.5, 0.5, 0.55), overcast);
+        self.base_mut().set_color(color);
+    }
+}
+
+fn mix(from: Color, to: Color, weight: f32) -> Color {
+    Color::from_rgba(
+        from.r + (to.r - from.r) * weight,
+        from.g + (to.g - from.g) * weight,
+        from.b + (to.b - from.b) * weight,
+        from.a + (to.a - from.a) * weight,
+    )
+}
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EnvironmentSynced>()
+            .init_resource::<Environment>()
+            .add_systems(Update, (advance_environment, apply_environment).chain());
+    }
+}
+
+fn advance_environment(
+    mut synced: EventReader<EnvironmentSynced>,
+    mut environment: ResMut<Environment>,
+    mut last_tick: Local<Option<Instant>>,
+) {
+    let now = Instant::now();
+    let elapsed = now.duration_since(last_tick.replace(now).unwrap_or(now));
+    if let Some(state) = &mut environment.0 {
+        // The server picks the next weather seed, keep ours until it syncs
+        state.advance(elapsed.as_secs_f32());
+    }
+    if let Some(ev) = synced.read().last() {
+        environment.0 = Some(ev.state);
+    }
+}
+
+#[main_thread_system]
+fn apply_environment(
+    environment: Res<Environment>,
+    mut query: Query<&mut GodotNodeHandle, With<DayNightComp>>,
+) {
+    let _timing = frame_budget::scope("apply_environment");
+    let Some(state) = &environment.0 else {
+        return;
+    };
+    for mut handle in query.iter_mut() {
+        if let Some(mut node) = handle.try_get::<DayNightNode>() {
+            node.bind_mut().apply(state);
+        }
+    }
+}
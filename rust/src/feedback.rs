@@ -0,0 +1,235 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::{
+    classes::{AnimatedSprite2D, Camera2D, Input},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::FeedbackKind;
+use rand::Rng;
+
+use crate::camera::FollowCameraComp;
+use crate::flow::in_game;
+use crate::net_events::from_server;
+use crate::player::{Player, PlayerNode};
+use crate::settings::ClientSettings;
+use crate::spectator::SpectatorCameraComp;
+use crate::{Users, frame_budget};
+
+/// Events further than this from our player aren't felt at all
+const FEEDBACK_RADIUS: f32 = 800.0;
+/// Camera offset at full trauma, in pixels
+const MAX_SHAKE: f32 = 16.0;
+/// Trauma lost per second
+const TRAUMA_DECAY: f32 = 1.5;
+const FLASH_COLOR: Color = Color::from_rgb(1.0, 0.35, 0.35);
+
+/// How strongly each kind of event is felt, at full intensity
+struct Profile {
+    /// Trauma added to the camera shake
+    shake: f32,
+    /// Players this close to the event flash, 0 for none
+    flash_radius: f32,
+    flash_secs: f64,
+    /// Weak and strong motor magnitudes
+    rumble: (f32, f32),
+    rumble_secs: f32,
+}
+
+fn profile(kind: FeedbackKind) -> Profile {
+    match kind {
+        FeedbackKind::Hit => Profile {
+            shake: 0.4,
+            flash_radius: 48.0,
+            flash_secs: 0.2,
+            rumble: (0.6, 0.3),
+            rumble_secs: 0.15,
+        },
+        FeedbackKind::Explosion => Profile {
+            shake: 1.0,
+            flash_radius: 160.0,
+            flash_secs: 0.35,
+            rumble: (0.8, 1.0),
+            rumble_secs: 0.4,
+        },
+        FeedbackKind::Bump => Profile {
+            shake: 0.25,
+            flash_radius: 0.0,
+            flash_secs: 0.0,
+            rumble: (0.4, 0.0),
+            rumble_secs: 0.1,
+        },
+    }
+}
+
+/// Vibrate a controller, or every connected one when `device` is None. Written by
+/// feedback and gameplay systems, and from GDScript with `NetworkManagerNode.rumble`.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RumbleRequest {
+    pub device: Option<i32>,
+    /// Weak (high frequency) and strong (low frequency) motor magnitudes, 0 to 1
+    pub weak: f32,
+    pub strong: f32,
+    pub duration_secs: f32,
+}
+
+/// How much the cameras shake, from 0 to 1; the offset grows with its square so small
+/// hits stay subtle
+#[derive(Resource, Debug, Default)]
+pub struct ScreenShake {
+    pub trauma: f32,
+}
+
+pub struct FeedbackPlugin;
+
+impl Plugin for FeedbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenShake>()
+            .add_event::<RumbleRequest>()
+            .add_systems(
+                Update,
+                (
+                    play_feedback,
+                    shake_cameras.after(play_feedback),
+                    apply_rumble.after(play_feedback),
+                )
+                    .run_if(in_game),
+            );
+    }
+}
+
+/// Turn server feedback events into shake, flashes and controller rumble, weaker the
+/// further they are from our player
+#[main_thread_system]
+fn play_feedback(
+    mut events: EventReader<from_server::FeedbackEvent>,
+    users: Res<Users>,
+    settings: Res<ClientSettings>,
+    mut shake: ResMut<ScreenShake>,
+    mut rumble: EventWriter<RumbleRequest>,
+    mut players: Query<(&Player, &mut GodotNodeHandle)>,
+) {
+    let _timing = frame_budget::scope("play_feedback");
+    if events.is_empty() {
+        return;
+    }
+    let mut nodes: Vec<(ClientId, Gd<PlayerNode>)> = players
+        .iter_mut()
+        .filter_map(|(player, mut handle)| Some((player.0, handle.try_get::<PlayerNode>()?)))
+        .collect();
+    let own_position = nodes
+        .iter()
+        .find(|(client_id, _)| *client_id == users.self_id)
+        .map(|(_, node)| node.get_global_position());
+
+    for ev in events.read() {
+        let profile = profile(ev.kind);
+        let position = Vector2::new(ev.x, ev.y);
+        // Spectating without a player of our own, felt at full strength
+        let falloff = own_position.map_or(1.0, |own| {
+            (1.0 - own.distance_to(position) / FEEDBACK_RADIUS).max(0.0)
+        });
+        let strength = ev.intensity.clamp(0.0, 1.0) * falloff;
+        if strength <= 0.0 {
+            continue;
+        }
+
+        if settings.screen_shake {
+            shake.trauma = (shake.trauma + profile.shake * strength).min(1.0);
+        }
+
+        if profile.flash_radius > 0.0 {
+            for (_, node) in nodes.iter_mut() {
+                if node.get_global_position().distance_to(position) <= profile.flash_radius {
+                    flash(node, profile.flash_secs);
+                }
+            }
+        }
+
+        let (weak, strong) = profile.rumble;
+        rumble.write(RumbleRequest {
+            device: None,
+            weak: weak * strength,
+            strong: strong * strength,
+            duration_secs: profile.rumble_secs,
+        });
+    }
+}
+
+/// Start the requested vibrations, unless the player turned rumble off
+#[main_thread_system]
+fn apply_rumble(mut requests: EventReader<RumbleRequest>, settings: Res<ClientSettings>) {
+    let _timing = frame_budget::scope("apply_rumble");
+    if !settings.rumble {
+        requests.clear();
+        return;
+    }
+    let mut input = Input::singleton();
+    for request in requests.read() {
+        let devices: Vec<i32> = match request.device {
+            Some(device) => vec![device],
+            None => input
+                .get_connected_joypads()
+                .iter_shared()
+                .map(|device| device as i32)
+                .collect(),
+        };
+        for device in devices {
+            input
+                .start_joy_vibration_ex(
+                    device,
+                    request.weak.clamp(0.0, 1.0),
+                    request.strong.clamp(0.0, 1.0),
+                )
+                .duration(request.duration_secs.max(0.0))
+                .done();
+        }
+    }
+}
+
+/// Tint the player's sprite, then fade back. The body draws nothing itself, and the
+/// modulates are taken: the player's by the away fade, the sprite's by the teleport
+/// fade-in, so this uses the sprite's self_modulate.
+fn flash(node: &mut Gd<PlayerNode>, secs: f64) {
+    let Some(mut sprite) = node.try_get_node_as::<AnimatedSprite2D>("AnimatedSprite2D") else {
+        return;
+    };
+    sprite.set_self_modulate(FLASH_COLOR);
+    let Some(mut tween) = sprite.create_tween() else {
+        return;
+    };
+    tween.tween_property(&sprite, "self_modulate", &Color::WHITE.to_variant(), secs);
+}
+
+/// Offset the follow and spectator cameras randomly while there is trauma left.
+/// Their `offset` is reserved for this, the position is theirs to move.
+#[main_thread_system]
+fn shake_cameras(
+    mut shake: ResMut<ScreenShake>,
+    mut cameras: Query<
+        &mut GodotNodeHandle,
+        Or<(With<FollowCameraComp>, With<SpectatorCameraComp>)>,
+    >,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    let _timing = frame_budget::scope("shake_cameras");
+    let now = Instant::now();
+    let elapsed = now.duration_since(last_frame.replace(now).unwrap_or(now));
+    // Untouched once the offset was reset, so a still camera costs nothing
+    if shake.trauma <= 0.0 && !shake.is_changed() {
+        return;
+    }
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * elapsed.as_secs_f32()).max(0.0);
+
+    let amount = MAX_SHAKE * shake.trauma * shake.trauma;
+    let mut rng = rand::thread_rng();
+    for mut handle in cameras.iter_mut() {
+        let Some(mut camera) = handle.try_get::<Camera2D>() else {
+            continue;
+        };
+        let offset = Vector2::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0)) * amount;
+        camera.set_offset(offset);
+    }
+}
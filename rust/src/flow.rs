@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use godot::{
+    classes::{Control, IControl, Input, InputMap},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::host::HostState;
+use crate::input_context::MenuOpen;
+use crate::migration::SessionResume;
+use crate::results::MatchResults;
+use crate::transport::ConnectionState;
+use crate::{SyncState, frame_budget};
+
+/// Input action opening and closing the pause menu, used only if the project defines it
+const PAUSE_ACTION: &str = "pause_menu";
+
+/// Which screen the app is on, derived from the connection and sync state. Gameplay
+/// systems only run in game, and each `FlowScreenNode` is shown in its own state.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AppFlowState {
+    #[default]
+    MainMenu,
+    /// Connecting, syncing the world, or resuming a dropped session
+    Connecting,
+    InGame,
+    /// The pause menu is open (see `MenuOpen`) or the host paused the game; the
+    /// connection stays live
+    Paused,
+    /// A match ended; kept until the next one starts
+    Results,
+}
+
+impl AppFlowState {
+    /// Name used for `FlowScreenNode.state`
+    pub fn name(self) -> &'static str {
+        match self {
+            AppFlowState::MainMenu => "main_menu",
+            AppFlowState::Connecting => "connecting",
+            AppFlowState::InGame => "in_game",
+            AppFlowState::Paused => "paused",
+            AppFlowState::Results => "results",
+        }
+    }
+}
+
+/// Run condition: the world is loaded, whether or not the game is paused
+pub fn in_game(flow: Res<AppFlowState>) -> bool {
+    matches!(*flow, AppFlowState::InGame | AppFlowState::Paused)
+}
+
+#[derive(Component, Default)]
+pub struct FlowScreenComp;
+
+/// A menu screen (main menu, pause menu, results...), visible only in the flow state
+/// named by `state`: "main_menu", "connecting", "in_game", "paused" or "results"
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Control)]
+#[bevy_bundle((FlowScreenComp))]
+pub struct FlowScreenNode {
+    base: Base<Control>,
+    #[export]
+    state: GString,
+}
+
+#[godot_api]
+impl IControl for FlowScreenNode {
+    fn init(base: Base<Control>) -> Self {
+        Self {
+            base,
+            state: GString::from(AppFlowState::MainMenu.name()),
+        }
+    }
+}
+
+#[godot_api]
+impl FlowScreenNode {
+    /// The screen was shown or hidden by a flow state change
+    #[signal]
+    fn screen_toggled(visible: bool);
+
+    fn show_for(&mut self, flow: AppFlowState) {
+        let visible = self.state.to_string() == flow.name();
+        if self.base().is_visible() != visible {
+            self.base_mut().set_visible(visible);
+            self.base_mut()
+                .emit_signal("screen_toggled", &[visible.to_variant()]);
+        }
+    }
+}
+
+pub struct FlowPlugin;
+
+impl Plugin for FlowPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AppFlowState>().add_systems(
+            Update,
+            (
+                toggle_pause_menu,
+                advance_flow.after(toggle_pause_menu),
+                show_flow_screens.after(advance_flow),
+            ),
+        );
+    }
+}
+
+#[main_thread_system]
+fn toggle_pause_menu(flow: Res<AppFlowState>, mut menu: ResMut<MenuOpen>) {
+    let _timing = frame_budget::scope("toggle_pause_menu");
+    if !in_game(flow) {
+        return;
+    }
+    if InputMap::singleton().has_action(PAUSE_ACTION)
+        && Input::singleton().is_action_just_pressed(PAUSE_ACTION)
+    {
+        menu.0 = !menu.0;
+    }
+}
+
+fn advance_flow(
+    mut flow: ResMut<AppFlowState>,
+    connection: Res<ConnectionState>,
+    sync: Res<SyncState>,
+    resume: Res<SessionResume>,
+    host: Res<HostState>,
+    menu: Res<MenuOpen>,
+    results: Res<MatchResults>,
+) {
+    let next = match (*connection, *sync) {
+        (ConnectionState::Disconnected, _) if !resume.is_resuming() => AppFlowState::MainMenu,
+        (_, SyncState::Ready) if results.showing => AppFlowState::Results,
+        (_, SyncState::Ready) if menu.0 || host.paused => AppFlowState::Paused,
+        (_, SyncState::Ready) => AppFlowState::InGame,
+        _ => AppFlowState::Connecting,
+    };
+    if flow.set_if_neq(next) {
+        info!("App flow: {:?}", next);
+    }
+}
+
+#[main_thread_system]
+fn show_flow_screens(
+    flow: Res<AppFlowState>,
+    mut query: Query<&mut GodotNodeHandle, With<FlowScreenComp>>,
+) {
+    let _timing = frame_budget::scope("show_flow_screens");
+    for mut handle in query.iter_mut() {
+        if !flow.is_changed() && !handle.is_added() {
+            continue;
+        }
+        if let Some(mut screen) = handle.try_get::<FlowScreenNode>() {
+            screen.bind_mut().show_for(*flow);
+        }
+    }
+}
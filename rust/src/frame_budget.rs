@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::console::{ConsoleAppExt, ConsoleOutput};
+
+/// At most one over-budget warning per system in this long
+const WARN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Timings recorded since the last `collect_system_timings`. Main-thread systems only
+/// run on the main thread, but a static keeps `scope` free of system parameters.
+static PENDING: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// Times a system until dropped: `let _timing = frame_budget::scope("my_system");`
+#[must_use = "the system is timed until the guard is dropped"]
+pub struct TimingScope {
+    name: &'static str,
+    started: Instant,
+}
+
+pub fn scope(name: &'static str) -> TimingScope {
+    TimingScope {
+        name,
+        started: Instant::now(),
+    }
+}
+
+impl Drop for TimingScope {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.push((self.name, self.started.elapsed()));
+        }
+    }
+}
+
+/// How long a single main-thread system may take before we warn about it
+#[derive(Resource, Debug, Clone)]
+pub struct FrameBudget {
+    pub system_budget: Duration,
+}
+
+impl Default for FrameBudget {
+    fn default() -> Self {
+        Self {
+            system_budget: Duration::from_millis(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTiming {
+    pub last: Duration,
+    /// Exponential moving average over frames the system ran
+    pub average: Duration,
+    pub max: Duration,
+    /// Runs over `FrameBudget::system_budget`
+    pub over_budget: u32,
+}
+
+/// Per-system timings of the instrumented main-thread systems
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SystemTimings {
+    pub systems: BTreeMap<&'static str, SystemTiming>,
+    last_warning: HashMap<&'static str, Instant>,
+}
+
+impl SystemTimings {
+    /// The `count` systems with the highest average, slowest first
+    pub fn slowest(&self, count: usize) -> Vec<(&'static str, SystemTiming)> {
+        let mut systems: Vec<_> = self
+            .systems
+            .iter()
+            .map(|(name, timing)| (*name, *timing))
+            .collect();
+        systems.sort_by(|a, b| b.1.average.cmp(&a.1.average));
+        systems.truncate(count);
+        systems
+    }
+}
+
+pub struct FrameBudgetPlugin;
+
+impl Plugin for FrameBudgetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameBudget>()
+            .init_resource::<SystemTimings>()
+            .add_systems(Last, collect_system_timings)
+            .add_console_command(
+                "timings",
+                "timings [count]|reset: slowest main-thread systems",
+                timings_command,
+            )
+            .add_console_command(
+                "frame_budget",
+                "frame_budget <ms>: warn when a system takes longer",
+                frame_budget_command,
+            );
+    }
+}
+
+fn collect_system_timings(budget: Res<FrameBudget>, mut timings: ResMut<SystemTimings>) {
+    let pending = match PENDING.lock() {
+        Ok(mut pending) => std::mem::take(&mut *pending),
+        Err(_) => return,
+    };
+    let now = Instant::now();
+    for (name, elapsed) in pending {
+        let timing = timings.systems.entry(name).or_default();
+        timing.average = if timing.average.is_zero() {
+            elapsed
+        } else {
+            timing.average.mul_f32(0.9) + elapsed.mul_f32(0.1)
+        };
+        timing.last = elapsed;
+        timing.max = timing.max.max(elapsed);
+        if elapsed <= budget.system_budget {
+            continue;
+        }
+        timing.over_budget += 1;
+        let over_budget = timing.over_budget;
+        if timings
+            .last_warning
+            .get(name)
+            .is_none_or(|at| now.duration_since(*at) >= WARN_INTERVAL)
+        {
+            timings.last_warning.insert(name, now);
+            warn!(
+                "{} took {:.2} ms, budget {:.2} ms ({} times over)",
+                name,
+                elapsed.as_secs_f64() * 1000.0,
+                budget.system_budget.as_secs_f64() * 1000.0,
+                over_budget
+            );
+        }
+    }
+}
+
+fn timings_command(
+    In(args): In<Vec<String>>,
+    mut timings: ResMut<SystemTimings>,
+    budget: Res<FrameBudget>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    if args.first().map(String::as_str) == Some("reset") {
+        *timings = SystemTimings::default();
+        output.write(ConsoleOutput("timings reset".to_string()));
+        return;
+    }
+    let count = args.first().and_then(|n| n.parse().ok()).unwrap_or(10);
+    output.write(ConsoleOutput(format!(
+        "budget {:.2} ms per system",
+        budget.system_budget.as_secs_f64() * 1000.0
+    )));
+    for (name, timing) in timings.slowest(count) {
+        output.write(ConsoleOutput(format!(
+            "{}: avg {:.2} ms, max {:.2} ms, {} over",
+            name,
+            timing.average.as_secs_f64() * 1000.0,
+            timing.max.as_secs_f64() * 1000.0,
+            timing.over_budget
+        )));
+    }
+}
+
+fn frame_budget_command(
+    In(args): In<Vec<String>>,
+    mut budget: ResMut<FrameBudget>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    let Some(ms) = args.first().and_then(|ms| ms.parse::<f64>().ok()) else {
+        output.write(ConsoleOutput("usage: frame_budget <ms>".to_string()));
+        return;
+    };
+    budget.system_budget = Duration::from_secs_f64(ms.max(0.0) / 1000.0);
+    output.write(ConsoleOutput(format!("system budget {} ms", ms)));
+}
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::{
+    classes::{CanvasItem, Label},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::{LocalizedText, NoticeLevel, texts};
+
+use crate::localization::Translations;
+use crate::net_events::from_server;
+use crate::player::{Player, PlayerNode};
+use crate::{Users, chat, frame_budget, show_local_line};
+
+/// Child of the host's PlayerNode; a node with this name in the player scene is
+/// shown and hidden instead of the default label
+const CROWN_NAME: &str = "HostCrown";
+
+/// Who administrates the match, as last sent by the server
+#[derive(Resource, Debug, Clone, Default)]
+pub struct HostState {
+    pub host: Option<ClientId>,
+    /// Our input is ignored while the host has the game paused
+    pub paused: bool,
+}
+
+pub struct HostPlugin;
+
+impl Plugin for HostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HostState>()
+            .add_systems(Update, (track_host, show_host_crown.after(track_host)));
+    }
+}
+
+fn track_host(
+    mut commands: Commands,
+    mut host_changes: EventReader<from_server::HostChanged>,
+    mut pauses: EventReader<from_server::Paused>,
+    mut state: ResMut<HostState>,
+    users: Res<Users>,
+    translations: Res<Translations>,
+) {
+    for ev in host_changes.read() {
+        // The first one after joining only tells us who it already was
+        let announce = state.host.is_some() && state.host != ev.host;
+        state.host = ev.host;
+        let Some(name) = ev.host.and_then(|host| users.names.get(&host)) else {
+            continue;
+        };
+        if announce {
+            let text = translations.localize(&LocalizedText::new(texts::HOST_CHANGED).arg(name));
+            show_local_line(&mut commands, chat::notice_line(NoticeLevel::Info, &text));
+        }
+    }
+    // Announced by the server with a notice saying who paused
+    if let Some(ev) = pauses.read().last() {
+        state.paused = ev.paused;
+    }
+}
+
+/// Crown over the host's player, moved when the role changes
+#[main_thread_system]
+fn show_host_crown(state: Res<HostState>, mut query: Query<(Ref<Player>, &mut GodotNodeHandle)>) {
+    let _timing = frame_budget::scope("show_host_crown");
+    for (player, mut handle) in query.iter_mut() {
+        if !state.is_changed() && !player.is_added() {
+            continue;
+        }
+        let Some(mut player_node) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+        let is_host = state.host == Some(player.0);
+        match player_node.get_node_or_null(CROWN_NAME) {
+            Some(crown) => {
+                if let Ok(mut crown) = crown.try_cast::<CanvasItem>() {
+                    crown.set_visible(is_host);
+                }
+            }
+            None if is_host => {
+                let mut label = Label::new_alloc();
+                label.set_name(CROWN_NAME);
+                label.set_text("👑");
+                label.set_position(Vector2::new(-8.0, -60.0));
+                player_node.add_child(&label);
+            }
+            None => {}
+        }
+    }
+}
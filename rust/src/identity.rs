@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fmt, fs, io};
+
+use bevy::prelude::*;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use godot::classes::ProjectSettings;
+use godot::prelude::*;
+use godot_bevy::prelude::*;
+use protocol::{LocalizedText, SignedJoin, texts};
+
+/// Where the client keeps its identity key, created on first start
+const IDENTITY_PATH: &str = "user://identity.key";
+
+/// How far a signed Join's timestamp may be from the server clock, in seconds.
+/// Nonces only need to be remembered for this long.
+pub const JOIN_SIGNATURE_WINDOW_SECS: u64 = 120;
+
+/// This client's identity key, None when it couldn't be loaded or created
+#[derive(Resource, Default)]
+pub struct LocalIdentity(pub Option<SigningKey>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentityError {
+    BadKey,
+    BadSignature,
+    Expired,
+    Replayed,
+}
+
+impl IdentityError {
+    /// Sent to the client, which shows it in its language
+    pub fn text(&self) -> LocalizedText {
+        LocalizedText::new(match self {
+            IdentityError::BadKey => texts::IDENTITY_BAD_KEY,
+            IdentityError::BadSignature => texts::IDENTITY_BAD_SIGNATURE,
+            IdentityError::Expired => texts::IDENTITY_EXPIRED,
+            IdentityError::Replayed => texts::IDENTITY_REPLAYED,
+        })
+    }
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.text().fmt(f)
+    }
+}
+
+/// The bytes covered by a Join signature
+fn join_payload(name: &str, issued_at: u64, nonce: u64) -> Vec<u8> {
+    let mut payload = b"join\0".to_vec();
+    payload.extend_from_slice(name.as_bytes());
+    payload.push(0);
+    payload.extend_from_slice(&issued_at.to_le_bytes());
+    payload.extend_from_slice(&nonce.to_le_bytes());
+    payload
+}
+
+pub fn sign_join(key: &SigningKey, name: &str, issued_at: u64) -> SignedJoin {
+    let nonce = rand::random();
+    let signature = key.sign(&join_payload(name, issued_at, nonce));
+    SignedJoin {
+        public_key: key.verifying_key().to_bytes(),
+        issued_at,
+        nonce,
+        signature: signature.to_bytes().to_vec(),
+    }
+}
+
+/// Check that `signed` was made by its key for a Join with `name`
+pub fn verify_join(name: &str, signed: &SignedJoin) -> Result<(), IdentityError> {
+    let key = VerifyingKey::from_bytes(&signed.public_key).map_err(|_| IdentityError::BadKey)?;
+    let signature =
+        Signature::from_slice(&signed.signature).map_err(|_| IdentityError::BadSignature)?;
+    key.verify_strict(
+        &join_payload(name, signed.issued_at, signed.nonce),
+        &signature,
+    )
+    .map_err(|_| IdentityError::BadSignature)
+}
+
+/// Signed Joins seen recently, so a captured one can't be sent again
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ReplayGuard {
+    /// (public key, nonce) to issue time
+    seen: HashMap<([u8; 32], u64), u64>,
+}
+
+impl ReplayGuard {
+    /// Accept a verified Join at most once, and only while it's fresh
+    pub fn check(&mut self, signed: &SignedJoin, now: u64) -> Result<(), IdentityError> {
+        if signed.issued_at.abs_diff(now) > JOIN_SIGNATURE_WINDOW_SECS {
+            return Err(IdentityError::Expired);
+        }
+        self.seen
+            .retain(|_, issued_at| issued_at.abs_diff(now) <= JOIN_SIGNATURE_WINDOW_SECS);
+        if self
+            .seen
+            .insert((signed.public_key, signed.nonce), signed.issued_at)
+            .is_some()
+        {
+            return Err(IdentityError::Replayed);
+        }
+        Ok(())
+    }
+}
+
+/// Read the key at `path`, or generate one and save it there
+pub fn load_or_create_key(path: &Path) -> io::Result<SigningKey> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            let bytes: [u8; 32] = bytes.try_into().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "identity key must be 32 bytes")
+            })?;
+            Ok(SigningKey::from_bytes(&bytes))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let key = SigningKey::from_bytes(&rand::random());
+            fs::write(path, key.to_bytes())?;
+            Ok(key)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[main_thread_system]
+pub fn load_local_identity(mut identity: ResMut<LocalIdentity>) {
+    let path = ProjectSettings::singleton()
+        .globalize_path(IDENTITY_PATH)
+        .to_string();
+    match load_or_create_key(Path::new(&path)) {
+        Ok(key) => identity.0 = Some(key),
+        Err(err) => godot_print!("Identity key unavailable ({}), joining unsigned", err),
+    }
+}
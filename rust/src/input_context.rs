@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+use godot::{
+    classes::{Control, Engine, LineEdit, SceneTree},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::frame_budget;
+
+/// What keyboard and controller input is for right now; the player only moves in
+/// `Gameplay`, so typing WASD in chat doesn't walk
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputContext {
+    #[default]
+    Gameplay,
+    /// A text field has focus, e.g. the chat input or the console
+    ChatFocused,
+    /// A menu is open, see `MenuOpen`
+    Menu,
+}
+
+/// Set from GDScript with `NetworkManagerNode.set_menu_open` while a menu is shown
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct MenuOpen(pub bool);
+
+pub struct InputContextPlugin;
+
+impl Plugin for InputContextPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputContext>()
+            .init_resource::<MenuOpen>()
+            .add_systems(Update, update_input_context);
+    }
+}
+
+/// A focused text field wins over an open menu, which wins over gameplay
+#[main_thread_system]
+fn update_input_context(menu: Res<MenuOpen>, mut context: ResMut<InputContext>) {
+    let _timing = frame_budget::scope("update_input_context");
+    let typing = Engine::singleton()
+        .get_main_loop()
+        .and_then(|main_loop| main_loop.try_cast::<SceneTree>().ok())
+        .and_then(|tree| tree.get_root())
+        .and_then(|root| root.gui_get_focus_owner())
+        .is_some_and(is_text_field);
+    let next = if typing {
+        InputContext::ChatFocused
+    } else if menu.0 {
+        InputContext::Menu
+    } else {
+        InputContext::Gameplay
+    };
+    context.set_if_neq(next);
+}
+
+fn is_text_field(control: Gd<Control>) -> bool {
+    match control.try_cast::<LineEdit>() {
+        Ok(line_edit) => line_edit.is_editable(),
+        Err(control) => control.is_class("TextEdit"),
+    }
+}
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use godot::{
+    classes::{IRichTextLabel, RichTextLabel},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::{ClientMessage, LeaderboardEntry};
+
+use crate::net_events::from_server;
+use crate::transport::{SendToServer, connection_ready};
+use crate::{chat, frame_budget};
+
+/// The server's best accounts, as last sent
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Leaderboard(pub Vec<LeaderboardEntry>);
+
+#[derive(Component, Default)]
+pub struct LeaderboardComp;
+
+/// Wins, playtime and messages of the server's best players. Asks for fresh numbers
+/// every time it's shown; `refresh()` asks again.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=RichTextLabel)]
+#[bevy_bundle((LeaderboardComp))]
+pub struct LeaderboardNode {
+    base: Base<RichTextLabel>,
+    /// Whether the current numbers were asked for since it was last shown
+    requested: bool,
+}
+
+#[godot_api]
+impl IRichTextLabel for LeaderboardNode {
+    fn init(base: Base<RichTextLabel>) -> Self {
+        Self {
+            base,
+            requested: false,
+        }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_use_bbcode(true);
+        self.base_mut().set_fit_content(true);
+    }
+}
+
+#[godot_api]
+impl LeaderboardNode {
+    #[func]
+    fn refresh(&mut self) {
+        self.requested = false;
+    }
+
+    fn show_entries(&mut self, entries: &[LeaderboardEntry]) {
+        let lines: Vec<String> = entries
+            .iter()
+            .zip(1..)
+            .map(|(entry, rank)| {
+                format!(
+                    "{}. {}  {} wins, {}h{:02}, {} messages",
+                    rank,
+                    chat::escape_bbcode(&entry.name),
+                    entry.wins,
+                    entry.playtime_secs / 3600,
+                    entry.playtime_secs / 60 % 60,
+                    entry.messages
+                )
+            })
+            .collect();
+        self.base_mut().set_text(&lines.join("\n"));
+    }
+}
+
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Leaderboard>().add_systems(
+            Update,
+            (
+                track_leaderboard,
+                request_leaderboards.run_if(connection_ready),
+                update_leaderboards.after(track_leaderboard),
+            ),
+        );
+    }
+}
+
+fn track_leaderboard(
+    mut events: EventReader<from_server::Leaderboard>,
+    mut leaderboard: ResMut<Leaderboard>,
+) {
+    if let Some(ev) = events.read().last() {
+        leaderboard.0 = ev.entries.clone();
+    }
+}
+
+/// One request for all the leaderboards that were just shown
+#[main_thread_system]
+fn request_leaderboards(
+    mut query: Query<&mut GodotNodeHandle, With<LeaderboardComp>>,
+    mut to_server: EventWriter<SendToServer>,
+) {
+    let _timing = frame_budget::scope("request_leaderboards");
+    let mut request = false;
+    for mut handle in query.iter_mut() {
+        let Some(mut node) = handle.try_get::<LeaderboardNode>() else {
+            continue;
+        };
+        let mut node = node.bind_mut();
+        let visible = node.base().is_visible_in_tree();
+        if visible && !node.requested {
+            request = true;
+        }
+        node.requested = visible;
+    }
+    if request {
+        to_server.write(SendToServer(ClientMessage::RequestLeaderboard {}));
+    }
+}
+
+#[main_thread_system]
+fn update_leaderboards(
+    leaderboard: Res<Leaderboard>,
+    mut query: Query<&mut GodotNodeHandle, With<LeaderboardComp>>,
+) {
+    let _timing = frame_budget::scope("update_leaderboards");
+    for mut handle in query.iter_mut() {
+        if !leaderboard.is_changed() && !handle.is_added() {
+            continue;
+        }
+        if let Some(mut node) = handle.try_get::<LeaderboardNode>() {
+            node.bind_mut().show_entries(&leaderboard.0);
+        }
+    }
+}
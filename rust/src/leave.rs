@@ -0,0 +1,106 @@
+//! Leaving a game on purpose, from a `LeaveButtonNode` or
+//! `NetworkManagerNode.disconnect_from_server`: tell the server, drop the connection and
+//! everything that came with it, and go back to the main menu.
+
+use bevy::prelude::*;
+use godot::{classes::Node, prelude::*};
+use godot_bevy::prelude::*;
+use protocol::ClientMessage;
+
+use crate::chat::{Chat, ChatScrollback, PendingChat};
+use crate::host::HostState;
+use crate::input_context::MenuOpen;
+use crate::leaderboard::Leaderboard;
+use crate::migration::SessionResume;
+use crate::platforms::Platforms;
+use crate::player::{Player, RemoteMoves};
+use crate::presence::CurrentServer;
+use crate::prop_sync::{Prop, PropAuthority, PropIndex};
+use crate::results::MatchResults;
+use crate::scoreboard::Pings;
+use crate::transport::{ClientTransport, ConnectionState, Outbox, Transport};
+use crate::{ServerPassword, SyncState, Users, frame_budget, ui};
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct LeaveGame;
+
+pub struct LeavePlugin;
+
+impl Plugin for LeavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LeaveGame>().add_systems(Update, leave_game);
+    }
+}
+
+#[main_thread_system]
+fn leave_game(
+    mut events: EventReader<LeaveGame>,
+    mut client: Transport,
+    mut connection: ResMut<ConnectionState>,
+    mut resume: ResMut<SessionResume>,
+    mut commands: Commands,
+) {
+    let _timing = frame_budget::scope("leave_game");
+    if events.read().count() == 0 {
+        return;
+    }
+    // Not coming back, so no resume attempt when the connection goes
+    resume.clear();
+    if client.is_connected() {
+        client.try_send(ClientMessage::Disconnect {});
+    }
+    client.close();
+    *connection = ConnectionState::Disconnected;
+    commands.queue(reset_session);
+    godot_print!("--- Left the game");
+}
+
+/// Despawn the players and props and free their nodes, and forget what the server told
+/// us, so the next game starts from scratch
+fn reset_session(world: &mut World) {
+    despawn_world(world);
+
+    let mut chats = world.query::<(&mut Chat, &mut ChatScrollback)>();
+    for (mut chat, mut scrollback) in chats.iter_mut(world) {
+        chat.messages.clear();
+        // Keep `rendered` so the cleared log is pushed to the label
+        scrollback.unread = 0;
+        scrollback.seen_len = 0;
+    }
+
+    world.resource_mut::<Outbox>().clear();
+    world.insert_resource(Users::default());
+    world.insert_resource(SyncState::default());
+    world.insert_resource(PendingChat::default());
+    world.insert_resource(ServerPassword::default());
+    world.insert_resource(CurrentServer::default());
+    world.insert_resource(HostState::default());
+    world.insert_resource(MenuOpen::default());
+    world.insert_resource(Pings::default());
+    world.insert_resource(Leaderboard::default());
+    world.insert_resource(MatchResults::default());
+    world.insert_resource(Platforms::default());
+    world.insert_resource(ui::ServerShutdown::default());
+    world.insert_resource(ui::ActiveVote::default());
+    world.insert_resource(ui::PendingInvite::default());
+}
+
+/// Despawn the players and props, free their nodes and forget their replication state.
+/// Also used before a full resync, which spawns them again.
+pub fn despawn_world(world: &mut World) {
+    let mut query =
+        world.query_filtered::<(Entity, &mut GodotNodeHandle), Or<(With<Player>, With<Prop>)>>();
+    let mut to_destroy = Vec::new();
+    for (entity, mut handle) in query.iter_mut(world) {
+        if let Some(mut node) = handle.try_get::<Node>() {
+            node.queue_free();
+        }
+        to_destroy.push(entity);
+    }
+    for entity in to_destroy {
+        world.despawn(entity);
+    }
+    world.insert_resource(RemoteMoves::default());
+    world.insert_resource(PropIndex::default());
+    world.insert_resource(PropAuthority::default());
+}
@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use godot::{
+    classes::{INode2D, Node2D, PackedScene, ResourceLoader},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::frame_budget;
+use crate::net_events::from_server;
+
+#[derive(Component, Default)]
+pub struct LevelComp;
+
+/// Holds the level scene the server picked, replaced when a level vote passes.
+/// Players are spawned outside of it, so they stay when the level changes.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Node2D)]
+#[bevy_bundle((LevelComp))]
+pub struct LevelNode {
+    base: Base<Node2D>,
+    /// Scene path of the loaded level, empty until the server sends one
+    #[var]
+    level: GString,
+}
+
+#[godot_api]
+impl INode2D for LevelNode {
+    fn init(base: Base<Node2D>) -> Self {
+        Self {
+            base,
+            level: GString::new(),
+        }
+    }
+}
+
+#[godot_api]
+impl LevelNode {
+    #[signal]
+    fn level_changed(level: GString);
+
+    fn load(&mut self, level: &str) {
+        if self.level.to_string() == level {
+            return;
+        }
+        let Some(scene) = ResourceLoader::singleton()
+            .load(level)
+            .and_then(|resource| resource.try_cast::<PackedScene>().ok())
+            .and_then(|scene| scene.instantiate())
+        else {
+            godot_print!("Could not load level {}", level);
+            return;
+        };
+        for mut child in self.base().get_children().iter_shared() {
+            child.queue_free();
+        }
+        self.base_mut().add_child(&scene);
+        self.level = GString::from(level);
+        let level = self.level.to_variant();
+        self.base_mut().emit_signal("level_changed", &[level]);
+    }
+}
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, load_levels);
+    }
+}
+
+#[main_thread_system]
+fn load_levels(
+    mut changes: EventReader<from_server::ChangeLevel>,
+    mut query: Query<&mut GodotNodeHandle, With<LevelComp>>,
+) {
+    let _timing = frame_budget::scope("load_levels");
+    let Some(change) = changes.read().last() else {
+        return;
+    };
+    godot_print!("Loading level {}", change.level);
+    for mut handle in query.iter_mut() {
+        if let Some(mut level) = handle.try_get::<LevelNode>() {
+            level.bind_mut().load(&change.level);
+        }
+    }
+}
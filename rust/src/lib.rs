@@ -1,29 +1,91 @@
-use std::{collections::HashMap, thread::sleep, time::Duration};
-
-use bevy::{app::ScheduleRunnerPlugin, prelude::*};
-use bevy_quinnet::{
-    client::{
-        QuinnetClient, QuinnetClientPlugin,
-        certificate::CertificateVerificationMode,
-        client_connected,
-        connection::{ClientEndpointConfiguration, ConnectionEvent, ConnectionFailedEvent},
-    },
-    shared::{ClientId, channels::ChannelsConfiguration},
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    thread::sleep,
+    time::{Duration, Instant},
 };
+
+use bevy::{app::ScheduleRunnerPlugin, ecs::system::SystemParam, prelude::*};
+use bevy_quinnet::shared::ClientId;
 use godot::prelude::*;
 use godot_bevy::prelude::*;
+use protocol::{AdminCommand, ClientMessage, LocalizedText, ServerMessage, texts};
 use rand::{Rng, distributions::Alphanumeric};
 use tokio::sync::mpsc;
 
-use crate::protocol::{ClientMessage, ServerMessage};
+use crate::transport::{
+    ClientTransport, SendToServer, Transport, TransportEvent, connection_ready,
+};
 
-use crate::chat::{Chat, ChatInput, ChatNode};
+use crate::chat::{
+    Chat, ChatCommand, ChatDisplayOptions, ChatInput, ChatLimits, ChatNode, ChatScrollback,
+    PendingChat,
+};
+use crate::net_handlers::{NetDispatch, NetHandlers};
+use crate::node_safety::{NodeHandleExt, NodeInvalidated};
 
+mod accounts;
+mod anomalies;
+mod audio;
+mod bench;
+mod bubbles;
+mod camera;
 mod chat;
+mod chat_log;
+#[cfg(not(feature = "websocket"))]
+mod connection_tester;
+mod console;
+mod crash;
+mod determinism;
+mod emotes;
+mod environment;
+mod feedback;
+mod flow;
+mod frame_budget;
+mod host;
+mod identity;
+mod input_context;
+mod leaderboard;
+mod leave;
+mod levels;
+mod lifecycle;
+mod localization;
+mod loopback;
+mod matches;
+mod migration;
+mod msg_trace;
+mod names;
+pub mod navigation;
+mod net_debug;
+pub mod net_events;
+pub mod net_handlers;
+mod net_send;
+mod net_stats;
+mod network_manager;
+mod node_safety;
+mod persistence;
+mod platforms;
 mod player;
-mod protocol;
+mod presence;
+mod prop_sync;
+mod props;
+pub mod query;
+mod rcon;
+mod reports;
+mod results;
+mod rooms;
+mod scoreboard;
+mod scripting;
+pub mod server;
+mod server_log;
+mod sessions;
+mod settings;
+mod shared_rng;
+mod spectator;
+mod state_check;
+mod transport;
 mod ui;
-mod server;
+mod votes;
+mod webhooks;
 
 use player::SpawnPlayerEvent;
 
@@ -31,59 +93,192 @@ use player::SpawnPlayerEvent;
 struct Users {
     self_id: ClientId,
     names: HashMap<ClientId, String>,
+    /// Set once InitClient has been processed
+    initialized: bool,
+    /// Last applied PlayerUpdate sequence number per remote client
+    last_update_seq: HashMap<ClientId, u32>,
+    /// Target of `/r`
+    last_whisper_from: Option<String>,
+    /// Friend account ids and whether they are online
+    friends: BTreeMap<String, bool>,
+    /// Invite code of the private room we're in, None in the lobby
+    room: Option<String>,
 }
 
+impl Users {
+    /// A player resumed its session under a new client id
+    fn migrate(&mut self, from: ClientId, to: ClientId) {
+        if let Some(name) = self.names.remove(&from) {
+            self.names.insert(to, name);
+        }
+        if let Some(seq) = self.last_update_seq.remove(&from) {
+            self.last_update_seq.insert(to, seq);
+        }
+    }
+}
+
+/// Progress of the initial world sync; gameplay is hidden and input isn't sent until Ready
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncState {
+    /// Not connected, or waiting for InitClient
+    #[default]
+    Connecting,
+    /// InitClient received, the world state is streaming in
+    Syncing,
+    Ready,
+}
+
+/// We moved to another room (None: the lobby)
+#[derive(Event, Debug, Clone)]
+pub struct RoomChanged {
+    pub code: Option<String>,
+}
+
+/// Limits how much of the receive queue `handle_server_messages` drains per frame.
+/// Whatever is left stays queued in the transport for the next frame.
+#[derive(Resource, Debug, Clone)]
+pub struct NetworkBudget {
+    pub max_messages_per_frame: usize,
+    pub max_time_per_frame: Duration,
+}
+
+impl Default for NetworkBudget {
+    fn default() -> Self {
+        Self {
+            max_messages_per_frame: 256,
+            max_time_per_frame: Duration::from_millis(4),
+        }
+    }
+}
+
+/// Name to join with, random when unset
+#[derive(Resource, Debug, Clone, Default)]
+pub struct LocalUsername(pub Option<String>);
+
+/// Password sent with Join, for servers that have one; set by the host dialog
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ServerPassword(pub Option<String>);
+
 #[derive(Resource, Deref, DerefMut)]
 pub struct ChatReceiver(mpsc::Receiver<String>);
 
-#[derive(Event)]
+#[derive(Event, Clone)]
 pub struct ChatMessage {
+    /// The player who said it, None for local and system lines
+    pub client_id: Option<ClientId>,
     pub username: String,
     pub message: String,
 }
 
 #[bevy_app]
 fn build_app(app: &mut App) {
+    app.insert_resource(crash::install_panic_hook());
     app.add_plugins(GodotDefaultPlugins);
+    app.add_plugins(net_events::from_server::EventsPlugin);
+    app.add_plugins(platforms::PlatformPlugin);
+    app.add_plugins(chat_log::ChatLogPlugin);
+    app.add_plugins((
+        levels::LevelPlugin,
+        localization::LocalizationPlugin,
+        settings::SettingsPlugin,
+        host::HostPlugin,
+        spectator::SpectatorPlugin,
+        camera::CameraFollowPlugin,
+        feedback::FeedbackPlugin,
+        net_send::NetSendPlugin,
+        input_context::InputContextPlugin,
+        flow::FlowPlugin,
+        leave::LeavePlugin,
+        scoreboard::ScoreboardPlugin,
+        state_check::StateCheckPlugin,
+        leaderboard::LeaderboardPlugin,
+        results::ResultsPlugin,
+    ));
 
     app.add_plugins((
         ScheduleRunnerPlugin::default(),
-        QuinnetClientPlugin::default(),
+        transport::TransportPlugin,
+        net_stats::NetworkStatsPlugin,
         player::PlayerPlugin,
+        audio::AudioPlugin,
+        emotes::EmotePlugin,
+        bubbles::BubblePlugin,
+        environment::EnvironmentPlugin,
+        prop_sync::PropPlugin,
+        presence::PresencePlugin,
+        migration::MigrationPlugin,
+        lifecycle::LifecyclePlugin,
+        // Debug tooling; nested because plugin tuples stop at 15
+        (
+            console::ConsolePlugin,
+            net_debug::NetDebugPlugin,
+            msg_trace::MessageTracePlugin,
+            frame_budget::FrameBudgetPlugin,
+        ),
+        network_manager::NetworkManagerPlugin,
+        node_safety::NodeSafetyPlugin,
     ))
     .insert_resource(Users::default())
+    .init_resource::<LocalUsername>()
+    .init_resource::<ServerPassword>()
+    .init_resource::<NetHandlers<ServerMessage>>()
+    .init_resource::<NetworkBudget>()
+    .init_resource::<ChatLimits>()
+    .init_resource::<PendingChat>()
+    .init_resource::<ChatDisplayOptions>()
+    .init_resource::<ui::ServerShutdown>()
+    .init_resource::<ui::PendingInvite>()
+    .init_resource::<ui::ActiveVote>()
+    .init_resource::<ui::JoinAttempt>()
+    .init_resource::<shared_rng::SharedRng>()
+    .init_resource::<SyncState>()
+    .init_resource::<identity::LocalIdentity>()
     .add_systems(
         Startup,
-        (hello_world, start_chat_listener, ui::start_ui_listener),
+        (
+            hello_world,
+            start_chat_listener,
+            ui::start_ui_listener,
+            chat::init_chat_display_options,
+            identity::load_local_identity,
+        ),
     )
     .add_systems(
         Update,
         (
             handle_client_events,
-            (handle_terminal_messages, handle_server_messages).run_if(client_connected),
+            (handle_terminal_messages, handle_server_messages).run_if(connection_ready),
             chat::read_chat_messages,
+            chat::expire_pending_chat,
             handle_chat_sync,
             ui::handle_ui_commands,
+            ui::advance_join_attempt.after(ui::handle_ui_commands),
+            ui::update_join_dialogs.after(ui::advance_join_attempt),
+            ui::emit_session_signals.after(ui::advance_join_attempt),
+            ui::update_shutdown_banner,
+            ui::show_toasts,
+            ui::update_loading_bar,
+            ui::update_loading_overlay,
+            ui::track_votes,
+            ui::update_vote_nodes.after(ui::track_votes),
         ),
     )
+    .add_systems(First, notify_server_after_panic)
     .add_systems(PostUpdate, on_app_exit);
 
     app.add_event::<ChatMessage>();
+    app.add_event::<ui::NoticeReceived>();
+    app.add_event::<ui::SyncProgress>();
+    app.add_event::<RoomChanged>();
 }
 
 fn hello_world() {
     godot::prelude::godot_print!("Hello from godot-bevy!");
 }
 
-fn start_connection(mut client: ResMut<QuinnetClient>) {
+fn start_connection(mut client: Transport) {
     godot_print!("Starting connection");
-    client
-        .open_connection(
-            ClientEndpointConfiguration::from_strings("[::1]:6000", "[::]:0").unwrap(),
-            CertificateVerificationMode::SkipVerification,
-            ChannelsConfiguration::default(),
-        )
-        .unwrap();
+    client.open("[::1]:6000").unwrap();
 }
 
 fn start_chat_listener(mut commands: Commands) {
@@ -102,12 +297,38 @@ fn start_chat_listener(mut commands: Commands) {
 
 #[main_thread_system]
 fn handle_chat_sync(
-    mut query: Query<(Entity, &mut GodotNodeHandle, &mut Chat), With<RichTextLabelMarker>>,
+    mut query: Query<
+        (Entity, &mut GodotNodeHandle, &Chat, &mut ChatScrollback),
+        With<RichTextLabelMarker>,
+    >,
     mut _events: EventReader<ChatMessage>,
+    mut invalidated: EventWriter<NodeInvalidated>,
+    pending: Res<PendingChat>,
 ) {
-    for (_, mut handle, chat) in query.iter_mut() {
-        let mut rich_text_label = handle.get::<ChatNode>();
-        rich_text_label.set_text(&chat.messages.join("\n"));
+    let _timing = frame_budget::scope("handle_chat_sync");
+    for (entity, mut handle, chat, mut scrollback) in query.iter_mut() {
+        let Some(mut chat_node) = handle.get_or_invalidate::<ChatNode>(entity, &mut invalidated)
+        else {
+            continue;
+        };
+        let mut chat_node = chat_node.bind_mut();
+        let at_bottom = chat_node.is_at_bottom();
+
+        let new_lines = chat.messages.len().saturating_sub(scrollback.seen_len);
+        scrollback.seen_len = chat.messages.len();
+
+        let rendered = pending.render(&chat.messages);
+        if rendered != scrollback.rendered {
+            chat_node.sync_text(&rendered, at_bottom);
+            scrollback.rendered = rendered;
+        }
+
+        if at_bottom {
+            scrollback.unread = 0;
+        } else {
+            scrollback.unread += new_lines;
+        }
+        chat_node.show_unread(scrollback.unread);
     }
     _events.clear();
 }
@@ -115,179 +336,463 @@ fn handle_chat_sync(
 fn handle_terminal_messages(
     mut terminal_messages: ResMut<ChatReceiver>,
     mut app_exit_events: EventWriter<AppExit>,
-    mut client: ResMut<QuinnetClient>,
+    mut to_server: EventWriter<SendToServer>,
+    mut pending: ResMut<PendingChat>,
+    users: Res<Users>,
+    limits: Res<ChatLimits>,
+    mut commands: Commands,
 ) {
     while let Ok(message) = terminal_messages.try_recv() {
         godot_print!("{}", message);
         if message == "quit" {
             app_exit_events.write(AppExit::Success);
         } else {
-            client
-                .connection_mut()
-                .try_send_message(ClientMessage::ChatMessage { message: message });
+            send_chat(
+                &mut commands,
+                &mut to_server,
+                &mut pending,
+                &users,
+                limits.apply(&message),
+            );
+        }
+    }
+}
+
+/// Send a chat message and show it right away. The server echo carries the same
+/// `local_id` and replaces this entry instead of adding a second one.
+/// Slash commands (see `ChatCommand`) are handled here too.
+fn send_chat(
+    commands: &mut Commands,
+    to_server: &mut EventWriter<SendToServer>,
+    pending: &mut PendingChat,
+    users: &Users,
+    message: String,
+) {
+    let command = match ChatCommand::parse(&message) {
+        Ok(command) => command,
+        Err(usage) => {
+            show_local_line(commands, format!("* {}", usage));
+            return;
+        }
+    };
+    let (whisper_to, text) = match command {
+        ChatCommand::Say(text) => (None, text),
+        ChatCommand::Whisper { to, text } => (Some(to), text),
+        ChatCommand::Reply(text) => match users.last_whisper_from.clone() {
+            Some(to) => (Some(to), text),
+            None => {
+                show_local_line(commands, "* Nobody to reply to".to_string());
+                return;
+            }
+        },
+        ChatCommand::AddFriend(friend_id) => {
+            to_server.write(SendToServer(ClientMessage::AddFriend { friend_id }));
+            return;
+        }
+        ChatCommand::RemoveFriend(friend_id) => {
+            to_server.write(SendToServer(ClientMessage::RemoveFriend { friend_id }));
+            return;
+        }
+        ChatCommand::JoinFriend(friend_id) => {
+            to_server.write(SendToServer(ClientMessage::JoinFriend { friend_id }));
+            return;
+        }
+        ChatCommand::CreateRoom => {
+            to_server.write(SendToServer(ClientMessage::CreateRoom {}));
+            return;
+        }
+        ChatCommand::JoinRoom(code) => {
+            to_server.write(SendToServer(ClientMessage::JoinRoom { code }));
+            return;
+        }
+        ChatCommand::LeaveRoom => {
+            to_server.write(SendToServer(ClientMessage::LeaveRoom {}));
+            return;
+        }
+        ChatCommand::StartVote(kind) => {
+            to_server.write(SendToServer(ClientMessage::StartVote { kind }));
+            return;
+        }
+        ChatCommand::CastVote(yes) => {
+            to_server.write(SendToServer(ClientMessage::CastVote { yes }));
+            return;
+        }
+        ChatCommand::Admin(command) => {
+            to_server.write(SendToServer(ClientMessage::Admin { command }));
+            return;
+        }
+        ChatCommand::SaveChat => {
+            commands.send_event(chat_log::SaveChatLog);
+            return;
+        }
+        ChatCommand::Report { name, reason } => {
+            to_server.write(SendToServer(ClientMessage::Report { name, reason }));
+            return;
+        }
+        ChatCommand::TransferHost(name) => {
+            let to = users
+                .names
+                .iter()
+                .find(|(_, username)| username.to_lowercase() == name.to_lowercase())
+                .map(|(client_id, _)| *client_id);
+            match to {
+                Some(to) => {
+                    to_server.write(SendToServer(ClientMessage::Admin {
+                        command: AdminCommand::TransferHost { to },
+                    }));
+                }
+                None => show_local_line(commands, format!("* No player named {}", name)),
+            }
+            return;
+        }
+        ChatCommand::ListFriends => {
+            let line = if users.friends.is_empty() {
+                "* No friends yet, add one with /friend add <name>".to_string()
+            } else {
+                let friends: Vec<String> = users
+                    .friends
+                    .iter()
+                    .map(|(friend_id, online)| {
+                        let status = if *online { "online" } else { "offline" };
+                        format!("{} ({})", chat::escape_bbcode(friend_id), status)
+                    })
+                    .collect();
+                format!("* Friends: {}", friends.join(", "))
+            };
+            show_local_line(commands, line);
+            return;
+        }
+    };
+
+    let line = match &whisper_to {
+        Some(to) => chat::whisper_line("To", to, &text),
+        None => {
+            let username = users
+                .names
+                .get(&users.self_id)
+                .cloned()
+                .unwrap_or_else(|| "me".to_string());
+            chat::chat_line(&username, &text)
+        }
+    };
+    let local_id = pending.insert(line.clone());
+    to_server.write(SendToServer(match whisper_to {
+        Some(to) => ClientMessage::Whisper { to, text, local_id },
+        None => ClientMessage::ChatMessage {
+            message: text,
+            local_id,
+        },
+    }));
+    show_local_line(commands, line);
+}
+
+/// Destroy the player entity for this client and free its node
+fn despawn_player(world: &mut World, client_id: ClientId) {
+    let mut to_destroy = Vec::new();
+
+    // First, find all player node handles associated with this client ID
+    let mut query = world.query::<(&player::Player, &mut GodotNodeHandle, Entity)>();
+    for (player, mut handle, entity) in query.iter_mut(world) {
+        if player.0 == client_id {
+            godot_print!("Destroying player entity for client: {}", client_id);
+
+            // Free the Godot node
+            if let Some(mut player_node) = handle.try_get::<player::PlayerNode>() {
+                player_node.queue_free();
+                godot_print!("Queued Godot player node for freeing");
+            }
+
+            // Mark this entity for destruction
+            to_destroy.push(entity);
+        }
+    }
+
+    // Now destroy all marked entities
+    for entity in to_destroy {
+        world.despawn(entity);
+    }
+}
+
+/// Add a line to the chat logs without notifying listeners, for feedback only we see
+fn show_local_line(commands: &mut Commands, line: String) {
+    commands.queue(move |world: &mut World| {
+        let mut chat_node = world.query::<&mut Chat>();
+        for mut chat_node in chat_node.iter_mut(world) {
+            chat_node.messages.push(line.clone());
+        }
+    });
+}
+
+/// What goes into a Join besides the name
+#[derive(SystemParam)]
+struct JoinCredentials<'w> {
+    identity: Res<'w, identity::LocalIdentity>,
+    password: Res<'w, ServerPassword>,
+}
+
+impl JoinCredentials<'_> {
+    /// Signed with our identity key when we have one
+    fn join_message(&self, name: String) -> ClientMessage {
+        let identity = self
+            .identity
+            .0
+            .as_ref()
+            .map(|key| identity::sign_join(key, &name, persistence::unix_now()));
+        ClientMessage::Join {
+            name,
+            identity,
+            password: self.password.0.clone(),
         }
     }
 }
 
+fn random_username() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(7)
+        .map(char::from)
+        .collect()
+}
+
 fn handle_client_events(
-    mut connection_events: EventReader<ConnectionEvent>,
-    mut connection_failed_events: EventReader<ConnectionFailedEvent>,
-    mut client: ResMut<QuinnetClient>,
-    mut commands: Commands,
+    mut transport_events: EventReader<TransportEvent>,
+    mut to_server: EventWriter<SendToServer>,
+    local_username: Res<LocalUsername>,
+    mut shutdown: ResMut<ui::ServerShutdown>,
+    mut pending_invite: ResMut<ui::PendingInvite>,
+    mut sync: ResMut<SyncState>,
+    credentials: JoinCredentials,
+    resume: Res<migration::SessionResume>,
 ) {
-    if !connection_events.is_empty() {
+    let mut connected = false;
+    for ev in transport_events.read() {
+        match ev {
+            TransportEvent::Connected => connected = true,
+            TransportEvent::ConnectionFailed(err) => {
+                godot::prelude::godot_print!(
+                    "Failed to connect: {}, make sure the chat-server is running.",
+                    err
+                );
+            }
+            TransportEvent::Disconnected => {
+                godot::prelude::godot_print!("Connection to the server lost");
+                shutdown.deadline = None;
+                *sync = SyncState::Connecting;
+            }
+        }
+    }
+    if connected && resume.is_resuming() {
+        // Same session, the server remembers our name and room
+        if let Some(token) = resume.token {
+            to_server.write(SendToServer(ClientMessage::Resume { token }));
+        }
+    } else if connected {
         // We are connected
-        let username: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(7)
-            .map(char::from)
-            .collect();
+        let username = local_username.0.clone().unwrap_or_else(random_username);
 
         godot::prelude::godot_print!("--- Joining with name: {}", username);
         godot::prelude::godot_print!("--- Type 'quit' to disconnect");
 
-        client
-            .connection_mut()
-            .send_message(ClientMessage::Join { name: username })
-            .unwrap();
-
-        // Remove the UI now that we are connected
-        commands.queue(|world: &mut World| {
-            let mut to_destroy: Vec<Entity> = Vec::new();
-            let mut query = world.query::<(&mut GodotNodeHandle, Entity)>();
-            for (mut handle, entity) in query.iter_mut(world) {
-                let mut removed_any = false;
-                if let Some(mut node) = handle.try_get::<ui::HostButtonNode>() {
-                    node.queue_free();
-                    removed_any = true;
-                }
-                if let Some(mut node) = handle.try_get::<ui::JoinButtonNode>() {
-                    node.queue_free();
-                    removed_any = true;
-                }
-                if removed_any {
-                    to_destroy.push(entity);
-                }
-            }
-            for e in to_destroy {
-                world.despawn(e);
-            }
-        });
-
-        connection_events.clear();
-    }
-    for ev in connection_failed_events.read() {
-        godot::prelude::godot_print!(
-            "Failed to connect: {:?}, make sure the chat-server is running.",
-            ev.err
-        );
+        // Queued until the connection is ready for sends
+        to_server.write(SendToServer(credentials.join_message(username)));
+        if let Some(code) = pending_invite.0.take() {
+            to_server.write(SendToServer(ClientMessage::JoinRoom { code }));
+        }
+        // The Host/Join buttons are on the main menu screen, hidden by the flow state
     }
 }
 
 fn handle_server_messages(
     mut users: ResMut<Users>,
-    mut client: ResMut<QuinnetClient>,
+    mut client: Transport,
+    budget: Res<NetworkBudget>,
+    mut to_server: EventWriter<SendToServer>,
+    mut pre_init: Local<VecDeque<ServerMessage>>,
+    mut chunks: Local<protocol::ChunkAssembly>,
+    mut sync: ResMut<SyncState>,
+    mut shutdown: ResMut<ui::ServerShutdown>,
+    mut pending: ResMut<PendingChat>,
+    mut format: chat::ChatFormat,
+    mut remote_moves: ResMut<player::RemoteMoves>,
+    credentials: JoinCredentials,
+    mut resume: ResMut<migration::SessionResume>,
+    mut trace: ResMut<msg_trace::MessageTrace>,
+    mut net_handlers: NetDispatch<ServerMessage>,
     mut commands: Commands,
 ) {
-    while let Some((_, message)) = client
-        .connection_mut()
-        .try_receive_message::<ServerMessage>()
-    {
+    let deadline = Instant::now() + budget.max_time_per_frame;
+    let mut remaining = budget.max_messages_per_frame;
+    while remaining > 0 && Instant::now() < deadline {
+        // Replay what arrived before InitClient first, in arrival order
+        let message = if users.initialized && !pre_init.is_empty() {
+            pre_init.pop_front()
+        } else {
+            let message = client.receive();
+            if let Some(message) = &message {
+                trace.record(msg_trace::Direction::Received, message);
+            }
+            message
+        };
+        let Some(message) = message else {
+            break;
+        };
+        remaining -= 1;
+
+        // Reassemble chunked messages, then handle them like any other
+        let message = match message {
+            ServerMessage::InitBegin {
+                total_len,
+                chunk_count,
+            } => {
+                chunks.begin(total_len, chunk_count);
+                continue;
+            }
+            ServerMessage::InitChunk { index, data } => {
+                if let Err(err) = chunks.push(index, &data) {
+                    warn!("Dropping chunked transfer: {}", err);
+                }
+                continue;
+            }
+            ServerMessage::InitEnd {} => match chunks.finish() {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!("Could not reassemble chunked message: {}", err);
+                    continue;
+                }
+            },
+            message => message,
+        };
+
+        // Until InitClient is processed we don't know our own id, so we can't tell
+        // whether a ClientConnected is about us or someone else
+        if !users.initialized
+            && !matches!(
+                message,
+                ServerMessage::InitClient { .. }
+                    | ServerMessage::Pong { .. }
+                    | ServerMessage::NameAssigned { .. }
+                    | ServerMessage::NameRejected { .. }
+                    | ServerMessage::JoinRejected { .. }
+                    | ServerMessage::ShuttingDown { .. }
+                    | ServerMessage::SystemNotice { .. }
+            )
+        {
+            pre_init.push_back(message);
+            continue;
+        }
+
+        net_handlers.dispatch(&message);
         match message {
             ServerMessage::ClientConnected {
                 client_id,
                 username,
+                x,
+                y,
+                cosmetics,
             } => {
                 info!("{} joined", username);
                 users.names.insert(client_id, username.clone());
 
                 // Only spawn players for other clients (not ourselves)
-                // Our own player will be spawned in the InitClient handler
-                if client_id != users.self_id {
+                // Our own player is spawned from the WorldPlayers stream.
+                // Players join in the lobby, so they're only seen from there.
+                if client_id != users.self_id && users.room.is_none() {
                     godot_print!("Sending spawn event for remote client ID: {:?}", client_id);
                     commands.send_event(SpawnPlayerEvent {
                         client_id,
-                        position: None, // Use default position in scene
+                        position: Some(Vector2::new(x, y)),
+                        cosmetics,
                     });
                 }
 
+                let joined = LocalizedText::new(texts::PLAYER_JOINED);
+                let line = format
+                    .translations
+                    .localize(&joined.clone().arg(chat::escape_bbcode(&username)));
+                let message = format.translations.localize(&joined.arg(&username));
                 commands.queue(move |world: &mut World| {
                     let mut chat_node = world.query::<&mut Chat>();
                     for mut chat_node in chat_node.iter_mut(world) {
-                        chat_node.messages.push(format!("{} joined", username));
+                        chat_node.messages.push(line.clone());
                     }
                     // Send event to sync chat
                     world.send_event(ChatMessage {
+                        client_id: None,
                         username: username.clone(),
-                        message: format!("{} joined", username),
+                        message,
                     });
                 });
             }
             ServerMessage::ClientDisconnected { client_id } => {
+                users.last_update_seq.remove(&client_id);
                 if let Some(username) = users.names.remove(&client_id) {
                     godot::prelude::godot_print!("{} left", username.clone());
+                    let left = LocalizedText::new(texts::PLAYER_LEFT);
+                    let line = format
+                        .translations
+                        .localize(&left.clone().arg(chat::escape_bbcode(&username)));
+                    let message = format.translations.localize(&left.arg(&username));
                     commands.queue(move |world: &mut World| {
                         // Update chat
                         let mut chat_node = world.query::<&mut Chat>();
                         for mut chat_node in chat_node.iter_mut(world) {
-                            chat_node.messages.push(format!("{} left", username));
+                            chat_node.messages.push(line.clone());
                         }
                         // Send event to sync chat
                         world.send_event(ChatMessage {
+                            client_id: None,
                             username: username.clone(),
-                            message: format!("{} left", username),
+                            message,
                         });
 
-                        // Find and destroy the player entity for this client
-                        let mut to_destroy = Vec::new();
-
-                        // First, find all player node handles associated with this client ID
-                        let mut query =
-                            world.query::<(&player::Player, &mut GodotNodeHandle, Entity)>();
-                        for (player, mut handle, entity) in query.iter_mut(world) {
-                            if player.0 == client_id {
-                                godot_print!(
-                                    "Destroying player entity for disconnected client: {}",
-                                    client_id
-                                );
-
-                                // Free the Godot node
-                                if let Some(mut player_node) =
-                                    handle.try_get::<player::PlayerNode>()
-                                {
-                                    player_node.queue_free();
-                                    godot_print!("Queued Godot player node for freeing");
-                                }
-
-                                // Mark this entity for destruction
-                                to_destroy.push(entity);
-                            }
-                        }
-
-                        // Now destroy all marked entities
-                        for entity in to_destroy {
-                            world.despawn(entity);
-                        }
+                        despawn_player(world, client_id);
                     });
                 } else {
                     warn!("ClientDisconnected for an unknown client_id: {}", client_id);
                 }
             }
-            ServerMessage::ChatMessage { client_id, message } => {
+            ServerMessage::PlayerLeftRoom { client_id } => {
+                // Still connected, only out of sight: keep its name for chat and whispers
+                godot_print!("Client {} is no longer in our room", client_id);
+                commands.queue(move |world: &mut World| despawn_player(world, client_id));
+            }
+            ServerMessage::ChatMessage {
+                client_id,
+                message,
+                local_id,
+                sent_at,
+            } => {
                 if let Some(username) = users.names.get(&client_id) {
                     let username = username.clone(); // Clone here to own the data
-                    if client_id != users.self_id {
+                    // Our own message is already shown, the echo only confirms it
+                    let local_line = if client_id == users.self_id {
+                        pending.take(local_id)
+                    } else {
                         godot::prelude::godot_print!("{}: {}", username, message);
-                    }
+                        None
+                    };
+                    let lines = format
+                        .display
+                        .decorate(sent_at, &chat::chat_line(&username, &message));
                     commands.queue(move |world: &mut World| {
                         let mut chat_node = world.query::<&mut Chat>();
                         for mut chat_node in chat_node.iter_mut(world) {
-                            chat_node
-                                .messages
-                                .push(format!("{}: {}", username, message));
+                            match &local_line {
+                                // Keep the position of the optimistic entry, it was
+                                // shown moments ago so no day separator is needed
+                                Some(local_line) => chat_node.replace_last(
+                                    local_line,
+                                    lines.last().cloned().unwrap_or_default(),
+                                ),
+                                None => chat_node.messages.extend(lines.iter().cloned()),
+                            }
                         }
                         // Send event to sync chat
-                        world.send_event(ChatMessage { username, message });
+                        world.send_event(ChatMessage {
+                            client_id: Some(client_id),
+                            username,
+                            message,
+                        });
                     });
                 } else {
                     warn!("Chat message from an unknown client_id: {}", client_id)
@@ -295,86 +800,345 @@ fn handle_server_messages(
             }
             ServerMessage::InitClient {
                 client_id,
-                usernames,
+                rng_seed,
             } => {
                 godot_print!("Setting self_id to: {:?}", client_id);
+                commands.insert_resource(shared_rng::SharedRng::new(rng_seed));
                 users.self_id = client_id;
-                users.names = usernames;
-
-                // Spawn player for self after we've received our own client_id
-                godot_print!(
-                    "Sending spawn event for local player with client ID: {:?}",
-                    client_id
-                );
-                commands.send_event(SpawnPlayerEvent {
-                    client_id,
-                    position: None, // Use default position in scene
-                });
-
-                // Spawn all other existing players
-                for &other_client_id in users.names.keys() {
-                    // Don't spawn our own player twice
-                    if other_client_id != client_id {
-                        godot_print!(
-                            "Spawning existing player with client ID: {:?}",
-                            other_client_id
-                        );
-                        commands.send_event(SpawnPlayerEvent {
-                            client_id: other_client_id,
-                            position: None, // Use default position in scene
-                        });
-                    }
+                users.initialized = true;
+                *sync = SyncState::Syncing;
+            }
+            ServerMessage::WorldPlayers { players } => {
+                // Our own player comes in the stream too
+                for player in players {
+                    godot_print!(
+                        "Spawning existing player with client ID: {:?}",
+                        player.client_id
+                    );
+                    users.names.insert(player.client_id, player.username);
+                    commands.send_event(SpawnPlayerEvent {
+                        client_id: player.client_id,
+                        position: Some(Vector2::new(player.x, player.y)),
+                        cosmetics: player.cosmetics,
+                    });
+                }
+            }
+            ServerMessage::ChatBacklog { records } => {
+                for record in records {
+                    let line = chat::chat_line(&record.username, &record.message);
+                    let lines = format.display.decorate(record.sent_at, &line);
+                    queue_chat_lines(&mut commands, record.username, record.message, lines);
                 }
             }
+            ServerMessage::SyncProgress { done, total } => {
+                commands.send_event(ui::SyncProgress { done, total });
+            }
+            ServerMessage::SyncComplete {} => {
+                godot_print!("World state fully received");
+                *sync = SyncState::Ready;
+            }
             ServerMessage::PlayerUpdate {
                 client_id,
+                seq,
                 x,
                 y,
                 horizontal,
                 vertical,
             } => {
-                let player_id = users.self_id.clone();
-                commands.queue(move |world: &mut World| {
-                    // query the player node by client_id
-                    let mut player_query = world.query::<&mut GodotNodeHandle>();
-                    for mut handle in player_query.iter_mut(world) {
-                        let player_node = handle.try_get::<player::PlayerNode>();
-                        if player_node.is_none() {
-                            continue;
-                        }
-                        let mut player_node = player_node.unwrap();
-
-                        // Only update remote players - never override local player position
-                        if player_node.bind().client_id == client_id as u32
-                            && client_id != player_id
-                        {
-                            // First, check if position is significantly different (to prevent small jitters)
-                            let current_pos = player_node.get_position();
-                            let distance =
-                                ((current_pos.x - x).powi(2) + (current_pos.y - y).powi(2)).sqrt();
-                            // Only update if there's a significant change (more than 2 pixels)
-                            if distance > 2.0 {
-                                player_node.set_position(Vector2::new(x, y));
-                            }
-                        }
+                if let Some(&last) = users.last_update_seq.get(&client_id) {
+                    if !protocol::is_newer_seq(seq, last) {
+                        // Arrived out of order, applying it would rubber-band the player
+                        continue;
                     }
-                    world.send_event(player::PlayerInputEvent {
+                }
+                users.last_update_seq.insert(client_id, seq);
+                // Never override the local player position or input, our own
+                // player is simulated from the input read every tick
+                if client_id != users.self_id {
+                    remote_moves.0.insert(client_id, Vector2::new(x, y));
+                    commands.send_event(player::PlayerInputEvent {
                         client_id,
                         horizontal,
                         vertical,
+                        received: Some(Instant::now()),
                     });
+                }
+            }
+            ServerMessage::Pong { .. } => {}
+            ServerMessage::VoteEnded { kind, passed } => {
+                let key = if passed {
+                    texts::VOTE_PASSED
+                } else {
+                    texts::VOTE_FAILED
+                };
+                let kind = format.translations.localize(&kind.text());
+                let text = format
+                    .translations
+                    .localize(&LocalizedText::new(key).arg(kind));
+                show_local_line(
+                    &mut commands,
+                    chat::notice_line(protocol::NoticeLevel::Info, &text),
+                );
+            }
+            // Read from their events by `ui::track_votes`, `levels::load_levels`,
+            // `host::track_host`, `feedback::play_feedback`, `scoreboard::track_pings`,
+            // `player::apply_teleports`, `player::update_cosmetics`,
+            // `state_check::verify_state_checksum`, `leaderboard::track_leaderboard`,
+            // `results::track_results`, `platforms::track_platforms` and
+            // `state_check::rebuild_on_world_reset`
+            ServerMessage::VoteProgress { .. }
+            | ServerMessage::ChangeLevel { .. }
+            | ServerMessage::HostChanged { .. }
+            | ServerMessage::Paused { .. }
+            | ServerMessage::FeedbackEvent { .. }
+            | ServerMessage::PlayerPings { .. }
+            | ServerMessage::Teleport { .. }
+            | ServerMessage::StateChecksum { .. }
+            | ServerMessage::CosmeticsChanged { .. }
+            | ServerMessage::Leaderboard { .. }
+            | ServerMessage::MatchResults { .. }
+            | ServerMessage::MatchStarted { .. }
+            | ServerMessage::Platforms { .. }
+            | ServerMessage::WorldReset {} => {}
+            ServerMessage::SessionToken { token } => resume.token = Some(token),
+            ServerMessage::Resumed {
+                client_id,
+                previous_client_id,
+            } => {
+                godot_print!("--- Session resumed");
+                users.self_id = client_id;
+                users.migrate(previous_client_id, client_id);
+                commands.send_event(player::PlayerMigrated {
+                    from: previous_client_id,
+                    to: client_id,
                 });
+                resume.deadline = None;
+                // We're running again if we got here, the server may still think we're away
+                to_server.write(SendToServer(ClientMessage::SetAway { away: false }));
+                commands.send_event(migration::MigrationEvent::Resumed);
+            }
+            ServerMessage::ResumeFailed {} => {
+                let name = users
+                    .names
+                    .get(&users.self_id)
+                    .cloned()
+                    .unwrap_or_else(random_username);
+                godot_print!("Session expired, joining again as {}", name);
+                resume.clear();
+                commands.send_event(migration::MigrationEvent::Failed);
+                to_server.write(SendToServer(credentials.join_message(name)));
+            }
+            ServerMessage::PlayerAway { client_id, away } => {
+                commands.send_event(lifecycle::PlayerAway { client_id, away });
             }
+            ServerMessage::ClientMigrated { from, to } => {
+                users.migrate(from, to);
+                commands.send_event(player::PlayerMigrated { from, to });
+            }
+            // Consumed by the reassembly above
+            ServerMessage::InitBegin { .. }
+            | ServerMessage::InitChunk { .. }
+            | ServerMessage::InitEnd {} => {}
+            ServerMessage::ShuttingDown { seconds } => {
+                let text = if seconds == 0 {
+                    LocalizedText::new(texts::SHUTTING_DOWN)
+                } else {
+                    LocalizedText::new(texts::SHUTTING_DOWN_IN).arg(seconds)
+                };
+                let line = format!("* {}", format.translations.localize(&text));
+                godot_print!("{}", line);
+                shutdown.deadline = Some(Instant::now() + Duration::from_secs(seconds as u64));
+                queue_chat_lines(&mut commands, String::new(), line.clone(), vec![line]);
+            }
+            ServerMessage::StateRestored { saved_at } => {
+                let age = persistence::unix_now().saturating_sub(saved_at);
+                let text = LocalizedText::new(texts::STATE_RESTORED).arg(age / 60);
+                let line = format!("* {}", format.translations.localize(&text));
+                queue_chat_lines(&mut commands, String::new(), line.clone(), vec![line]);
+            }
+            ServerMessage::NameAssigned { name } => {
+                godot_print!("--- Joined as: {}", name);
+            }
+            ServerMessage::NameRejected { reason } => {
+                let fallback = random_username();
+                godot_print!(
+                    "Name rejected ({}), joining as {}",
+                    format.translations.localize(&reason),
+                    fallback
+                );
+                to_server.write(SendToServer(credentials.join_message(fallback)));
+            }
+            ServerMessage::JoinRejected { reason } => {
+                godot_print!(
+                    "Server refused to let us in: {}",
+                    format.translations.localize(&reason)
+                );
+                client.close();
+            }
+            ServerMessage::SystemNotice { level, text } => {
+                let text = format.translations.localize(&text);
+                godot_print!("* [{:?}] {}", level, text);
+                let line = chat::notice_line(level, &text);
+                commands.send_event(ui::NoticeReceived {
+                    level,
+                    text: text.clone(),
+                });
+                queue_chat_lines(&mut commands, String::new(), text, vec![line]);
+            }
+            ServerMessage::Whisper {
+                from,
+                text,
+                sent_at,
+            } => {
+                godot_print!("(whisper) {}: {}", from, text);
+                let lines = format
+                    .display
+                    .decorate(sent_at, &chat::whisper_line("From", &from, &text));
+                users.last_whisper_from = Some(from.clone());
+                queue_chat_lines(&mut commands, from, text, lines);
+            }
+            ServerMessage::FriendList { friends } => {
+                users.friends = friends
+                    .into_iter()
+                    .map(|friend| (friend.friend_id, friend.online))
+                    .collect();
+            }
+            ServerMessage::FriendPresence { friend_id, online } => {
+                users.friends.insert(friend_id.clone(), online);
+                let key = if online {
+                    texts::FRIEND_ONLINE
+                } else {
+                    texts::FRIEND_OFFLINE
+                };
+                let text = format
+                    .translations
+                    .localize(&LocalizedText::new(key).arg(&friend_id));
+                let line = chat::notice_line(protocol::NoticeLevel::Info, &text);
+                commands.send_event(ui::NoticeReceived {
+                    level: protocol::NoticeLevel::Info,
+                    text,
+                });
+                show_local_line(&mut commands, line);
+            }
+            ServerMessage::FriendLocation { friend_id, room } => {
+                // Follow the friend there
+                if room != users.room {
+                    to_server.write(SendToServer(match &room {
+                        Some(code) => ClientMessage::JoinRoom { code: code.clone() },
+                        None => ClientMessage::LeaveRoom {},
+                    }));
+                }
+                let key = match room {
+                    Some(_) => texts::FRIEND_IN_ROOM,
+                    None => texts::FRIEND_IN_LOBBY,
+                };
+                let text = format
+                    .translations
+                    .localize(&LocalizedText::new(key).arg(&friend_id));
+                show_local_line(
+                    &mut commands,
+                    chat::notice_line(protocol::NoticeLevel::Info, &text),
+                );
+            }
+            ServerMessage::RoomJoined { code } => {
+                let text = match &code {
+                    Some(code) => LocalizedText::new(texts::ROOM_JOINED).arg(code),
+                    None => LocalizedText::new(texts::ROOM_LEFT),
+                };
+                let text = format.translations.localize(&text);
+                show_local_line(
+                    &mut commands,
+                    chat::notice_line(protocol::NoticeLevel::Info, &text),
+                );
+                users.room = code.clone();
+                commands.send_event(RoomChanged { code });
+            }
+            ServerMessage::Emote { client_id, id } => {
+                commands.send_event(emotes::EmoteEvent { client_id, id });
+            }
+            ServerMessage::PropSnapshot { props } => {
+                commands.send_event(prop_sync::PropSnapshotEvent(props));
+            }
+            ServerMessage::AuthorityGranted { prop_id } => {
+                commands.send_event(prop_sync::AuthorityEvent::Granted { prop_id });
+            }
+            ServerMessage::AuthorityRevoked { state } => {
+                commands.send_event(prop_sync::AuthorityEvent::Revoked { state });
+            }
+            ServerMessage::GameRules { rules } => {
+                commands.insert_resource(player::ActiveRules(rules));
+            }
+            ServerMessage::Environment { state } => {
+                commands.send_event(environment::EnvironmentSynced { state });
+            }
+            ServerMessage::PlaySoundAt { sound_id, x, y } => {
+                commands.send_event(audio::PlaySoundEvent {
+                    sound_id,
+                    position: Vector2::new(x, y),
+                });
+            }
+            ServerMessage::WhisperDelivered { local_id, .. } => {
+                pending.take(local_id);
+            }
+            ServerMessage::WhisperFailed {
+                local_id,
+                to,
+                reason,
+            } => {
+                pending.fail(local_id);
+                let text = LocalizedText::new(texts::WHISPER_FAILED)
+                    .arg(chat::escape_bbcode(&to))
+                    .arg(format.translations.localize(&reason));
+                let line = format!("* {}", format.translations.localize(&text));
+                queue_chat_lines(&mut commands, String::new(), String::new(), vec![line]);
+            }
+        }
+    }
+}
+
+/// Append the lines for one message to every chat log and notify listeners once
+fn queue_chat_lines(
+    commands: &mut Commands,
+    username: String,
+    message: String,
+    lines: Vec<String>,
+) {
+    commands.queue(move |world: &mut World| {
+        let mut chat_node = world.query::<&mut Chat>();
+        for mut chat_node in chat_node.iter_mut(world) {
+            chat_node.messages.extend(lines.iter().cloned());
         }
+        world.send_event(ChatMessage {
+            client_id: None,
+            username,
+            message,
+        });
+    });
+}
+
+/// A system panicked earlier (godot-rust caught it and the app keeps ticking):
+/// say goodbye to the server so it doesn't wait for a timeout, then exit
+fn notify_server_after_panic(
+    mut client: Transport,
+    panics: Res<crash::PanicWatch>,
+    mut app_exit_events: EventWriter<AppExit>,
+    mut notified: Local<bool>,
+) {
+    if *notified || !panics.has_panicked() {
+        return;
+    }
+    *notified = true;
+    if client.is_connected() {
+        client.try_send(ClientMessage::Disconnect {});
+        sleep(Duration::from_secs_f32(0.1));
+        client.close();
     }
+    app_exit_events.write(AppExit::error());
 }
 
-pub fn on_app_exit(app_exit_events: EventReader<AppExit>, mut client: ResMut<QuinnetClient>) {
+pub fn on_app_exit(app_exit_events: EventReader<AppExit>, mut client: Transport) {
     if !app_exit_events.is_empty() {
-        client
-            .connection_mut()
-            .send_message(ClientMessage::Disconnect {})
-            .unwrap();
+        client.try_send(ClientMessage::Disconnect {});
         // TODO Clean: event to let the async client send his last messages.
         sleep(Duration::from_secs_f32(0.1));
     }
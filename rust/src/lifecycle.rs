@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::prelude::*;
+use godot_bevy::prelude::*;
+
+use crate::frame_budget;
+use crate::player::{PlayerIndex, PlayerNode};
+
+/// Movement updates while backgrounded, just enough to show we're still there
+pub const BACKGROUND_SEND_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the app is in the background (mobile exports), set from the
+/// `NetworkManagerNode`'s application notifications
+#[derive(Resource, Debug, Clone, Default)]
+pub struct AppLifecycle {
+    pub backgrounded: bool,
+}
+
+/// A player went away (app backgrounded) or came back
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerAway {
+    pub client_id: ClientId,
+    pub away: bool,
+}
+
+pub struct LifecyclePlugin;
+
+impl Plugin for LifecyclePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerAway>()
+            .init_resource::<AppLifecycle>()
+            .add_systems(Update, show_away_players);
+    }
+}
+
+/// Away players are drawn faded
+#[main_thread_system]
+fn show_away_players(
+    mut events: EventReader<PlayerAway>,
+    index: Res<PlayerIndex>,
+    mut query: Query<&mut GodotNodeHandle>,
+) {
+    let _timing = frame_budget::scope("show_away_players");
+    for ev in events.read() {
+        let Some(mut handle) = index
+            .0
+            .get(&ev.client_id)
+            .and_then(|entity| query.get_mut(*entity).ok())
+        else {
+            continue;
+        };
+        let Some(mut player) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+        let alpha = if ev.away { 0.4 } else { 1.0 };
+        player.set_modulate(Color::from_rgba(1.0, 1.0, 1.0, alpha));
+    }
+}
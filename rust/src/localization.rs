@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use godot::{classes::TranslationServer, prelude::*};
+use godot_bevy::prelude::*;
+use protocol::{LocalizedText, texts};
+
+use crate::frame_budget;
+
+/// The project's translations of the protocol texts for the current locale, read
+/// from Godot's `TranslationServer` on the main thread so any system can localize
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Translations {
+    locale: String,
+    templates: HashMap<&'static str, String>,
+}
+
+impl Translations {
+    /// `text` in the player's language, in English when the project has no translation
+    pub fn localize(&self, text: &LocalizedText) -> String {
+        let template = self
+            .templates
+            .get(text.key.as_str())
+            .map(String::as_str)
+            .or_else(|| texts::english(&text.key))
+            .unwrap_or(&text.key);
+        text.fill(template)
+    }
+}
+
+pub struct LocalizationPlugin;
+
+impl Plugin for LocalizationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Translations>()
+            .add_systems(First, refresh_translations);
+    }
+}
+
+/// Reload the templates whenever the game switches locale
+#[main_thread_system]
+fn refresh_translations(mut translations: ResMut<Translations>) {
+    let _timing = frame_budget::scope("refresh_translations");
+    let server = TranslationServer::singleton();
+    let locale = server.get_locale().to_string();
+    if translations.locale == locale {
+        return;
+    }
+    translations.templates = texts::ENGLISH
+        .iter()
+        .filter_map(|(key, _)| {
+            // Untranslated keys come back unchanged
+            let translated = server.translate(&StringName::from(*key)).to_string();
+            (translated != *key).then_some((*key, translated))
+        })
+        .collect();
+    godot_print!(
+        "Locale {}: {} of {} texts translated",
+        locale,
+        translations.templates.len(),
+        texts::ENGLISH.len()
+    );
+    translations.locale = locale;
+}
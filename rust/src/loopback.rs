@@ -0,0 +1,222 @@
+//! In-process connections between a client and a server running in the same process,
+//! e.g. for offline play: the messages go through queues instead of QUIC, but are
+//! encoded and decoded exactly as on the wire, so both ends run the same handlers as
+//! online. The client opens one with `LOOPBACK_ADDRESS`, and the server accepts it on
+//! its next update, whether or not it also listens for QUIC clients.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use protocol::{ClientMessage, ServerMessage};
+
+use crate::transport::{ClientTransport, TransportError, TransportEvent, skip_undecodable};
+
+/// Address that opens a loopback connection instead of a network one
+pub const LOOPBACK_ADDRESS: &str = "loopback";
+/// Loopback client ids count up from here, far from the ids quinnet hands out
+const FIRST_CLIENT_ID: ClientId = 1 << 48;
+
+/// Connections opened by clients and not yet picked up by the server
+static PENDING: Mutex<Vec<SharedLink>> = Mutex::new(Vec::new());
+
+fn pending() -> MutexGuard<'static, Vec<SharedLink>> {
+    PENDING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[derive(Debug, Default)]
+struct Link {
+    to_server: VecDeque<Vec<u8>>,
+    to_client: VecDeque<Vec<u8>>,
+    /// Picked up by the server
+    accepted: bool,
+    /// Closed by either end
+    closed: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SharedLink(Arc<Mutex<Link>>);
+
+impl SharedLink {
+    fn lock(&self) -> MutexGuard<'_, Link> {
+        // The queues stay consistent even if the other end panicked mid-update
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+    }
+}
+
+/// The client end, see `transport::Transport`
+#[derive(Resource, Debug, Default)]
+pub struct LoopbackTransport {
+    link: Option<SharedLink>,
+    /// Whether `TransportEvent::Connected` was written for this link
+    announced: bool,
+}
+
+impl LoopbackTransport {
+    /// Whether this transport is in use, connected or not
+    pub fn is_open(&self) -> bool {
+        self.link.is_some()
+    }
+}
+
+impl ClientTransport for LoopbackTransport {
+    fn open(&mut self, server_addr: &str) -> Result<(), TransportError> {
+        if server_addr != LOOPBACK_ADDRESS {
+            return Err(TransportError::InvalidAddress(server_addr.to_string()));
+        }
+        self.close();
+        let link = SharedLink::default();
+        pending().push(link.clone());
+        self.link = Some(link);
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if let Some(link) = self.link.take() {
+            link.close();
+        }
+        self.announced = false;
+    }
+
+    fn is_connected(&self) -> bool {
+        self.link.as_ref().is_some_and(|link| {
+            let link = link.lock();
+            link.accepted && !link.closed
+        })
+    }
+
+    fn send(&mut self, message: ClientMessage) -> Result<(), TransportError> {
+        let link = self.link.as_ref().ok_or(TransportError::NotConnected)?;
+        let mut link = link.lock();
+        if link.closed {
+            return Err(TransportError::NotConnected);
+        }
+        link.to_server.push_back(protocol::encode(&message));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<ServerMessage> {
+        let link = self.link.as_ref()?;
+        loop {
+            let payload = link.lock().to_client.pop_front()?;
+            match protocol::decode::<ServerMessage>(&payload) {
+                Ok(message) => return Some(message),
+                Err(err) => skip_undecodable("loopback", err),
+            }
+        }
+    }
+}
+
+/// Report the loopback connection coming up and going down, like the network backends
+pub fn poll_loopback(
+    mut client: ResMut<LoopbackTransport>,
+    mut events: EventWriter<TransportEvent>,
+) {
+    let Some(link) = &client.link else {
+        return;
+    };
+    let (accepted, closed) = {
+        let link = link.lock();
+        // What the server sent before closing is still read first, e.g. why we were kicked
+        (link.accepted, link.closed && link.to_client.is_empty())
+    };
+    if closed {
+        client.link = None;
+        events.write(if client.announced {
+            TransportEvent::Disconnected
+        } else {
+            TransportEvent::ConnectionFailed("the server closed the loopback".to_string())
+        });
+        client.announced = false;
+    } else if accepted && !client.announced {
+        client.announced = true;
+        events.write(TransportEvent::Connected);
+    }
+}
+
+/// The server end: every loopback client it accepted
+#[derive(Resource, Debug, Default)]
+pub struct LoopbackServer {
+    clients: HashMap<ClientId, SharedLink>,
+    next_id: ClientId,
+}
+
+impl LoopbackServer {
+    /// Take the connections opened since the last call, and drop the ones their client
+    /// closed. Returns the ids of the dropped ones.
+    pub fn update(&mut self) -> Vec<ClientId> {
+        for link in mem::take(&mut *pending()) {
+            let mut state = link.lock();
+            if state.closed {
+                continue;
+            }
+            state.accepted = true;
+            drop(state);
+            let client_id = FIRST_CLIENT_ID + self.next_id;
+            self.next_id += 1;
+            info!("Loopback client {} connected", client_id);
+            self.clients.insert(client_id, link);
+        }
+        let closed: Vec<ClientId> = self
+            .clients
+            .iter()
+            .filter(|(_, link)| link.lock().closed)
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        for client_id in &closed {
+            self.clients.remove(client_id);
+        }
+        closed
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.clients.keys().copied()
+    }
+
+    pub fn contains(&self, client_id: ClientId) -> bool {
+        self.clients.contains_key(&client_id)
+    }
+
+    /// The next encoded message from this client
+    pub fn receive_payload(&mut self, client_id: ClientId) -> Option<Vec<u8>> {
+        self.clients.get(&client_id)?.lock().to_server.pop_front()
+    }
+
+    pub fn send(&mut self, client_id: ClientId, message: &ServerMessage) {
+        if let Some(link) = self.clients.get(&client_id) {
+            link.lock().to_client.push_back(protocol::encode(message));
+        }
+    }
+
+    /// Messages already queued are still delivered
+    pub fn disconnect(&mut self, client_id: ClientId) {
+        if let Some(link) = self.clients.remove(&client_id) {
+            link.close();
+        }
+    }
+
+    pub fn broadcast(&mut self, message: &ServerMessage) {
+        let payload = protocol::encode(message);
+        for link in self.clients.values() {
+            link.lock().to_client.push_back(payload.clone());
+        }
+    }
+
+    pub fn disconnect_all(&mut self) {
+        for (_, link) in self.clients.drain() {
+            link.close();
+        }
+    }
+}
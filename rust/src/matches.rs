@@ -0,0 +1,160 @@
+//! Timed matches on the server: scores and stats are counted while one runs, then
+//! everyone gets the rankings, and after a pause (or once the players voted to play
+//! again) the next match starts from zero
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bevy_quinnet::shared::ClientId;
+use protocol::{MatchRanking, MatchStats};
+
+use crate::votes::Ballot;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchPhase {
+    #[default]
+    Playing,
+    /// The results were sent, waiting for the next match
+    Results,
+}
+
+/// The running match: its phase, since when, and what each player did so far
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub phase: MatchPhase,
+    phase_started: Instant,
+    stats: HashMap<ClientId, MatchStats>,
+    /// Scores restored from a save, by account id, until their player joins again
+    unclaimed: HashMap<String, MatchStats>,
+    /// Votes to start the next match early, opened by the first `PlayAgain` during
+    /// the results
+    pub rematch: Option<Ballot>,
+}
+
+impl Default for Match {
+    fn default() -> Self {
+        Self {
+            phase: MatchPhase::Playing,
+            phase_started: Instant::now(),
+            stats: HashMap::new(),
+            unclaimed: HashMap::new(),
+            rematch: None,
+        }
+    }
+}
+
+impl Match {
+    /// Time spent in the current phase
+    pub fn elapsed(&self) -> Duration {
+        self.phase_started.elapsed()
+    }
+
+    fn stats_mut(&mut self, client_id: ClientId) -> &mut MatchStats {
+        self.stats.entry(client_id).or_insert(MatchStats {
+            client_id,
+            ..Default::default()
+        })
+    }
+
+    /// For gameplay to reward players; ignored between matches
+    pub fn add_score(&mut self, client_id: ClientId, points: u32) {
+        if self.phase == MatchPhase::Playing {
+            let stats = self.stats_mut(client_id);
+            stats.score = stats.score.saturating_add(points);
+        }
+    }
+
+    #[cfg(feature = "server-movement")]
+    pub fn record_move(&mut self, client_id: ClientId, distance: f32) {
+        if self.phase == MatchPhase::Playing && distance.is_finite() {
+            self.stats_mut(client_id).distance += distance;
+        }
+    }
+
+    #[cfg(feature = "server-chat")]
+    pub fn record_message(&mut self, client_id: ClientId) {
+        if self.phase == MatchPhase::Playing {
+            self.stats_mut(client_id).messages += 1;
+        }
+    }
+
+    /// Stop scoring and rank `players` (connected ones, with their names), best first
+    pub fn finish(
+        &mut self,
+        players: &HashMap<ClientId, String>,
+    ) -> (Vec<MatchRanking>, Vec<MatchStats>) {
+        self.phase = MatchPhase::Results;
+        self.phase_started = Instant::now();
+        let mut stats: Vec<MatchStats> = players
+            .keys()
+            .map(|&client_id| {
+                self.stats.get(&client_id).copied().unwrap_or(MatchStats {
+                    client_id,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(b.distance.total_cmp(&a.distance))
+        });
+        let mut rankings = Vec::with_capacity(stats.len());
+        let mut rank = 0;
+        let mut previous = None;
+        for (index, entry) in stats.iter().enumerate() {
+            if previous != Some(entry.score) {
+                rank = index as u32 + 1;
+                previous = Some(entry.score);
+            }
+            rankings.push(MatchRanking {
+                client_id: entry.client_id,
+                name: players[&entry.client_id].clone(),
+                rank,
+            });
+        }
+        (rankings, stats)
+    }
+
+    /// What to save: the scores of `players` (client and account ids), plus the restored
+    /// ones not claimed yet, by account id
+    pub fn scores_by_account(
+        &self,
+        players: impl IntoIterator<Item = (ClientId, String)>,
+    ) -> HashMap<String, MatchStats> {
+        let mut scores = self.unclaimed.clone();
+        for (client_id, account) in players {
+            if let Some(&stats) = self.stats.get(&client_id) {
+                scores.insert(account, stats);
+            }
+        }
+        scores
+    }
+
+    /// Scores saved before a restart, handed back by `claim` as their players rejoin
+    pub fn restore_scores(&mut self, scores: HashMap<String, MatchStats>) {
+        self.unclaimed = scores;
+    }
+
+    pub fn claim(&mut self, account: &str, client_id: ClientId) {
+        if let Some(mut stats) = self.unclaimed.remove(account) {
+            stats.client_id = client_id;
+            self.stats.insert(client_id, stats);
+        }
+    }
+
+    /// Back to zero for a new match
+    pub fn restart(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn migrate(&mut self, from: ClientId, to: ClientId) {
+        if let Some(mut stats) = self.stats.remove(&from) {
+            stats.client_id = to;
+            self.stats.insert(to, stats);
+        }
+        if let Some(ballot) = &mut self.rematch {
+            ballot.migrate(from, to);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use godot::prelude::*;
+
+use crate::presence::CurrentServer;
+use crate::transport::{ClientTransport, ConnectionState, Transport, TransportEvent};
+
+/// A bit shorter than the server's default `session_resume_secs`
+const RESUME_WINDOW: Duration = Duration::from_secs(25);
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Used when we don't know which server we were on
+const DEFAULT_ADDRESS: &str = "[::1]:6000";
+
+/// Progress of resuming the session after the connection dropped (e.g. a phone
+/// switching networks); the world stays as it is in the meantime
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationEvent {
+    /// The connection dropped, reconnecting
+    Started,
+    /// Back in the same session
+    Resumed,
+    /// The session expired or the server was unreachable, we joined (or must join) anew
+    Failed,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SessionResume {
+    /// Last SessionToken from the server, None when there is no session to resume
+    pub token: Option<u64>,
+    /// Set while resuming: when to give up
+    pub deadline: Option<Instant>,
+    next_attempt: Option<Instant>,
+}
+
+impl SessionResume {
+    pub fn is_resuming(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// Leaving on purpose, don't try to come back
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+pub struct MigrationPlugin;
+
+impl Plugin for MigrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MigrationEvent>()
+            .init_resource::<SessionResume>()
+            .add_systems(Update, reconnect_dropped_session);
+    }
+}
+
+fn reconnect_dropped_session(
+    mut transport_events: EventReader<TransportEvent>,
+    mut resume: ResMut<SessionResume>,
+    mut client: Transport,
+    mut connection: ResMut<ConnectionState>,
+    server: Res<CurrentServer>,
+    mut migration_events: EventWriter<MigrationEvent>,
+) {
+    let now = Instant::now();
+    for ev in transport_events.read() {
+        match ev {
+            TransportEvent::Disconnected if resume.token.is_some() && !resume.is_resuming() => {
+                godot_print!("Connection dropped, trying to resume the session");
+                resume.deadline = Some(now + RESUME_WINDOW);
+                resume.next_attempt = Some(now);
+                migration_events.write(MigrationEvent::Started);
+            }
+            TransportEvent::ConnectionFailed(_) | TransportEvent::Disconnected
+                if resume.is_resuming() =>
+            {
+                resume.next_attempt = Some(now + RETRY_INTERVAL);
+            }
+            _ => {}
+        }
+    }
+
+    let Some(deadline) = resume.deadline else {
+        return;
+    };
+    if now >= deadline {
+        godot_print!("Could not resume the session");
+        resume.clear();
+        migration_events.write(MigrationEvent::Failed);
+        return;
+    }
+    if *connection == ConnectionState::Disconnected
+        && resume.next_attempt.is_some_and(|at| now >= at)
+    {
+        let address = server.address.as_deref().unwrap_or(DEFAULT_ADDRESS);
+        if client.open(address).is_ok() {
+            *connection = ConnectionState::Connecting;
+            resume.next_attempt = None;
+        } else {
+            resume.next_attempt = Some(now + RETRY_INTERVAL);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use godot::classes::ProjectSettings;
+use protocol::MessageKind;
+use serde::Serialize;
+
+use crate::console::{ConsoleAppExt, ConsoleOutput};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEntry {
+    /// Unix time, milliseconds
+    pub at_ms: u64,
+    pub direction: Direction,
+    /// Message variant, e.g. "PlayerUpdate"
+    pub kind: &'static str,
+    /// Encoded size in bytes
+    pub size: usize,
+    pub channel: &'static str,
+}
+
+/// The last `capacity` messages sent and received, while enabled; recorded by the
+/// transport systems. Off by default: recording encodes every message a second time.
+#[derive(Resource, Debug, Clone)]
+pub struct MessageTrace {
+    pub enabled: bool,
+    pub capacity: usize,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl Default for MessageTrace {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 1000,
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl MessageTrace {
+    pub fn record<T: Serialize + MessageKind>(&mut self, direction: Direction, message: &T) {
+        if !self.enabled {
+            return;
+        }
+        let at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TraceEntry {
+            at_ms,
+            direction,
+            kind: message.kind(),
+            size: protocol::encode(message).len(),
+            channel: protocol::channels::DEFAULT,
+        });
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+}
+
+pub struct MessageTracePlugin;
+
+impl Plugin for MessageTracePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_console_command(
+            "trace",
+            "trace on|off|show [count]|clear|export <file>: record protocol messages",
+            trace_command,
+        );
+    }
+}
+
+fn trace_command(
+    In(args): In<Vec<String>>,
+    mut trace: ResMut<MessageTrace>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    let mut say = |line: String| {
+        output.write(ConsoleOutput(line));
+    };
+    match args.first().map(String::as_str) {
+        Some("on") => {
+            trace.enabled = true;
+            say(format!("tracing, keeping the last {}", trace.capacity));
+        }
+        Some("off") => {
+            trace.enabled = false;
+            say("tracing stopped".to_string());
+        }
+        Some("clear") => {
+            trace.entries.clear();
+            say("trace cleared".to_string());
+        }
+        Some("show") => {
+            let count = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(20);
+            let mut lines: Vec<_> = trace
+                .entries()
+                .rev()
+                .take(count)
+                .map(|entry| {
+                    format!(
+                        "{} {:?} {} {} B",
+                        entry.at_ms, entry.direction, entry.kind, entry.size
+                    )
+                })
+                .collect();
+            lines.reverse();
+            for line in lines {
+                say(line);
+            }
+        }
+        Some("export") => {
+            let Some(file) = args.get(1) else {
+                say("usage: trace export <file>".to_string());
+                return;
+            };
+            // Relative names go next to the other user data
+            let path = if file.contains('/') || file.contains('\\') {
+                file.clone()
+            } else {
+                ProjectSettings::singleton()
+                    .globalize_path(format!("user://{}", file).as_str())
+                    .to_string()
+            };
+            let entries: Vec<_> = trace.entries().collect();
+            let result = serde_json::to_string_pretty(&entries)
+                .map_err(|err| err.to_string())
+                .and_then(|json| fs::write(&path, json).map_err(|err| err.to_string()));
+            match result {
+                Ok(()) => say(format!("{} messages written to {}", entries.len(), path)),
+                Err(err) => say(format!("error: {}", err)),
+            }
+        }
+        _ => say("usage: trace on|off|show [count]|clear|export <file>".to_string()),
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use protocol::{LocalizedText, texts};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Characters that render as nothing but can be used to impersonate other names
+/// or reorder the text around them
+const INVISIBLE_CHARS: &[char] = &[
+    '\u{00AD}', '\u{034F}', '\u{061C}', '\u{180E}', '\u{200B}', '\u{200C}', '\u{200D}', '\u{200E}',
+    '\u{200F}', '\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}', '\u{2060}', '\u{2061}',
+    '\u{2062}', '\u{2063}', '\u{2064}', '\u{2066}', '\u{2067}', '\u{2068}', '\u{2069}', '\u{FEFF}',
+];
+
+/// Greek and Cyrillic letters that look like a Latin one, and that letter
+const HOMOGLYPHS: &[(char, char)] = &[
+    ('а', 'a'),
+    ('в', 'b'),
+    ('е', 'e'),
+    ('к', 'k'),
+    ('м', 'm'),
+    ('н', 'h'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('т', 't'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('ѕ', 's'),
+    ('ԁ', 'd'),
+    ('һ', 'h'),
+    ('α', 'a'),
+    ('β', 'b'),
+    ('ε', 'e'),
+    ('ι', 'i'),
+    ('κ', 'k'),
+    ('ν', 'v'),
+    ('ο', 'o'),
+    ('ρ', 'p'),
+    ('τ', 't'),
+    ('υ', 'u'),
+    ('χ', 'x'),
+    ('ζ', 'z'),
+];
+
+/// Latin glyphs easily mistaken for each other, and the one standing for all of them
+const LOOK_ALIKES: &[(char, char)] = &[('i', 'l'), ('1', 'l'), ('|', 'l'), ('!', 'l'), ('0', 'o')];
+
+/// Separators that are hard to tell apart or to notice, ignored when comparing names
+const IGNORED_IN_SKELETON: &[char] = &['.', '_', '-', '\''];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+/// The alphabets whose letters are confused with each other, None for anything else
+fn script(c: char) -> Option<Script> {
+    if !c.is_alphabetic() {
+        return None;
+    }
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' => Some(Script::Latin),
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameError {
+    TooShort { min: usize },
+    TooLong { max: usize },
+    NoLetters,
+    MixedScripts,
+    LooksLike(String),
+}
+
+impl NameError {
+    /// Sent to the client, which shows it in its language
+    pub fn text(&self) -> LocalizedText {
+        match self {
+            NameError::TooShort { min } => LocalizedText::new(texts::NAME_TOO_SHORT).arg(min),
+            NameError::TooLong { max } => LocalizedText::new(texts::NAME_TOO_LONG).arg(max),
+            NameError::NoLetters => LocalizedText::new(texts::NAME_NO_LETTERS),
+            NameError::MixedScripts => LocalizedText::new(texts::NAME_CONFUSABLE),
+            NameError::LooksLike(name) => LocalizedText::new(texts::NAME_LOOKS_LIKE).arg(name),
+        }
+    }
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.text().fmt(f)
+    }
+}
+
+/// NFC-normalize, strip control and zero-width characters, collapse whitespace and
+/// check length bounds (in grapheme clusters). Names mixing Latin, Greek and Cyrillic
+/// letters, the usual way to pass for someone else ("Bоb" with a Cyrillic о), are
+/// refused; names reading the same as another player's are checked with `skeleton`.
+pub fn sanitize_username(raw: &str, min_len: usize, max_len: usize) -> Result<String, NameError> {
+    let cleaned: String = raw
+        .nfc()
+        .filter(|c| !c.is_control() && !INVISIBLE_CHARS.contains(c))
+        .collect();
+    let name = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let len = name.graphemes(true).count();
+    if len < min_len {
+        return Err(NameError::TooShort { min: min_len });
+    }
+    if len > max_len {
+        return Err(NameError::TooLong { max: max_len });
+    }
+    if !name.chars().any(char::is_alphanumeric) {
+        return Err(NameError::NoLetters);
+    }
+    let mut scripts = name.chars().filter_map(script);
+    let first = scripts.next();
+    if scripts.any(|other| Some(other) != first) {
+        return Err(NameError::MixedScripts);
+    }
+    Ok(name)
+}
+
+/// What a name looks like, for telling whether two names can be told apart: case,
+/// spaces and separators dropped, and look-alike letters (including Greek and Cyrillic
+/// ones) folded into one. "Ol1ver" and "oliver" have the same skeleton.
+pub fn skeleton(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && !IGNORED_IN_SKELETON.contains(c))
+        .flat_map(char::to_lowercase)
+        .map(|c| {
+            let c = HOMOGLYPHS
+                .iter()
+                .find(|(glyph, _)| *glyph == c)
+                .map_or(c, |(_, latin)| *latin);
+            LOOK_ALIKES
+                .iter()
+                .find(|(glyph, _)| *glyph == c)
+                .map_or(c, |(_, canonical)| *canonical)
+        })
+        .collect()
+}
@@ -0,0 +1,232 @@
+//! Where server-side characters can walk: each level may come with a grid,
+//! `<nav_dir>/<level file stem>.toml`, loaded whenever the level changes, and
+//! `Navigation::find_path` runs A* over it, so NPC AI paths around obstacles the same
+//! way for everyone.
+//!
+//! ```toml
+//! cell_size = 32.0
+//! origin = [0.0, 0.0]
+//! rows = [
+//!     "..........",
+//!     "..####....",
+//!     "..........",
+//! ]
+//! ```
+//!
+//! `#` blocks a cell, anything else is walkable; the first row is the top one.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// Step costs, a diagonal being about √2 times a straight step
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+#[derive(Debug, Deserialize)]
+struct GridFile {
+    cell_size: f32,
+    #[serde(default)]
+    origin: (f32, f32),
+    rows: Vec<String>,
+}
+
+type Cell = (usize, usize);
+
+/// A level's walkable cells
+#[derive(Debug, Clone)]
+pub struct NavGrid {
+    cell_size: f32,
+    /// World position of the top-left corner
+    origin: (f32, f32),
+    width: usize,
+    height: usize,
+    /// Row by row
+    blocked: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let file: GridFile = toml::from_str(&data).map_err(|err| err.to_string())?;
+        if file.cell_size <= 0.0 {
+            return Err("cell_size must be positive".to_string());
+        }
+        let width = file
+            .rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let blocked = file
+            .rows
+            .iter()
+            .flat_map(|row| {
+                // Short rows are padded with walkable cells
+                let cells: Vec<bool> = row.chars().map(|c| c == '#').collect();
+                (0..width).map(move |x| cells.get(x).copied().unwrap_or(false))
+            })
+            .collect();
+        Ok(Self {
+            cell_size: file.cell_size,
+            origin: file.origin,
+            width,
+            height: file.rows.len(),
+            blocked,
+        })
+    }
+
+    /// The cell holding world position (x, y), None outside the grid
+    pub fn cell_at(&self, x: f32, y: f32) -> Option<Cell> {
+        let cx = ((x - self.origin.0) / self.cell_size).floor();
+        let cy = ((y - self.origin.1) / self.cell_size).floor();
+        if cx < 0.0 || cy < 0.0 {
+            return None;
+        }
+        let cell = (cx as usize, cy as usize);
+        (cell.0 < self.width && cell.1 < self.height).then_some(cell)
+    }
+
+    pub fn is_walkable(&self, (x, y): Cell) -> bool {
+        x < self.width && y < self.height && !self.blocked[y * self.width + x]
+    }
+
+    fn center(&self, (x, y): Cell) -> (f32, f32) {
+        (
+            self.origin.0 + (x as f32 + 0.5) * self.cell_size,
+            self.origin.1 + (y as f32 + 0.5) * self.cell_size,
+        )
+    }
+
+    /// Walkable neighbours with their step cost; diagonals only when both cells
+    /// they pass between are walkable, so paths don't cut corners
+    fn neighbours(&self, (x, y): Cell) -> impl Iterator<Item = (Cell, u32)> + '_ {
+        (-1i32..=1)
+            .flat_map(|dy| (-1i32..=1).map(move |dx| (dx, dy)))
+            .filter(|&offset| offset != (0, 0))
+            .filter_map(move |(dx, dy)| {
+                let nx = x.checked_add_signed(dx as isize)?;
+                let ny = y.checked_add_signed(dy as isize)?;
+                if !self.is_walkable((nx, ny)) {
+                    return None;
+                }
+                if dx != 0 && dy != 0 {
+                    (self.is_walkable((nx, y)) && self.is_walkable((x, ny)))
+                        .then_some(((nx, ny), DIAGONAL_COST))
+                } else {
+                    Some(((nx, ny), STRAIGHT_COST))
+                }
+            })
+    }
+
+    /// Octile distance, exact on an open grid
+    fn estimate(&self, (ax, ay): Cell, (bx, by): Cell) -> u32 {
+        let dx = ax.abs_diff(bx) as u32;
+        let dy = ay.abs_diff(by) as u32;
+        STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+    }
+
+    /// Waypoints from `from` to `to` around blocked cells: the centers of the cells to
+    /// cross, then `to` itself. None when either end is outside the grid or blocked, or
+    /// no path connects them.
+    pub fn find_path(&self, from: (f32, f32), to: (f32, f32)) -> Option<Vec<(f32, f32)>> {
+        let start = self.cell_at(from.0, from.1)?;
+        let goal = self.cell_at(to.0, to.1)?;
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<Cell, Cell> = HashMap::new();
+        let mut costs: HashMap<Cell, u32> = HashMap::from([(start, 0)]);
+        open.push(Reverse((self.estimate(start, goal), 0, start)));
+        while let Some(Reverse((_, cost, cell))) = open.pop() {
+            if cell == goal {
+                let mut cells = vec![goal];
+                while let Some(&previous) = came_from.get(cells.last()?) {
+                    cells.push(previous);
+                }
+                // Already in the start cell, and the last waypoint is the target itself
+                cells.pop();
+                cells.reverse();
+                cells.pop();
+                let mut waypoints: Vec<_> = cells.into_iter().map(|c| self.center(c)).collect();
+                waypoints.push(to);
+                return Some(waypoints);
+            }
+            if costs.get(&cell).is_some_and(|&best| cost > best) {
+                // A cheaper way here was found after this one was queued
+                continue;
+            }
+            for (next, step) in self.neighbours(cell) {
+                let next_cost = cost + step;
+                if costs.get(&next).is_none_or(|&best| next_cost < best) {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((
+                        next_cost + self.estimate(next, goal),
+                        next_cost,
+                        next,
+                    )));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The current level's grid, if it has one
+#[derive(Resource, Debug, Default)]
+pub struct Navigation {
+    grid: Option<NavGrid>,
+}
+
+impl Navigation {
+    /// Load the grid for `level`, or go without one when it has none
+    pub fn load_level(&mut self, nav_dir: &Path, level: Option<&str>) {
+        self.grid = None;
+        let Some(path) = level.and_then(|level| grid_path(nav_dir, level)) else {
+            return;
+        };
+        if !path.exists() {
+            debug!("No navigation grid at {}", path.display());
+            return;
+        }
+        match NavGrid::load(&path) {
+            Ok(grid) => {
+                info!(
+                    "Loaded navigation grid {} ({}x{})",
+                    path.display(),
+                    grid.width,
+                    grid.height
+                );
+                self.grid = Some(grid);
+            }
+            Err(err) => warn!("Navigation grid {} not loaded: {}", path.display(), err),
+        }
+    }
+
+    pub fn grid(&self) -> Option<&NavGrid> {
+        self.grid.as_ref()
+    }
+
+    /// `NavGrid::find_path` on the current level's grid; without one, straight to `to`
+    pub fn find_path(&self, from: (f32, f32), to: (f32, f32)) -> Option<Vec<(f32, f32)>> {
+        match &self.grid {
+            Some(grid) => grid.find_path(from, to),
+            None => Some(vec![to]),
+        }
+    }
+}
+
+/// `<nav_dir>/<stem>.toml` for a level scene such as `res://levels/meadow.tscn`
+fn grid_path(nav_dir: &Path, level: &str) -> Option<PathBuf> {
+    let stem = Path::new(level.rsplit('/').next()?).file_stem()?;
+    Some(nav_dir.join(stem).with_extension("toml"))
+}
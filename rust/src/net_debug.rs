@@ -0,0 +1,222 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use godot::{
+    classes::{INode2D, InputEvent, InputEventKey, Node2D, ThemeDB},
+    global::Key,
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::frame_budget::{self, FrameBudget, SystemTimings};
+use crate::player::{PlayerNode, RemoteMotion};
+use crate::prop_sync::{Prop, PropAuthority, PropNode};
+
+/// Slowest main-thread systems listed in the corner
+const TIMING_LINES: usize = 8;
+
+/// Snapshots younger than this are drawn green, then yellow, then red past `STALE_AGE`
+const FRESH_AGE: Duration = Duration::from_millis(100);
+const STALE_AGE: Duration = Duration::from_millis(250);
+
+/// One networked entity as the overlay draws it
+#[derive(Debug, Clone, Copy)]
+pub struct SyncMarker {
+    /// Last state from the server
+    pub authoritative: Vector2,
+    /// Where we show it: extrapolated, smoothed or locally simulated
+    pub predicted: Vector2,
+    pub snapshot_age: Duration,
+    /// We simulate it (our prop authority), the server follows us
+    pub local_authority: bool,
+}
+
+#[derive(Component, Default)]
+pub struct NetDebugOverlayComp;
+
+/// F3 overlay: for each networked entity, a ring at the server position, a dot at the
+/// shown position, a line between them and the age of the last snapshot; plus the
+/// slowest main-thread systems
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Node2D)]
+#[bevy_bundle((NetDebugOverlayComp))]
+pub struct NetDebugOverlayNode {
+    base: Base<Node2D>,
+    markers: Vec<SyncMarker>,
+    /// (text, over budget)
+    timings: Vec<(String, bool)>,
+}
+
+#[godot_api]
+impl INode2D for NetDebugOverlayNode {
+    fn init(base: Base<Node2D>) -> Self {
+        Self {
+            base,
+            markers: Vec::new(),
+            timings: Vec::new(),
+        }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_visible(false);
+        // Above the players and props
+        self.base_mut().set_z_index(100);
+    }
+
+    fn input(&mut self, event: Gd<InputEvent>) {
+        let Ok(key) = event.try_cast::<InputEventKey>() else {
+            return;
+        };
+        if key.is_pressed() && !key.is_echo() && key.get_keycode() == Key::F3 {
+            let visible = !self.base().is_visible();
+            self.base_mut().set_visible(visible);
+        }
+    }
+
+    fn draw(&mut self) {
+        let font = ThemeDB::singleton().get_fallback_font();
+        let markers = self.markers.clone();
+        for marker in markers {
+            let color = if marker.local_authority {
+                Color::CYAN
+            } else if marker.snapshot_age < FRESH_AGE {
+                Color::GREEN
+            } else if marker.snapshot_age < STALE_AGE {
+                Color::YELLOW
+            } else {
+                Color::RED
+            };
+            // Markers are in world coordinates
+            let authoritative = self.base().to_local(marker.authoritative);
+            let predicted = self.base().to_local(marker.predicted);
+            let mut base = self.base_mut();
+            base.draw_line(authoritative, predicted, color);
+            base.draw_arc(authoritative, 6.0, 0.0, std::f32::consts::TAU, 16, color);
+            base.draw_circle(predicted, 2.5, Color::WHITE);
+            if let Some(font) = &font {
+                base.draw_string(
+                    font,
+                    authoritative + Vector2::new(8.0, -8.0),
+                    &format!("{} ms", marker.snapshot_age.as_millis()),
+                );
+            }
+        }
+
+        let Some(font) = &font else {
+            return;
+        };
+        // Pinned to the top-left of the screen, whatever the camera does
+        let to_world = self.base().get_canvas_transform().affine_inverse();
+        let timings = self.timings.clone();
+        for (i, (line, over_budget)) in timings.iter().enumerate() {
+            let screen = Vector2::new(10.0, 20.0 + 16.0 * i as f32);
+            let position = self.base().to_local(to_world * screen);
+            let color = if *over_budget {
+                Color::RED
+            } else {
+                Color::WHITE
+            };
+            self.base_mut()
+                .draw_string_ex(font, position, line.as_str())
+                .modulate(color)
+                .done();
+        }
+    }
+}
+
+impl NetDebugOverlayNode {
+    pub fn is_shown(&self) -> bool {
+        self.base().is_visible()
+    }
+
+    pub fn set_markers(&mut self, markers: Vec<SyncMarker>) {
+        self.markers = markers;
+        self.base_mut().queue_redraw();
+    }
+
+    pub fn set_timings(&mut self, timings: Vec<(String, bool)>) {
+        self.timings = timings;
+    }
+}
+
+pub struct NetDebugPlugin;
+
+impl Plugin for NetDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_net_debug_overlay);
+    }
+}
+
+#[main_thread_system]
+fn update_net_debug_overlay(
+    mut overlays: Query<&mut GodotNodeHandle, With<NetDebugOverlayComp>>,
+    mut players: Query<(&mut GodotNodeHandle, &RemoteMotion), Without<NetDebugOverlayComp>>,
+    mut props: Query<
+        (&mut GodotNodeHandle, &Prop),
+        (Without<NetDebugOverlayComp>, Without<RemoteMotion>),
+    >,
+    authority: Res<PropAuthority>,
+    timings: Res<SystemTimings>,
+    budget: Res<FrameBudget>,
+) {
+    let _timing = frame_budget::scope("update_net_debug_overlay");
+    let mut shown: Vec<_> = overlays
+        .iter_mut()
+        .filter_map(|mut handle| handle.try_get::<NetDebugOverlayNode>())
+        .filter(|overlay| overlay.bind().is_shown())
+        .collect();
+    if shown.is_empty() {
+        return;
+    }
+
+    let now = Instant::now();
+    let mut markers = Vec::new();
+    for (mut handle, motion) in players.iter_mut() {
+        // The local player (and bots) never get server positions
+        let Some((authoritative, at)) = motion.authoritative else {
+            continue;
+        };
+        let Some(player) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+        markers.push(SyncMarker {
+            authoritative,
+            predicted: player.get_global_position(),
+            snapshot_age: now.duration_since(at),
+            local_authority: false,
+        });
+    }
+    for (mut handle, prop) in props.iter_mut() {
+        let Some(body) = handle.try_get::<PropNode>() else {
+            continue;
+        };
+        markers.push(SyncMarker {
+            authoritative: Vector2::new(prop.target.x, prop.target.y),
+            predicted: body.get_global_position(),
+            snapshot_age: now.duration_since(prop.last_snapshot),
+            local_authority: authority.owned.contains(&prop.prop_id),
+        });
+    }
+
+    let timing_lines: Vec<_> = timings
+        .slowest(TIMING_LINES)
+        .into_iter()
+        .map(|(name, timing)| {
+            (
+                format!(
+                    "{} {:.2} ms (max {:.2})",
+                    name,
+                    timing.average.as_secs_f64() * 1000.0,
+                    timing.max.as_secs_f64() * 1000.0
+                ),
+                timing.last > budget.system_budget,
+            )
+        })
+        .collect();
+
+    for overlay in &mut shown {
+        let mut overlay = overlay.bind_mut();
+        overlay.set_timings(timing_lines.clone());
+        overlay.set_markers(markers.clone());
+    }
+}
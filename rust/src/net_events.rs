@@ -0,0 +1,106 @@
+//! One Bevy event per protocol message, generated from the protocol's own variant
+//! lists. Received messages are written as these events by `NetDispatch`, so
+//! gameplay systems can read e.g. `EventReader<from_server::Emote>`.
+
+use bevy::prelude::*;
+
+/// An event struct per `ServerMessage` variant, `EventsPlugin` to register them and
+/// `write` to turn a message into its event
+macro_rules! server_events {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident { $($(#[$field_meta:meta])* $field:ident: $ty:ty),* $(,)? }
+            ),* $(,)?
+        }
+    ) => {
+        $(
+            $(#[$variant_meta])*
+            #[derive(Event, Debug, Clone)]
+            pub struct $variant {
+                $($(#[$field_meta])* pub $field: $ty,)*
+            }
+        )*
+
+        /// Registers every event of this module
+        pub struct EventsPlugin;
+
+        impl Plugin for EventsPlugin {
+            fn build(&self, app: &mut App) {
+                $(app.add_event::<$variant>();)*
+            }
+        }
+
+        pub fn write(commands: &mut Commands, message: protocol::$name) {
+            match message {
+                $(
+                    protocol::$name::$variant { $($field),* } => {
+                        commands.send_event($variant { $($field),* });
+                    }
+                )*
+            }
+        }
+    };
+}
+
+/// Same as `server_events!` for `ClientMessage`, with the sending client in `sender`
+macro_rules! client_events {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident { $($(#[$field_meta:meta])* $field:ident: $ty:ty),* $(,)? }
+            ),* $(,)?
+        }
+    ) => {
+        $(
+            $(#[$variant_meta])*
+            #[derive(Event, Debug, Clone)]
+            pub struct $variant {
+                pub sender: protocol::ClientId,
+                $($(#[$field_meta])* pub $field: $ty,)*
+            }
+        )*
+
+        /// Registers every event of this module
+        pub struct EventsPlugin;
+
+        impl Plugin for EventsPlugin {
+            fn build(&self, app: &mut App) {
+                $(app.add_event::<$variant>();)*
+            }
+        }
+
+        pub fn write(
+            commands: &mut Commands,
+            sender: protocol::ClientId,
+            message: protocol::$name,
+        ) {
+            match message {
+                $(
+                    protocol::$name::$variant { $($field),* } => {
+                        commands.send_event($variant { sender, $($field),* });
+                    }
+                )*
+            }
+        }
+    };
+}
+
+/// Received by clients. The chunked transfer messages (`InitBegin`, `InitChunk`,
+/// `InitEnd`) are reassembled first and never written.
+pub mod from_server {
+    use super::*;
+
+    protocol::server_messages!(server_events);
+}
+
+/// Received by the server
+pub mod from_client {
+    use super::*;
+
+    protocol::client_messages!(client_events);
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use bevy::ecs::system::{SystemId, SystemParam};
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use protocol::{ClientMessage, MessageKind, ServerMessage};
+
+use crate::net_events::{from_client, from_server};
+
+/// What net handlers are given: a `ServerMessage` on clients, the sender and its
+/// `ClientMessage` on the server
+pub trait NetMessage: Clone + Send + Sync + 'static {
+    fn kinds() -> &'static [&'static str];
+
+    fn kind(&self) -> &'static str;
+
+    /// Write the matching `net_events` event
+    fn write_event(self, commands: &mut Commands);
+}
+
+impl NetMessage for ServerMessage {
+    fn kinds() -> &'static [&'static str] {
+        Self::KINDS
+    }
+
+    fn kind(&self) -> &'static str {
+        MessageKind::kind(self)
+    }
+
+    fn write_event(self, commands: &mut Commands) {
+        from_server::write(commands, self);
+    }
+}
+
+impl NetMessage for (ClientId, ClientMessage) {
+    fn kinds() -> &'static [&'static str] {
+        ClientMessage::KINDS
+    }
+
+    fn kind(&self) -> &'static str {
+        self.1.kind()
+    }
+
+    fn write_event(self, commands: &mut Commands) {
+        from_client::write(commands, self.0, self.1);
+    }
+}
+
+/// Systems to run for each received message kind, after the built-in handling. They
+/// borrow the message, so several handlers of one kind share a single copy.
+#[derive(Resource)]
+pub struct NetHandlers<M: NetMessage> {
+    handlers: HashMap<&'static str, Vec<SystemId<InRef<'static, M>>>>,
+}
+
+impl<M: NetMessage> Default for NetHandlers<M> {
+    fn default() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+/// Handed to the systems that receive messages, to pass them on to the handlers and
+/// as `net_events`
+#[derive(SystemParam)]
+pub struct NetDispatch<'w, 's, M: NetMessage> {
+    handlers: Res<'w, NetHandlers<M>>,
+    commands: Commands<'w, 's>,
+}
+
+impl<M: NetMessage> NetDispatch<'_, '_, M> {
+    /// Queue the handlers registered for this message's kind, and write its event
+    pub fn dispatch(&mut self, message: &M) {
+        self.queue(message.kind(), || message.clone());
+        message.clone().write_event(&mut self.commands);
+    }
+
+    /// Only builds the handlers' copy when there are handlers
+    fn queue(&mut self, kind: &'static str, message: impl FnOnce() -> M) {
+        let Some(handlers) = self.handlers.handlers.get(kind) else {
+            return;
+        };
+        let handlers = handlers.clone();
+        let message = message();
+        self.commands.queue(move |world: &mut World| {
+            for handler in handlers {
+                if let Err(err) = world.run_system_with(handler, &message) {
+                    warn!("Net handler for {} failed: {}", kind, err);
+                }
+            }
+        });
+    }
+}
+
+impl NetDispatch<'_, '_, (ClientId, ClientMessage)> {
+    pub fn dispatch_from(&mut self, client_id: ClientId, message: &ClientMessage) {
+        self.queue(message.kind(), || (client_id, message.clone()));
+        from_client::write(&mut self.commands, client_id, message.clone());
+    }
+}
+
+pub trait NetHandlerAppExt {
+    /// Run `system` for every received message of `kind` (a variant name, e.g.
+    /// "ChatMessage"). Panics on a kind the protocol doesn't have.
+    fn add_net_handler<M: NetMessage, Marker>(
+        &mut self,
+        kind: &'static str,
+        system: impl IntoSystem<InRef<'static, M>, (), Marker> + 'static,
+    ) -> &mut Self;
+}
+
+impl NetHandlerAppExt for App {
+    fn add_net_handler<M: NetMessage, Marker>(
+        &mut self,
+        kind: &'static str,
+        system: impl IntoSystem<InRef<'static, M>, (), Marker> + 'static,
+    ) -> &mut Self {
+        assert!(
+            M::kinds().contains(&kind),
+            "no {} message in the protocol",
+            kind
+        );
+        let system = self.world_mut().register_system(system);
+        self.init_resource::<NetHandlers<M>>();
+        self.world_mut()
+            .resource_mut::<NetHandlers<M>>()
+            .handlers
+            .entry(kind)
+            .or_default()
+            .push(system);
+        self
+    }
+}
@@ -0,0 +1,99 @@
+//! The network send stage: systems sending periodic state (e.g. movement) run in
+//! `NetSendSet` on `Update` and send when `NetSendClock` says so. The send rate is thereby
+//! independent of the physics tick rate (`physics/common/physics_ticks_per_second`) and
+//! of the frame rate.
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::console::{ConsoleAppExt, ConsoleOutput};
+use crate::lifecycle::{AppLifecycle, BACKGROUND_SEND_INTERVAL};
+use crate::net_stats::NetworkStats;
+
+/// Periodic sends per second at full rate
+const DEFAULT_SEND_RATE: f32 = 60.0;
+const MIN_SEND_RATE: f32 = 1.0;
+const MAX_SEND_RATE: f32 = 240.0;
+
+/// Systems sending to the server, after the clock ticked and before the outbox is flushed
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NetSendSet;
+
+#[derive(Resource, Debug, Clone)]
+pub struct NetSendClock {
+    /// Periodic sends per second, stretched by `NetworkStats` on a poor link
+    pub rate: f32,
+    /// Whether a periodic send is due this frame
+    pub due: bool,
+    last_tick: Option<Instant>,
+}
+
+impl Default for NetSendClock {
+    fn default() -> Self {
+        Self {
+            rate: DEFAULT_SEND_RATE,
+            due: false,
+            last_tick: None,
+        }
+    }
+}
+
+impl NetSendClock {
+    /// Time between periodic sends at full rate
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.rate.clamp(MIN_SEND_RATE, MAX_SEND_RATE))
+    }
+}
+
+pub struct NetSendPlugin;
+
+impl Plugin for NetSendPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetSendClock>()
+            .add_systems(Update, tick_send_clock.before(NetSendSet))
+            .add_console_command(
+                "send_rate",
+                "send_rate <per second>: movement updates sent at full rate",
+                send_rate_command,
+            );
+    }
+}
+
+fn tick_send_clock(
+    mut clock: ResMut<NetSendClock>,
+    stats: Res<NetworkStats>,
+    lifecycle: Res<AppLifecycle>,
+) {
+    let interval = if lifecycle.backgrounded {
+        BACKGROUND_SEND_INTERVAL
+    } else {
+        stats.rate.interval(clock.interval())
+    };
+    let now = Instant::now();
+    clock.due = clock
+        .last_tick
+        .is_none_or(|last| now.duration_since(last) >= interval);
+    if clock.due {
+        clock.last_tick = Some(now);
+    }
+}
+
+fn send_rate_command(
+    In(args): In<Vec<String>>,
+    mut clock: ResMut<NetSendClock>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    let Some(rate) = args.first().and_then(|rate| rate.parse::<f32>().ok()) else {
+        output.write(ConsoleOutput(format!(
+            "send rate {} per second (send_rate <per second>)",
+            clock.rate
+        )));
+        return;
+    };
+    clock.rate = rate.clamp(MIN_SEND_RATE, MAX_SEND_RATE);
+    output.write(ConsoleOutput(format!(
+        "send rate {} per second",
+        clock.rate
+    )));
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::transport::{ClientTransport, ConnectionState, Transport};
+
+/// How often the rate controller looks at the link
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+/// Slowest we go, as a fraction of the full send rate
+const MIN_SCALE: f32 = 0.25;
+/// Round trips above this count as congestion even without losses
+const HIGH_RTT: Duration = Duration::from_millis(250);
+const LOSS_THRESHOLD: f32 = 0.02;
+
+/// Raw counters from the transport, cumulative since the connection opened
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinkStats {
+    pub rtt: Duration,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+    pub congestion_events: u64,
+}
+
+/// Scales send rates down when the link struggles and back up once it recovers:
+/// halve on loss, congestion or high RTT, then recover a little every sample.
+#[derive(Debug, Clone)]
+pub struct SendRate {
+    pub scale: f32,
+    last: LinkStats,
+    last_sample: Option<Instant>,
+}
+
+impl Default for SendRate {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            last: LinkStats::default(),
+            last_sample: None,
+        }
+    }
+}
+
+impl SendRate {
+    /// Feed the latest counters, returns the packet loss of the sample window
+    /// when one was taken
+    pub fn sample(&mut self, stats: LinkStats) -> Option<f32> {
+        if self
+            .last_sample
+            .is_some_and(|last| last.elapsed() < SAMPLE_INTERVAL)
+        {
+            return None;
+        }
+        self.last_sample = Some(Instant::now());
+
+        let sent = stats.sent_packets.saturating_sub(self.last.sent_packets);
+        let lost = stats.lost_packets.saturating_sub(self.last.lost_packets);
+        let congested = stats.congestion_events > self.last.congestion_events;
+        self.last = stats;
+        let loss = if sent == 0 {
+            0.0
+        } else {
+            lost as f32 / sent as f32
+        };
+
+        if congested || loss > LOSS_THRESHOLD || stats.rtt > HIGH_RTT {
+            self.scale = (self.scale * 0.5).max(MIN_SCALE);
+        } else {
+            self.scale = (self.scale + 0.1).min(1.0);
+        }
+        Some(loss)
+    }
+
+    /// `base` stretched by how far the rate was scaled down
+    pub fn interval(&self, base: Duration) -> Duration {
+        base.div_f32(self.scale)
+    }
+}
+
+/// Link quality as seen by the client, and the resulting send rate
+#[derive(Resource, Debug, Clone, Default)]
+pub struct NetworkStats {
+    pub rtt: Duration,
+    /// Packet loss over the last sample window, 0.0 to 1.0
+    pub loss: f32,
+    pub rate: SendRate,
+}
+
+pub struct NetworkStatsPlugin;
+
+impl Plugin for NetworkStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkStats>()
+            .add_systems(PreUpdate, update_network_stats);
+    }
+}
+
+fn update_network_stats(
+    transport: Transport,
+    connection: Res<ConnectionState>,
+    mut stats: ResMut<NetworkStats>,
+) {
+    if *connection != ConnectionState::Ready {
+        if connection.is_changed() {
+            // Start over at full rate on the next connection
+            *stats = NetworkStats::default();
+        }
+        return;
+    }
+    let Some(link) = transport.link_stats() else {
+        return;
+    };
+    if let Some(loss) = stats.rate.sample(link) {
+        stats.rtt = link.rtt;
+        stats.loss = loss;
+    }
+}
@@ -0,0 +1,299 @@
+use bevy::prelude::*;
+use godot::{classes::INode, prelude::*};
+use godot_bevy::prelude::*;
+use protocol::ClientMessage;
+use tokio::sync::mpsc::{self, Sender};
+
+use crate::chat::{ChatLimits, PendingChat};
+use crate::feedback::RumbleRequest;
+use crate::frame_budget;
+use crate::input_context::MenuOpen;
+use crate::leave::LeaveGame;
+use crate::lifecycle::AppLifecycle;
+use crate::migration::MigrationEvent;
+use crate::state_check::RequestFullSync;
+use crate::transport::{ClientTransport, SendToServer, Transport, TransportEvent};
+use crate::{ChatMessage, LocalUsername, RoomChanged, Users, send_chat};
+
+#[derive(Clone, Debug)]
+pub enum NetworkCommand {
+    SendChat(String),
+    SetUsername(String),
+    CreateRoom,
+    JoinRoom(String),
+    LeaveRoom,
+    Disconnect,
+    /// The app went to the background (mobile), or came back
+    Backgrounded,
+    Foregrounded,
+    Rumble(RumbleRequest),
+    SetMenuOpen(bool),
+}
+
+#[derive(Component, Default)]
+pub struct NetworkManagerComp;
+
+/// GDScript entry point to the network layer.
+/// Methods push commands into Bevy; network activity comes back as signals.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Node)]
+#[bevy_bundle((NetworkManagerComp))]
+pub struct NetworkManagerNode {
+    base: Base<Node>,
+    #[bevy_bundle]
+    pub sender: Option<Sender<NetworkCommand>>,
+}
+
+#[godot_api]
+impl NetworkManagerNode {
+    #[signal]
+    fn connected();
+
+    #[signal]
+    fn disconnected();
+
+    #[signal]
+    fn chat_received(username: GString, message: GString);
+
+    /// `code` is empty when back in the lobby
+    #[signal]
+    fn room_joined(code: GString);
+
+    /// The connection dropped and is being resumed: `state` is "started", "resumed" or "failed"
+    #[signal]
+    fn migration(state: GString);
+
+    #[func]
+    fn send_chat(&self, text: GString) {
+        self.push(NetworkCommand::SendChat(text.to_string()));
+    }
+
+    #[func]
+    fn set_username(&self, name: GString) {
+        self.push(NetworkCommand::SetUsername(name.to_string()));
+    }
+
+    /// Answered by `room_joined` with the invite code
+    #[func]
+    fn create_room(&self) {
+        self.push(NetworkCommand::CreateRoom);
+    }
+
+    #[func]
+    fn join_room(&self, code: GString) {
+        self.push(NetworkCommand::JoinRoom(code.to_string()));
+    }
+
+    #[func]
+    fn leave_room(&self) {
+        self.push(NetworkCommand::LeaveRoom);
+    }
+
+    /// Leave the game and return to the main menu. `disconnect` itself is taken by
+    /// `Object::disconnect` (signals)
+    #[func]
+    fn disconnect_from_server(&self) {
+        self.push(NetworkCommand::Disconnect);
+    }
+
+    /// Call with true while a menu is shown and false when it closes: the player doesn't
+    /// move meanwhile. Focused text fields (e.g. the chat input) are detected on their own.
+    #[func]
+    fn set_menu_open(&self, open: bool) {
+        self.push(NetworkCommand::SetMenuOpen(open));
+    }
+
+    /// Vibrate every connected controller; magnitudes from 0 to 1. Skipped when the
+    /// player turned rumble off in the settings.
+    #[func]
+    fn rumble(&self, weak: f32, strong: f32, duration_secs: f32) {
+        self.rumble_device(-1, weak, strong, duration_secs);
+    }
+
+    /// Same as `rumble` for one controller, -1 for all of them
+    #[func]
+    fn rumble_device(&self, device: i32, weak: f32, strong: f32, duration_secs: f32) {
+        self.push(NetworkCommand::Rumble(RumbleRequest {
+            device: (device >= 0).then_some(device),
+            weak,
+            strong,
+            duration_secs,
+        }));
+    }
+
+    fn push(&self, command: NetworkCommand) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(command);
+        } else {
+            godot_print!("NetworkManagerNode used before the Bevy app was ready");
+        }
+    }
+}
+
+#[godot_api]
+impl INode for NetworkManagerNode {
+    fn init(base: Base<Node>) -> Self {
+        Self { base, sender: None }
+    }
+
+    fn on_notification(&mut self, what: NodeNotification) {
+        match what {
+            NodeNotification::APPLICATION_PAUSED => self.push(NetworkCommand::Backgrounded),
+            NodeNotification::APPLICATION_RESUMED => self.push(NetworkCommand::Foregrounded),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct NetworkCommandReceiver(pub mpsc::Receiver<NetworkCommand>);
+
+pub struct NetworkManagerPlugin;
+
+impl Plugin for NetworkManagerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, start_network_manager_listener)
+            .add_systems(
+                Update,
+                (handle_network_commands, emit_network_signals)
+                    .run_if(resource_exists::<NetworkCommandReceiver>),
+            );
+    }
+}
+
+fn start_network_manager_listener(mut commands: Commands) {
+    let (tx, rx) = mpsc::channel::<NetworkCommand>(100);
+
+    commands.queue(move |world: &mut World| {
+        let mut query = world.query::<&mut GodotNodeHandle>();
+        for mut handle in query.iter_mut(world) {
+            if let Some(mut node) = handle.try_get::<NetworkManagerNode>() {
+                node.bind_mut().sender = Some(tx.clone());
+            }
+        }
+    });
+
+    commands.insert_resource(NetworkCommandReceiver(rx));
+}
+
+fn handle_network_commands(
+    mut receiver: ResMut<NetworkCommandReceiver>,
+    mut client: Transport,
+    mut to_server: EventWriter<SendToServer>,
+    mut username: ResMut<LocalUsername>,
+    mut pending: ResMut<PendingChat>,
+    users: Res<Users>,
+    limits: Res<ChatLimits>,
+    mut lifecycle: ResMut<AppLifecycle>,
+    mut commands: Commands,
+) {
+    while let Ok(command) = receiver.try_recv() {
+        match command {
+            NetworkCommand::SendChat(message) => {
+                send_chat(
+                    &mut commands,
+                    &mut to_server,
+                    &mut pending,
+                    &users,
+                    limits.apply(&message),
+                );
+            }
+            NetworkCommand::SetUsername(name) => {
+                if client.is_connected() {
+                    warn!("Username change will apply on the next connection");
+                }
+                username.0 = Some(name);
+            }
+            NetworkCommand::CreateRoom => {
+                to_server.write(SendToServer(ClientMessage::CreateRoom {}));
+            }
+            NetworkCommand::JoinRoom(code) => {
+                to_server.write(SendToServer(ClientMessage::JoinRoom { code }));
+            }
+            NetworkCommand::LeaveRoom => {
+                to_server.write(SendToServer(ClientMessage::LeaveRoom {}));
+            }
+            NetworkCommand::Disconnect => {
+                commands.send_event(LeaveGame);
+            }
+            NetworkCommand::Backgrounded => {
+                lifecycle.backgrounded = true;
+                // Straight to the transport, the app may be suspended before the outbox flushes
+                if client.is_connected() {
+                    client.try_send(ClientMessage::SetAway { away: true });
+                }
+            }
+            NetworkCommand::Foregrounded => {
+                lifecycle.backgrounded = false;
+                // A dropped connection is resumed by the migration path instead
+                if client.is_connected() {
+                    to_server.write(SendToServer(ClientMessage::SetAway { away: false }));
+                    commands.send_event(RequestFullSync);
+                }
+            }
+            NetworkCommand::Rumble(request) => {
+                commands.send_event(request);
+            }
+            NetworkCommand::SetMenuOpen(open) => {
+                commands.insert_resource(MenuOpen(open));
+            }
+        }
+    }
+}
+
+#[main_thread_system]
+fn emit_network_signals(
+    mut query: Query<&mut GodotNodeHandle, With<NetworkManagerComp>>,
+    mut transport_events: EventReader<TransportEvent>,
+    mut chat_events: EventReader<ChatMessage>,
+    mut room_events: EventReader<RoomChanged>,
+    mut migration_events: EventReader<MigrationEvent>,
+) {
+    let _timing = frame_budget::scope("emit_network_signals");
+    let transport_events: Vec<_> = transport_events.read().cloned().collect();
+    let chat_events: Vec<_> = chat_events
+        .read()
+        .map(|ev| (ev.username.clone(), ev.message.clone()))
+        .collect();
+    let room_events: Vec<String> = room_events
+        .read()
+        .map(|ev| ev.code.clone().unwrap_or_default())
+        .collect();
+    let migration_events: Vec<&str> = migration_events
+        .read()
+        .map(|ev| match ev {
+            MigrationEvent::Started => "started",
+            MigrationEvent::Resumed => "resumed",
+            MigrationEvent::Failed => "failed",
+        })
+        .collect();
+
+    for mut handle in query.iter_mut() {
+        let Some(mut node) = handle.try_get::<NetworkManagerNode>() else {
+            continue;
+        };
+        for ev in &transport_events {
+            match ev {
+                TransportEvent::Connected => {
+                    node.emit_signal("connected", &[]);
+                }
+                TransportEvent::Disconnected => {
+                    node.emit_signal("disconnected", &[]);
+                }
+                TransportEvent::ConnectionFailed(_) => {}
+            }
+        }
+        for (username, message) in &chat_events {
+            node.emit_signal(
+                "chat_received",
+                &[username.to_variant(), message.to_variant()],
+            );
+        }
+        for code in &room_events {
+            node.emit_signal("room_joined", &[code.to_variant()]);
+        }
+        for state in &migration_events {
+            node.emit_signal("migration", &[state.to_variant()]);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+use godot::{obj::Inherits, prelude::*};
+use godot_bevy::prelude::*;
+
+use crate::frame_budget;
+
+/// The Godot node behind this entity was freed (scene change, queue_free race, ...)
+#[derive(Event, Debug, Clone, Copy)]
+pub struct NodeInvalidated {
+    pub entity: Entity,
+}
+
+pub trait NodeHandleExt {
+    /// Like `GodotNodeHandle::get`, but reports a dead handle instead of panicking.
+    /// Returns None both for freed nodes and nodes of another type; only the former
+    /// is reported as invalidated.
+    fn get_or_invalidate<T>(
+        &mut self,
+        entity: Entity,
+        invalidated: &mut EventWriter<NodeInvalidated>,
+    ) -> Option<Gd<T>>
+    where
+        T: GodotClass + Inherits<Node>;
+}
+
+impl NodeHandleExt for GodotNodeHandle {
+    fn get_or_invalidate<T>(
+        &mut self,
+        entity: Entity,
+        invalidated: &mut EventWriter<NodeInvalidated>,
+    ) -> Option<Gd<T>>
+    where
+        T: GodotClass + Inherits<Node>,
+    {
+        let node = self.try_get::<T>();
+        if node.is_none() && self.try_get::<Node>().is_none() {
+            invalidated.write(NodeInvalidated { entity });
+        }
+        node
+    }
+}
+
+pub struct NodeSafetyPlugin;
+
+impl Plugin for NodeSafetyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<NodeInvalidated>()
+            .add_systems(First, detect_freed_nodes)
+            .add_systems(Last, despawn_invalidated_entities);
+    }
+}
+
+/// Sweep for handles whose node has been freed behind our back
+#[main_thread_system]
+fn detect_freed_nodes(
+    mut query: Query<(Entity, &mut GodotNodeHandle)>,
+    mut invalidated: EventWriter<NodeInvalidated>,
+) {
+    let _timing = frame_budget::scope("detect_freed_nodes");
+    for (entity, mut handle) in query.iter_mut() {
+        if handle.try_get::<Node>().is_none() {
+            invalidated.write(NodeInvalidated { entity });
+        }
+    }
+}
+
+fn despawn_invalidated_entities(
+    mut invalidated: EventReader<NodeInvalidated>,
+    mut commands: Commands,
+) {
+    for ev in invalidated.read() {
+        // Several systems may report the same entity in one frame
+        if let Ok(mut entity) = commands.get_entity(ev.entity) {
+            entity.try_despawn();
+        }
+    }
+}
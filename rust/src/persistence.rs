@@ -0,0 +1,59 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use protocol::{ChatRecord, MatchStats, PropState};
+use serde::{Deserialize, Serialize};
+
+use crate::accounts::AccountProfile;
+use crate::reports::PlayerReport;
+
+/// Everything the dedicated server writes to disk between restarts.
+/// Connections (and their ClientIds) don't survive a restart, players simply rejoin:
+/// where each connected player stood goes into its account's `last_position`, and its
+/// match score is handed back when it joins again. Rooms and the match clock start over.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerSnapshot {
+    /// Unix timestamp, seconds
+    pub saved_at: u64,
+    pub chat_history: Vec<ChatRecord>,
+    /// By account id
+    #[serde(default)]
+    pub accounts: HashMap<String, AccountProfile>,
+    #[serde(default)]
+    pub reports: Vec<PlayerReport>,
+    #[serde(default)]
+    pub props: Vec<PropState>,
+    /// Scores of the running match, by account id
+    #[serde(default)]
+    pub match_scores: HashMap<String, MatchStats>,
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn load_snapshot(path: &Path) -> io::Result<Option<ServerSnapshot>> {
+    match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Write to a temporary file first so a crash mid-write never leaves a truncated save
+pub fn write_snapshot(path: &Path, snapshot: &ServerSnapshot) -> io::Result<()> {
+    let data = serde_json::to_string_pretty(snapshot)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,164 @@
+//! Moving platforms and elevators. The server only sends their paths and its clock
+//! (`ServerMessage::Platforms`); every client moves them from that clock on its own, and
+//! carries the players standing on them, so riders move the same way everywhere
+//! without a message per frame.
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use godot::{classes::Node2D, prelude::*};
+use godot_bevy::prelude::*;
+use protocol::PlatformPath;
+
+use crate::frame_budget;
+use crate::net_events::from_server;
+use crate::net_stats::NetworkStats;
+use crate::player::{Player, PlayerNode, PlayerSystemSet};
+
+/// Resyncs moving our clock estimate by at most this are eased in, larger ones jump
+const MAX_EASED_CORRECTION_MS: i64 = 500;
+/// How long a correction takes to ease in; longer than the largest eased one, so the
+/// clock never runs backwards
+const CORRECTION_EASE: Duration = Duration::from_secs(1);
+
+/// The server's platforms and our estimate of its clock
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Platforms {
+    pub paths: Vec<PlatformPath>,
+    /// Server clock at the last sync, and when we got it
+    synced: Option<(u64, Instant)>,
+    /// How far ahead of the last sync our previous estimate was, eased out over
+    /// `CORRECTION_EASE` so platforms don't jump on every resync
+    correction_ms: i64,
+}
+
+impl Platforms {
+    /// The server clock now, None before the first sync
+    pub fn server_time_ms(&self) -> Option<u64> {
+        self.synced.map(|(server_ms, at)| {
+            let elapsed = at.elapsed();
+            let left = 1.0 - (elapsed.as_secs_f32() / CORRECTION_EASE.as_secs_f32()).min(1.0);
+            let correction = (self.correction_ms as f32 * left) as i64;
+            (server_ms as i64 + elapsed.as_millis() as i64 + correction).max(0) as u64
+        })
+    }
+}
+
+#[derive(Component, Default)]
+pub struct PlatformComp;
+
+/// Put in the level scene for each entry of the server's `platforms`, in the same
+/// order: it is moved along that entry's path
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Node2D, init)]
+#[bevy_bundle((PlatformComp))]
+pub struct PlatformNode {
+    base: Base<Node2D>,
+    /// Index in the server's `platforms`
+    #[export]
+    index: i32,
+}
+
+pub struct PlatformPlugin;
+
+impl Plugin for PlatformPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Platforms>()
+            .add_systems(Update, (track_platforms, move_platforms).chain())
+            .add_systems(
+                PhysicsUpdate,
+                carry_riders
+                    .after(PlayerSystemSet::Movement)
+                    .before(PlayerSystemSet::Animation),
+            );
+    }
+}
+
+fn track_platforms(
+    mut events: EventReader<from_server::Platforms>,
+    mut platforms: ResMut<Platforms>,
+    stats: Res<NetworkStats>,
+) {
+    if let Some(ev) = events.read().last() {
+        platforms.paths = ev.platforms.clone();
+        // The clock was read half a round trip ago
+        let server_ms = ev.server_time_ms + (stats.rtt / 2).as_millis() as u64;
+        let correction = platforms
+            .server_time_ms()
+            .map_or(0, |estimate| estimate as i64 - server_ms as i64);
+        platforms.correction_ms = if correction.abs() <= MAX_EASED_CORRECTION_MS {
+            correction
+        } else {
+            0
+        };
+        platforms.synced = Some((server_ms, Instant::now()));
+    }
+}
+
+#[main_thread_system]
+fn move_platforms(
+    platforms: Res<Platforms>,
+    mut query: Query<&mut GodotNodeHandle, With<PlatformComp>>,
+) {
+    let _timing = frame_budget::scope("move_platforms");
+    let Some(now) = platforms.server_time_ms() else {
+        return;
+    };
+    for mut handle in query.iter_mut() {
+        let Some(mut node) = handle.try_get::<PlatformNode>() else {
+            continue;
+        };
+        let index = node.bind().index;
+        let Some(path) = usize::try_from(index)
+            .ok()
+            .and_then(|index| platforms.paths.get(index))
+        else {
+            continue;
+        };
+        let (x, y) = path.position_at(now);
+        node.set_position(Vector2::new(x, y));
+    }
+}
+
+/// Move every player standing on a platform by as much as the platform moved since the
+/// last physics tick, ours like the others, so everyone predicts the same ride
+#[main_thread_system]
+fn carry_riders(
+    platforms: Res<Platforms>,
+    mut players: Query<&mut GodotNodeHandle, With<Player>>,
+    mut last_tick_ms: Local<Option<u64>>,
+) {
+    let _timing = frame_budget::scope("carry_riders");
+    let Some(now) = platforms.server_time_ms() else {
+        return;
+    };
+    let Some(previous) = last_tick_ms.replace(now) else {
+        return;
+    };
+    if platforms.paths.is_empty() || previous == now {
+        return;
+    }
+    let moves: Vec<((f32, f32), Vector2)> = platforms
+        .paths
+        .iter()
+        .map(|path| {
+            let from = path.position_at(previous);
+            let to = path.position_at(now);
+            (from, Vector2::new(to.0 - from.0, to.1 - from.1))
+        })
+        .collect();
+    for mut handle in players.iter_mut() {
+        let Some(mut player_node) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+        let position = player_node.get_position();
+        let ride = platforms
+            .paths
+            .iter()
+            .zip(&moves)
+            .find(|(path, (from, _))| path.carries(*from, (position.x, position.y)));
+        if let Some((_, (_, delta))) = ride {
+            player_node.set_position(position + *delta);
+        }
+    }
+}
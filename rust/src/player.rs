@@ -1,15 +1,41 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
 use bevy::prelude::*;
 use bevy_quinnet::shared::ClientId;
 use godot::{
-    classes::{AnimatedSprite2D, CharacterBody2D, Input, ResourceLoader},
+    classes::{AnimatedSprite2D, CharacterBody2D, Engine, Input, Label, ResourceLoader},
     prelude::*,
 };
 use godot_bevy::prelude::*;
-
-use crate::Users;
+use protocol::{Cosmetics, GameRules, WorldBounds};
+use rand::Rng;
+
+use crate::flow::in_game;
+use crate::frame_budget;
+use crate::host::HostState;
+use crate::input_context::InputContext;
+use crate::lifecycle::AppLifecycle;
+use crate::net_events::from_server;
+use crate::net_send::{NetSendClock, NetSendSet};
+use crate::settings::ClientSettings;
+use crate::shared_rng::{RngStream, SharedRng};
+use crate::transport::{ConnectionState, SendToServer};
+use crate::{SyncState, Users};
 
 const PLAYER_SPEED: f32 = 150.0;
 const INPUT_DEADZONE: f32 = 0.2;
+/// Physics layer (1-based) every PlayerNode is on; masked in only when players collide
+const PLAYER_COLLISION_LAYER: i32 = 2;
+/// Remote players keep moving on their last input this long after updates stop
+const EXTRAPOLATION_WINDOW: Duration = Duration::from_millis(250);
+/// Child label shown over players whose updates stalled
+const STALL_INDICATOR_NAME: &str = "ConnectionIssue";
+/// A teleported player's sprite fades in over this long, unless the scene handles
+/// `teleported` itself
+const TELEPORT_FADE_SECS: f64 = 0.2;
 
 #[derive(Component, Default, Clone, Copy)]
 pub struct Player(pub ClientId);
@@ -17,6 +43,13 @@ pub struct Player(pub ClientId);
 #[derive(Component, Default, Clone, Copy)]
 pub struct PlayerFacing(pub FacingDir);
 
+/// Our own input as last read on the physics tick, sent by `send_player_update`
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct LocalInput {
+    pub horizontal: f32,
+    pub vertical: f32,
+}
+
 // Persist last known input for smooth motion/animation across frames
 #[derive(Component, Default, Clone, Copy)]
 pub struct PlayerInputState {
@@ -24,15 +57,98 @@ pub struct PlayerInputState {
     pub vertical: f32,
 }
 
+/// When this player's input last arrived, for dead reckoning over short stalls
+#[derive(Component, Default, Clone, Copy)]
+pub struct RemoteMotion {
+    pub last_update: Option<Instant>,
+    pub stalled: bool,
+    /// Last position the server reported, and when
+    pub authoritative: Option<(Vector2, Instant)>,
+}
+
+/// Visual offset of the sprite from the body after a server correction, decayed
+/// to zero by `decay_correction_offsets`. The body (simulation) snaps right away.
+#[derive(Component, Default, Clone, Copy)]
+pub struct CorrectionOffset {
+    pub offset: Vector2,
+    /// What the sprite is currently moved by
+    applied: Vector2,
+}
+
+/// How server corrections are smoothed out
+#[derive(Resource, Debug, Clone)]
+pub struct CorrectionSmoothing {
+    /// Corrections shorter than this are ignored, they only add jitter
+    pub min_distance: f32,
+    /// Corrections longer than this are shown as a teleport
+    pub max_distance: f32,
+    /// Time for the remaining offset to halve
+    pub half_life: Duration,
+}
+
+impl Default for CorrectionSmoothing {
+    fn default() -> Self {
+        Self {
+            min_distance: 2.0,
+            max_distance: 128.0,
+            half_life: Duration::from_millis(60),
+        }
+    }
+}
+
+/// Our own player's body after the last two physics ticks, and how far its sprite is
+/// moved back towards the older one, see `interpolate_local_player`
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct LocalInterpolation {
+    previous: Option<Vector2>,
+    current: Option<Vector2>,
+    applied: Vector2,
+}
+
 // Track last played animation to avoid restarting the same animation every frame
 #[derive(Component, Default, Clone)]
 pub struct PlayerAnimState {
     pub current: String,
 }
+/// Maps each client to its spawned player entity
+#[derive(Resource, Default, Debug)]
+pub struct PlayerIndex(pub HashMap<ClientId, Entity>);
+
+/// Latest server position of each remote player this frame, applied in one pass.
+/// Drained rather than rebuilt so its capacity is reused from frame to frame.
+#[derive(Resource, Default, Debug)]
+pub struct RemoteMoves(pub HashMap<ClientId, Vector2>);
+
+/// Inputs received for each player since its last movement tick, oldest first.
+/// Drained in order every tick, so rapid direction changes arriving in one busy
+/// frame are all applied; the queues are kept to reuse their capacity.
+#[derive(Resource, Default, Debug)]
+pub struct PlayerInputQueues(pub HashMap<ClientId, VecDeque<QueuedInput>>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueuedInput {
+    /// Where in the tick the input starts to apply, from 0 to 1
+    pub offset: f32,
+    pub horizontal: f32,
+    pub vertical: f32,
+}
+
+/// Rules last sent by the server
+#[derive(Resource, Default, Debug, Clone)]
+pub struct ActiveRules(pub GameRules);
+
 #[derive(Event)]
 pub struct SpawnPlayerEvent {
     pub client_id: ClientId,
     pub position: Option<Vector2>,
+    pub cosmetics: Cosmetics,
+}
+
+/// A player's session was resumed under a new client id
+#[derive(Event, Clone, Copy)]
+pub struct PlayerMigrated {
+    pub from: ClientId,
+    pub to: ClientId,
 }
 
 #[derive(Event, Default, Clone)]
@@ -40,6 +156,10 @@ pub struct PlayerInputEvent {
     pub client_id: ClientId,
     pub horizontal: f32,
     pub vertical: f32,
+    /// When an input from the network arrived. Inputs arriving between two physics
+    /// ticks are replayed at the same point of the next one; None applies it from the
+    /// start of the tick (our own input, read at the start of it).
+    pub received: Option<Instant>,
 }
 
 // Player facing direction (cardinal only)
@@ -77,15 +197,26 @@ pub struct PlayerNode {
     pub client_id: u32,
 }
 
+#[godot_api]
+impl PlayerNode {
+    /// The server moved this player, e.g. on respawn; hook a teleport effect here. The
+    /// sprite fades in when nothing is connected.
+    #[signal]
+    fn teleported(from: Vector2, to: Vector2);
+}
+
 #[derive(Resource)]
 pub struct PlayerSceneResource {
     pub scene_path: String,
+    /// Holds a `<id>.tscn` per cosmetic, see `Cosmetics`
+    pub cosmetics_dir: String,
 }
 
 impl Default for PlayerSceneResource {
     fn default() -> Self {
         Self {
             scene_path: "res://player.tscn".to_string(),
+            cosmetics_dir: "res://cosmetics".to_string(),
         }
     }
 }
@@ -95,21 +226,49 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<PlayerSceneResource>()
+            .init_resource::<PlayerIndex>()
+            .init_resource::<RemoteMoves>()
+            .init_resource::<PlayerInputQueues>()
+            .init_resource::<ActiveRules>()
+            .init_resource::<CorrectionSmoothing>()
+            .init_resource::<LocalInput>()
+            .init_resource::<LocalInterpolation>()
             .add_event::<PlayerInputEvent>()
             .add_event::<SpawnPlayerEvent>()
+            .add_event::<PlayerMigrated>()
             .add_systems(
                 PhysicsUpdate,
                 (
-                    player_input_system.in_set(PlayerSystemSet::InputDetection),
+                    player_input_system
+                        .in_set(PlayerSystemSet::InputDetection)
+                        .run_if(in_game),
                     player_movement_system.in_set(PlayerSystemSet::Movement),
                     player_animation_system.in_set(PlayerSystemSet::Animation),
                 )
                     .chain(),
             )
+            .add_systems(
+                PhysicsUpdate,
+                record_local_position.in_set(PlayerSystemSet::Animation),
+            )
             .add_systems(
                 Update,
-                player_spawner_system.in_set(PlayerSystemSet::Spawning),
-            );
+                (
+                    player_spawner_system.in_set(PlayerSystemSet::Spawning),
+                    apply_player_collision
+                        .run_if(resource_changed::<ActiveRules>)
+                        .after(PlayerSystemSet::Spawning),
+                    show_stall_indicators,
+                    apply_remote_moves.after(PlayerSystemSet::Spawning),
+                    apply_teleports.after(apply_remote_moves),
+                    update_cosmetics.after(PlayerSystemSet::Spawning),
+                    decay_correction_offsets.after(apply_teleports),
+                    interpolate_local_player.after(decay_correction_offsets),
+                    migrate_players.after(PlayerSystemSet::Spawning),
+                    send_player_update.in_set(NetSendSet).run_if(in_game),
+                ),
+            )
+            .add_systems(Last, prune_player_index);
     }
 }
 
@@ -118,8 +277,16 @@ fn player_spawner_system(
     mut commands: Commands,
     mut spawn_events: EventReader<SpawnPlayerEvent>,
     scene_resource: Res<PlayerSceneResource>,
+    mut index: ResMut<PlayerIndex>,
+    shared_rng: Res<SharedRng>,
+    rules: Res<ActiveRules>,
 ) {
+    let _timing = frame_budget::scope("player_spawner_system");
     for event in spawn_events.read() {
+        if index.0.contains_key(&event.client_id) {
+            godot_print!("Player for client {:?} already spawned", event.client_id);
+            continue;
+        }
         godot_print!("Spawning player for client: {:?}", event.client_id);
 
         // Load the player scene
@@ -139,11 +306,6 @@ fn player_spawner_system(
         // Get the root node as CharacterBody2D
         let character = instance.try_cast::<PlayerNode>();
         if let Ok(mut character) = character {
-            // Set initial position if provided
-            if let Some(position) = event.position {
-                character.set_position(position);
-            }
-
             // Set the client_id field directly on the PlayerNode
             let raw_id = event.client_id;
             godot_print!("Setting player node client_id field to: {}", raw_id);
@@ -156,6 +318,8 @@ fn player_spawner_system(
                 PlayerFacing::default(),
                 PlayerInputState::default(),
                 PlayerAnimState::default(),
+                RemoteMotion::default(),
+                CorrectionOffset::default(),
             ));
 
             godot_print!(
@@ -163,6 +327,7 @@ fn player_spawner_system(
                 entity.id(),
                 event.client_id
             );
+            index.0.insert(event.client_id, entity.id());
 
             // Now add to scene tree AFTER creating the entity
             let mut root = godot::classes::Engine::singleton()
@@ -174,13 +339,19 @@ fn player_spawner_system(
             // First add to the scene tree
             root.add_child(&character);
 
-            // Try multiple ways to ensure position is set
-            // Set position with a random X coordinate between 200 and 600
-            let random_x = rand::random::<f32>() * 400.0 + 200.0;
-            character.set_position(Vector2::new(random_x, 100.0));
-            character.set_global_position(Vector2::new(random_x, 100.0));
+            // The server picks spawn points; without one, fall back to a seeded
+            // X between 200 and 600 so every client still agrees
+            let position = event.position.unwrap_or_else(|| {
+                let x = shared_rng
+                    .rng_for(RngStream::SpawnOffset, event.client_id)
+                    .gen_range(200.0..600.0);
+                Vector2::new(x, 100.0)
+            });
+            character.set_global_position(position);
+            set_player_collision(&mut character, &rules.0);
 
             character.set_velocity(Vector2::ZERO);
+            attach_cosmetics(&mut character, &event.cosmetics, &scene_resource);
 
             godot_print!(
                 "Player spawned and added to scene with client ID: {:?}",
@@ -192,13 +363,348 @@ fn player_spawner_system(
     }
 }
 
+/// Replace the player's cosmetic children (named after their slot) with the scenes of
+/// `cosmetics`
+fn attach_cosmetics(
+    character: &mut Gd<PlayerNode>,
+    cosmetics: &Cosmetics,
+    scene_resource: &PlayerSceneResource,
+) {
+    for (slot, id) in cosmetics.slots() {
+        if let Some(mut old) = character.get_node_or_null(slot) {
+            // Out of the tree now so the new one can take its name
+            character.remove_child(&old);
+            old.queue_free();
+        }
+        let Some(id) = id.filter(|id| Cosmetics::valid_id(id)) else {
+            continue;
+        };
+        let path = format!("{}/{}.tscn", scene_resource.cosmetics_dir, id);
+        let Some(mut node) = ResourceLoader::singleton()
+            .load(&path)
+            .and_then(|resource| resource.try_cast::<PackedScene>().ok())
+            .and_then(|scene| scene.instantiate())
+        else {
+            godot_warn!("Unknown cosmetic {} (no {})", id, path);
+            continue;
+        };
+        node.set_name(slot);
+        character.add_child(&node);
+    }
+}
+
+/// A player changed clothes while we could see it
+#[main_thread_system]
+fn update_cosmetics(
+    mut events: EventReader<from_server::CosmeticsChanged>,
+    index: Res<PlayerIndex>,
+    scene_resource: Res<PlayerSceneResource>,
+    mut query: Query<&mut GodotNodeHandle, With<Player>>,
+) {
+    let _timing = frame_budget::scope("update_cosmetics");
+    for ev in events.read() {
+        let Some(mut handle) = index
+            .0
+            .get(&ev.client_id)
+            .and_then(|entity| query.get_mut(*entity).ok())
+        else {
+            continue;
+        };
+        if let Some(mut character) = handle.try_get::<PlayerNode>() {
+            attach_cosmetics(&mut character, &ev.cosmetics, &scene_resource);
+        }
+    }
+}
+
+fn set_player_collision(character: &mut Gd<PlayerNode>, rules: &GameRules) {
+    character.set_collision_layer_value(PLAYER_COLLISION_LAYER, true);
+    character.set_collision_mask_value(PLAYER_COLLISION_LAYER, rules.player_collision);
+}
+
+/// Rules changed mid-match, update the players already in the scene
+#[main_thread_system]
+fn apply_player_collision(
+    rules: Res<ActiveRules>,
+    mut query: Query<&mut GodotNodeHandle, With<Player>>,
+) {
+    let _timing = frame_budget::scope("apply_player_collision");
+    for mut handle in query.iter_mut() {
+        if let Some(mut character) = handle.try_get::<PlayerNode>() {
+            set_player_collision(&mut character, &rules.0);
+        }
+    }
+}
+
+/// Flag remote players whose updates stopped arriving
+#[main_thread_system]
+fn show_stall_indicators(
+    mut query: Query<(&mut GodotNodeHandle, &RemoteMotion), Changed<RemoteMotion>>,
+) {
+    let _timing = frame_budget::scope("show_stall_indicators");
+    for (mut handle, motion) in query.iter_mut() {
+        let Some(mut player_node) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+        let indicator = player_node.get_node_or_null(STALL_INDICATOR_NAME);
+        match (motion.stalled, indicator) {
+            (true, None) => {
+                let mut label = Label::new_alloc();
+                label.set_name(STALL_INDICATOR_NAME);
+                label.set_text("⚠ connection issues");
+                label.set_modulate(Color::YELLOW);
+                label.set_position(Vector2::new(-56.0, -40.0));
+                player_node.add_child(&label);
+            }
+            (false, Some(mut indicator)) => indicator.queue_free(),
+            _ => {}
+        }
+    }
+}
+
+#[main_thread_system]
+fn apply_remote_moves(
+    mut moves: ResMut<RemoteMoves>,
+    index: Res<PlayerIndex>,
+    smoothing: Res<CorrectionSmoothing>,
+    mut query: Query<(
+        &mut GodotNodeHandle,
+        &mut RemoteMotion,
+        &mut CorrectionOffset,
+    )>,
+) {
+    let _timing = frame_budget::scope("apply_remote_moves");
+    for (client_id, position) in moves.0.drain() {
+        let Some((mut handle, mut motion, mut correction)) = index
+            .0
+            .get(&client_id)
+            .and_then(|entity| query.get_mut(*entity).ok())
+        else {
+            continue;
+        };
+        let Some(mut player_node) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+        motion.authoritative = Some((position, Instant::now()));
+        let shown = player_node.get_position() + correction.offset;
+        let error = player_node.get_position().distance_to(position);
+        if error <= smoothing.min_distance {
+            continue;
+        }
+        player_node.set_position(position);
+        // Keep showing the player where it was and let the offset decay from there
+        correction.offset = if error > smoothing.max_distance {
+            Vector2::ZERO
+        } else {
+            shown - position
+        };
+    }
+}
+
+/// Move teleported players, our own included. The body snaps; a short move (a position
+/// the server corrected) is eased like any other correction, a long one fades in.
+#[main_thread_system]
+fn apply_teleports(
+    mut events: EventReader<from_server::Teleport>,
+    index: Res<PlayerIndex>,
+    smoothing: Res<CorrectionSmoothing>,
+    mut query: Query<(
+        &mut GodotNodeHandle,
+        &mut RemoteMotion,
+        &mut CorrectionOffset,
+    )>,
+) {
+    let _timing = frame_budget::scope("apply_teleports");
+    for ev in events.read() {
+        let Some((mut handle, mut motion, mut correction)) = index
+            .0
+            .get(&ev.client_id)
+            .and_then(|entity| query.get_mut(*entity).ok())
+        else {
+            continue;
+        };
+        let Some(mut player_node) = handle.try_get::<PlayerNode>() else {
+            continue;
+        };
+        let from = player_node.get_position();
+        let to = Vector2::new(ev.x, ev.y);
+        let shown = from + correction.offset;
+        player_node.set_position(to);
+        player_node.set_velocity(Vector2::ZERO);
+        motion.authoritative = Some((to, Instant::now()));
+        let eased = from.distance_to(to) <= smoothing.max_distance;
+        correction.offset = if eased { shown - to } else { Vector2::ZERO };
+
+        if !eased
+            && player_node
+                .get_signal_connection_list("teleported")
+                .is_empty()
+        {
+            if let Some(mut sprite) =
+                player_node.try_get_node_as::<AnimatedSprite2D>("AnimatedSprite2D")
+            {
+                sprite.set_modulate(Color::from_rgba(1.0, 1.0, 1.0, 0.0));
+                if let Some(mut tween) = sprite.create_tween() {
+                    tween.tween_property(
+                        &sprite,
+                        "modulate",
+                        &Color::WHITE.to_variant(),
+                        TELEPORT_FADE_SECS,
+                    );
+                }
+            }
+        }
+        player_node.emit_signal("teleported", &[from.to_variant(), to.to_variant()]);
+    }
+}
+
+/// Ease the sprites of corrected players back onto their bodies
+#[main_thread_system]
+fn decay_correction_offsets(
+    smoothing: Res<CorrectionSmoothing>,
+    mut query: Query<(&mut GodotNodeHandle, &mut CorrectionOffset)>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    let _timing = frame_budget::scope("decay_correction_offsets");
+    let now = Instant::now();
+    let elapsed = now.duration_since(last_frame.replace(now).unwrap_or(now));
+    let half_lives = elapsed.as_secs_f32() / smoothing.half_life.as_secs_f32().max(0.001);
+    let decay = 0.5f32.powf(half_lives);
+    for (mut handle, mut correction) in query.iter_mut() {
+        if correction.offset == Vector2::ZERO && correction.applied == Vector2::ZERO {
+            continue;
+        }
+        correction.offset *= decay;
+        if correction.offset.length() < 0.1 {
+            correction.offset = Vector2::ZERO;
+        }
+        let Some(mut sprite) = handle
+            .try_get::<PlayerNode>()
+            .and_then(|player| player.try_get_node_as::<AnimatedSprite2D>("AnimatedSprite2D"))
+        else {
+            continue;
+        };
+        let position = sprite.get_position();
+        sprite.set_position(position + correction.offset - correction.applied);
+        correction.applied = correction.offset;
+    }
+}
+
+/// Where the physics tick left our own player, after movement and platform rides
+#[main_thread_system]
+fn record_local_position(
+    users: Res<Users>,
+    mut interpolation: ResMut<LocalInterpolation>,
+    mut query: Query<(&Player, &mut GodotNodeHandle)>,
+) {
+    let _timing = frame_budget::scope("record_local_position");
+    let Some(position) = query
+        .iter_mut()
+        .find(|(player, _)| player.0 == users.self_id)
+        .and_then(|(_, mut handle)| handle.try_get::<PlayerNode>())
+        .map(|player_node| player_node.get_position())
+    else {
+        *interpolation = LocalInterpolation {
+            applied: interpolation.applied,
+            ..default()
+        };
+        return;
+    };
+    interpolation.previous = Some(interpolation.current.unwrap_or(position));
+    interpolation.current = Some(position);
+}
+
+/// Draw our own player's sprite between its last two physics positions, by how far
+/// the frame is into the next tick. Off by default (`interpolate_local_player`); a body
+/// moved outside the physics tick (teleport, correction) is drawn where it is.
+#[main_thread_system]
+fn interpolate_local_player(
+    settings: Res<ClientSettings>,
+    users: Res<Users>,
+    mut interpolation: ResMut<LocalInterpolation>,
+    mut query: Query<(&Player, &mut GodotNodeHandle)>,
+) {
+    let _timing = frame_budget::scope("interpolate_local_player");
+    if !settings.interpolate_local_player && interpolation.applied == Vector2::ZERO {
+        return;
+    }
+    let Some(player_node) = query
+        .iter_mut()
+        .find(|(player, _)| player.0 == users.self_id)
+        .and_then(|(_, mut handle)| handle.try_get::<PlayerNode>())
+    else {
+        return;
+    };
+    let position = player_node.get_position();
+    let offset = match (interpolation.previous, interpolation.current) {
+        (Some(previous), Some(current))
+            if settings.interpolate_local_player && current == position =>
+        {
+            let fraction = Engine::singleton().get_physics_interpolation_fraction() as f32;
+            previous.lerp(current, fraction.clamp(0.0, 1.0)) - current
+        }
+        _ => {
+            // Moved since the tick, start over from here
+            interpolation.previous = Some(position);
+            interpolation.current = Some(position);
+            Vector2::ZERO
+        }
+    };
+    let Some(mut sprite) = player_node.try_get_node_as::<AnimatedSprite2D>("AnimatedSprite2D")
+    else {
+        return;
+    };
+    let sprite_position = sprite.get_position();
+    sprite.set_position(sprite_position + offset - interpolation.applied);
+    interpolation.applied = offset;
+}
+
+#[main_thread_system]
+fn migrate_players(
+    mut events: EventReader<PlayerMigrated>,
+    mut index: ResMut<PlayerIndex>,
+    mut query: Query<(&mut Player, &mut GodotNodeHandle)>,
+) {
+    let _timing = frame_budget::scope("migrate_players");
+    for ev in events.read() {
+        let Some(entity) = index.0.remove(&ev.from) else {
+            continue;
+        };
+        index.0.insert(ev.to, entity);
+        if let Ok((mut player, mut handle)) = query.get_mut(entity) {
+            player.0 = ev.to;
+            if let Some(mut node) = handle.try_get::<PlayerNode>() {
+                node.bind_mut().client_id = ev.to as u32;
+            }
+        }
+    }
+}
+
+fn prune_player_index(
+    mut removed: RemovedComponents<Player>,
+    mut index: ResMut<PlayerIndex>,
+    mut queues: ResMut<PlayerInputQueues>,
+) {
+    for entity in removed.read() {
+        index.0.retain(|client_id, e| {
+            if *e == entity {
+                queues.0.remove(client_id);
+            }
+            *e != entity
+        });
+    }
+}
+
 #[main_thread_system]
 fn player_input_system(
     mut query: Query<(&Player, &mut GodotNodeHandle)>,
     mut input_events: EventWriter<PlayerInputEvent>,
-    mut client: ResMut<bevy_quinnet::client::QuinnetClient>,
+    mut local_input: ResMut<LocalInput>,
     users: Res<Users>,
+    lifecycle: Res<AppLifecycle>,
+    host: Res<HostState>,
+    context: Res<InputContext>,
 ) {
+    let _timing = frame_budget::scope("player_input_system");
     for (player, mut handle) in query.iter_mut() {
         let player_node = handle.try_get::<PlayerNode>();
         if player_node.is_none() {
@@ -220,23 +726,21 @@ fn player_input_system(
             if vertical.abs() < INPUT_DEADZONE {
                 vertical = 0.0;
             }
-
-            let player_node = handle.get::<CharacterBody2D>();
+            if lifecycle.backgrounded || host.paused || *context != InputContext::Gameplay {
+                horizontal = 0.0;
+                vertical = 0.0;
+            }
 
             input_events.write(PlayerInputEvent {
                 client_id: users.self_id,
                 horizontal,
                 vertical,
+                received: None,
             });
-
-            client.connection_mut().try_send_message(
-                crate::protocol::ClientMessage::PlayerUpdate {
-                    x: player_node.get_position().x,
-                    y: player_node.get_position().y,
-                    horizontal,
-                    vertical,
-                },
-            );
+            *local_input = LocalInput {
+                horizontal,
+                vertical,
+            };
 
             // We found our player, no need to check others
             break;
@@ -244,6 +748,57 @@ fn player_input_system(
     } // End of for loop
 }
 
+/// Send our position and input when the send clock ticks; input changes go out right away
+#[main_thread_system]
+fn send_player_update(
+    mut query: Query<(&Player, &mut GodotNodeHandle)>,
+    mut to_server: EventWriter<SendToServer>,
+    local_input: Res<LocalInput>,
+    clock: Res<NetSendClock>,
+    connection: Res<ConnectionState>,
+    sync: Res<SyncState>,
+    users: Res<Users>,
+    mut seq: Local<u32>,
+    mut last_sent: Local<Option<(f32, f32)>>,
+    mut clock_origin: Local<Option<Instant>>,
+) {
+    let _timing = frame_budget::scope("send_player_update");
+    // Movement is only useful live, don't queue it while connecting or syncing
+    if *connection != ConnectionState::Ready || *sync != SyncState::Ready {
+        return;
+    }
+    let LocalInput {
+        horizontal,
+        vertical,
+    } = *local_input;
+    let changed = last_sent.is_none_or(|sent| sent != (horizontal, vertical));
+    if !clock.due && !changed {
+        return;
+    }
+    let Some(player_node) = query
+        .iter_mut()
+        .find(|(player, _)| player.0 == users.self_id)
+        .and_then(|(_, mut handle)| handle.try_get::<PlayerNode>())
+    else {
+        return;
+    };
+
+    *last_sent = Some((horizontal, vertical));
+    *seq = seq.wrapping_add(1);
+    let position = player_node.get_position();
+    to_server.write(SendToServer(protocol::ClientMessage::PlayerUpdate {
+        seq: *seq,
+        x: position.x,
+        y: position.y,
+        horizontal,
+        vertical,
+        sent_at_ms: clock_origin
+            .get_or_insert_with(Instant::now)
+            .elapsed()
+            .as_millis() as u32,
+    }));
+}
+
 #[main_thread_system]
 fn player_movement_system(
     mut input_events: EventReader<PlayerInputEvent>,
@@ -252,17 +807,40 @@ fn player_movement_system(
         &mut GodotNodeHandle,
         &mut PlayerFacing,
         &mut PlayerInputState,
+        &mut RemoteMotion,
     )>,
     _physics_delta: Res<PhysicsDelta>,
+    rules: Res<ActiveRules>,
+    mut queues: ResMut<PlayerInputQueues>,
+    mut last_tick: Local<Option<Instant>>,
 ) {
-    // Collect input events by client_id for faster lookup
-    let mut input_by_client = std::collections::HashMap::new();
+    let _timing = frame_budget::scope("player_movement_system");
+    let now = Instant::now();
+    let since_last_tick = last_tick.replace(now).map(|last| now.duration_since(last));
+
+    // Queue every input of this tick in arrival order: a fast peer can get several
+    // updates in between two ticks
     for input_event in input_events.read() {
-        input_by_client.insert(input_event.client_id, input_event.clone());
+        let offset = match (input_event.received, since_last_tick) {
+            (Some(received), Some(span)) if !span.is_zero() => {
+                let since = received.saturating_duration_since(now - span);
+                (since.as_secs_f32() / span.as_secs_f32()).min(1.0)
+            }
+            _ => 0.0,
+        };
+        queues
+            .0
+            .entry(input_event.client_id)
+            .or_default()
+            .push_back(QueuedInput {
+                offset,
+                horizontal: deadzone(input_event.horizontal),
+                vertical: deadzone(input_event.vertical),
+            });
     }
 
     // Process all players
-    for (player, mut handle, mut facing, mut input_state) in query.iter_mut() {
+    for (player, mut handle, mut facing, mut input_state, mut motion) in query.iter_mut() {
         let client_id = player.0;
         let player_node = handle.try_get::<PlayerNode>();
         if player_node.is_none() {
@@ -270,31 +848,45 @@ fn player_movement_system(
         }
         let mut player_node = player_node.unwrap();
 
-        // Start with zero velocity
-        let mut velocity = Vector2::ZERO;
-
         // Determine effective input for this player, persist when new input arrives
-        let mut h = input_state.horizontal;
-        let mut v = input_state.vertical;
-        if let Some(input) = input_by_client.get(&client_id) {
-            h = input.horizontal;
-            v = input.vertical;
-            // Deadzone filtering
-            if h.abs() < INPUT_DEADZONE {
-                h = 0.0;
+        let mut held = (input_state.horizontal, input_state.vertical);
+        let mut no_input = VecDeque::new();
+        let queue = match queues
+            .0
+            .get_mut(&client_id)
+            .filter(|queue| !queue.is_empty())
+        {
+            Some(queue) => {
+                motion.last_update = Some(Instant::now());
+                motion.stalled = false;
+                queue
             }
-            if v.abs() < INPUT_DEADZONE {
-                v = 0.0;
+            None => {
+                if !motion.stalled
+                    && motion
+                        .last_update
+                        .is_some_and(|last| last.elapsed() > EXTRAPOLATION_WINDOW)
+                {
+                    // Extrapolated long enough, stop rather than run off on stale input
+                    motion.stalled = true;
+                    held = (0.0, 0.0);
+                }
+                &mut no_input
             }
-            // Persist
-            input_state.horizontal = h;
-            input_state.vertical = v;
+        };
+        let (position, (h, v)) = predict_tick(held, queue, rules.0.bounds, |velocity| {
+            player_node.set_velocity(velocity);
+            player_node.move_and_slide();
+            player_node.get_position()
+        });
+        input_state.horizontal = h;
+        input_state.vertical = v;
+        if position != player_node.get_position() {
+            player_node.set_position(position);
         }
 
-        // Compute velocity and facing from persisted input
+        // Compute facing from persisted input
         if h != 0.0 || v != 0.0 {
-            velocity.x = h * PLAYER_SPEED;
-            velocity.y = v * PLAYER_SPEED;
             // Update facing to the primary cardinal direction
             let ax = h.abs();
             let ay = v.abs();
@@ -309,15 +901,74 @@ fn player_movement_system(
             } else {
                 FacingDir::Up
             };
-            velocity = velocity.normalized() * PLAYER_SPEED;
         }
+    }
 
-        // Apply to Godot node
-        player_node.set_velocity(velocity);
-        player_node.move_and_slide();
+    // Inputs for players not spawned (yet) would only be stale by then
+    for queue in queues.0.values_mut() {
+        queue.clear();
     }
 }
 
+fn deadzone(axis: f32) -> f32 {
+    if axis.abs() < INPUT_DEADZONE {
+        0.0
+    } else {
+        axis
+    }
+}
+
+/// Every player moves at full speed in the direction of its input
+fn input_velocity(horizontal: f32, vertical: f32) -> Vector2 {
+    if horizontal == 0.0 && vertical == 0.0 {
+        Vector2::ZERO
+    } else {
+        Vector2::new(horizontal, vertical).normalized() * PLAYER_SPEED
+    }
+}
+
+/// Apply the queued inputs in order: the velocity over one tick, each input's weighted
+/// by the part of the tick it was held for (`held` until the first one), and the
+/// input held at the end of it
+fn drain_inputs(held: (f32, f32), queue: &mut VecDeque<QueuedInput>) -> (Vector2, (f32, f32)) {
+    let mut velocity = Vector2::ZERO;
+    let (mut from, mut current) = (0.0, held);
+    while let Some(input) = queue.pop_front() {
+        // Offsets only move forward, an input is never applied before an older one
+        let offset = input.offset.max(from);
+        velocity += input_velocity(current.0, current.1) * (offset - from);
+        from = offset;
+        current = (input.horizontal, input.vertical);
+    }
+    (
+        velocity + input_velocity(current.0, current.1) * (1.0 - from),
+        current,
+    )
+}
+
+/// One physics tick of a player's movement, as the game predicts it: the velocity of the
+/// held and queued inputs (see `drain_inputs`), the body moved by it with `slide`, and
+/// the result kept within `bounds` since the server would clamp it anyway. `slide` is
+/// Godot's `move_and_slide` in the game, a straight line for the determinism check.
+/// Returns the new position and the input held from now on.
+pub fn predict_tick(
+    held: (f32, f32),
+    queue: &mut VecDeque<QueuedInput>,
+    bounds: Option<WorldBounds>,
+    slide: impl FnOnce(Vector2) -> Vector2,
+) -> (Vector2, (f32, f32)) {
+    let (velocity, held) = drain_inputs(held, queue);
+    let position = slide(velocity);
+    let position = match bounds {
+        Some(bounds) => {
+            let (x, y) = bounds.clamp(position.x, position.y);
+            Vector2::new(x, y)
+        }
+        None => position,
+    };
+    (position, held)
+}
+
 #[main_thread_system]
 fn player_animation_system(
     mut query: Query<(
@@ -328,6 +979,7 @@ fn player_animation_system(
         &mut PlayerAnimState,
     )>,
 ) {
+    let _timing = frame_budget::scope("player_animation_system");
     for (_player, mut handle, facing, input_state, mut anim_state) in query.iter_mut() {
         let player_node = handle.try_get::<PlayerNode>();
         if player_node.is_none() {
@@ -353,7 +1005,11 @@ fn player_animation_system(
 
         // Only switch animation if it changed to prevent restarts/glitches
         if anim_state.current != anim_name {
-            let mut sprite = player_node.get_node_as::<AnimatedSprite2D>("AnimatedSprite2D");
+            let Some(mut sprite) =
+                player_node.try_get_node_as::<AnimatedSprite2D>("AnimatedSprite2D")
+            else {
+                continue;
+            };
             sprite.play_ex().name(&anim_name).done();
             anim_state.current = anim_name;
         }
@@ -0,0 +1,116 @@
+use bevy::prelude::*;
+
+use crate::Users;
+use crate::transport::{ClientTransport, Transport};
+
+/// What the local player is currently doing, as shown by rich-presence integrations
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum PresenceActivity {
+    /// Not connected to any server
+    #[default]
+    MainMenu,
+    /// Connected, waiting for the server to initialize us
+    InLobby,
+    /// Fully joined and playing
+    InMatch,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Presence {
+    pub activity: PresenceActivity,
+    pub server: Option<String>,
+    pub players: usize,
+    pub max_players: Option<usize>,
+}
+
+/// Sent whenever the derived presence changes
+#[derive(Event, Clone, Debug)]
+pub struct PresenceChanged(pub Presence);
+
+/// Address of the server we are connected (or connecting) to
+#[derive(Resource, Debug, Clone, Default)]
+pub struct CurrentServer {
+    pub address: Option<String>,
+    pub max_players: Option<usize>,
+}
+
+/// Integration point for Steamworks, Discord, etc.
+/// Implementations live behind their own cargo feature and are registered with
+/// [`PresenceAppExt::add_presence_provider`].
+pub trait PresenceProvider: Send + Sync + 'static {
+    fn update(&mut self, presence: &Presence);
+    fn clear(&mut self) {}
+}
+
+#[derive(Resource, Default)]
+pub struct PresenceProviders(Vec<Box<dyn PresenceProvider>>);
+
+pub trait PresenceAppExt {
+    fn add_presence_provider(&mut self, provider: impl PresenceProvider) -> &mut Self;
+}
+
+impl PresenceAppExt for App {
+    fn add_presence_provider(&mut self, provider: impl PresenceProvider) -> &mut Self {
+        self.init_resource::<PresenceProviders>();
+        self.world_mut()
+            .resource_mut::<PresenceProviders>()
+            .0
+            .push(Box::new(provider));
+        self
+    }
+}
+
+pub struct PresencePlugin;
+
+impl Plugin for PresencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PresenceProviders>()
+            .init_resource::<CurrentServer>()
+            .add_event::<PresenceChanged>()
+            .add_systems(PostUpdate, (update_presence, notify_providers).chain());
+    }
+}
+
+fn update_presence(
+    client: Transport,
+    users: Res<Users>,
+    server: Res<CurrentServer>,
+    mut last: Local<Option<Presence>>,
+    mut events: EventWriter<PresenceChanged>,
+) {
+    let presence = if !client.is_connected() {
+        Presence::default()
+    } else {
+        Presence {
+            activity: if users.initialized {
+                PresenceActivity::InMatch
+            } else {
+                PresenceActivity::InLobby
+            },
+            server: server.address.clone(),
+            players: users.names.len(),
+            max_players: server.max_players,
+        }
+    };
+
+    if last.as_ref() != Some(&presence) {
+        *last = Some(presence.clone());
+        events.write(PresenceChanged(presence));
+    }
+}
+
+fn notify_providers(
+    mut events: EventReader<PresenceChanged>,
+    mut providers: ResMut<PresenceProviders>,
+) {
+    // Only the latest state matters to the providers
+    if let Some(PresenceChanged(presence)) = events.read().last() {
+        for provider in providers.0.iter_mut() {
+            if presence.activity == PresenceActivity::MainMenu {
+                provider.clear();
+            } else {
+                provider.update(presence);
+            }
+        }
+    }
+}
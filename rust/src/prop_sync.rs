@@ -0,0 +1,323 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use godot::{
+    classes::{Engine, PackedScene, ResourceLoader, RigidBody2D, SceneTree},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::{ClientMessage, PROP_INTERACT_RADIUS, PropState};
+
+use crate::Users;
+use crate::frame_budget;
+use crate::player::{PlayerIndex, PlayerNode};
+use crate::transport::{SendToServer, connection_ready};
+
+/// Re-request authority this often while touching a prop, shorter than the server lease
+const REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+/// How often owned props are reported
+const UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+/// Fraction of the way to the server position covered per frame, for props we don't own
+const SMOOTHING: f32 = 0.3;
+
+/// A pushable prop; whoever has authority simulates it, everyone else follows snapshots
+#[derive(GodotClass)]
+#[class(base=RigidBody2D, init)]
+pub struct PropNode {
+    base: Base<RigidBody2D>,
+    #[var]
+    pub prop_id: u32,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Prop {
+    pub prop_id: u32,
+    /// Latest server state, what we smooth towards without authority
+    pub target: PropState,
+    pub last_snapshot: Instant,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct PropIndex(pub HashMap<u32, Entity>);
+
+#[derive(Resource)]
+pub struct PropSceneResource {
+    pub scene_path: String,
+    /// Path that could not be instantiated, not loaded again until `scene_path` changes
+    failed: Option<String>,
+}
+
+impl Default for PropSceneResource {
+    fn default() -> Self {
+        Self {
+            scene_path: "res://prop.tscn".to_string(),
+            failed: None,
+        }
+    }
+}
+
+/// Props we're currently allowed to simulate, and when we last asked for each
+#[derive(Resource, Default, Debug)]
+pub struct PropAuthority {
+    pub owned: HashSet<u32>,
+    last_request: HashMap<u32, Instant>,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct PropSnapshotEvent(pub Vec<PropState>);
+
+#[derive(Event, Debug, Clone, Copy)]
+pub enum AuthorityEvent {
+    Granted { prop_id: u32 },
+    Revoked { state: PropState },
+}
+
+pub struct PropPlugin;
+
+impl Plugin for PropPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PropIndex>()
+            .init_resource::<PropSceneResource>()
+            .init_resource::<PropAuthority>()
+            .add_event::<PropSnapshotEvent>()
+            .add_event::<AuthorityEvent>()
+            .add_systems(
+                Update,
+                (
+                    apply_prop_snapshots,
+                    handle_authority_events,
+                    follow_server_props,
+                    (request_prop_authority, send_prop_updates).run_if(connection_ready),
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn to_state(prop_id: u32, body: &Gd<PropNode>) -> PropState {
+    let position = body.get_global_position();
+    let velocity = body.get_linear_velocity();
+    PropState {
+        prop_id,
+        x: position.x,
+        y: position.y,
+        rotation: body.get_rotation(),
+        vx: velocity.x,
+        vy: velocity.y,
+    }
+}
+
+fn snap_to(body: &mut Gd<PropNode>, state: &PropState) {
+    body.set_global_position(Vector2::new(state.x, state.y));
+    body.set_rotation(state.rotation);
+    body.set_linear_velocity(Vector2::new(state.vx, state.vy));
+}
+
+/// Without authority the body is frozen (kinematic) and moved by snapshots
+fn set_simulated(body: &mut Gd<PropNode>, simulated: bool) {
+    body.set_freeze_enabled(!simulated);
+}
+
+#[main_thread_system]
+fn apply_prop_snapshots(
+    mut commands: Commands,
+    mut snapshots: EventReader<PropSnapshotEvent>,
+    mut scene: ResMut<PropSceneResource>,
+    mut index: ResMut<PropIndex>,
+    mut props: Query<&mut Prop>,
+) {
+    let _timing = frame_budget::scope("apply_prop_snapshots");
+    for snapshot in snapshots.read() {
+        for state in &snapshot.0 {
+            if let Some(mut prop) = index
+                .0
+                .get(&state.prop_id)
+                .and_then(|entity| props.get_mut(*entity).ok())
+            {
+                prop.target = *state;
+                prop.last_snapshot = Instant::now();
+                continue;
+            }
+            if let Some(entity) = spawn_prop(&mut commands, &mut scene, state) {
+                index.0.insert(state.prop_id, entity);
+            }
+        }
+    }
+}
+
+fn spawn_prop(
+    commands: &mut Commands,
+    scene: &mut PropSceneResource,
+    state: &PropState,
+) -> Option<Entity> {
+    // Every snapshot names every prop, don't retry (and log) a missing scene for each
+    if scene.failed.as_ref() == Some(&scene.scene_path) {
+        return None;
+    }
+    let Some(mut body) = ResourceLoader::singleton()
+        .load(&scene.scene_path)
+        .and_then(|resource| resource.try_cast::<PackedScene>().ok())
+        .and_then(|packed| packed.instantiate())
+        .and_then(|node| node.try_cast::<PropNode>().ok())
+    else {
+        godot_print!("Could not instantiate a PropNode from {}", scene.scene_path);
+        scene.failed = Some(scene.scene_path.clone());
+        return None;
+    };
+    body.bind_mut().prop_id = state.prop_id;
+    set_simulated(&mut body, false);
+
+    let entity = commands
+        .spawn((
+            GodotNodeHandle::new(body.clone()),
+            Prop {
+                prop_id: state.prop_id,
+                target: *state,
+                last_snapshot: Instant::now(),
+            },
+        ))
+        .id();
+
+    let mut root = Engine::singleton()
+        .get_main_loop()
+        .and_then(|main_loop| main_loop.try_cast::<SceneTree>().ok())
+        .and_then(|tree| tree.get_current_scene())?;
+    root.add_child(&body);
+    snap_to(&mut body, state);
+    Some(entity)
+}
+
+#[main_thread_system]
+fn handle_authority_events(
+    mut events: EventReader<AuthorityEvent>,
+    mut authority: ResMut<PropAuthority>,
+    index: Res<PropIndex>,
+    mut query: Query<(&mut GodotNodeHandle, &mut Prop)>,
+) {
+    let _timing = frame_budget::scope("handle_authority_events");
+    for ev in events.read() {
+        let prop_id = match ev {
+            AuthorityEvent::Granted { prop_id } => *prop_id,
+            AuthorityEvent::Revoked { state } => state.prop_id,
+        };
+        let Some((mut handle, mut prop)) = index
+            .0
+            .get(&prop_id)
+            .and_then(|entity| query.get_mut(*entity).ok())
+        else {
+            continue;
+        };
+        let Some(mut body) = handle.try_get::<PropNode>() else {
+            continue;
+        };
+        match ev {
+            AuthorityEvent::Granted { .. } => {
+                if authority.owned.insert(prop_id) {
+                    set_simulated(&mut body, true);
+                }
+            }
+            AuthorityEvent::Revoked { state } => {
+                // Reconcile with the server's copy
+                authority.owned.remove(&prop_id);
+                prop.target = *state;
+                prop.last_snapshot = Instant::now();
+                set_simulated(&mut body, false);
+                snap_to(&mut body, state);
+            }
+        }
+    }
+}
+
+#[main_thread_system]
+fn follow_server_props(
+    authority: Res<PropAuthority>,
+    mut query: Query<(&mut GodotNodeHandle, &Prop)>,
+) {
+    let _timing = frame_budget::scope("follow_server_props");
+    for (mut handle, prop) in query.iter_mut() {
+        if authority.owned.contains(&prop.prop_id) {
+            continue;
+        }
+        let Some(mut body) = handle.try_get::<PropNode>() else {
+            continue;
+        };
+        let target = Vector2::new(prop.target.x, prop.target.y);
+        let position = body.get_global_position();
+        body.set_global_position(position.lerp(target, SMOOTHING));
+        let rotation = body.get_rotation();
+        body.set_rotation(rotation + (prop.target.rotation - rotation) * SMOOTHING);
+    }
+}
+
+/// Ask for every prop our player is touching, again and again to keep the lease
+#[main_thread_system]
+fn request_prop_authority(
+    users: Res<Users>,
+    players: Res<PlayerIndex>,
+    mut authority: ResMut<PropAuthority>,
+    mut to_server: EventWriter<SendToServer>,
+    mut handles: Query<&mut GodotNodeHandle>,
+    props: Query<(Entity, &Prop)>,
+) {
+    let _timing = frame_budget::scope("request_prop_authority");
+    let Some(player_position) = players
+        .0
+        .get(&users.self_id)
+        .and_then(|entity| handles.get_mut(*entity).ok())
+        .and_then(|mut handle| handle.try_get::<PlayerNode>())
+        .map(|player| player.get_global_position())
+    else {
+        return;
+    };
+
+    for (entity, prop) in props.iter() {
+        let Some(body) = handles
+            .get_mut(entity)
+            .ok()
+            .and_then(|mut handle| handle.try_get::<PropNode>())
+        else {
+            continue;
+        };
+        if body.get_global_position().distance_to(player_position) > PROP_INTERACT_RADIUS {
+            continue;
+        }
+        let due = authority
+            .last_request
+            .get(&prop.prop_id)
+            .is_none_or(|last| last.elapsed() >= REQUEST_INTERVAL);
+        if due {
+            authority.last_request.insert(prop.prop_id, Instant::now());
+            to_server.write(SendToServer(ClientMessage::RequestAuthority {
+                prop_id: prop.prop_id,
+            }));
+        }
+    }
+}
+
+#[main_thread_system]
+fn send_prop_updates(
+    authority: Res<PropAuthority>,
+    mut to_server: EventWriter<SendToServer>,
+    mut query: Query<(&mut GodotNodeHandle, &Prop)>,
+    mut last_sent: Local<Option<Instant>>,
+) {
+    let _timing = frame_budget::scope("send_prop_updates");
+    if authority.owned.is_empty() || last_sent.is_some_and(|last| last.elapsed() < UPDATE_INTERVAL)
+    {
+        return;
+    }
+    *last_sent = Some(Instant::now());
+    for (mut handle, prop) in query.iter_mut() {
+        if !authority.owned.contains(&prop.prop_id) {
+            continue;
+        }
+        if let Some(body) = handle.try_get::<PropNode>() {
+            to_server.write(SendToServer(ClientMessage::PropUpdate {
+                state: to_state(prop.prop_id, &body),
+            }));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+#[cfg(feature = "server-movement")]
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+#[cfg(feature = "server-movement")]
+use protocol::PROP_INTERACT_RADIUS;
+use protocol::PropState;
+
+/// Authority lapses when the owner stops re-requesting it for this long
+#[cfg(feature = "server-movement")]
+const AUTHORITY_LEASE: Duration = Duration::from_secs(2);
+/// Updates moving a prop faster than this are rejected, in pixels per second
+#[cfg(feature = "server-movement")]
+const MAX_PROP_SPEED: f32 = 800.0;
+/// Server positions lag behind the client's, don't refuse a request for that
+#[cfg(feature = "server-movement")]
+const REQUEST_SLACK: f32 = 1.5;
+
+#[derive(Debug, Clone)]
+pub struct Prop {
+    pub state: PropState,
+    /// Client simulating the prop, the server only stores its reports
+    pub owner: Option<ClientId>,
+    #[cfg(feature = "server-movement")]
+    lease_until: Instant,
+    #[cfg(feature = "server-movement")]
+    last_update: Instant,
+}
+
+#[cfg(feature = "server-movement")]
+pub enum Grant {
+    Granted {
+        /// Previous owner, farther away than the requester, and the state to snap back to
+        revoked: Option<(ClientId, PropState)>,
+    },
+    Refused,
+}
+
+/// Pushable props, shared by every room
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Props(HashMap<u32, Prop>);
+
+#[cfg(feature = "server-movement")]
+fn distance(state: &PropState, position: (f32, f32)) -> f32 {
+    (state.x - position.0).hypot(state.y - position.1)
+}
+
+impl Props {
+    /// One prop at rest per spawn position, ids starting at 1
+    pub fn spawn(positions: &[(f32, f32)]) -> Self {
+        #[cfg(feature = "server-movement")]
+        let now = Instant::now();
+        Self(
+            positions
+                .iter()
+                .zip(1..)
+                .map(|(&(x, y), prop_id)| {
+                    let state = PropState {
+                        prop_id,
+                        x,
+                        y,
+                        rotation: 0.0,
+                        vx: 0.0,
+                        vy: 0.0,
+                    };
+                    let prop = Prop {
+                        state,
+                        owner: None,
+                        #[cfg(feature = "server-movement")]
+                        lease_until: now,
+                        #[cfg(feature = "server-movement")]
+                        last_update: now,
+                    };
+                    (prop_id, prop)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn snapshot(&self) -> Vec<PropState> {
+        self.0.values().map(|prop| prop.state).collect()
+    }
+
+    /// The nearest player touching a prop gets to simulate it
+    #[cfg(feature = "server-movement")]
+    pub fn request(
+        &mut self,
+        prop_id: u32,
+        client_id: ClientId,
+        positions: &HashMap<ClientId, (f32, f32)>,
+    ) -> Grant {
+        let Some(prop) = self.0.get_mut(&prop_id) else {
+            return Grant::Refused;
+        };
+        let Some(&position) = positions.get(&client_id) else {
+            return Grant::Refused;
+        };
+        let requester_distance = distance(&prop.state, position);
+        if requester_distance > PROP_INTERACT_RADIUS * REQUEST_SLACK {
+            return Grant::Refused;
+        }
+
+        let now = Instant::now();
+        let revoked = match prop.owner {
+            None => None,
+            Some(owner) if owner == client_id => None,
+            Some(owner) => {
+                let owner_distance = positions
+                    .get(&owner)
+                    .map_or(f32::INFINITY, |&pos| distance(&prop.state, pos));
+                if owner_distance <= requester_distance {
+                    return Grant::Refused;
+                }
+                Some((owner, prop.state))
+            }
+        };
+        if prop.owner != Some(client_id) {
+            prop.last_update = now;
+        }
+        prop.owner = Some(client_id);
+        prop.lease_until = now + AUTHORITY_LEASE;
+        Grant::Granted { revoked }
+    }
+
+    /// Store the owner's report, unless it moves the prop implausibly fast
+    #[cfg(feature = "server-movement")]
+    pub fn update(&mut self, client_id: ClientId, state: PropState) -> Result<(), &'static str> {
+        if !state.is_finite() {
+            return Err("not finite");
+        }
+        let Some(prop) = self.0.get_mut(&state.prop_id) else {
+            return Err("unknown prop");
+        };
+        if prop.owner != Some(client_id) {
+            return Err("not the owner");
+        }
+        let now = Instant::now();
+        // Packets may arrive bunched up, give each one a minimum time span
+        let elapsed = now.duration_since(prop.last_update).as_secs_f32().max(0.05);
+        let moved = distance(&prop.state, (state.x, state.y));
+        if moved / elapsed > MAX_PROP_SPEED {
+            return Err("moved too fast");
+        }
+        prop.state = state;
+        prop.last_update = now;
+        Ok(())
+    }
+
+    #[cfg(feature = "server-movement")]
+    pub fn revoke(&mut self, prop_id: u32) -> Option<(ClientId, PropState)> {
+        let prop = self.0.get_mut(&prop_id)?;
+        prop.owner.take().map(|owner| (owner, prop.state))
+    }
+
+    /// Put every prop back where it spawned, taking authority back from the owners
+    pub fn reset(&mut self, positions: &[(f32, f32)]) -> Vec<(ClientId, PropState)> {
+        let owners: Vec<(u32, ClientId)> = self
+            .0
+            .values()
+            .filter_map(|prop| Some((prop.state.prop_id, prop.owner?)))
+            .collect();
+        *self = Self::spawn(positions);
+        owners
+            .into_iter()
+            .filter_map(|(prop_id, owner)| Some((owner, self.0.get(&prop_id)?.state)))
+            .collect()
+    }
+
+    /// Put the props back where a save left them, at rest. Ids no longer spawned are
+    /// ignored.
+    pub fn restore(&mut self, states: &[PropState]) {
+        for state in states {
+            if let Some(prop) = self.0.get_mut(&state.prop_id) {
+                prop.state = PropState {
+                    vx: 0.0,
+                    vy: 0.0,
+                    ..*state
+                };
+            }
+        }
+    }
+
+    /// Take authority back from owners whose lease ran out or who left
+    #[cfg(feature = "server-movement")]
+    pub fn expire(&mut self, connected: impl Fn(ClientId) -> bool) -> Vec<(ClientId, PropState)> {
+        let now = Instant::now();
+        self.0
+            .values_mut()
+            .filter_map(|prop| {
+                let owner = prop.owner?;
+                if prop.lease_until > now && connected(owner) {
+                    return None;
+                }
+                prop.owner = None;
+                // Whatever the owner last reported is where it comes to rest
+                prop.state.vx = 0.0;
+                prop.state.vy = 0.0;
+                Some((owner, prop.state))
+            })
+            .collect()
+    }
+}
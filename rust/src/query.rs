@@ -0,0 +1,85 @@
+//! The query endpoint: a UDP port next to the game's answering `protocol::query`
+//! requests with the server's name, level, players and version, so server lists can
+//! show a server without joining it.
+
+use std::{
+    io,
+    net::UdpSocket,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use protocol::query::{self, ServerInfo};
+
+/// What the query thread answers with, kept current by the server
+#[derive(Resource, Clone)]
+pub struct QueryInfo(Arc<RwLock<ServerInfo>>);
+
+impl QueryInfo {
+    pub fn set(&self, info: ServerInfo) {
+        *self
+            .0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = info;
+    }
+
+    fn encoded(&self) -> Vec<u8> {
+        self.0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .encode()
+    }
+}
+
+/// Answer queries on `address` in a background thread, with `info` until it's changed
+pub fn spawn_listener(address: &str, info: ServerInfo) -> io::Result<QueryInfo> {
+    let socket = UdpSocket::bind(address)?;
+    let shared = QueryInfo(Arc::new(RwLock::new(info)));
+    let answers = shared.clone();
+
+    thread::spawn(move || {
+        let mut buf = [0; query::REQUEST_LEN];
+        loop {
+            let (len, from) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) => {
+                    warn!("Query endpoint: {}", err);
+                    continue;
+                }
+            };
+            if len < query::REQUEST_LEN || !buf.starts_with(query::REQUEST) {
+                continue;
+            }
+            let answer = answers.encoded();
+            if answer.len() > len {
+                debug!("Server info too large to answer a query");
+                continue;
+            }
+            if let Err(err) = socket.send_to(&answer, from) {
+                debug!("Could not answer a query from {}: {}", from, err);
+            }
+        }
+    });
+
+    Ok(shared)
+}
+
+/// Ask the server whose query endpoint is at `address` ("host:port") for its info.
+/// Blocking: run it off the main thread.
+pub fn query_server(address: &str, timeout: Duration) -> Result<ServerInfo, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|err| err.to_string())?;
+    socket
+        .set_read_timeout(Some(timeout))
+        .map_err(|err| err.to_string())?;
+    socket
+        .send_to(&query::request(), address)
+        .map_err(|err| format!("Could not reach {}: {}", address, err))?;
+    let mut buf = [0; query::REQUEST_LEN];
+    let len = socket.recv(&mut buf).map_err(|err| match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => "Timed out".to_string(),
+        _ => err.to_string(),
+    })?;
+    ServerInfo::decode(&buf[..len]).map_err(|err| format!("Invalid answer: {}", err))
+}
@@ -0,0 +1,279 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc as std_mpsc,
+    thread,
+};
+
+use bevy::prelude::*;
+use protocol::Cosmetics;
+use tokio::sync::mpsc::{self, Sender};
+
+use crate::server_log::LogQuery;
+
+/// Operator commands, one per line over the RCON socket
+#[derive(Debug, Clone)]
+pub enum RconCommand {
+    /// Warn clients, then stop the server after `seconds`
+    Shutdown {
+        seconds: u32,
+    },
+    /// Re-read the server config file
+    Reload,
+    /// Announce `text` to every player
+    Say {
+        text: String,
+    },
+    Kick {
+        name: String,
+        reason: Option<String>,
+    },
+    /// Turn player-vs-player collision on or off
+    Collision {
+        enabled: bool,
+    },
+    /// Players and tick timing per room
+    Rooms,
+    /// Clients with speed, rate or malformed-message anomalies
+    Anomalies,
+    /// The latest player reports
+    Reports,
+    /// Move a player to a position, or with `respawn` to its next spawn point
+    Teleport {
+        name: String,
+        x: f32,
+        y: f32,
+    },
+    Respawn {
+        name: String,
+    },
+    /// Props back where they started and every player to a spawn point
+    ResetWorld,
+    /// Recent server log lines, answered a page at a time
+    Log {
+        query: LogQuery,
+    },
+    /// Dress a player (online or not) from its profile, `id` None takes it off
+    Cosmetic {
+        name: String,
+        slot: CosmeticSlot,
+        id: Option<String>,
+    },
+    Help,
+}
+
+/// What the `cosmetic` command changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CosmeticSlot {
+    Hat,
+    Trail,
+}
+
+impl fmt::Display for CosmeticSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CosmeticSlot::Hat => write!(f, "hat"),
+            CosmeticSlot::Trail => write!(f, "trail"),
+        }
+    }
+}
+
+pub const DEFAULT_SHUTDOWN_SECONDS: u32 = 10;
+/// Lines sent by `log tail` without a count
+pub const DEFAULT_LOG_TAIL: usize = 50;
+
+impl RconCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("shutdown") => {
+                let seconds = match words.next() {
+                    Some(arg) => arg
+                        .parse()
+                        .map_err(|_| format!("invalid number of seconds: {}", arg))?,
+                    None => DEFAULT_SHUTDOWN_SECONDS,
+                };
+                Ok(RconCommand::Shutdown { seconds })
+            }
+            Some("reload") => Ok(RconCommand::Reload),
+            Some("rooms") => Ok(RconCommand::Rooms),
+            Some("anomalies") => Ok(RconCommand::Anomalies),
+            Some("reports") => Ok(RconCommand::Reports),
+            Some("reset") => Ok(RconCommand::ResetWorld),
+            Some("say") if !rest.is_empty() => Ok(RconCommand::Say {
+                text: rest.to_string(),
+            }),
+            Some("kick") if !rest.is_empty() => {
+                let (name, reason) = split_name(rest)?;
+                Ok(RconCommand::Kick {
+                    name,
+                    reason: (!reason.is_empty()).then(|| reason.to_string()),
+                })
+            }
+            Some("teleport") => {
+                let (name, rest) = split_name(rest)?;
+                let mut coords = rest.split_whitespace().map(str::parse::<f32>);
+                match (coords.next(), coords.next(), coords.next()) {
+                    (Some(Ok(x)), Some(Ok(y)), None) if !name.is_empty() => {
+                        Ok(RconCommand::Teleport { name, x, y })
+                    }
+                    _ => Err("usage: teleport <name|\"name\"> <x> <y>".to_string()),
+                }
+            }
+            Some("respawn") if !rest.is_empty() => {
+                let (name, _) = split_name(rest)?;
+                Ok(RconCommand::Respawn { name })
+            }
+            Some("cosmetic") => {
+                let usage = "usage: cosmetic <name|\"name\"> hat|trail <id|none>";
+                let (name, rest) = split_name(rest)?;
+                match rest.split_whitespace().collect::<Vec<_>>()[..] {
+                    [slot, id] if !name.is_empty() => {
+                        let slot = match slot {
+                            "hat" => CosmeticSlot::Hat,
+                            "trail" => CosmeticSlot::Trail,
+                            _ => return Err(usage.to_string()),
+                        };
+                        let id = (id != "none").then(|| id.to_string());
+                        if id.as_deref().is_some_and(|id| !Cosmetics::valid_id(id)) {
+                            return Err(format!("invalid cosmetic id: {}", rest));
+                        }
+                        Ok(RconCommand::Cosmetic { name, slot, id })
+                    }
+                    _ => Err(usage.to_string()),
+                }
+            }
+            Some("log") => match words.next() {
+                Some("tail") => {
+                    let count = match words.next() {
+                        Some(arg) => arg
+                            .parse()
+                            .map_err(|_| format!("invalid number of lines: {}", arg))?,
+                        None => DEFAULT_LOG_TAIL,
+                    };
+                    Ok(RconCommand::Log {
+                        query: LogQuery::Tail(count),
+                    })
+                }
+                Some("grep") => match rest.strip_prefix("grep").map(str::trim) {
+                    Some(term) if !term.is_empty() => Ok(RconCommand::Log {
+                        query: LogQuery::Grep(term.to_string()),
+                    }),
+                    _ => Err("usage: log grep <text>".to_string()),
+                },
+                _ => Err("usage: log tail [lines] | log grep <text>".to_string()),
+            },
+            Some("collision") => match words.next() {
+                Some("on") => Ok(RconCommand::Collision { enabled: true }),
+                Some("off") => Ok(RconCommand::Collision { enabled: false }),
+                _ => Err("usage: collision on|off".to_string()),
+            },
+            Some("say" | "kick" | "respawn") => Err(format!("missing argument for {}", command)),
+            Some("help") => Ok(RconCommand::Help),
+            Some(other) => Err(format!("unknown command: {}", other)),
+            None => Err("empty command".to_string()),
+        }
+    }
+}
+
+/// Player names may contain spaces, those must be quoted: `kick "two words" reason`
+fn split_name(rest: &str) -> Result<(String, &str), String> {
+    if let Some(quoted) = rest.strip_prefix('"') {
+        let (name, rest) = quoted
+            .split_once('"')
+            .ok_or_else(|| "unterminated quote".to_string())?;
+        Ok((name.to_string(), rest.trim()))
+    } else {
+        let (name, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        Ok((name.to_string(), rest.trim()))
+    }
+}
+
+pub const HELP: &str = concat!(
+    "commands: shutdown [seconds], reload, say <text>, kick <name|\"name\"> [reason], ",
+    "teleport <name|\"name\"> <x> <y>, respawn <name|\"name\">, reset, collision on|off, rooms, ",
+    "anomalies, reports, cosmetic <name|\"name\"> hat|trail <id|none>, log tail [lines], ",
+    "log grep <text>, help"
+);
+
+/// A command waiting to be run by the Bevy app, which answers on `reply`: usually
+/// once, long answers in several chunks written as they come
+pub struct RconRequest {
+    pub command: RconCommand,
+    pub reply: std_mpsc::Sender<String>,
+}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct RconReceiver(pub mpsc::Receiver<RconRequest>);
+
+/// Accept RCON connections on `address` in a background thread.
+/// When `password` is set, clients must send `auth <password>` first.
+pub fn spawn_listener(address: &str, password: Option<String>) -> io::Result<RconReceiver> {
+    let listener = TcpListener::bind(address)?;
+    let (tx, rx) = mpsc::channel::<RconRequest>(100);
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let tx = tx.clone();
+            let password = password.clone();
+            thread::spawn(move || {
+                if let Err(err) = serve(stream, password, tx) {
+                    warn!("RCON connection closed: {}", err);
+                }
+            });
+        }
+    });
+
+    Ok(RconReceiver(rx))
+}
+
+fn serve(stream: TcpStream, password: Option<String>, tx: Sender<RconRequest>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut authenticated = password.is_none();
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if !authenticated {
+            if line.strip_prefix("auth ") == password.as_deref() {
+                authenticated = true;
+                writeln!(writer, "ok")?;
+            } else {
+                writeln!(writer, "error: authentication required")?;
+            }
+            continue;
+        }
+
+        match RconCommand::parse(line) {
+            Ok(command) => {
+                let (reply_tx, reply_rx) = std_mpsc::channel();
+                let request = RconRequest {
+                    command,
+                    reply: reply_tx,
+                };
+                if tx.blocking_send(request).is_err() {
+                    // The server is gone
+                    return Ok(());
+                }
+                // Until the app drops its end
+                let mut replied = false;
+                for reply in reply_rx {
+                    writeln!(writer, "{}", reply)?;
+                    replied = true;
+                }
+                if !replied {
+                    writeln!(writer, "error: no reply")?;
+                }
+            }
+            Err(err) => writeln!(writer, "error: {}", err)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Player reports sent with `/report`, kept for the operator (RCON `reports`, the
+//! webhook and the save file) along with what the reported player said last
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use protocol::ChatRecord;
+use serde::{Deserialize, Serialize};
+
+/// Reports kept, the oldest go first
+const MAX_REPORTS: usize = 200;
+/// Chat lines of the reported player stored with a report
+const CONTEXT_LINES: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerReport {
+    pub reporter: String,
+    pub reported: String,
+    pub reason: String,
+    /// Unix timestamp, seconds
+    pub sent_at: u64,
+    /// The reported player's last chat messages before the report, oldest first
+    pub recent_chat: Vec<String>,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Reports {
+    pub list: VecDeque<PlayerReport>,
+    /// When each reporter last sent one, by name so reconnecting doesn't reset it
+    last_sent: HashMap<String, Instant>,
+}
+
+impl Reports {
+    /// Count a report from `reporter` if its cooldown is over, otherwise the seconds left
+    pub fn take_slot(&mut self, reporter: &str, cooldown: Duration) -> Result<(), u64> {
+        let now = Instant::now();
+        if let Some(last) = self.last_sent.get(reporter) {
+            let elapsed = now.duration_since(*last);
+            if elapsed < cooldown {
+                return Err((cooldown - elapsed).as_secs().max(1));
+            }
+        }
+        self.last_sent.insert(reporter.to_string(), now);
+        Ok(())
+    }
+
+    pub fn add(&mut self, report: PlayerReport) {
+        if self.list.len() >= MAX_REPORTS {
+            self.list.pop_front();
+        }
+        self.list.push_back(report);
+    }
+
+    /// The latest `count` reports, newest first, one line each plus the chat context
+    pub fn summary(&self, count: usize) -> String {
+        if self.list.is_empty() {
+            return "no reports".to_string();
+        }
+        self.list
+            .iter()
+            .rev()
+            .take(count)
+            .map(|report| {
+                let mut lines = vec![format!(
+                    "[{}] {} reported {}: {}",
+                    report.sent_at, report.reporter, report.reported, report.reason
+                )];
+                lines.extend(
+                    report
+                        .recent_chat
+                        .iter()
+                        .map(|line| format!("    > {}", line)),
+                );
+                lines.join("\n")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// What `name` said last in `history`, oldest first
+pub fn recent_chat(history: &VecDeque<ChatRecord>, name: &str) -> Vec<String> {
+    let mut lines: Vec<String> = history
+        .iter()
+        .rev()
+        .filter(|record| record.username == name)
+        .take(CONTEXT_LINES)
+        .map(|record| record.message.clone())
+        .collect();
+    lines.reverse();
+    lines
+}
@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use godot::{
+    classes::{Button, HBoxContainer, IVBoxContainer, Label, RichTextLabel, VBoxContainer},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use protocol::{MatchRanking, MatchStats, VoteKind};
+use tokio::sync::mpsc::Sender;
+
+use crate::net_events::from_server;
+use crate::ui::{ActiveVote, UiCommand};
+use crate::{chat, frame_budget};
+
+/// The last match's results. While `showing`, the app is in `AppFlowState::Results`.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MatchResults {
+    pub rankings: Vec<MatchRanking>,
+    pub stats: Vec<MatchStats>,
+    /// Cleared when the next match starts
+    pub showing: bool,
+}
+
+#[derive(Component, Default)]
+pub struct ResultsComp;
+
+/// Rankings and stats of the match that just ended, with Play Again and Leave buttons
+/// and how the vote to play again stands. Meant for the "results" `FlowScreenNode`;
+/// scripts can also vote with `vote_play_again(yes)`.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=VBoxContainer)]
+#[bevy_bundle((ResultsComp))]
+pub struct ResultsNode {
+    base: Base<VBoxContainer>,
+    #[bevy_bundle]
+    pub sender: Option<Sender<UiCommand>>,
+    table: Option<Gd<RichTextLabel>>,
+    rematch: Option<Gd<Label>>,
+}
+
+#[godot_api]
+impl IVBoxContainer for ResultsNode {
+    fn init(base: Base<VBoxContainer>) -> Self {
+        Self {
+            base,
+            sender: None,
+            table: None,
+            rematch: None,
+        }
+    }
+
+    fn ready(&mut self) {
+        let mut table = RichTextLabel::new_alloc();
+        table.set_use_bbcode(true);
+        table.set_fit_content(true);
+        self.base_mut().add_child(&table);
+        self.table = Some(table);
+        let rematch = Label::new_alloc();
+        self.base_mut().add_child(&rematch);
+        self.rematch = Some(rematch);
+
+        let mut buttons = HBoxContainer::new_alloc();
+        for (text, method) in [("Play Again", "play_again"), ("Leave", "leave")] {
+            let mut button = Button::new_alloc();
+            button.set_text(text);
+            button.connect(
+                "pressed",
+                &Callable::from_object_method(&self.to_gd(), method),
+            );
+            buttons.add_child(&button);
+        }
+        self.base_mut().add_child(&buttons);
+    }
+}
+
+#[godot_api]
+impl ResultsNode {
+    #[func]
+    fn play_again(&mut self) {
+        self.vote_play_again(true);
+    }
+
+    #[func]
+    fn vote_play_again(&mut self, yes: bool) {
+        self.send(UiCommand::PlayAgain(yes));
+    }
+
+    #[func]
+    fn leave(&mut self) {
+        self.send(UiCommand::Leave);
+    }
+
+    fn send(&self, command: UiCommand) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(command);
+        } else {
+            godot_print!("Results button pressed, but sender not set yet");
+        }
+    }
+
+    fn show_results(&mut self, results: &MatchResults) {
+        let lines: Vec<String> = results
+            .rankings
+            .iter()
+            .map(|ranking| {
+                let stats = results
+                    .stats
+                    .iter()
+                    .find(|stats| stats.client_id == ranking.client_id)
+                    .copied()
+                    .unwrap_or_default();
+                format!(
+                    "{}. {}  {} points, {:.0} px walked, {} messages",
+                    ranking.rank,
+                    chat::escape_bbcode(&ranking.name),
+                    stats.score,
+                    stats.distance,
+                    stats.messages
+                )
+            })
+            .collect();
+        if let Some(table) = &mut self.table {
+            table.set_text(&lines.join("\n"));
+        }
+    }
+
+    fn show_rematch(&mut self, vote: &ActiveVote) {
+        let text = match &vote.0 {
+            Some((progress, _)) if progress.kind == VoteKind::PlayAgain => format!(
+                "{} of {} want to play again",
+                progress.yes, progress.eligible
+            ),
+            _ => String::new(),
+        };
+        if let Some(rematch) = &mut self.rematch {
+            rematch.set_text(&text);
+        }
+    }
+}
+
+pub struct ResultsPlugin;
+
+impl Plugin for ResultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchResults>()
+            .add_systems(Update, (track_results, update_results.after(track_results)));
+    }
+}
+
+fn track_results(
+    mut ended: EventReader<from_server::MatchResults>,
+    mut started: EventReader<from_server::MatchStarted>,
+    mut results: ResMut<MatchResults>,
+) {
+    if let Some(ev) = ended.read().last() {
+        results.rankings = ev.rankings.clone();
+        results.stats = ev.stats.clone();
+        results.showing = true;
+    }
+    if started.read().count() > 0 {
+        results.showing = false;
+    }
+}
+
+#[main_thread_system]
+fn update_results(
+    results: Res<MatchResults>,
+    vote: Res<ActiveVote>,
+    mut query: Query<&mut GodotNodeHandle, With<ResultsComp>>,
+) {
+    let _timing = frame_budget::scope("update_results");
+    for mut handle in query.iter_mut() {
+        let added = handle.is_added();
+        let Some(mut node) = handle.try_get::<ResultsNode>() else {
+            continue;
+        };
+        let mut node = node.bind_mut();
+        if results.is_changed() || added {
+            node.show_results(&results);
+        }
+        if vote.is_changed() || added {
+            node.show_rematch(&vote);
+        }
+    }
+}
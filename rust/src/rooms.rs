@@ -0,0 +1,261 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+#[cfg(feature = "server-rooms")]
+use rand::Rng;
+
+/// No 0/O or 1/I, codes are read out loud and typed by hand
+#[cfg(feature = "server-rooms")]
+const INVITE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+#[cfg(feature = "server-rooms")]
+const INVITE_CODE_LEN: usize = 6;
+
+#[derive(Debug, Clone)]
+pub struct Room {
+    pub host: ClientId,
+    pub members: HashSet<ClientId>,
+}
+
+/// Private sessions, keyed by invite code. Clients in no room share the lobby.
+/// Rooms disappear with their last member.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Rooms {
+    rooms: HashMap<String, Room>,
+    member_of: HashMap<ClientId, String>,
+}
+
+/// Case-insensitive, surrounding spaces ignored
+#[cfg(feature = "server-rooms")]
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+impl Rooms {
+    pub fn room_of(&self, client_id: ClientId) -> Option<&String> {
+        self.member_of.get(&client_id)
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Room> {
+        self.rooms.get(code)
+    }
+
+    /// Move `host` into a new room and return its invite code
+    #[cfg(feature = "server-rooms")]
+    pub fn create(&mut self, host: ClientId) -> String {
+        self.leave(host);
+        let mut rng = rand::thread_rng();
+        let code = loop {
+            let code: String = (0..INVITE_CODE_LEN)
+                .map(|_| INVITE_ALPHABET[rng.gen_range(0..INVITE_ALPHABET.len())] as char)
+                .collect();
+            if !self.rooms.contains_key(&code) {
+                break code;
+            }
+        };
+        self.rooms.insert(
+            code.clone(),
+            Room {
+                host,
+                members: HashSet::from([host]),
+            },
+        );
+        self.member_of.insert(host, code.clone());
+        code
+    }
+
+    /// Returns false for unknown codes, the client then stays where it is
+    pub fn join(&mut self, client_id: ClientId, code: &str) -> bool {
+        if !self.rooms.contains_key(code) {
+            return false;
+        }
+        self.leave(client_id);
+        if let Some(room) = self.rooms.get_mut(code) {
+            room.members.insert(client_id);
+        }
+        self.member_of.insert(client_id, code.to_string());
+        true
+    }
+
+    /// Back to the lobby. The first remaining member becomes host if the host left.
+    pub fn leave(&mut self, client_id: ClientId) {
+        let Some(code) = self.member_of.remove(&client_id) else {
+            return;
+        };
+        let Some(room) = self.rooms.get_mut(&code) else {
+            return;
+        };
+        room.members.remove(&client_id);
+        if room.members.is_empty() {
+            self.rooms.remove(&code);
+        } else if room.host == client_id {
+            room.host = *room.members.iter().next().unwrap();
+        }
+    }
+
+    /// A resumed session got a new client id, keep its membership (and host role)
+    pub fn migrate(&mut self, from: ClientId, to: ClientId) {
+        let Some(code) = self.member_of.remove(&from) else {
+            return;
+        };
+        if let Some(room) = self.rooms.get_mut(&code) {
+            room.members.remove(&from);
+            room.members.insert(to);
+            if room.host == from {
+                room.host = to;
+            }
+        }
+        self.member_of.insert(to, code);
+    }
+
+    /// Clients sharing `client_id`'s room (or the lobby), itself included
+    pub fn peers<'a>(
+        &self,
+        client_id: ClientId,
+        clients: impl Iterator<Item = &'a ClientId>,
+    ) -> Vec<ClientId> {
+        let room = self.room_of(client_id);
+        clients
+            .filter(|other| self.room_of(**other) == room)
+            .copied()
+            .collect()
+    }
+}
+
+/// Ticks a room may owe at most; beyond that the time is dropped and counted as skipped
+#[cfg(feature = "server-movement")]
+const MAX_CATCHUP_TICKS: u32 = 3;
+
+/// Lobby (None) or private room invite code
+pub type RoomKey = Option<String>;
+
+/// A movement update waiting for its room's next tick
+#[cfg(feature = "server-movement")]
+#[derive(Debug, Clone, Copy)]
+pub struct QueuedMove {
+    pub seq: u32,
+    pub x: f32,
+    pub y: f32,
+    pub horizontal: f32,
+    pub vertical: f32,
+    /// Client clock when the input was read, see `ClientMessage::PlayerUpdate`
+    pub sent_at_ms: u32,
+}
+
+/// The input a player held over a tick of `tick`, from the moves queued during it:
+/// each move's input weighted by the time until the next one (by the client's clock),
+/// the last one held for what's left of the tick. None without moves.
+#[cfg(feature = "server-movement")]
+pub fn blend_moves(moves: &[QueuedMove], tick: Duration) -> Option<(f32, f32)> {
+    let last = moves.last()?;
+    let tick_ms = tick.as_secs_f32() * 1000.0;
+    let (mut horizontal, mut vertical, mut held_ms) = (0.0, 0.0, 0.0);
+    for pair in moves.windows(2) {
+        // A client that stopped sending for a while held its input for a tick at most
+        let ms = (pair[1].sent_at_ms.wrapping_sub(pair[0].sent_at_ms) as f32).min(tick_ms);
+        horizontal += pair[0].horizontal * ms;
+        vertical += pair[0].vertical * ms;
+        held_ms += ms;
+    }
+    let rest_ms = (tick_ms - held_ms).max(0.0);
+    horizontal += last.horizontal * rest_ms;
+    vertical += last.vertical * rest_ms;
+    held_ms += rest_ms;
+    if held_ms <= 0.0 {
+        return Some((last.horizontal, last.vertical));
+    }
+    Some((horizontal / held_ms, vertical / held_ms))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TickMetrics {
+    pub ticks: u64,
+    /// Ticks dropped because the room fell too far behind
+    pub skipped: u64,
+    pub last: Duration,
+    /// Moving average over roughly the last 8 ticks
+    pub average: Duration,
+    pub max: Duration,
+}
+
+#[cfg(feature = "server-movement")]
+impl TickMetrics {
+    fn record(&mut self, elapsed: Duration) {
+        self.ticks += 1;
+        self.last = elapsed;
+        self.max = self.max.max(elapsed);
+        self.average = if self.ticks == 1 {
+            elapsed
+        } else {
+            (self.average * 7 + elapsed) / 8
+        };
+    }
+}
+
+/// One room's simulation: its own time accumulator and pending work
+#[derive(Debug, Clone, Default)]
+pub struct RoomTick {
+    #[cfg(feature = "server-movement")]
+    accumulator: Duration,
+    /// Every move per player since the last tick, oldest first; clients refreshing
+    /// faster than the tick rate send several
+    #[cfg(feature = "server-movement")]
+    pub moves: HashMap<ClientId, Vec<QueuedMove>>,
+    pub metrics: TickMetrics,
+}
+
+#[cfg(feature = "server-movement")]
+impl RoomTick {
+    fn accumulate(&mut self, delta: Duration, tick: Duration) {
+        let cap = tick * MAX_CATCHUP_TICKS;
+        self.accumulator += delta;
+        if self.accumulator > cap {
+            self.metrics.skipped +=
+                ((self.accumulator - cap).as_secs_f32() / tick.as_secs_f32()).ceil() as u64;
+            self.accumulator = cap;
+        }
+    }
+
+    /// Consume one tick if one is due
+    pub fn take_tick(&mut self, tick: Duration) -> bool {
+        if self.accumulator < tick {
+            return false;
+        }
+        self.accumulator -= tick;
+        true
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        self.metrics.record(elapsed);
+    }
+}
+
+/// Every room (and the lobby) ticks separately, so a busy room can't hold up the others
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RoomTicks(pub HashMap<RoomKey, RoomTick>);
+
+#[cfg(feature = "server-movement")]
+impl RoomTicks {
+    pub fn queue_move(&mut self, room: RoomKey, client_id: ClientId, queued: QueuedMove) {
+        self.0
+            .entry(room)
+            .or_default()
+            .moves
+            .entry(client_id)
+            .or_default()
+            .push(queued);
+    }
+
+    /// Forget closed rooms and advance everyone's clock
+    pub fn advance(&mut self, rooms: &Rooms, delta: Duration, tick: Duration) {
+        self.0
+            .retain(|key, _| key.as_ref().is_none_or(|code| rooms.get(code).is_some()));
+        self.0.entry(None).or_default();
+        for room in self.0.values_mut() {
+            room.accumulate(delta, tick);
+        }
+    }
+}
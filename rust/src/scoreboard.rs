@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::{
+    classes::{IRichTextLabel, Input, InputMap, RichTextLabel},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::net_events::from_server;
+use crate::{Users, chat, frame_budget};
+
+/// Round trip times from which a player is shown as lagging, then as badly lagging
+const SLOW_PING_MS: u32 = 150;
+const BAD_PING_MS: u32 = 300;
+
+/// Round trip time of each player in our room, as last sent by the server
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Pings(pub HashMap<ClientId, u32>);
+
+#[derive(Component, Default)]
+pub struct ScoreboardComp;
+
+/// The players in our room with their ping, laggy ones in orange or red. Shown while
+/// `hold_action` is held, or always if the project has no such action.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=RichTextLabel)]
+#[bevy_bundle((ScoreboardComp))]
+pub struct ScoreboardNode {
+    base: Base<RichTextLabel>,
+    #[export]
+    hold_action: GString,
+}
+
+#[godot_api]
+impl IRichTextLabel for ScoreboardNode {
+    fn init(base: Base<RichTextLabel>) -> Self {
+        Self {
+            base,
+            hold_action: GString::from("scoreboard"),
+        }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_use_bbcode(true);
+        self.base_mut().set_fit_content(true);
+    }
+}
+
+impl ScoreboardNode {
+    fn held(&self) -> bool {
+        let action = StringName::from(&self.hold_action);
+        !InputMap::singleton().has_action(&action) || Input::singleton().is_action_pressed(&action)
+    }
+
+    fn show_pings(&mut self, users: &Users, pings: &Pings) {
+        let mut rows: Vec<(&String, ClientId)> = users
+            .names
+            .iter()
+            .map(|(client_id, name)| (name, *client_id))
+            .filter(|(_, client_id)| pings.0.contains_key(client_id))
+            .collect();
+        rows.sort();
+        let lines: Vec<String> = rows
+            .into_iter()
+            .map(|(name, client_id)| {
+                let ms = pings.0[&client_id];
+                let you = if client_id == users.self_id {
+                    " (you)"
+                } else {
+                    ""
+                };
+                let ping = match ms {
+                    ms if ms >= BAD_PING_MS => format!("[color=red]{} ms[/color]", ms),
+                    ms if ms >= SLOW_PING_MS => format!("[color=orange]{} ms[/color]", ms),
+                    ms => format!("{} ms", ms),
+                };
+                format!("{}{}  {}", chat::escape_bbcode(name), you, ping)
+            })
+            .collect();
+        self.base_mut().set_text(&lines.join("\n"));
+    }
+}
+
+pub struct ScoreboardPlugin;
+
+impl Plugin for ScoreboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Pings>()
+            .add_systems(Update, (track_pings, update_scoreboards.after(track_pings)));
+    }
+}
+
+fn track_pings(mut events: EventReader<from_server::PlayerPings>, mut pings: ResMut<Pings>) {
+    if let Some(ev) = events.read().last() {
+        pings.0 = ev
+            .pings
+            .iter()
+            .map(|ping| (ping.client_id, ping.rtt_ms))
+            .collect();
+    }
+}
+
+#[main_thread_system]
+fn update_scoreboards(
+    pings: Res<Pings>,
+    users: Res<Users>,
+    mut query: Query<&mut GodotNodeHandle, With<ScoreboardComp>>,
+) {
+    let _timing = frame_budget::scope("update_scoreboards");
+    for mut handle in query.iter_mut() {
+        let added = handle.is_added();
+        let Some(mut scoreboard) = handle.try_get::<ScoreboardNode>() else {
+            continue;
+        };
+        let mut scoreboard = scoreboard.bind_mut();
+        let held = scoreboard.held();
+        if scoreboard.base().is_visible() != held {
+            scoreboard.base_mut().set_visible(held);
+        }
+        if pings.is_changed() || users.is_changed() || added {
+            scoreboard.show_pings(&users, &pings);
+        }
+    }
+}
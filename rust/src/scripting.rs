@@ -0,0 +1,301 @@
+//! Gameplay rules server operators write themselves, without rebuilding the server: the
+//! Rhai script at `script_path` may define any of these hooks
+//!
+//! - `fn on_join(name, x, y)`: a player joined at (x, y); return `[x, y]` to move it
+//! - `fn on_chat(name, message)`
+//! - `fn on_tick(dt)`: ten times a second, `dt` in seconds
+//! - `fn on_player_move(name, x, y)`: return `[x, y]` to put the player back somewhere
+//!
+//! and acts through `say(text)`, `tell(name, text)`, `teleport(name, x, y)`,
+//! `hit(name, intensity)`, `explode(x, y, intensity)`, `players()` and `position(name)`.
+//! Hooks keep data between calls in `this`, a map.
+//! The script is reloaded when the file changes; a failing hook is logged and skipped.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy::prelude::*;
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope};
+
+/// How often the script file is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Time between two `on_tick` calls
+pub const TICK_INTERVAL: Duration = Duration::from_millis(100);
+/// Operations one hook may run, so an endless loop only costs a frame
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What a script asked the server to do, applied after its hooks ran
+#[derive(Debug, Clone)]
+pub enum ScriptAction {
+    /// Announce to every player
+    Say(String),
+    /// Tell one player
+    Tell {
+        name: String,
+        text: String,
+    },
+    Teleport {
+        name: String,
+        x: f32,
+        y: f32,
+    },
+    /// The player took damage, felt by those around it
+    Hit {
+        name: String,
+        intensity: f32,
+    },
+    /// Felt by the lobby players around it
+    Explosion {
+        x: f32,
+        y: f32,
+        intensity: f32,
+    },
+}
+
+/// Shared with the functions the script calls
+#[derive(Debug, Default)]
+struct ScriptState {
+    /// Every player's position, by name, as of the current hooks
+    players: HashMap<String, (f32, f32)>,
+    actions: Vec<ScriptAction>,
+}
+
+type SharedState = Arc<Mutex<ScriptState>>;
+
+fn lock(state: &SharedState) -> MutexGuard<'_, ScriptState> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A number the script passed or returned, integer or not
+fn number(value: &Dynamic) -> Option<f32> {
+    value
+        .as_float()
+        .map(|value| value as f32)
+        .or_else(|_| value.as_int().map(|value| value as f32))
+        .ok()
+}
+
+/// An `[x, y]` returned by a hook
+fn point(value: Dynamic) -> Option<(f32, f32)> {
+    match value.try_cast::<Array>()?.as_slice() {
+        [x, y] => Some((number(x)?, number(y)?)),
+        _ => None,
+    }
+}
+
+struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// Names of the functions the script defines
+    hooks: HashSet<String>,
+    /// `this` in every hook
+    this: Dynamic,
+    state: SharedState,
+}
+
+impl Script {
+    fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let state = SharedState::default();
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!("Script: {}", text));
+
+        let shared = state.clone();
+        engine.register_fn("say", move |text: &str| {
+            lock(&shared)
+                .actions
+                .push(ScriptAction::Say(text.to_string()));
+        });
+        let shared = state.clone();
+        engine.register_fn("tell", move |name: &str, text: &str| {
+            lock(&shared).actions.push(ScriptAction::Tell {
+                name: name.to_string(),
+                text: text.to_string(),
+            });
+        });
+        let shared = state.clone();
+        engine.register_fn("teleport", move |name: &str, x: Dynamic, y: Dynamic| {
+            if let (Some(x), Some(y)) = (number(&x), number(&y)) {
+                lock(&shared).actions.push(ScriptAction::Teleport {
+                    name: name.to_string(),
+                    x,
+                    y,
+                });
+            }
+        });
+        let shared = state.clone();
+        engine.register_fn("hit", move |name: &str, intensity: Dynamic| {
+            if let Some(intensity) = number(&intensity) {
+                lock(&shared).actions.push(ScriptAction::Hit {
+                    name: name.to_string(),
+                    intensity,
+                });
+            }
+        });
+        let shared = state.clone();
+        engine.register_fn(
+            "explode",
+            move |x: Dynamic, y: Dynamic, intensity: Dynamic| {
+                if let (Some(x), Some(y), Some(intensity)) =
+                    (number(&x), number(&y), number(&intensity))
+                {
+                    lock(&shared)
+                        .actions
+                        .push(ScriptAction::Explosion { x, y, intensity });
+                }
+            },
+        );
+        let shared = state.clone();
+        engine.register_fn("players", move || -> Array {
+            let mut names: Vec<String> = lock(&shared).players.keys().cloned().collect();
+            names.sort();
+            names.into_iter().map(Dynamic::from).collect()
+        });
+        let shared = state.clone();
+        engine.register_fn("position", move |name: &str| -> Dynamic {
+            match lock(&shared).players.get(name) {
+                Some(&(x, y)) => Dynamic::from_array(vec![
+                    Dynamic::from_float(x.into()),
+                    Dynamic::from_float(y.into()),
+                ]),
+                None => Dynamic::UNIT,
+            }
+        });
+
+        let ast = engine.compile(&source).map_err(|err| err.to_string())?;
+        let hooks = ast
+            .iter_functions()
+            .map(|function| function.name.to_string())
+            .collect();
+        // Top-level statements run once, on load
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|err| err.to_string())?;
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            hooks,
+            this: Dynamic::from_map(Map::new()),
+            state,
+        })
+    }
+
+    /// What `hook` returned; None when the script doesn't define it or it failed
+    fn call(&mut self, hook: &str, args: impl FuncArgs) -> Option<Dynamic> {
+        if !self.hooks.contains(hook) {
+            return None;
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.this);
+        match self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            hook,
+            args,
+        ) {
+            Ok(result) => Some(result),
+            Err(err) => {
+                warn!("Script hook {} failed: {}", hook, err);
+                None
+            }
+        }
+    }
+}
+
+/// The loaded script, if any, and which file it came from
+#[derive(Resource, Default)]
+pub struct Scripts {
+    script: Option<Script>,
+    /// Path and modification time last loaded (or tried), to notice changes
+    source: Option<(PathBuf, Option<SystemTime>)>,
+    last_check: Option<Instant>,
+}
+
+impl Scripts {
+    /// (Re)load the script at `path` if it changed, checking every couple of seconds.
+    /// A script that fails to load leaves the previous one running.
+    pub fn watch(&mut self, path: Option<&Path>) {
+        if self
+            .last_check
+            .is_some_and(|last| last.elapsed() < CHECK_INTERVAL)
+        {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+
+        let Some(path) = path else {
+            if self.source.take().is_some() {
+                info!("Script unloaded");
+            }
+            self.script = None;
+            return;
+        };
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        let source = (path.to_path_buf(), modified);
+        if self.source.as_ref() == Some(&source) {
+            return;
+        }
+        self.source = Some(source);
+        match Script::load(path) {
+            Ok(script) => {
+                info!("Loaded script {}", path.display());
+                self.script = Some(script);
+            }
+            Err(err) => warn!("Script {} not loaded: {}", path.display(), err),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.script.is_some()
+    }
+
+    /// Player positions by name, for `players()` and `position(name)` in the next hooks
+    pub fn set_players(&mut self, players: impl Iterator<Item = (String, (f32, f32))>) {
+        if let Some(script) = &self.script {
+            lock(&script.state).players = players.collect();
+        }
+    }
+
+    fn call(&mut self, hook: &str, args: impl FuncArgs) -> Option<Dynamic> {
+        self.script.as_mut()?.call(hook, args)
+    }
+
+    /// Where the script wants the player instead, if anywhere
+    pub fn on_join(&mut self, name: &str, x: f32, y: f32) -> Option<(f32, f32)> {
+        point(self.call("on_join", (name.to_string(), x as f64, y as f64))?)
+    }
+
+    pub fn on_chat(&mut self, name: &str, message: &str) {
+        self.call("on_chat", (name.to_string(), message.to_string()));
+    }
+
+    pub fn on_tick(&mut self, dt: f32) {
+        self.call("on_tick", (dt as f64,));
+    }
+
+    /// Where the script wants the player instead, if anywhere
+    pub fn on_player_move(&mut self, name: &str, x: f32, y: f32) -> Option<(f32, f32)> {
+        point(self.call("on_player_move", (name.to_string(), x as f64, y as f64))?)
+    }
+
+    /// What the hooks asked for since the last call
+    pub fn take_actions(&mut self) -> Vec<ScriptAction> {
+        match &self.script {
+            Some(script) => std::mem::take(&mut lock(&script.state).actions),
+            None => Vec::new(),
+        }
+    }
+}
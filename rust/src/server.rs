@@ -1,83 +1,3457 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs, io,
+    net::IpAddr,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy::{
+    app::{App, PluginsState, Startup},
+    ecs::{
+        resource::Resource,
+        system::{ResMut, SystemParam},
+    },
+    log::LogPlugin,
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+};
+use bevy_quinnet::{
+    server::{
+        ConnectionLostEvent, Endpoint, QuinnetServer, QuinnetServerPlugin,
+        ServerEndpointConfiguration, certificate::CertificateRetrievalMode,
+    },
+    shared::ClientId,
+};
+#[cfg(any(feature = "server-chat", feature = "server-movement"))]
+use protocol::sounds;
+use protocol::{
+    AdminCommand, ChatRecord, ClientMessage, Cosmetics, DecodeError, EnvironmentState,
+    FeedbackKind, FriendStatus, GameRules, LeaderboardEntry, LocalizedText, NoticeLevel,
+    PlatformPath, PlayerInfo, PlayerPing, ServerMessage, VoteKind, WorldBounds, query::ServerInfo,
+    texts,
+};
+use rand::Rng;
+use serde::Deserialize;
+#[cfg(feature = "server-chat")]
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::accounts::{self, Accounts, LastPosition};
+use crate::anomalies::Anomalies;
+use crate::bench::{self, Bench, BenchOptions};
+use crate::determinism::{self, Determinism, DeterminismOptions};
+use crate::identity::{self, ReplayGuard};
+use crate::loopback::LoopbackServer;
+use crate::matches::{Match, MatchPhase};
+use crate::navigation::Navigation;
+use crate::net_events::from_client;
+use crate::net_handlers::{NetDispatch, NetHandlers};
+use crate::net_stats::{LinkStats, SendRate};
+use crate::persistence::{self, ServerSnapshot};
+#[cfg(feature = "server-movement")]
+use crate::props::Grant;
+use crate::props::Props;
+use crate::query::{self, QueryInfo};
+use crate::rcon::{self, CosmeticSlot, RconCommand, RconReceiver};
+use crate::reports::{self, PlayerReport, Reports};
+#[cfg(any(feature = "server-movement", feature = "server-rooms"))]
+use crate::rooms;
+#[cfg(feature = "server-movement")]
+use crate::rooms::{QueuedMove, RoomTick};
+use crate::rooms::{RoomTicks, Rooms};
+use crate::scripting::{self, ScriptAction, Scripts};
+use crate::server_log::{self, ServerLog};
+use crate::sessions::Sessions;
+use crate::votes::{Ballot, Outcome, VoteRules, Votes};
+use crate::webhooks::{self, Webhook};
+use crate::{crash, names, protocol};
+
+/// Read at startup; the settings copied by `apply_reloadable` are re-applied
+/// whenever the file changes or on the RCON `reload` command
+pub const CONFIG_PATH: &str = "server.toml";
+
+#[derive(Resource, Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Chat messages longer than this (in grapheme clusters) are rejected
+    pub max_chat_length: usize,
+    pub min_name_length: usize,
+    pub max_name_length: usize,
+    /// Number of chat messages kept for late joiners and saves
+    pub chat_history_len: usize,
+    pub save_path: PathBuf,
+    pub autosave_interval_secs: u64,
+    /// Minimum time between two footstep sounds of the same player
+    pub footstep_interval_ms: u64,
+    /// Operator console, disabled when None
+    pub rcon_address: Option<String>,
+    pub rcon_password: Option<String>,
+    /// Shown in server lists, see `query_address`
+    pub server_name: String,
+    /// UDP address answering server-info queries (see `query`) without a join, disabled
+    /// when None
+    pub query_address: Option<String>,
+    /// Sent to every client when they join
+    pub motd: Option<String>,
+    pub max_players: usize,
+    /// Chat token bucket: sustained messages per second, and burst size
+    pub chat_rate_per_sec: f32,
+    pub chat_burst: f32,
+    /// Whole words masked out of chat, case-insensitive
+    pub word_filter: Vec<String>,
+    /// Announced in turn, one every `reminder_interval_secs`
+    pub reminders: Vec<String>,
+    pub reminder_interval_secs: u64,
+    /// Real seconds in a full day/night cycle, 0 freezes the clock
+    pub day_length_secs: f32,
+    /// How often clients get the environment state to correct their clocks
+    pub environment_sync_secs: u64,
+    /// Where joining players appear, in turn; a random spot when empty
+    pub spawn_points: Vec<(f32, f32)>,
+    /// Returning players appear where they left (and in the room they were in, if it's
+    /// still open) instead of at a spawn point; turn off for round-based modes
+    pub restore_positions: bool,
+    pub player_collision: bool,
+    /// Positions from clients, spawn points and restored positions are clamped to it
+    pub world_bounds: Option<WorldBounds>,
+    /// Where pushable props start, only read at startup
+    pub props: Vec<(f32, f32)>,
+    /// Moving platforms and elevators, carrying the players standing on them
+    pub platforms: Vec<PlatformPath>,
+    /// How often props are sent to clients, per second
+    pub prop_sync_rate: f32,
+    /// How often clients get a checksum of their room's state to detect desyncs, 0 disables
+    pub state_checksum_secs: u64,
+    /// Simulation ticks per second, run separately for each room
+    pub room_tick_rate: f32,
+    /// Time between updates while nobody is connected, instead of running flat out;
+    /// 0 never idles
+    pub idle_tick_ms: u64,
+    /// Clients sending more undecodable or invalid messages than this are disconnected
+    pub max_malformed_messages: u32,
+    /// Refuse joins that aren't signed with an identity key
+    pub require_identity: bool,
+    /// PEM certificate chain and private key, e.g. Let's Encrypt's fullchain.pem and
+    /// privkey.pem. A self-signed certificate is generated when unset. Read at startup.
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    /// Hostname in the generated self-signed certificate
+    pub server_hostname: String,
+    /// How long the player of a dropped connection is kept for the client to resume, 0 disables
+    pub session_resume_secs: u64,
+    /// The same for players who backgrounded their app, whose OS may cut the connection
+    pub away_resume_secs: u64,
+    /// Movement faster than this (pixels per second) is recorded as an anomaly
+    pub max_player_speed: f32,
+    /// Movement updates per second above this are recorded as an anomaly
+    pub max_updates_per_sec: u32,
+    /// How often clients with anomalies are written to the log, 0 disables
+    pub anomaly_report_secs: u64,
+    /// Level scenes players can vote for; the first is loaded at startup
+    pub levels: Vec<String>,
+    /// Holds each level's navigation grid, see `navigation`
+    pub nav_dir: PathBuf,
+    /// How long a vote stays open
+    pub vote_timeout_secs: u64,
+    /// Fraction of the players that must vote for a vote to count
+    pub vote_quorum: f32,
+    /// Minimum time between two votes started by the same player
+    pub vote_cooldown_secs: u64,
+    /// Players besides the target who must vote on a kick, and be there to start one
+    pub vote_kick_min_voters: u32,
+    /// Minimum time between two reports sent by the same player
+    pub report_cooldown_secs: u64,
+    /// Length of a match, after which everyone gets the results; 0 plays without matches
+    pub match_length_secs: u64,
+    /// How long the results are shown before the next match starts
+    pub results_secs: u64,
+    /// Interface the game endpoint listens on: "0.0.0.0" for every interface, or one
+    /// address, e.g. the machine's LAN address to stay off its public interfaces
+    pub listen_address: String,
+    pub port: u16,
+    /// Players must join with this password when set
+    pub join_password: Option<String>,
+    /// Server start, joins and leaves, and reports are posted here, e.g. a Discord or
+    /// Slack incoming webhook URL
+    pub webhook_url: Option<String>,
+    /// Rhai script with gameplay hooks, see `scripting`; reloaded when it changes
+    pub script_path: Option<PathBuf>,
+    /// Serve only clients in this process (see `loopback`), without opening the QUIC
+    /// endpoint. Set by `--offline`, which also turns off RCON and the webhook and saves
+    /// to a temporary file.
+    #[serde(skip)]
+    pub offline: bool,
+    /// The command line overrides, applied again over every reload
+    #[serde(skip)]
+    args: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_chat_length: protocol::DEFAULT_MAX_CHAT_LENGTH,
+            min_name_length: 2,
+            max_name_length: 16,
+            chat_history_len: 50,
+            save_path: PathBuf::from("server_state.json"),
+            autosave_interval_secs: 60,
+            footstep_interval_ms: 350,
+            rcon_address: Some("127.0.0.1:6001".to_string()),
+            rcon_password: None,
+            server_name: "Godot Bevy server".to_string(),
+            query_address: Some(format!("0.0.0.0:{}", protocol::query::DEFAULT_PORT)),
+            motd: None,
+            max_players: 16,
+            chat_rate_per_sec: 1.0,
+            chat_burst: 5.0,
+            word_filter: Vec::new(),
+            reminders: Vec::new(),
+            reminder_interval_secs: 300,
+            day_length_secs: 1200.0,
+            environment_sync_secs: 10,
+            spawn_points: Vec::new(),
+            restore_positions: true,
+            player_collision: false,
+            // The demo scene's viewport
+            world_bounds: Some(WorldBounds {
+                min_x: 0.0,
+                min_y: 0.0,
+                max_x: 1152.0,
+                max_y: 648.0,
+            }),
+            props: vec![(300.0, 250.0), (500.0, 250.0)],
+            platforms: Vec::new(),
+            prop_sync_rate: 10.0,
+            state_checksum_secs: 5,
+            room_tick_rate: 30.0,
+            idle_tick_ms: 100,
+            max_malformed_messages: 20,
+            require_identity: false,
+            tls_cert_path: None,
+            tls_key_path: None,
+            server_hostname: "0.0.0.0".to_string(),
+            session_resume_secs: 30,
+            away_resume_secs: 600,
+            max_player_speed: 200.0,
+            max_updates_per_sec: 90,
+            anomaly_report_secs: 300,
+            levels: Vec::new(),
+            nav_dir: PathBuf::from("navigation"),
+            vote_timeout_secs: 30,
+            vote_quorum: 0.5,
+            vote_cooldown_secs: 60,
+            vote_kick_min_voters: 3,
+            report_cooldown_secs: 120,
+            match_length_secs: 0,
+            results_secs: 20,
+            listen_address: "0.0.0.0".to_string(),
+            port: 6000,
+            join_password: None,
+            webhook_url: None,
+            script_path: None,
+            offline: false,
+            args: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(data) => toml::from_str(&data)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Take over the settings that can change without restarting the endpoint, keeping
+    /// the command line overrides. Addresses and the save path only apply on the next
+    /// start.
+    pub fn apply_reloadable(&mut self, new: ServerConfig) {
+        self.max_chat_length = new.max_chat_length;
+        self.min_name_length = new.min_name_length;
+        self.max_name_length = new.max_name_length;
+        self.chat_history_len = new.chat_history_len;
+        self.autosave_interval_secs = new.autosave_interval_secs;
+        self.footstep_interval_ms = new.footstep_interval_ms;
+        self.motd = new.motd;
+        self.server_name = new.server_name;
+        self.max_players = new.max_players;
+        self.chat_rate_per_sec = new.chat_rate_per_sec;
+        self.chat_burst = new.chat_burst;
+        self.word_filter = new.word_filter;
+        self.reminders = new.reminders;
+        self.reminder_interval_secs = new.reminder_interval_secs;
+        self.day_length_secs = new.day_length_secs;
+        self.environment_sync_secs = new.environment_sync_secs;
+        self.spawn_points = new.spawn_points;
+        self.restore_positions = new.restore_positions;
+        self.player_collision = new.player_collision;
+        self.world_bounds = new.world_bounds;
+        self.platforms = new.platforms;
+        self.prop_sync_rate = new.prop_sync_rate;
+        self.state_checksum_secs = new.state_checksum_secs;
+        self.room_tick_rate = new.room_tick_rate;
+        self.idle_tick_ms = new.idle_tick_ms;
+        self.max_malformed_messages = new.max_malformed_messages;
+        self.require_identity = new.require_identity;
+        self.session_resume_secs = new.session_resume_secs;
+        self.away_resume_secs = new.away_resume_secs;
+        self.max_player_speed = new.max_player_speed;
+        self.max_updates_per_sec = new.max_updates_per_sec;
+        self.anomaly_report_secs = new.anomaly_report_secs;
+        self.levels = new.levels;
+        self.nav_dir = new.nav_dir;
+        self.vote_timeout_secs = new.vote_timeout_secs;
+        self.vote_quorum = new.vote_quorum;
+        self.vote_cooldown_secs = new.vote_cooldown_secs;
+        self.vote_kick_min_voters = new.vote_kick_min_voters;
+        self.report_cooldown_secs = new.report_cooldown_secs;
+        self.match_length_secs = new.match_length_secs;
+        self.results_secs = new.results_secs;
+        self.join_password = new.join_password;
+        self.webhook_url = new.webhook_url;
+        self.script_path = new.script_path;
+        let args = std::mem::take(&mut self.args);
+        if let Err(err) = self.apply_args(&args) {
+            warn!("Could not apply the command line again: {}", err);
+        }
+    }
+
+    /// Command line overrides, e.g. from the host dialog: `--port <port>`,
+    /// `--max-players <n>`, `--password <password>`, `--listen <address>` and
+    /// `--offline`. Everything from `--bench` or `--determinism` on is the benchmark's or
+    /// the determinism check's.
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        self.args = args.to_vec();
+        let mut args = args
+            .iter()
+            .take_while(|arg| *arg != "--bench" && *arg != "--determinism");
+        while let Some(flag) = args.next() {
+            if flag == "--offline" {
+                // Nothing reaches other machines, and the save isn't the real server's
+                self.offline = true;
+                self.rcon_address = None;
+                self.webhook_url = None;
+                self.save_path = std::env::temp_dir().join("offline_server_state.json");
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--port" => {
+                    self.port = value
+                        .parse()
+                        .map_err(|_| format!("invalid port: {}", value))?;
+                }
+                "--max-players" => {
+                    self.max_players = value
+                        .parse()
+                        .map_err(|_| format!("invalid number of players: {}", value))?;
+                }
+                "--password" => {
+                    self.join_password = (!value.is_empty()).then(|| value.clone());
+                }
+                "--listen" => {
+                    value
+                        .parse::<IpAddr>()
+                        .map_err(|_| format!("invalid listen address: {}", value))?;
+                    self.listen_address = value.clone();
+                }
+                _ => return Err(format!("unknown option: {}", flag)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn endpoint_address(&self) -> String {
+        format!("{}:{}", self.listen_address, self.port)
+    }
+
+    /// What the query endpoint answers
+    pub fn server_info(&self, users: &Users, level: &Level) -> ServerInfo {
+        ServerInfo {
+            name: self.server_name.clone(),
+            level: level.0.clone(),
+            players: users.names.len(),
+            max_players: self.max_players,
+            password: self.join_password.is_some(),
+            protocol_version: protocol::PROTOCOL_VERSION,
+            port: self.port,
+        }
+    }
+
+    pub fn certificate_mode(&self) -> CertificateRetrievalMode {
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) => CertificateRetrievalMode::LoadFromFile {
+                cert_file: cert.to_string_lossy().into_owned(),
+                key_file: key.to_string_lossy().into_owned(),
+            },
+            _ => CertificateRetrievalMode::GenerateSelfSigned {
+                server_hostname: self.server_hostname.clone(),
+            },
+        }
+    }
+
+    pub fn vote_rules(&self) -> VoteRules {
+        VoteRules {
+            timeout: Duration::from_secs(self.vote_timeout_secs),
+            quorum: self.vote_quorum.clamp(0.0, 1.0),
+            cooldown: Duration::from_secs(self.vote_cooldown_secs),
+            kick_min_voters: self.vote_kick_min_voters,
+        }
+    }
+
+    pub fn game_rules(&self) -> GameRules {
+        GameRules {
+            player_collision: self.player_collision,
+            bounds: self.world_bounds,
+        }
+    }
+
+    pub fn clamp_position(&self, x: f32, y: f32) -> (f32, f32) {
+        match &self.world_bounds {
+            Some(bounds) => bounds.clamp(x, y),
+            None => (x, y),
+        }
+    }
+
+    /// Mask filtered words with asterisks, one per character
+    #[cfg(feature = "server-chat")]
+    pub fn filter_words(&self, message: &str) -> String {
+        if self.word_filter.is_empty() {
+            return message.to_string();
+        }
+        message
+            .split_word_bounds()
+            .map(|word| {
+                let lower = word.to_lowercase();
+                if self.word_filter.iter().any(|w| w.to_lowercase() == lower) {
+                    "*".repeat(word.chars().count())
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ChatHistory(pub VecDeque<ChatRecord>);
+
+/// Set when the server started from a save, so joining clients can be told
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RestoredFrom(pub Option<u64>);
+
+/// Set by the `shutdown` RCON command
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PendingShutdown {
+    pub deadline: Option<Instant>,
+}
+
+impl PendingShutdown {
+    fn remaining_secs(&self) -> Option<u32> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()).as_secs() as u32)
+    }
+}
+
+/// Set while no player is connected (or may resume), the runner then sleeps `interval`
+/// between updates
+#[derive(Resource, Debug, Clone, Default)]
+struct IdleMode {
+    active: bool,
+    interval: Duration,
+}
+
+/// Picked at startup and shared with clients, so their seeded randomness agrees
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MatchSeed(pub u64);
+
+/// Accounts listed in a Leaderboard
+const LEADERBOARD_SIZE: usize = 10;
+/// How long a sorted leaderboard is answered with before sorting the accounts again
+const LEADERBOARD_REFRESH: Duration = Duration::from_secs(5);
+/// How often the playtime of connected players is added to their profile
+const PLAYTIME_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often every player is sent the round trip times of its room
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Players per WorldPlayers batch
+const PLAYERS_PER_BATCH: usize = 8;
+/// Chat records per ChatBacklog batch
+const BACKLOG_PER_BATCH: usize = 10;
+/// Minimum time between two full syncs of one client, below the client's own backoff
+/// so only a misbehaving client is refused
+const FULL_SYNC_INTERVAL: Duration = Duration::from_millis(500);
+
+/// World state still to be sent to a client that just joined, one batch per tick
+#[derive(Debug, Clone)]
+pub struct JoinStream {
+    /// Looked up when sent, so players who left meanwhile are skipped
+    players: VecDeque<ClientId>,
+    backlog: VecDeque<ChatRecord>,
+    props_sent: bool,
+    done: u32,
+    total: u32,
+}
+
+impl JoinStream {
+    fn new(players: VecDeque<ClientId>, history: &ChatHistory) -> Self {
+        let total = players.len().div_ceil(PLAYERS_PER_BATCH)
+            + history.0.len().div_ceil(BACKLOG_PER_BATCH)
+            + 1;
+        Self {
+            players,
+            backlog: history.0.clone(),
+            props_sent: false,
+            done: 0,
+            total: total as u32,
+        }
+    }
+
+    /// The next batch, None once everything was sent
+    fn next_batch(&mut self, users: &Users, props: &Props) -> Option<ServerMessage> {
+        let batch = if !self.players.is_empty() {
+            let count = self.players.len().min(PLAYERS_PER_BATCH);
+            let players = self
+                .players
+                .drain(..count)
+                .filter_map(|client_id| users.player_info(client_id))
+                .collect();
+            ServerMessage::WorldPlayers { players }
+        } else if !self.backlog.is_empty() {
+            let count = self.backlog.len().min(BACKLOG_PER_BATCH);
+            ServerMessage::ChatBacklog {
+                records: self.backlog.drain(..count).collect(),
+            }
+        } else if !self.props_sent {
+            self.props_sent = true;
+            ServerMessage::PropSnapshot {
+                props: props.snapshot(),
+            }
+        } else {
+            return None;
+        };
+        self.done += 1;
+        Some(batch)
+    }
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct JoinStreams(HashMap<ClientId, JoinStream>);
+
+/// Time of day and weather, replicated to every client
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Environment(pub EnvironmentState);
+
+/// Level scene every client has loaded, None to leave their scene alone
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Level(pub Option<String>);
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Users {
+    names: HashMap<ClientId, String>,
+    /// Last relayed PlayerUpdate sequence number per client
+    last_update_seq: HashMap<ClientId, u32>,
+    /// Chat rate limiter: tokens left and when they were last refilled
+    chat_tokens: HashMap<ClientId, (f32, Instant)>,
+    /// Last reported position, where this player's sounds are played
+    positions: HashMap<ClientId, (f32, f32)>,
+    /// When the last footstep sound was sent
+    last_footstep: HashMap<ClientId, Instant>,
+    /// When this player last ran into another one
+    last_bump: HashMap<ClientId, Instant>,
+    /// When this client's world was last sent again on request
+    last_full_sync: HashMap<ClientId, Instant>,
+    /// Joins so far, cycles through the configured spawn points
+    spawns_assigned: usize,
+    links: HashMap<ClientId, ClientLink>,
+    /// Messages rejected at the decode boundary so far
+    malformed: HashMap<ClientId, u32>,
+    /// Names of dropped clients whose session may still be resumed. Out of `names` so
+    /// nothing is sent to them, while peers keep seeing their player.
+    suspended: HashMap<ClientId, String>,
+    /// Clients with their app in the background
+    away: HashSet<ClientId>,
+    anomalies: Anomalies,
+    votes: Votes,
+    /// Scores and stats of the current match
+    current_match: Match,
+    /// Who may send `Admin` commands: the earliest player still connected unless the
+    /// role was transferred. Kept while the host's session may resume.
+    host: Option<ClientId>,
+    /// What each player wears, from its profile
+    cosmetics: HashMap<ClientId, Cosmetics>,
+    /// Set by the host, movement updates are dropped while paused
+    paused: bool,
+    /// Account id each joined player (suspended ones included) plays as
+    account_ids: HashMap<ClientId, String>,
+    /// Where players were when they left, by account; stored in their profile
+    /// by `store_last_positions`
+    departed: Vec<(String, LastPosition)>,
+}
+
+/// Adaptive snapshot rate for one client
+#[derive(Debug, Clone, Default)]
+struct ClientLink {
+    rate: SendRate,
+    last_snapshot: Option<Instant>,
+}
+
+impl Users {
+    /// Where the server last accepted this player's position
+    pub fn position(&self, client_id: ClientId) -> Option<(f32, f32)> {
+        self.positions.get(&client_id).copied()
+    }
+
+    /// Everyone with a player in the world, including dropped clients that may resume
+    fn world_players(&self) -> VecDeque<ClientId> {
+        self.names
+            .keys()
+            .chain(self.suspended.keys())
+            .copied()
+            .collect()
+    }
+
+    /// World players sharing `client_id`'s room (or the lobby), itself included.
+    /// Players only see each other within a room.
+    fn room_players(&self, rooms: &Rooms, client_id: ClientId) -> VecDeque<ClientId> {
+        let room = rooms.room_of(client_id);
+        self.world_players()
+            .into_iter()
+            .filter(|other| rooms.room_of(*other) == room)
+            .collect()
+    }
+
+    fn player_info(&self, client_id: ClientId) -> Option<PlayerInfo> {
+        // Suspended players are still in the world, they may come back
+        let username = self
+            .names
+            .get(&client_id)
+            .or_else(|| self.suspended.get(&client_id))?
+            .clone();
+        let (x, y) = self.positions.get(&client_id).copied()?;
+        Some(PlayerInfo {
+            client_id,
+            username,
+            x,
+            y,
+            cosmetics: self.cosmetics.get(&client_id).cloned().unwrap_or_default(),
+        })
+    }
+
+    /// Carry a resumed session's state over to its new client id
+    fn migrate(&mut self, from: ClientId, to: ClientId) {
+        fn rekey<V>(map: &mut HashMap<ClientId, V>, from: ClientId, to: ClientId) {
+            if let Some(value) = map.remove(&from) {
+                map.insert(to, value);
+            }
+        }
+        if let Some(name) = self.suspended.remove(&from) {
+            self.names.insert(to, name);
+        }
+        rekey(&mut self.last_update_seq, from, to);
+        rekey(&mut self.chat_tokens, from, to);
+        rekey(&mut self.positions, from, to);
+        rekey(&mut self.last_footstep, from, to);
+        rekey(&mut self.last_bump, from, to);
+        rekey(&mut self.last_full_sync, from, to);
+        rekey(&mut self.links, from, to);
+        rekey(&mut self.malformed, from, to);
+        rekey(&mut self.cosmetics, from, to);
+        rekey(&mut self.account_ids, from, to);
+        if self.away.remove(&from) {
+            self.away.insert(to);
+        }
+        self.anomalies.migrate(from, to);
+        self.votes.migrate(from, to);
+        self.current_match.migrate(from, to);
+        if self.host == Some(from) {
+            self.host = Some(to);
+        }
+    }
+
+    /// Pick where a joining player appears (`saved` when it's returning) and record it
+    /// as their position
+    fn assign_spawn(
+        &mut self,
+        client_id: ClientId,
+        config: &ServerConfig,
+        saved: Option<(f32, f32)>,
+    ) -> (f32, f32) {
+        let spawn = match saved {
+            Some(saved) => saved,
+            None => {
+                let spawn = if config.spawn_points.is_empty() {
+                    (rand::thread_rng().gen_range(200.0..600.0), 100.0)
+                } else {
+                    config.spawn_points[self.spawns_assigned % config.spawn_points.len()]
+                };
+                self.spawns_assigned = self.spawns_assigned.wrapping_add(1);
+                spawn
+            }
+        };
+        let spawn = config.clamp_position(spawn.0, spawn.1);
+        self.positions.insert(client_id, spawn);
+        self.anomalies.reset_position(client_id);
+        spawn
+    }
+
+    /// Whether this client may have the world sent again now, recorded if so
+    fn take_full_sync(&mut self, client_id: ClientId) -> bool {
+        let now = Instant::now();
+        if self
+            .last_full_sync
+            .get(&client_id)
+            .is_some_and(|last| now.duration_since(*last) < FULL_SYNC_INTERVAL)
+        {
+            return false;
+        }
+        self.last_full_sync.insert(client_id, now);
+        true
+    }
+
+    /// Refill this client's bucket and take a token if there is one
+    fn take_chat_token(&mut self, client_id: ClientId, config: &ServerConfig) -> bool {
+        let now = Instant::now();
+        let (tokens, last) = self
+            .chat_tokens
+            .entry(client_id)
+            .or_insert((config.chat_burst, now));
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f32() * config.chat_rate_per_sec)
+            .min(config.chat_burst);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub fn create_server() {
+    if let Err(err) = run_server(std::env::args().skip(1).collect()) {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    }
+}
+
+/// Run the server with `server.toml` and the overrides in `args`, see
+/// `ServerConfig::apply_args`, `BenchOptions::from_args` and
+/// `DeterminismOptions::from_args`. Errs on an invalid argument, or when the server
+/// couldn't start or a benchmark or determinism check failed.
+pub fn run_server(args: Vec<String>) -> Result<(), String> {
+    // The server catches its own panics in `server_runner`; watching keeps them from
+    // counting as the hosting game's
+    let _panics = crash::install_panic_hook();
+
+    let bench = BenchOptions::from_args(args.iter().cloned())?;
+    let determinism = DeterminismOptions::from_args(args.iter().cloned())?;
+
+    let mut config = match ServerConfig::load(Path::new(CONFIG_PATH)) {
+        Ok(config) => config.unwrap_or_default(),
+        Err(err) => {
+            warn!("Invalid {}, using defaults: {}", CONFIG_PATH, err);
+            ServerConfig::default()
+        }
+    };
+    config.apply_args(&args)?;
+    if let Some(bench) = &bench {
+        // Room for every bot, and nothing they do outlives the run
+        config.max_players = config.max_players.max(bench.n_clients);
+        config.require_identity = false;
+        config.join_password = None;
+        config.listen_address = "0.0.0.0".to_string();
+        config.restore_positions = false;
+        config.save_path = std::env::temp_dir().join("bench_server_state.json");
+        config.rcon_address = None;
+        config.query_address = None;
+        // Sleeping before the bots join would count as slow frames
+        config.idle_tick_ms = 0;
+    }
+    if determinism.is_some() {
+        // Only the loopback clients, from a clean world
+        config.offline = true;
+        config.require_identity = false;
+        config.join_password = None;
+        config.restore_positions = false;
+        config.save_path = std::env::temp_dir().join("determinism_server_state.json");
+        config.rcon_address = None;
+        config.query_address = None;
+        config.match_length_secs = 0;
+        // Nothing else moves the players
+        config.script_path = None;
+    }
+
+    let port = config.port;
+    let props = Props::spawn(&config.props);
+    let level = Level(config.levels.first().cloned());
+
+    let mut app = App::new();
+    // When hosted from the game, its logging may already be set up: the server logs there
+    // and keeps no lines for RCON
+    if !bevy::log::tracing::dispatcher::has_been_set() {
+        app.add_plugins(LogPlugin {
+            custom_layer: server_log::capture,
+            ..default()
+        });
+    }
+    app.add_plugins((QuinnetServerPlugin::default(), MinimalServerPlugin))
+        .set_runner(server_runner)
+        .insert_resource(config)
+        .insert_resource(props)
+        .insert_resource(level);
+    #[cfg(feature = "server-chat")]
+    app.add_plugins(ChatServerPlugin);
+    #[cfg(feature = "server-movement")]
+    app.add_plugins(MovementRelayPlugin);
+    #[cfg(feature = "server-rooms")]
+    app.add_plugins(RoomsPlugin);
+
+    if let Some(options) = bench {
+        app.insert_resource(Bench::start(options, port))
+            .add_systems(Update, bench::finish_bench);
+    }
+    if let Some(options) = determinism {
+        app.insert_resource(Determinism::start(options))
+            .add_systems(Update, determinism::finish_determinism);
+    }
+    if app.run().is_error() {
+        return Err("the server stopped with an error, see the log".to_string());
+    }
+    Ok(())
+}
+
+/// What every server runs: joins, sessions, accounts, admin and votes, matches,
+/// persistence and RCON. Chat, movement and rooms are separate plugins, each behind its
+/// cargo feature, so a game that only needs some of them builds only those. Room
+/// membership and the props stay here, since joins, saves and state checksums read
+/// them; the plugins are what change them. Expects the `ServerConfig`, `Props` and
+/// `Level` resources to be inserted.
+pub struct MinimalServerPlugin;
+
+impl Plugin for MinimalServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(from_client::EventsPlugin)
+            .add_event::<SendToClient>()
+            .add_event::<TeleportPlayer>()
+            .add_event::<Feedback>()
+            .insert_resource(Users::default())
+            .init_resource::<ChatHistory>()
+            .init_resource::<Accounts>()
+            .init_resource::<Rooms>()
+            .init_resource::<RestoredFrom>()
+            .init_resource::<PendingShutdown>()
+            .init_resource::<JoinStreams>()
+            .init_resource::<RoomTicks>()
+            .init_resource::<ReplayGuard>()
+            .init_resource::<Sessions>()
+            .init_resource::<IdleMode>()
+            .init_resource::<Webhook>()
+            .init_resource::<Reports>()
+            .init_resource::<LoopbackServer>()
+            .init_resource::<Scripts>()
+            .init_resource::<Navigation>()
+            .init_resource::<NetHandlers<(ClientId, ClientMessage)>>()
+            .insert_resource(MatchSeed(rand::random()))
+            .insert_resource(Environment(EnvironmentState {
+                weather_seed: rand::random(),
+                ..default()
+            }))
+            .add_systems(
+                Startup,
+                (
+                    restore_state,
+                    start_listening,
+                    start_rcon,
+                    start_query,
+                    webhooks::notify_server_start,
+                ),
+            )
+            .add_systems(PreUpdate, accept_loopback_clients)
+            .add_systems(
+                Update,
+                (
+                    handle_client_messages,
+                    handle_server_events,
+                    expire_sessions,
+                    handle_rcon_commands.run_if(resource_exists::<RconReceiver>),
+                    run_pending_shutdown,
+                    watch_config_file,
+                    broadcast_reminders,
+                    advance_environment,
+                    stream_join_state,
+                    autosave,
+                    report_anomalies,
+                    run_votes.after(handle_client_messages),
+                    send_level_on_join.after(handle_client_messages),
+                    run_admin_commands.after(handle_client_messages),
+                    store_last_positions,
+                    send_feedback,
+                    broadcast_pings,
+                    update_query_info.run_if(resource_exists::<QueryInfo>),
+                    load_navigation,
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    run_scripts,
+                    apply_teleports,
+                    broadcast_checksums,
+                    handle_reports,
+                    run_match,
+                    flush_sent_messages,
+                )
+                    .chain(),
+            )
+            .add_systems(Last, (track_idle, notify_joins_and_leaves, count_playtime));
+    }
+}
+
+/// Chat messages and whispers, with the backlog sent to joining players
+#[cfg(feature = "server-chat")]
+pub struct ChatServerPlugin;
+
+#[cfg(feature = "server-chat")]
+impl Plugin for ChatServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_chat.after(handle_client_messages));
+    }
+}
+
+/// Player updates relayed on the room ticks, the game rules that bound them, and
+/// physics props
+#[cfg(feature = "server-movement")]
+pub struct MovementRelayPlugin;
+
+#[cfg(feature = "server-movement")]
+impl Plugin for MovementRelayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                handle_movement.after(handle_client_messages),
+                tick_rooms.after(handle_movement).before(send_feedback),
+                sync_props,
+                broadcast_rule_changes,
+                sync_platforms.after(handle_client_messages),
+            ),
+        )
+        .insert_resource(ServerClock(Instant::now()));
+    }
+}
+
+/// Private rooms behind invite codes; without it everyone plays in the lobby
+#[cfg(feature = "server-rooms")]
+pub struct RoomsPlugin;
+
+#[cfg(feature = "server-rooms")]
+impl Plugin for RoomsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_rooms.after(handle_client_messages));
+    }
+}
+
+/// Same loop as `ScheduleRunnerPlugin`, but a panicking update first tells every
+/// client the server is going down, so they don't wait for a timeout
+fn server_runner(mut app: App) -> AppExit {
+    if app.plugins_state() != PluginsState::Cleaned {
+        app.finish();
+        app.cleanup();
+    }
+
+    loop {
+        let started = Instant::now();
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| app.update())) {
+            notify_clients_of_crash(app.world_mut());
+            panic::resume_unwind(payload);
+        }
+        if let Some(mut bench) = app.world_mut().get_resource_mut::<Bench>() {
+            bench.record(started.elapsed());
+        }
+        if let Some(exit) = app.should_exit() {
+            return exit;
+        }
+        // A connection waits at most one interval to be served
+        let idle = app.world().resource::<IdleMode>();
+        if idle.active {
+            sleep(idle.interval.saturating_sub(started.elapsed()));
+        }
+    }
+}
+
+fn track_idle(users: Res<Users>, config: Res<ServerConfig>, mut idle: ResMut<IdleMode>) {
+    let active = config.idle_tick_ms > 0 && users.names.is_empty() && users.suspended.is_empty();
+    if active != idle.active {
+        if active {
+            info!(
+                "Nobody connected, updating every {} ms",
+                config.idle_tick_ms
+            );
+        } else {
+            info!("Player connected, back to full rate");
+        }
+        idle.active = active;
+    }
+    idle.interval = Duration::from_millis(config.idle_tick_ms);
+}
+
+/// Post joins and leaves to the webhook. By name, so a resumed session (which gets a new
+/// client id) is no news, and a dropped player only leaves once it can't resume. Who is
+/// there is tracked without a webhook too, so setting one later only posts what changes.
+fn notify_joins_and_leaves(
+    users: Res<Users>,
+    config: Res<ServerConfig>,
+    webhook: Res<Webhook>,
+    mut present: Local<HashSet<String>>,
+) {
+    if !users.is_changed() {
+        return;
+    }
+    let now: HashSet<String> = users
+        .names
+        .values()
+        .chain(users.suspended.values())
+        .cloned()
+        .collect();
+    if config.webhook_url.is_none() {
+        *present = now;
+        return;
+    }
+    for name in now.difference(&present) {
+        webhook.notify(&config, format!("{} joined", name));
+    }
+    for name in present.difference(&now) {
+        webhook.notify(&config, format!("{} left", name));
+    }
+    *present = now;
+}
+
+fn notify_clients_of_crash(world: &mut World) {
+    if let Some(mut loopback) = world.get_resource_mut::<LoopbackServer>() {
+        loopback.broadcast(&ServerMessage::ShuttingDown { seconds: 0 });
+    }
+    let Some(mut server) = world.get_resource_mut::<QuinnetServer>() else {
+        return;
+    };
+    if let Some(endpoint) = server.get_endpoint_mut() {
+        let payload = protocol::encode(&ServerMessage::ShuttingDown { seconds: 0 });
+        for client_id in endpoint.clients() {
+            endpoint.try_send_payload(client_id, payload.clone());
+        }
+        // Give the endpoint's async tasks a moment to flush
+        sleep(Duration::from_millis(100));
+    }
+}
+
+fn restore_state(
+    config: Res<ServerConfig>,
+    mut history: ResMut<ChatHistory>,
+    mut accounts: ResMut<Accounts>,
+    mut reports: ResMut<Reports>,
+    mut users: ResMut<Users>,
+    mut props: ResMut<Props>,
+    mut restored: ResMut<RestoredFrom>,
+) {
+    match persistence::load_snapshot(&config.save_path) {
+        Ok(Some(snapshot)) => {
+            info!(
+                "Restored state saved at {} from {:?}",
+                snapshot.saved_at, config.save_path
+            );
+            history.0 = snapshot.chat_history.into();
+            accounts.0 = snapshot.accounts;
+            accounts.rekey_bound_profiles();
+            reports.list = snapshot.reports.into();
+            props.restore(&snapshot.props);
+            users.current_match.restore_scores(snapshot.match_scores);
+            restored.0 = Some(snapshot.saved_at);
+        }
+        Ok(None) => {}
+        Err(err) => warn!("Could not load {:?}: {}", config.save_path, err),
+    }
+}
+
+fn autosave(
+    config: Res<ServerConfig>,
+    history: Res<ChatHistory>,
+    accounts: Res<Accounts>,
+    reports: Res<Reports>,
+    users: Res<Users>,
+    rooms: Res<Rooms>,
+    props: Res<Props>,
+    mut last_save: Local<Option<Instant>>,
+) {
+    let last = *last_save.get_or_insert_with(Instant::now);
+    if last.elapsed() < Duration::from_secs(config.autosave_interval_secs) {
+        return;
+    }
+    *last_save = Some(Instant::now());
+    save_state(
+        &config, &history, &accounts, &reports, &users, &rooms, &props,
+    );
+}
+
+fn save_state(
+    config: &ServerConfig,
+    history: &ChatHistory,
+    accounts: &Accounts,
+    reports: &Reports,
+    users: &Users,
+    rooms: &Rooms,
+    props: &Props,
+) {
+    let players: Vec<(ClientId, String)> = users
+        .names
+        .iter()
+        .chain(users.suspended.iter())
+        .filter_map(|(client_id, _)| Some((*client_id, users.account_ids.get(client_id)?.clone())))
+        .collect();
+    // Connected players come back where they are, as if they had just left
+    let mut profiles = accounts.0.clone();
+    for (client_id, account) in &players {
+        if let Some(&(x, y)) = users.positions.get(client_id) {
+            profiles.entry(account.clone()).or_default().last_position = Some(LastPosition {
+                x,
+                y,
+                room: rooms.room_of(*client_id).cloned(),
+            });
+        }
+    }
+    let snapshot = ServerSnapshot {
+        saved_at: persistence::unix_now(),
+        chat_history: history.0.iter().cloned().collect(),
+        accounts: profiles,
+        reports: reports.list.iter().cloned().collect(),
+        props: props.snapshot(),
+        match_scores: users.current_match.scores_by_account(players),
+    };
+    if let Err(err) = persistence::write_snapshot(&config.save_path, &snapshot) {
+        warn!("Autosave to {:?} failed: {}", config.save_path, err);
+    }
+}
+
+fn start_listening(
+    mut server: ResMut<QuinnetServer>,
+    config: Res<ServerConfig>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if config.offline {
+        info!("Offline, only serving clients in this process");
+        return;
+    }
+    if config.tls_cert_path.is_some() != config.tls_key_path.is_some() {
+        warn!(
+            "tls_cert_path and tls_key_path must be set together, using a self-signed certificate"
+        );
+    }
+    let address = config.endpoint_address();
+    let Ok(endpoint) = ServerEndpointConfiguration::from_string(&address) else {
+        error!("Invalid listen address {}", address);
+        exit.write(AppExit::error());
+        return;
+    };
+    if let Err(err) = server.start_endpoint(
+        endpoint,
+        config.certificate_mode(),
+        protocol::channels::configuration(),
+    ) {
+        // Most likely a busy port or an unreadable certificate; serving self-signed
+        // instead would silently break clients that verify it
+        error!("Could not start the server endpoint: {}", err);
+        exit.write(AppExit::error());
+    }
+}
+
+fn start_rcon(mut commands: Commands, config: Res<ServerConfig>) {
+    let Some(address) = &config.rcon_address else {
+        return;
+    };
+    match rcon::spawn_listener(address, config.rcon_password.clone()) {
+        Ok(receiver) => {
+            info!("RCON listening on {}", address);
+            commands.insert_resource(receiver);
+        }
+        Err(err) => warn!("Could not start RCON on {}: {}", address, err),
+    }
+}
+
+fn start_query(
+    mut commands: Commands,
+    config: Res<ServerConfig>,
+    users: Res<Users>,
+    level: Res<Level>,
+) {
+    let Some(address) = config.query_address.as_ref().filter(|_| !config.offline) else {
+        return;
+    };
+    match query::spawn_listener(address, config.server_info(&users, &level)) {
+        Ok(info) => {
+            info!("Answering queries on {}", address);
+            commands.insert_resource(info);
+        }
+        Err(err) => warn!("Could not start the query endpoint on {}: {}", address, err),
+    }
+}
+
+/// Keep the query endpoint's answer current
+fn update_query_info(
+    info: Res<QueryInfo>,
+    config: Res<ServerConfig>,
+    users: Res<Users>,
+    level: Res<Level>,
+) {
+    if config.is_changed() || users.is_changed() || level.is_changed() {
+        info.set(config.server_info(&users, &level));
+    }
+}
+
+fn reload_config(config: &mut ServerConfig) -> Result<(), String> {
+    match ServerConfig::load(Path::new(CONFIG_PATH)) {
+        Ok(Some(new)) => {
+            config.apply_reloadable(new);
+            info!("Reloaded {}", CONFIG_PATH);
+            Ok(())
+        }
+        Ok(None) => Err(format!("{} not found", CONFIG_PATH)),
+        Err(err) => Err(format!("invalid {}: {}", CONFIG_PATH, err)),
+    }
+}
+
+/// Poll the config file's modification time every couple of seconds
+fn watch_config_file(
+    mut config: ResMut<ServerConfig>,
+    mut last_check: Local<Option<Instant>>,
+    mut last_modified: Local<Option<SystemTime>>,
+) {
+    if last_check.is_some_and(|last| last.elapsed() < Duration::from_secs(2)) {
+        return;
+    }
+    *last_check = Some(Instant::now());
+
+    let Ok(modified) = fs::metadata(CONFIG_PATH).and_then(|meta| meta.modified()) else {
+        return;
+    };
+    let previous = last_modified.replace(modified);
+    // The first check only records the startup version
+    if previous.is_some_and(|previous| previous != modified) {
+        if let Err(err) = reload_config(&mut config) {
+            warn!("Config not reloaded: {}", err);
+        }
+    }
+}
+
+/// Every client connection: quinnet's endpoint (unless offline) and the loopback
+#[derive(SystemParam)]
+pub struct Connections<'w> {
+    quinnet: ResMut<'w, QuinnetServer>,
+    loopback: ResMut<'w, LoopbackServer>,
+}
+
+impl Connections<'_> {
+    fn endpoint_mut(&mut self) -> ServerEndpoint<'_> {
+        ServerEndpoint {
+            quinnet: self.quinnet.get_endpoint_mut(),
+            loopback: &mut self.loopback,
+        }
+    }
+
+    fn stop_endpoint(&mut self) -> Result<(), String> {
+        self.loopback.disconnect_all();
+        if self.quinnet.get_endpoint().is_none() {
+            return Ok(());
+        }
+        self.quinnet.stop_endpoint().map_err(|err| err.to_string())
+    }
+}
+
+/// Sends to and receives from clients however they are connected. Mirrors the parts
+/// of quinnet's `Endpoint` the server uses; messages go out as `protocol::encode`
+/// payloads, so they are enveloped on every transport.
+pub struct ServerEndpoint<'a> {
+    quinnet: Option<&'a mut Endpoint>,
+    loopback: &'a mut LoopbackServer,
+}
+
+impl ServerEndpoint<'_> {
+    fn clients(&self) -> Vec<ClientId> {
+        let mut clients = self
+            .quinnet
+            .as_ref()
+            .map(|endpoint| endpoint.clients())
+            .unwrap_or_default();
+        clients.extend(self.loopback.clients());
+        clients
+    }
+
+    /// The next payload from this client into `buffer`, false once there is none
+    fn try_receive_payload_into(&mut self, client_id: ClientId, buffer: &mut Vec<u8>) -> bool {
+        if self.loopback.contains(client_id) {
+            // Already an owned buffer, taken as is
+            return match self.loopback.receive_payload(client_id) {
+                Some(payload) => {
+                    *buffer = payload;
+                    true
+                }
+                None => false,
+            };
+        }
+        let Some((_, payload)) = self
+            .quinnet
+            .as_mut()
+            .and_then(|endpoint| endpoint.try_receive_payload_from(client_id))
+        else {
+            return false;
+        };
+        buffer.extend_from_slice(&payload);
+        true
+    }
+
+    fn try_send_message(&mut self, client_id: ClientId, message: ServerMessage) {
+        if self.loopback.contains(client_id) {
+            self.loopback.send(client_id, &message);
+        } else if let Some(endpoint) = &mut self.quinnet {
+            endpoint.try_send_payload(client_id, protocol::encode(&message));
+        }
+    }
+
+    fn send_message(&mut self, client_id: ClientId, message: ServerMessage) -> Result<(), String> {
+        if self.loopback.contains(client_id) {
+            self.loopback.send(client_id, &message);
+            return Ok(());
+        }
+        match &mut self.quinnet {
+            Some(endpoint) => endpoint
+                .send_payload(client_id, protocol::encode(&message))
+                .map_err(|err| err.to_string()),
+            None => Err(format!("no client {}", client_id)),
+        }
+    }
+
+    fn send_group_message<'c>(
+        &mut self,
+        client_ids: impl Iterator<Item = &'c ClientId>,
+        message: ServerMessage,
+    ) -> Result<(), String> {
+        let mut remote = Vec::new();
+        for &client_id in client_ids {
+            if self.loopback.contains(client_id) {
+                self.loopback.send(client_id, &message);
+            } else {
+                remote.push(client_id);
+            }
+        }
+        let Some(endpoint) = &mut self.quinnet else {
+            return Ok(());
+        };
+        let payload = protocol::encode(&message);
+        for client_id in remote {
+            endpoint
+                .send_payload(client_id, payload.clone())
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn try_send_group_message<'c>(
+        &mut self,
+        client_ids: impl Iterator<Item = &'c ClientId>,
+        message: ServerMessage,
+    ) {
+        let mut remote = Vec::new();
+        for &client_id in client_ids {
+            if self.loopback.contains(client_id) {
+                self.loopback.send(client_id, &message);
+            } else {
+                remote.push(client_id);
+            }
+        }
+        if let Some(endpoint) = &mut self.quinnet {
+            let payload = protocol::encode(&message);
+            for client_id in remote {
+                endpoint.try_send_payload(client_id, payload.clone());
+            }
+        }
+    }
+
+    fn disconnect_client(&mut self, client_id: ClientId) -> Result<(), String> {
+        if self.loopback.contains(client_id) {
+            self.loopback.disconnect(client_id);
+            return Ok(());
+        }
+        match &mut self.quinnet {
+            Some(endpoint) => endpoint
+                .disconnect_client(client_id)
+                .map_err(|err| err.to_string()),
+            None => Err(format!("no client {}", client_id)),
+        }
+    }
+
+    /// Loopback clients have a perfect link
+    fn link_stats(&self, client_id: ClientId) -> Option<LinkStats> {
+        if self.loopback.contains(client_id) {
+            return Some(LinkStats::default());
+        }
+        let stats = self.quinnet.as_ref()?.get_connection_stats(client_id)?;
+        Some(LinkStats {
+            rtt: stats.path.rtt,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+            congestion_events: stats.path.congestion_events,
+        })
+    }
+}
+
+/// Take the loopback connections opened since the last frame. A closed one is handled
+/// like a lost QUIC connection; an offline server shuts down once nobody is left.
+fn accept_loopback_clients(
+    mut connections: Connections,
+    config: Res<ServerConfig>,
+    mut shutdown: ResMut<PendingShutdown>,
+    mut lost: EventWriter<ConnectionLostEvent>,
+) {
+    let closed = connections.loopback.update();
+    if closed.is_empty() {
+        return;
+    }
+    for &id in &closed {
+        lost.write(ConnectionLostEvent { id });
+    }
+    if config.offline && connections.loopback.clients().next().is_none() {
+        info!("Last offline player left, shutting down");
+        shutdown.deadline = Some(Instant::now());
+    }
+}
+
+/// Queue a message for one client, sent at the end of the frame. Systems that only
+/// send write this instead of taking the endpoint.
+#[derive(Event, Debug, Clone)]
+pub struct SendToClient {
+    pub client_id: ClientId,
+    pub message: ServerMessage,
+}
+
+fn flush_sent_messages(mut events: EventReader<SendToClient>, mut server: Connections) {
+    let endpoint = &mut server.endpoint_mut();
+    for ev in events.read() {
+        endpoint.try_send_message(ev.client_id, ev.message.clone());
+    }
+}
+
+/// One `SendToClient` per connected player
+fn send_to_all(sent: &mut EventWriter<SendToClient>, users: &Users, message: ServerMessage) {
+    sent.write_batch(users.names.keys().map(|&client_id| SendToClient {
+        client_id,
+        message: message.clone(),
+    }));
+}
+
+/// Move a player where the server says, e.g. to respawn it or to undo an impossible
+/// move. Sent to every player in its room as `ServerMessage::Teleport`, which clients
+/// apply even to their own player.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TeleportPlayer {
+    pub client_id: ClientId,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Something the players of `room` (None for the lobby) should feel, e.g. damage, an
+/// explosion or a collision. Sent to them as `ServerMessage::FeedbackEvent`.
+#[derive(Event, Debug, Clone)]
+pub struct Feedback {
+    pub room: Option<String>,
+    pub kind: FeedbackKind,
+    /// 0 to 1
+    pub intensity: f32,
+    pub x: f32,
+    pub y: f32,
+}
+
+fn send_feedback(
+    mut events: EventReader<Feedback>,
+    users: Res<Users>,
+    rooms: Res<Rooms>,
+    mut sent: EventWriter<SendToClient>,
+) {
+    for ev in events.read() {
+        let message = ServerMessage::FeedbackEvent {
+            kind: ev.kind,
+            intensity: ev.intensity.clamp(0.0, 1.0),
+            x: ev.x,
+            y: ev.y,
+        };
+        sent.write_batch(
+            users
+                .names
+                .keys()
+                .filter(|client_id| rooms.room_of(**client_id) == ev.room.as_ref())
+                .map(|&client_id| SendToClient {
+                    client_id,
+                    message: message.clone(),
+                }),
+        );
+    }
+}
+
+/// Tell everyone how laggy the players in their room are, from the QUIC path RTT
+fn broadcast_pings(
+    mut server: Connections,
+    users: Res<Users>,
+    rooms: Res<Rooms>,
+    mut sent: EventWriter<SendToClient>,
+    mut last_sent: Local<Option<Instant>>,
+) {
+    if users.names.is_empty() || last_sent.is_some_and(|last| last.elapsed() < PING_INTERVAL) {
+        return;
+    }
+    *last_sent = Some(Instant::now());
+    let endpoint = &mut server.endpoint_mut();
+    let pings: Vec<(Option<&String>, PlayerPing)> = users
+        .names
+        .keys()
+        .filter_map(|&client_id| {
+            let stats = endpoint.link_stats(client_id)?;
+            Some((
+                rooms.room_of(client_id),
+                PlayerPing {
+                    client_id,
+                    rtt_ms: stats.rtt.as_millis() as u32,
+                },
+            ))
+        })
+        .collect();
+    for &client_id in users.names.keys() {
+        let room = rooms.room_of(client_id);
+        let message = ServerMessage::PlayerPings {
+            pings: pings
+                .iter()
+                .filter(|(other_room, _)| *other_room == room)
+                .map(|(_, ping)| *ping)
+                .collect(),
+        };
+        sent.write(SendToClient { client_id, message });
+    }
+}
+
+/// Call the operator's script hooks (see `scripting`) with what happened this frame,
+/// then do what they asked for
+fn run_scripts(
+    mut chats: EventReader<from_client::ChatMessage>,
+    mut moves: EventReader<from_client::PlayerUpdate>,
+    mut scripts: ResMut<Scripts>,
+    users: Res<Users>,
+    rooms: Res<Rooms>,
+    config: Res<ServerConfig>,
+    mut teleports: EventWriter<TeleportPlayer>,
+    mut feedback: EventWriter<Feedback>,
+    mut sent: EventWriter<SendToClient>,
+    mut present: Local<HashSet<String>>,
+    mut last_tick: Local<Option<Instant>>,
+) {
+    scripts.watch(config.script_path.as_deref());
+    // By name like the webhook, so a resumed session doesn't join again
+    let joined: Vec<(ClientId, String)> = users
+        .names
+        .iter()
+        .filter(|(_, name)| !present.contains(*name))
+        .map(|(&client_id, name)| (client_id, name.clone()))
+        .collect();
+    *present = users
+        .names
+        .values()
+        .chain(users.suspended.values())
+        .cloned()
+        .collect();
+    if !scripts.is_loaded() {
+        chats.clear();
+        moves.clear();
+        return;
+    }
+
+    scripts.set_players(
+        users
+            .names
+            .iter()
+            .filter_map(|(client_id, name)| Some((name.clone(), *users.positions.get(client_id)?))),
+    );
+    for (client_id, name) in joined {
+        let Some(&(x, y)) = users.positions.get(&client_id) else {
+            continue;
+        };
+        if let Some((x, y)) = scripts.on_join(&name, x, y) {
+            teleports.write(TeleportPlayer { client_id, x, y });
+        }
+    }
+    for ev in chats.read() {
+        if let Some(name) = users.names.get(&ev.sender) {
+            scripts.on_chat(name, &ev.message);
+        }
+    }
+    for ev in moves.read() {
+        let (Some(name), Some(&(x, y))) =
+            (users.names.get(&ev.sender), users.positions.get(&ev.sender))
+        else {
+            continue;
+        };
+        if let Some((x, y)) = scripts.on_player_move(name, x, y) {
+            teleports.write(TeleportPlayer {
+                client_id: ev.sender,
+                x,
+                y,
+            });
+        }
+    }
+    if last_tick.is_none_or(|last| last.elapsed() >= scripting::TICK_INTERVAL) {
+        let dt = last_tick.map_or(0.0, |last| last.elapsed().as_secs_f32());
+        *last_tick = Some(Instant::now());
+        scripts.on_tick(dt);
+    }
+
+    for action in scripts.take_actions() {
+        match action {
+            ScriptAction::Say(text) => send_to_all(
+                &mut sent,
+                &users,
+                notice(NoticeLevel::Info, LocalizedText::raw(text)),
+            ),
+            ScriptAction::Tell { name, text } => match find_player(&users, &name) {
+                Some(client_id) => {
+                    sent.write(SendToClient {
+                        client_id,
+                        message: notice(NoticeLevel::Info, LocalizedText::raw(text)),
+                    });
+                }
+                None => warn!("Script told {}, who isn't connected", name),
+            },
+            ScriptAction::Teleport { name, x, y } => match find_player(&users, &name) {
+                Some(client_id) => {
+                    teleports.write(TeleportPlayer { client_id, x, y });
+                }
+                None => warn!("Script teleported {}, who isn't connected", name),
+            },
+            ScriptAction::Hit { name, intensity } => {
+                let hit = find_player(&users, &name)
+                    .and_then(|client_id| Some((client_id, *users.positions.get(&client_id)?)));
+                match hit {
+                    Some((client_id, (x, y))) => {
+                        feedback.write(Feedback {
+                            room: rooms.room_of(client_id).cloned(),
+                            kind: FeedbackKind::Hit,
+                            intensity,
+                            x,
+                            y,
+                        });
+                    }
+                    None => warn!("Script hit {}, who isn't connected", name),
+                }
+            }
+            ScriptAction::Explosion { x, y, intensity } => {
+                feedback.write(Feedback {
+                    room: None,
+                    kind: FeedbackKind::Explosion,
+                    intensity,
+                    x,
+                    y,
+                });
+            }
+        }
+    }
+}
+
+fn apply_teleports(
+    mut events: EventReader<TeleportPlayer>,
+    mut users: ResMut<Users>,
+    rooms: Res<Rooms>,
+    config: Res<ServerConfig>,
+    mut sent: EventWriter<SendToClient>,
+) {
+    for ev in events.read() {
+        if !users.names.contains_key(&ev.client_id) {
+            continue;
+        }
+        let (x, y) = config.clamp_position(ev.x, ev.y);
+        users.positions.insert(ev.client_id, (x, y));
+        // The jump isn't the player's own doing
+        users.anomalies.reset_position(ev.client_id);
+        let message = ServerMessage::Teleport {
+            client_id: ev.client_id,
+            x,
+            y,
+        };
+        let recipients = users.room_players(&rooms, ev.client_id);
+        sent.write_batch(
+            recipients
+                .into_iter()
+                .filter(|client_id| users.names.contains_key(client_id))
+                .map(|client_id| SendToClient {
+                    client_id,
+                    message: message.clone(),
+                }),
+        );
+    }
+}
+
+/// Send every synced client the `protocol::state_checksum` of its room, so it notices
+/// when it missed something and asks for a resync
+fn broadcast_checksums(
+    users: Res<Users>,
+    rooms: Res<Rooms>,
+    props: Res<Props>,
+    streams: Res<JoinStreams>,
+    config: Res<ServerConfig>,
+    mut sent: EventWriter<SendToClient>,
+    mut last_sent: Local<Option<Instant>>,
+) {
+    let interval = Duration::from_secs(config.state_checksum_secs);
+    if config.state_checksum_secs == 0
+        || users.names.is_empty()
+        || last_sent.is_some_and(|last| last.elapsed() < interval)
+    {
+        return;
+    }
+    *last_sent = Some(Instant::now());
+    let prop_ids: Vec<u32> = props.snapshot().iter().map(|state| state.prop_id).collect();
+    let mut by_room: HashMap<Option<&String>, u64> = HashMap::new();
+    for &client_id in users.names.keys() {
+        // Still receiving the world, it would disagree anyway
+        if streams.0.contains_key(&client_id) {
+            continue;
+        }
+        let checksum = *by_room.entry(rooms.room_of(client_id)).or_insert_with(|| {
+            let players = users.room_players(&rooms, client_id);
+            let infos: Vec<(ClientId, &str)> = players
+                .iter()
+                .filter_map(|&other| {
+                    let name = users.names.get(&other).or(users.suspended.get(&other))?;
+                    Some((other, name.as_str()))
+                })
+                .collect();
+            protocol::state_checksum(infos, prop_ids.iter().copied())
+        });
+        sent.write(SendToClient {
+            client_id,
+            message: ServerMessage::StateChecksum { checksum },
+        });
+    }
+}
+
+fn notice(level: NoticeLevel, text: LocalizedText) -> ServerMessage {
+    ServerMessage::SystemNotice { level, text }
+}
+
+fn broadcast_reminders(
+    mut sent: EventWriter<SendToClient>,
+    users: Res<Users>,
+    config: Res<ServerConfig>,
+    mut last_sent: Local<Option<Instant>>,
+    mut next: Local<usize>,
+) {
+    if config.reminders.is_empty() || users.names.is_empty() {
+        return;
+    }
+    let interval = Duration::from_secs(config.reminder_interval_secs);
+    if last_sent.get_or_insert_with(Instant::now).elapsed() < interval {
+        return;
+    }
+    *last_sent = Some(Instant::now());
+    let reminder = &config.reminders[*next % config.reminders.len()];
+    *next = next.wrapping_add(1);
+    send_to_all(
+        &mut sent,
+        &users,
+        notice(NoticeLevel::Info, LocalizedText::raw(reminder)),
+    );
+}
+
+fn advance_environment(
+    mut sent: EventWriter<SendToClient>,
+    users: Res<Users>,
+    config: Res<ServerConfig>,
+    mut environment: ResMut<Environment>,
+    mut last_tick: Local<Option<Instant>>,
+    mut last_sync: Local<Option<Instant>>,
+) {
+    let now = Instant::now();
+    let elapsed = now.duration_since(last_tick.replace(now).unwrap_or(now));
+    environment.0.day_length_secs = config.day_length_secs;
+    let new_day = environment.0.advance(elapsed.as_secs_f32());
+    if new_day {
+        environment.0.weather_seed = rand::random();
+    }
+
+    let sync_due =
+        last_sync.get_or_insert(now).elapsed() >= Duration::from_secs(config.environment_sync_secs);
+    if (new_day || sync_due) && !users.names.is_empty() {
+        *last_sync = Some(now);
+        send_to_all(
+            &mut sent,
+            &users,
+            ServerMessage::Environment {
+                state: environment.0,
+            },
+        );
+    }
+}
+
+/// When the server started, the clock platforms follow
+#[cfg(feature = "server-movement")]
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ServerClock(pub Instant);
+
+#[cfg(feature = "server-movement")]
+impl ServerClock {
+    pub fn now_ms(&self) -> u64 {
+        self.0.elapsed().as_millis() as u64
+    }
+}
+
+/// Send the platforms and the clock they follow to joining and resyncing players, to
+/// everyone when they change, and every `environment_sync_secs` to correct drift
+#[cfg(feature = "server-movement")]
+fn sync_platforms(
+    mut joins: EventReader<from_client::Join>,
+    mut resumes: EventReader<from_client::Resume>,
+    mut full_syncs: EventReader<from_client::RequestFullSync>,
+    users: Res<Users>,
+    config: Res<ServerConfig>,
+    clock: Res<ServerClock>,
+    mut sent: EventWriter<SendToClient>,
+    mut last_sync: Local<Option<Instant>>,
+) {
+    let requested: Vec<ClientId> = joins
+        .read()
+        .map(|ev| ev.sender)
+        .chain(resumes.read().map(|ev| ev.sender))
+        .chain(full_syncs.read().map(|ev| ev.sender))
+        // Also written for refused joins
+        .filter(|client_id| users.names.contains_key(client_id))
+        .collect();
+    let message = || ServerMessage::Platforms {
+        server_time_ms: clock.now_ms(),
+        platforms: config.platforms.clone(),
+    };
+    let interval = Duration::from_secs(config.environment_sync_secs.max(1));
+    let due =
+        !config.platforms.is_empty() && last_sync.is_none_or(|last| last.elapsed() >= interval);
+    // Removed platforms are announced too
+    if config.is_changed() || due {
+        *last_sync = Some(Instant::now());
+        send_to_all(&mut sent, &users, message());
+    } else if !config.platforms.is_empty() {
+        sent.write_batch(requested.into_iter().map(|client_id| SendToClient {
+            client_id,
+            message: message(),
+        }));
+    }
+}
+
+/// Tell everyone when a reload or RCON command changed the rules
+#[cfg(feature = "server-movement")]
+fn broadcast_rule_changes(
+    mut sent: EventWriter<SendToClient>,
+    users: Res<Users>,
+    config: Res<ServerConfig>,
+    mut last_sent: Local<Option<GameRules>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let rules = config.game_rules();
+    if last_sent.replace(rules).is_none_or(|last| last == rules) {
+        // Joining clients get the rules in the join flow
+        return;
+    }
+    info!("Game rules changed: {:?}", rules);
+    send_to_all(&mut sent, &users, ServerMessage::GameRules { rules });
+}
+
+/// Time all room ticks may take together in one server frame; rooms left over
+/// keep their time owed and go first next frame
+#[cfg(feature = "server-movement")]
+const ROOM_FRAME_BUDGET: Duration = Duration::from_millis(8);
+/// Players closer than this ran into each other, when player collision is on
+#[cfg(feature = "server-movement")]
+const BUMP_DISTANCE: f32 = 40.0;
+/// Minimum time between two bumps felt from the same player
+#[cfg(feature = "server-movement")]
+const BUMP_COOLDOWN: Duration = Duration::from_millis(500);
+/// Feedback intensity of a bump at full input
+#[cfg(feature = "server-movement")]
+const BUMP_INTENSITY: f32 = 0.3;
+
+#[cfg(feature = "server-movement")]
+fn tick_rooms(
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    config: Res<ServerConfig>,
+    rooms: Res<Rooms>,
+    mut ticks: ResMut<RoomTicks>,
+    mut feedback: EventWriter<Feedback>,
+    mut last_frame: Local<Option<Instant>>,
+    mut first: Local<usize>,
+) {
+    let now = Instant::now();
+    let delta = now.duration_since(last_frame.replace(now).unwrap_or(now));
+    let tick = Duration::from_secs_f32(1.0 / config.room_tick_rate.max(1.0));
+    ticks.advance(&rooms, delta, tick);
+
+    // Rotate who goes first, so the budget running out never hits the same room twice
+    let mut order: Vec<_> = ticks.0.keys().cloned().collect();
+    order.sort();
+    *first = (*first + 1) % order.len();
+    order.rotate_left(*first);
+
+    let endpoint = &mut server.endpoint_mut();
+    for key in order {
+        if now.elapsed() > ROOM_FRAME_BUDGET {
+            break;
+        }
+        let Some(room) = ticks.0.get_mut(&key) else {
+            continue;
+        };
+        if !room.take_tick(tick) {
+            continue;
+        }
+        let started = Instant::now();
+        run_room_tick(
+            endpoint,
+            &mut users,
+            &config,
+            &rooms,
+            room,
+            tick,
+            &mut feedback,
+        );
+        room.record(started.elapsed());
+    }
+}
+
+/// Relay the movement queued in one room since its last tick: the newest position,
+/// with the input blended over the tick
+#[cfg(feature = "server-movement")]
+fn run_room_tick(
+    endpoint: &mut ServerEndpoint,
+    users: &mut Users,
+    config: &ServerConfig,
+    rooms: &Rooms,
+    room: &mut RoomTick,
+    tick: Duration,
+    feedback: &mut EventWriter<Feedback>,
+) {
+    for (client_id, moves) in room.moves.drain() {
+        if !users.names.contains_key(&client_id) {
+            continue;
+        }
+        let (Some(queued), Some((horizontal, vertical))) =
+            (moves.last(), rooms::blend_moves(&moves, tick))
+        else {
+            continue;
+        };
+        let peers = rooms.peers(client_id, users.names.keys());
+        endpoint.try_send_group_message(
+            peers.iter(),
+            ServerMessage::PlayerUpdate {
+                client_id,
+                seq: queued.seq,
+                x: queued.x,
+                y: queued.y,
+                horizontal,
+                vertical,
+            },
+        );
+
+        let moving = horizontal != 0.0 || vertical != 0.0;
+        let footstep_due = users.last_footstep.get(&client_id).is_none_or(|last| {
+            last.elapsed() >= Duration::from_millis(config.footstep_interval_ms)
+        });
+        if moving && footstep_due {
+            users.last_footstep.insert(client_id, Instant::now());
+            endpoint.try_send_group_message(
+                peers.iter(),
+                ServerMessage::PlaySoundAt {
+                    sound_id: sounds::FOOTSTEP,
+                    x: queued.x,
+                    y: queued.y,
+                },
+            );
+        }
+
+        let bump_due = users
+            .last_bump
+            .get(&client_id)
+            .is_none_or(|last| last.elapsed() >= BUMP_COOLDOWN);
+        if moving && config.player_collision && bump_due {
+            let bumped = peers
+                .iter()
+                .filter(|&&peer| peer != client_id)
+                .find_map(|peer| {
+                    let &(x, y) = users.positions.get(peer)?;
+                    let distance = (x - queued.x).hypot(y - queued.y);
+                    (distance <= BUMP_DISTANCE).then_some((x, y))
+                });
+            if let Some((x, y)) = bumped {
+                users.last_bump.insert(client_id, Instant::now());
+                feedback.write(Feedback {
+                    room: rooms.room_of(client_id).cloned(),
+                    kind: FeedbackKind::Bump,
+                    intensity: horizontal.hypot(vertical).min(1.0) * BUMP_INTENSITY,
+                    x: (x + queued.x) / 2.0,
+                    y: (y + queued.y) / 2.0,
+                });
+            }
+        }
+    }
+}
+
+/// One batch of world state per joining client and tick, so a big world
+/// doesn't stall the server or the client in one go
+fn stream_join_state(
+    mut server: Connections,
+    users: Res<Users>,
+    props: Res<Props>,
+    mut streams: ResMut<JoinStreams>,
+) {
+    let endpoint = &mut server.endpoint_mut();
+    streams.0.retain(|&client_id, stream| {
+        if !users.names.contains_key(&client_id) {
+            return false;
+        }
+        match stream.next_batch(&users, &props) {
+            Some(batch) => {
+                for message in protocol::split_chunks(batch) {
+                    endpoint.try_send_message(client_id, message);
+                }
+                endpoint.try_send_message(
+                    client_id,
+                    ServerMessage::SyncProgress {
+                        done: stream.done,
+                        total: stream.total,
+                    },
+                );
+                true
+            }
+            None => {
+                endpoint.try_send_message(client_id, ServerMessage::SyncComplete {});
+                false
+            }
+        }
+    });
+}
+
+/// Broadcast prop states, at a rate following each client's link quality, and take
+/// back lapsed authority
+#[cfg(feature = "server-movement")]
+fn sync_props(
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    config: Res<ServerConfig>,
+    mut props: ResMut<Props>,
+) {
+    let endpoint = &mut server.endpoint_mut();
+    for (owner, state) in props.expire(|client_id| users.names.contains_key(&client_id)) {
+        endpoint.try_send_message(owner, ServerMessage::AuthorityRevoked { state });
+    }
+
+    if config.prop_sync_rate <= 0.0 || users.names.is_empty() {
+        return;
+    }
+    let base = Duration::from_secs_f32(1.0 / config.prop_sync_rate);
+    let users = &mut *users;
+    let mut snapshot = None;
+    for &client_id in users.names.keys() {
+        let link = users.links.entry(client_id).or_default();
+        if let Some(stats) = endpoint.link_stats(client_id) {
+            link.rate.sample(stats);
+        }
+        let interval = link.rate.interval(base);
+        if link
+            .last_snapshot
+            .is_some_and(|last| last.elapsed() < interval)
+        {
+            continue;
+        }
+        link.last_snapshot = Some(Instant::now());
+        let states = snapshot.get_or_insert_with(|| props.snapshot()).clone();
+        endpoint.try_send_message(client_id, ServerMessage::PropSnapshot { props: states });
+    }
+}
+
+fn handle_rcon_commands(
+    mut receiver: ResMut<RconReceiver>,
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    mut accounts: ResMut<Accounts>,
+    mut rooms: ResMut<Rooms>,
+    mut shutdown: ResMut<PendingShutdown>,
+    mut config: ResMut<ServerConfig>,
+    room_ticks: Res<RoomTicks>,
+    reports: Res<Reports>,
+    mut props: ResMut<Props>,
+    mut teleports: EventWriter<TeleportPlayer>,
+    mut sent: EventWriter<SendToClient>,
+    server_log: Option<Res<ServerLog>>,
+) {
+    while let Ok(request) = receiver.try_recv() {
+        let reply = match request.command {
+            RconCommand::Shutdown { seconds } => {
+                if let Some(remaining) = shutdown.remaining_secs() {
+                    format!("already shutting down in {} seconds", remaining)
+                } else {
+                    info!("Shutting down in {} seconds", seconds);
+                    shutdown.deadline = Some(Instant::now() + Duration::from_secs(seconds as u64));
+                    server.endpoint_mut().try_send_group_message(
+                        users.names.keys(),
+                        ServerMessage::ShuttingDown { seconds },
+                    );
+                    format!("shutting down in {} seconds", seconds)
+                }
+            }
+            RconCommand::Reload => match reload_config(&mut config) {
+                Ok(()) => "reloaded".to_string(),
+                Err(err) => format!("error: {}", err),
+            },
+            RconCommand::Say { text } => {
+                server.endpoint_mut().try_send_group_message(
+                    users.names.keys(),
+                    notice(NoticeLevel::Info, LocalizedText::raw(text)),
+                );
+                "sent".to_string()
+            }
+            RconCommand::Kick { name, reason } => match find_player(&users, &name) {
+                Some(client_id) => {
+                    kick_player(
+                        &mut server.endpoint_mut(),
+                        &mut users,
+                        &accounts,
+                        &mut rooms,
+                        client_id,
+                        reason.as_deref(),
+                    );
+                    format!("kicked {}", name)
+                }
+                None => format!("error: no player named {}", name),
+            },
+            RconCommand::Collision { enabled } => {
+                config.player_collision = enabled;
+                format!(
+                    "player collision {}",
+                    if enabled { "enabled" } else { "disabled" }
+                )
+            }
+            RconCommand::Teleport { name, x, y } => match find_player(&users, &name) {
+                Some(client_id) => {
+                    teleports.write(TeleportPlayer { client_id, x, y });
+                    format!("teleported {} to ({}, {})", name, x, y)
+                }
+                None => format!("error: no player named {}", name),
+            },
+            RconCommand::Respawn { name } => match find_player(&users, &name) {
+                Some(client_id) => {
+                    let (x, y) = users.assign_spawn(client_id, &config, None);
+                    teleports.write(TeleportPlayer { client_id, x, y });
+                    format!("respawned {} at ({}, {})", name, x, y)
+                }
+                None => format!("error: no player named {}", name),
+            },
+            RconCommand::ResetWorld => {
+                reset_world(&mut sent, &mut users, &mut props, &config, &mut teleports);
+                server
+                    .endpoint_mut()
+                    .try_send_group_message(users.names.keys(), ServerMessage::WorldReset {});
+                format!("reset the world for {} players", users.names.len())
+            }
+            RconCommand::Log { query } => match &server_log {
+                Some(server_log) => {
+                    let lines = server_log.query(&query);
+                    let pages = lines.len().div_ceil(RCON_LOG_PAGE_LINES);
+                    for (index, page) in lines.chunks(RCON_LOG_PAGE_LINES).enumerate() {
+                        let _ = request.reply.send(format!(
+                            "-- page {}/{} --\n{}",
+                            index + 1,
+                            pages,
+                            page.join("\n")
+                        ));
+                    }
+                    format!("-- {} lines --", lines.len())
+                }
+                None => {
+                    "error: no log kept, logging was set up before the server started".to_string()
+                }
+            },
+            RconCommand::Rooms => room_report(&users, &rooms, &room_ticks),
+            RconCommand::Anomalies => {
+                anomaly_report(&users).unwrap_or_else(|| "no anomalies".to_string())
+            }
+            RconCommand::Reports => reports.summary(RCON_REPORTS),
+            RconCommand::Cosmetic { name, slot, id } => {
+                let account = accounts.resolve(&name);
+                match accounts.0.get_mut(&account) {
+                    Some(profile) => {
+                        let equipped = match slot {
+                            CosmeticSlot::Hat => &mut profile.cosmetics.hat,
+                            CosmeticSlot::Trail => &mut profile.cosmetics.trail,
+                        };
+                        *equipped = id.clone();
+                        let cosmetics = profile.cosmetics.clone();
+                        if let Some(client_id) = find_player(&users, &name) {
+                            users.cosmetics.insert(client_id, cosmetics.clone());
+                            let recipients = users.room_players(&rooms, client_id);
+                            server.endpoint_mut().try_send_group_message(
+                                recipients
+                                    .iter()
+                                    .filter(|other| users.names.contains_key(*other)),
+                                ServerMessage::CosmeticsChanged {
+                                    client_id,
+                                    cosmetics,
+                                },
+                            );
+                        }
+                        format!(
+                            "{} of {} set to {}",
+                            slot,
+                            name,
+                            id.as_deref().unwrap_or("none")
+                        )
+                    }
+                    None => format!("error: no account named {}", name),
+                }
+            }
+            RconCommand::Help => rcon::HELP.to_string(),
+        };
+        let _ = request.reply.send(reply);
+    }
+}
+
+/// Reports listed by the RCON `reports` command
+const RCON_REPORTS: usize = 10;
+/// Log lines per chunk of a `log` answer
+const RCON_LOG_PAGE_LINES: usize = 50;
+
+/// Store reports with the reported player's recent chat, and pass them on to the log
+/// and the webhook
+fn handle_reports(
+    mut events: EventReader<from_client::Report>,
+    users: Res<Users>,
+    history: Res<ChatHistory>,
+    config: Res<ServerConfig>,
+    webhook: Res<Webhook>,
+    mut reports: ResMut<Reports>,
+    mut sent: EventWriter<SendToClient>,
+) {
+    for ev in events.read() {
+        let Some(reporter) = users.names.get(&ev.sender) else {
+            continue;
+        };
+        let reply = match find_player(&users, &ev.name) {
+            None => LocalizedText::new(texts::NO_SUCH_PLAYER).arg(&ev.name),
+            Some(reported) if reported == ev.sender => LocalizedText::new(texts::REPORT_SELF),
+            Some(reported) => {
+                let cooldown = Duration::from_secs(config.report_cooldown_secs);
+                match reports.take_slot(reporter, cooldown) {
+                    Err(remaining) => LocalizedText::new(texts::REPORT_TOO_FAST).arg(remaining),
+                    Ok(()) => {
+                        let reported = users.names[&reported].clone();
+                        let reason =
+                            protocol::truncate_chat(&ev.reason, config.max_chat_length).to_string();
+                        warn!("Report | {} -> {}: {}", reporter, reported, reason);
+                        webhook.notify(
+                            &config,
+                            format!("{} reported {}: {}", reporter, reported, reason),
+                        );
+                        reports.add(PlayerReport {
+                            reporter: reporter.clone(),
+                            reported: reported.clone(),
+                            recent_chat: reports::recent_chat(&history.0, &reported),
+                            reason,
+                            sent_at: persistence::unix_now(),
+                        });
+                        LocalizedText::new(texts::REPORT_SENT).arg(reported)
+                    }
+                }
+            }
+        };
+        sent.write(SendToClient {
+            client_id: ev.sender,
+            message: notice(NoticeLevel::Info, reply),
+        });
+    }
+}
+
+/// The connected player with this name, ignoring case
+fn find_player(users: &Users, name: &str) -> Option<ClientId> {
+    users
+        .names
+        .iter()
+        .find(|(_, username)| username.to_lowercase() == name.to_lowercase())
+        .map(|(id, _)| *id)
+}
+
+/// Disconnect a player, telling them why and everyone else that they were kicked
+fn kick_player(
+    endpoint: &mut ServerEndpoint,
+    users: &mut ResMut<Users>,
+    accounts: &Accounts,
+    rooms: &mut Rooms,
+    client_id: ClientId,
+    reason: Option<&str>,
+) {
+    let Some(name) = users.names.get(&client_id).cloned() else {
+        return;
+    };
+    let text = match reason {
+        Some(reason) => LocalizedText::new(texts::KICKED_REASON).arg(reason),
+        None => LocalizedText::new(texts::KICKED),
+    };
+    endpoint.try_send_message(client_id, notice(NoticeLevel::Critical, text));
+    let _ = endpoint.disconnect_client(client_id);
+    handle_disconnect(endpoint, users, accounts, rooms, client_id);
+    endpoint.try_send_group_message(
+        users.names.keys(),
+        notice(
+            NoticeLevel::Info,
+            LocalizedText::new(texts::PLAYER_KICKED).arg(name),
+        ),
+    );
+}
+
+/// Start and tally votes from their `net_events`, and carry out the ones that pass
+fn run_votes(
+    mut started: EventReader<from_client::StartVote>,
+    mut cast: EventReader<from_client::CastVote>,
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    accounts: Res<Accounts>,
+    mut rooms: ResMut<Rooms>,
+    config: Res<ServerConfig>,
+    mut level: ResMut<Level>,
+) {
+    let rules = config.vote_rules();
+    let endpoint = &mut server.endpoint_mut();
+    let mut changed = false;
+    for ev in started.read() {
+        let Some(starter) = users.names.get(&ev.sender).cloned() else {
+            continue;
+        };
+        let room = rooms.room_of(ev.sender).cloned();
+        let present = room_voters(&users, &rooms, room.as_ref());
+        let target = match &ev.kind {
+            VoteKind::Kick { name } => match find_player(&users, name) {
+                Some(target) if target == ev.sender => {
+                    Err(LocalizedText::new(texts::VOTE_KICK_SELF))
+                }
+                Some(target) if !present.contains(&target) => {
+                    Err(LocalizedText::new(texts::NO_SUCH_PLAYER).arg(name))
+                }
+                Some(_) if (present.len() as u32 - 1) < rules.kick_min_voters => {
+                    Err(LocalizedText::new(texts::VOTE_TOO_FEW).arg(rules.kick_min_voters))
+                }
+                Some(target) => Ok(Some(target)),
+                None => Err(LocalizedText::new(texts::NO_SUCH_PLAYER).arg(name)),
+            },
+            // The level is the same in every room
+            VoteKind::ChangeLevel { .. } if room.is_some() => {
+                Err(LocalizedText::new(texts::VOTE_LEVEL_IN_LOBBY))
+            }
+            VoteKind::ChangeLevel { level } => check_level(&config, level).map(|()| None),
+            VoteKind::PlayAgain => Err(LocalizedText::new(texts::VOTE_PLAY_AGAIN_ONLY)),
+        };
+        let started = target.and_then(|target| {
+            users
+                .votes
+                .start(ev.kind.clone(), ev.sender, target, room, &rules)
+                .map(|_| ())
+        });
+        match started {
+            Ok(()) => {
+                info!("{} started a vote to {}", starter, ev.kind);
+                changed = true;
+            }
+            Err(reason) => {
+                endpoint.try_send_message(ev.sender, notice(NoticeLevel::Warning, reason));
+            }
+        }
+    }
+    for ev in cast.read() {
+        if !users.names.contains_key(&ev.sender) {
+            continue;
+        }
+        if let Some(ballot) = &mut users.votes.active {
+            if rooms.room_of(ev.sender) == ballot.room.as_ref() {
+                ballot.cast(ev.sender, ev.yes);
+                changed = true;
+            }
+        }
+    }
+
+    let Some(ballot) = users.votes.active.take() else {
+        return;
+    };
+    let present = room_voters(&users, &rooms, ballot.room.as_ref());
+    let tally = ballot.tally(present.iter(), &rules);
+    let passed = match ballot.outcome(&tally, &rules) {
+        Outcome::Pending => {
+            if changed {
+                let started_by = users.names.get(&ballot.started_by).cloned();
+                endpoint.try_send_group_message(
+                    present.iter(),
+                    ServerMessage::VoteProgress {
+                        kind: ballot.kind.clone(),
+                        started_by: started_by.unwrap_or_default(),
+                        yes: tally.yes,
+                        no: tally.no,
+                        eligible: tally.eligible,
+                        quorum: tally.quorum,
+                        remaining_secs: ballot.remaining(&rules).as_secs_f32().ceil() as u32,
+                    },
+                );
+            }
+            users.votes.active = Some(ballot);
+            return;
+        }
+        Outcome::Passed => true,
+        Outcome::Failed => false,
+    };
+
+    info!(
+        "Vote to {} {}: {} yes, {} no, {} eligible",
+        ballot.kind,
+        if passed { "passed" } else { "failed" },
+        tally.yes,
+        tally.no,
+        tally.eligible
+    );
+    endpoint.try_send_group_message(
+        present.iter(),
+        ServerMessage::VoteEnded {
+            kind: ballot.kind.clone(),
+            passed,
+        },
+    );
+    if !passed {
+        return;
+    }
+    match ballot.kind {
+        VoteKind::Kick { .. } => {
+            if let Some(target) = ballot.target {
+                kick_player(
+                    endpoint,
+                    &mut users,
+                    &accounts,
+                    &mut rooms,
+                    target,
+                    Some("voted out by the other players"),
+                );
+            }
+        }
+        VoteKind::ChangeLevel { level: new_level } => {
+            change_level(endpoint, &users, &mut level, new_level);
+        }
+        // Never started here, see `run_match`
+        VoteKind::PlayAgain => {}
+    }
+}
+
+/// Votes are held in a room (or the lobby), among the players connected there
+fn room_voters(users: &Users, rooms: &Rooms, room: Option<&String>) -> Vec<ClientId> {
+    users
+        .names
+        .keys()
+        .filter(|client_id| rooms.room_of(**client_id) == room)
+        .copied()
+        .collect()
+}
+
+/// Why `level` can't be loaded, if it can't
+fn check_level(config: &ServerConfig, level: &str) -> Result<(), LocalizedText> {
+    if config.levels.is_empty() {
+        Err(LocalizedText::new(texts::NO_LEVELS))
+    } else if !config.levels.iter().any(|known| known == level) {
+        Err(LocalizedText::new(texts::UNKNOWN_LEVEL)
+            .arg(level)
+            .arg(config.levels.join(", ")))
+    } else {
+        Ok(())
+    }
+}
+
+fn change_level(
+    endpoint: &mut ServerEndpoint,
+    users: &Users,
+    level: &mut Level,
+    new_level: String,
+) {
+    info!("Changing level to {}", new_level);
+    level.0 = Some(new_level.clone());
+    endpoint.try_send_group_message(
+        users.names.keys(),
+        ServerMessage::ChangeLevel { level: new_level },
+    );
+}
+
+/// Carry out `Admin` commands from the host, hand the host role on when the host
+/// leaves, and tell everyone (and every joining player) who the host is
+fn run_admin_commands(
+    mut admin: EventReader<from_client::Admin>,
+    mut joins: EventReader<from_client::Join>,
+    mut resumes: EventReader<from_client::Resume>,
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    accounts: Res<Accounts>,
+    mut rooms: ResMut<Rooms>,
+    config: Res<ServerConfig>,
+    mut level: ResMut<Level>,
+    mut props: ResMut<Props>,
+    mut teleports: EventWriter<TeleportPlayer>,
+    mut sent: EventWriter<SendToClient>,
+    mut announced: Local<Option<ClientId>>,
+) {
+    let endpoint = &mut server.endpoint_mut();
+    for ev in admin.read() {
+        let Some(host_name) = users.names.get(&ev.sender).cloned() else {
+            continue;
+        };
+        if users.host != Some(ev.sender) {
+            endpoint.try_send_message(
+                ev.sender,
+                notice(NoticeLevel::Warning, LocalizedText::new(texts::NOT_HOST)),
+            );
+            continue;
+        }
+        let refused = match &ev.command {
+            AdminCommand::Kick { name } => match find_player(&users, name) {
+                Some(target) if target == ev.sender => Some(LocalizedText::new(texts::KICK_SELF)),
+                Some(target) => {
+                    info!("Host {} kicked {}", host_name, name);
+                    kick_player(
+                        endpoint,
+                        &mut users,
+                        &accounts,
+                        &mut rooms,
+                        target,
+                        Some("kicked by the host"),
+                    );
+                    None
+                }
+                None => Some(LocalizedText::new(texts::NO_SUCH_PLAYER).arg(name)),
+            },
+            AdminCommand::ChangeLevel { level: new_level } => {
+                match check_level(&config, new_level) {
+                    Ok(()) => {
+                        change_level(endpoint, &users, &mut level, new_level.clone());
+                        None
+                    }
+                    Err(reason) => Some(reason),
+                }
+            }
+            AdminCommand::Pause { paused } => {
+                if users.paused != *paused {
+                    info!(
+                        "Host {} {} the game",
+                        host_name,
+                        if *paused { "paused" } else { "resumed" }
+                    );
+                    users.paused = *paused;
+                    let key = if *paused {
+                        texts::GAME_PAUSED
+                    } else {
+                        texts::GAME_RESUMED
+                    };
+                    endpoint.try_send_group_message(
+                        users.names.keys(),
+                        ServerMessage::Paused { paused: *paused },
+                    );
+                    endpoint.try_send_group_message(
+                        users.names.keys(),
+                        notice(NoticeLevel::Info, LocalizedText::new(key).arg(&host_name)),
+                    );
+                }
+                None
+            }
+            AdminCommand::ResetWorld => {
+                info!("Host {} reset the world", host_name);
+                reset_world(&mut sent, &mut users, &mut props, &config, &mut teleports);
+                endpoint.try_send_group_message(users.names.keys(), ServerMessage::WorldReset {});
+                endpoint.try_send_group_message(
+                    users.names.keys(),
+                    notice(
+                        NoticeLevel::Info,
+                        LocalizedText::new(texts::WORLD_RESET).arg(&host_name),
+                    ),
+                );
+                None
+            }
+            AdminCommand::TransferHost { to } => match users.names.get(to).cloned() {
+                Some(name) => {
+                    info!("Host {} handed the host role to {}", host_name, name);
+                    users.host = Some(*to);
+                    None
+                }
+                None => Some(LocalizedText::new(texts::NO_SUCH_CLIENT)),
+            },
+        };
+        if let Some(reason) = refused {
+            endpoint.try_send_message(ev.sender, notice(NoticeLevel::Warning, reason));
+        }
+    }
+
+    let host_present = users
+        .host
+        .is_some_and(|host| users.names.contains_key(&host) || users.suspended.contains_key(&host));
+    if !host_present {
+        // Client ids count up, so the smallest is the player connected the longest
+        users.host = users.names.keys().min().copied();
+        if users.host.is_none() && users.suspended.is_empty() {
+            users.paused = false;
+        }
+    }
+    if users.host != *announced {
+        info!(
+            "Host is now {:?}",
+            users.host.and_then(|host| users.names.get(&host))
+        );
+        *announced = users.host;
+        endpoint.try_send_group_message(
+            users.names.keys(),
+            ServerMessage::HostChanged { host: users.host },
+        );
+    }
+
+    let joined = joins
+        .read()
+        .map(|ev| ev.sender)
+        .chain(resumes.read().map(|ev| ev.sender));
+    for client_id in joined {
+        // Also written for refused joins and failed resumes
+        if !users.names.contains_key(&client_id) {
+            continue;
+        }
+        endpoint.try_send_message(client_id, ServerMessage::HostChanged { host: users.host });
+        if users.paused {
+            endpoint.try_send_message(client_id, ServerMessage::Paused { paused: true });
+        }
+    }
+}
+
+/// Add the time since the last count to every connected player's playtime
+fn count_playtime(
+    users: Res<Users>,
+    mut accounts: ResMut<Accounts>,
+    mut last_count: Local<Option<Instant>>,
+) {
+    let last = *last_count.get_or_insert_with(Instant::now);
+    let elapsed = last.elapsed();
+    if elapsed < PLAYTIME_INTERVAL {
+        return;
+    }
+    // Whole seconds only, the rest counts next time
+    let secs = elapsed.as_secs();
+    *last_count = Some(last + Duration::from_secs(secs));
+    for client_id in users.names.keys() {
+        if let Some(account) = users.account_ids.get(client_id) {
+            accounts.profile_mut(account).stats.playtime_secs += secs;
+        }
+    }
+}
+
+/// End the match after `match_length_secs` with everyone's results, and start the
+/// next one `results_secs` later, or as soon as the players voted to play again
+fn run_match(
+    mut play_again: EventReader<from_client::PlayAgain>,
+    mut users: ResMut<Users>,
+    config: Res<ServerConfig>,
+    mut accounts: ResMut<Accounts>,
+    mut props: ResMut<Props>,
+    mut teleports: EventWriter<TeleportPlayer>,
+    mut sent: EventWriter<SendToClient>,
+) {
+    if config.match_length_secs == 0 {
+        play_again.clear();
+        return;
+    }
+    let users = &mut *users;
+    match users.current_match.phase {
+        MatchPhase::Playing => {
+            // Too late, or too early, for this match
+            play_again.clear();
+            if users.names.is_empty() {
+                // Nobody to play against, the clock starts with the first player
+                users.current_match.restart();
+                return;
+            }
+            if users.current_match.elapsed() < Duration::from_secs(config.match_length_secs) {
+                return;
+            }
+            let (rankings, stats) = users.current_match.finish(&users.names);
+            info!("Match over: {:?}", rankings);
+            // Winning alone doesn't count
+            if rankings.len() >= 2 {
+                for ranking in rankings.iter().filter(|ranking| ranking.rank == 1) {
+                    if let Some(account) = users.account_ids.get(&ranking.client_id) {
+                        accounts.profile_mut(account).stats.wins += 1;
+                    }
+                }
+            }
+            send_to_all(
+                &mut sent,
+                users,
+                ServerMessage::MatchResults { rankings, stats },
+            );
+        }
+        MatchPhase::Results => {
+            let results = Duration::from_secs(config.results_secs);
+            // The results end the vote, whatever its own timeout
+            let rules = VoteRules {
+                timeout: results,
+                ..config.vote_rules()
+            };
+            let mut changed = false;
+            for ev in play_again.read() {
+                if !users.names.contains_key(&ev.sender) {
+                    continue;
+                }
+                users
+                    .current_match
+                    .rematch
+                    .get_or_insert_with(|| Ballot::new(VoteKind::PlayAgain, ev.sender, None))
+                    .cast(ev.sender, ev.yes);
+                changed = true;
+            }
+            let timed_out = users.current_match.elapsed() >= results;
+            let mut agreed = false;
+            if let Some(ballot) = users.current_match.rematch.take() {
+                let tally = ballot.tally(users.names.keys(), &rules);
+                let outcome = if timed_out {
+                    // Decided with the ballots there are
+                    ballot.outcome(
+                        &tally,
+                        &VoteRules {
+                            timeout: Duration::ZERO,
+                            ..rules
+                        },
+                    )
+                } else {
+                    ballot.outcome(&tally, &rules)
+                };
+                let message = match outcome {
+                    Outcome::Pending => {
+                        let remaining = results.saturating_sub(users.current_match.elapsed());
+                        let started_by = users.names.get(&ballot.started_by).cloned();
+                        let message = ServerMessage::VoteProgress {
+                            kind: ballot.kind.clone(),
+                            started_by: started_by.unwrap_or_default(),
+                            yes: tally.yes,
+                            no: tally.no,
+                            eligible: tally.eligible,
+                            quorum: tally.quorum,
+                            remaining_secs: remaining.as_secs_f32().ceil() as u32,
+                        };
+                        users.current_match.rematch = Some(ballot);
+                        changed.then_some(message)
+                    }
+                    Outcome::Passed | Outcome::Failed => {
+                        agreed = outcome == Outcome::Passed;
+                        info!(
+                            "Vote to play again {}: {} yes, {} no, {} eligible",
+                            if agreed { "passed" } else { "failed" },
+                            tally.yes,
+                            tally.no,
+                            tally.eligible
+                        );
+                        Some(ServerMessage::VoteEnded {
+                            kind: VoteKind::PlayAgain,
+                            passed: agreed,
+                        })
+                    }
+                };
+                if let Some(message) = message {
+                    send_to_all(&mut sent, users, message);
+                }
+            }
+            if !agreed && !timed_out {
+                return;
+            }
+            users.current_match.restart();
+            info!("Match started");
+            reset_world(&mut sent, users, &mut props, &config, &mut teleports);
+            let duration_secs = config.match_length_secs.min(u32::MAX as u64) as u32;
+            send_to_all(
+                &mut sent,
+                users,
+                ServerMessage::MatchStarted { duration_secs },
+            );
+        }
+    }
+}
 
-use bevy::{
-    app::{App, ScheduleRunnerPlugin, Startup},
-    ecs::{resource::Resource, system::ResMut},
-    log::LogPlugin,
-    prelude::*,
-};
-use bevy_quinnet::{
-    server::{
-        ConnectionLostEvent, Endpoint, QuinnetServer, QuinnetServerPlugin,
-        ServerEndpointConfiguration, certificate::CertificateRetrievalMode,
-    },
-    shared::{ClientId, channels::ChannelsConfiguration},
-};
+/// Put the props back where they started, taking them from their owners, and everyone
+/// back to a spawn point
+fn reset_world(
+    sent: &mut EventWriter<SendToClient>,
+    users: &mut Users,
+    props: &mut Props,
+    config: &ServerConfig,
+    teleports: &mut EventWriter<TeleportPlayer>,
+) {
+    for (owner, state) in props.reset(&config.props) {
+        sent.write(SendToClient {
+            client_id: owner,
+            message: ServerMessage::AuthorityRevoked { state },
+        });
+    }
+    let players: Vec<ClientId> = users.names.keys().copied().collect();
+    for client_id in players {
+        let (x, y) = users.assign_spawn(client_id, config, None);
+        teleports.write(TeleportPlayer { client_id, x, y });
+    }
+}
+
+/// Remember where the players who left were, for their next join
+fn store_last_positions(mut users: ResMut<Users>, mut accounts: ResMut<Accounts>) {
+    for (account, position) in users.departed.drain(..) {
+        accounts.profile_mut(&account).last_position = Some(position);
+    }
+}
 
-use protocol::{ClientMessage, ServerMessage};
+/// The grid NPCs path on follows the level
+fn load_navigation(
+    config: Res<ServerConfig>,
+    level: Res<Level>,
+    mut navigation: ResMut<Navigation>,
+    mut loaded: Local<Option<(PathBuf, Option<String>)>>,
+) {
+    let current = (config.nav_dir.clone(), level.0.clone());
+    if loaded.as_ref() != Some(&current) {
+        navigation.load_level(&current.0, current.1.as_deref());
+        *loaded = Some(current);
+    }
+}
 
-use crate::protocol;
+/// Joining players load the current level
+fn send_level_on_join(
+    mut joins: EventReader<from_client::Join>,
+    users: Res<Users>,
+    level: Res<Level>,
+    mut sent: EventWriter<SendToClient>,
+) {
+    let Some(level) = &level.0 else {
+        joins.clear();
+        return;
+    };
+    for ev in joins.read() {
+        // Also written for refused joins
+        if users.names.contains_key(&ev.sender) {
+            sent.write(SendToClient {
+                client_id: ev.sender,
+                message: ServerMessage::ChangeLevel {
+                    level: level.clone(),
+                },
+            });
+        }
+    }
+}
 
-#[derive(Resource, Debug, Clone, Default)]
-pub struct Users {
-    names: HashMap<ClientId, String>,
+/// One line per client with anomalies, worst first; None when everyone looks honest
+fn anomaly_report(users: &Users) -> Option<String> {
+    let flagged = users.anomalies.flagged();
+    if flagged.is_empty() {
+        return None;
+    }
+    let lines: Vec<_> = flagged
+        .iter()
+        .map(|(client_id, stats)| {
+            let name = users
+                .names
+                .get(client_id)
+                .or_else(|| users.suspended.get(client_id))
+                .map(String::as_str)
+                .unwrap_or("?");
+            format!("{} ({}): {}", name, client_id, stats.summary())
+        })
+        .collect();
+    Some(lines.join("\n"))
 }
-/* 
-fn main() {
-    create_server();
-} */
 
-pub fn create_server() {
-    App::new()
-        .add_plugins((
-            ScheduleRunnerPlugin::default(),
-            //LogPlugin::default(),
-            QuinnetServerPlugin::default(),
-        ))
-        .insert_resource(Users::default())
-        .add_systems(Startup, start_listening)
-        .add_systems(Update, (handle_client_messages, handle_server_events))
-        .run();
-}
-
-fn start_listening(mut server: ResMut<QuinnetServer>) {
-    server
-        .start_endpoint(
-            ServerEndpointConfiguration::from_string("0.0.0.0:6000").unwrap(),
-            CertificateRetrievalMode::GenerateSelfSigned {
-                server_hostname: "0.0.0.0".to_string(),
-            },
-            ChannelsConfiguration::default(),
-        )
-        .unwrap();
+fn report_anomalies(
+    users: Res<Users>,
+    config: Res<ServerConfig>,
+    mut last: Local<Option<Instant>>,
+) {
+    if config.anomaly_report_secs == 0 {
+        return;
+    }
+    let interval = Duration::from_secs(config.anomaly_report_secs);
+    if last.get_or_insert_with(Instant::now).elapsed() < interval {
+        return;
+    }
+    *last = Some(Instant::now());
+    if let Some(report) = anomaly_report(&users) {
+        warn!("Anomaly report:\n{}", report);
+    }
+}
+
+/// One line per room: players and tick timing
+fn room_report(users: &Users, rooms: &Rooms, ticks: &RoomTicks) -> String {
+    let mut keys: Vec<_> = ticks.0.keys().collect();
+    keys.sort();
+    keys.iter()
+        .map(|key| {
+            let players = users
+                .names
+                .keys()
+                .filter(|client_id| rooms.room_of(**client_id) == key.as_ref())
+                .count();
+            let metrics = ticks.0[*key].metrics;
+            format!(
+                "{}: {} players, {} ticks ({} skipped), avg {:?}, last {:?}, max {:?}",
+                key.as_deref().unwrap_or("lobby"),
+                players,
+                metrics.ticks,
+                metrics.skipped,
+                metrics.average,
+                metrics.last,
+                metrics.max
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Once the grace period is over: save, close every connection and exit
+fn run_pending_shutdown(
+    shutdown: Res<PendingShutdown>,
+    mut server: Connections,
+    config: Res<ServerConfig>,
+    history: Res<ChatHistory>,
+    accounts: Res<Accounts>,
+    reports: Res<Reports>,
+    users: Res<Users>,
+    rooms: Res<Rooms>,
+    props: Res<Props>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    let Some(deadline) = shutdown.deadline else {
+        return;
+    };
+    if Instant::now() < deadline {
+        return;
+    }
+    save_state(
+        &config, &history, &accounts, &reports, &users, &rooms, &props,
+    );
+    if let Err(err) = server.stop_endpoint() {
+        warn!("Failed to stop the endpoint cleanly: {}", err);
+    }
+    info!("Server stopped");
+    app_exit_events.write(AppExit::Success);
+}
+
+/// What one client sent since the last frame
+struct ClientBatch {
+    client_id: ClientId,
+    messages: Vec<ClientMessage>,
+    /// Payloads dropped because they didn't decode or failed validation
+    rejected: u32,
+    /// The client's protocol version, when too old to play with
+    incompatible: Option<u32>,
+    /// The buffers the messages were read from, for the pool
+    payloads: Vec<Vec<u8>>,
+}
+
+/// Buffers received payloads are copied into, reused from frame to frame so receiving
+/// doesn't allocate per message
+#[derive(Default)]
+struct PayloadPool(Vec<Vec<u8>>);
+
+impl PayloadPool {
+    fn take(&mut self) -> Vec<u8> {
+        self.0.pop().unwrap_or_default()
+    }
+
+    fn give_back(&mut self, buffers: impl IntoIterator<Item = Vec<u8>>) {
+        self.0.extend(buffers.into_iter().map(|mut buffer| {
+            buffer.clear();
+            buffer
+        }));
+    }
+}
+
+/// The leaderboard as last sorted, so clients asking for it don't each sort every account
+#[derive(Default)]
+struct LeaderboardCache {
+    entries: Vec<LeaderboardEntry>,
+    sorted_at: Option<Instant>,
+}
+
+impl LeaderboardCache {
+    fn get(&mut self, accounts: &Accounts) -> Vec<LeaderboardEntry> {
+        if self
+            .sorted_at
+            .is_none_or(|sorted_at| sorted_at.elapsed() >= LEADERBOARD_REFRESH)
+        {
+            self.entries = accounts.leaderboard(LEADERBOARD_SIZE);
+            self.sorted_at = Some(Instant::now());
+        }
+        self.entries.clone()
+    }
 }
 
-fn handle_client_messages(mut server: ResMut<QuinnetServer>, mut users: ResMut<Users>) {
-    let endpoint = server.endpoint_mut();
+/// Drain every client's queue, then decode and sanity-check each client's messages
+/// on the task pool. Clients come back sorted by id, each with its messages in
+/// arrival order, so applying them stays deterministic.
+fn receive_client_messages(
+    endpoint: &mut ServerEndpoint,
+    max_chat_length: usize,
+    pool: &mut PayloadPool,
+) -> Vec<ClientBatch> {
+    let mut raw = Vec::new();
     for client_id in endpoint.clients() {
-        while let Some((_, message)) = endpoint.try_receive_message_from::<ClientMessage>(client_id)
+        let mut payloads = Vec::new();
+        loop {
+            let mut buffer = pool.take();
+            if !endpoint.try_receive_payload_into(client_id, &mut buffer) {
+                pool.give_back([buffer]);
+                break;
+            }
+            payloads.push(buffer);
+        }
+        if !payloads.is_empty() {
+            raw.push((client_id, payloads));
+        }
+    }
+    raw.sort_by_key(|(client_id, _)| *client_id);
+
+    let mut batches = ComputeTaskPool::get_or_init(TaskPool::default).scope(|scope| {
+        for (client_id, payloads) in raw {
+            scope.spawn(async move {
+                let mut messages = Vec::new();
+                let mut rejected = 0;
+                let mut incompatible = None;
+                for payload in &payloads {
+                    match decode_client_message(client_id, payload) {
+                        Ok(Some(message))
+                            if validate_client_message(client_id, &message, max_chat_length) =>
+                        {
+                            messages.push(message)
+                        }
+                        Ok(Some(_)) | Err(Undecoded::Malformed) => rejected += 1,
+                        Err(Undecoded::Incompatible { version }) => incompatible = Some(version),
+                        Ok(None) => {}
+                    }
+                }
+                ClientBatch {
+                    client_id,
+                    rejected,
+                    incompatible,
+                    messages,
+                    payloads,
+                }
+            });
+        }
+    });
+    for batch in &mut batches {
+        pool.give_back(batch.payloads.drain(..));
+    }
+    batches
+}
+
+/// Why a client's payload was dropped
+enum Undecoded {
+    /// Counted against the client, see `quarantine`
+    Malformed,
+    /// From a protocol version too old to play with
+    Incompatible { version: u32 },
+}
+
+/// The decode boundary: whatever a client sends, the worst outcome is a dropped message.
+/// Messages from a newer protocol version are skipped (`Ok(None)`) without counting as
+/// malformed.
+fn decode_client_message(
+    client_id: ClientId,
+    payload: &[u8],
+) -> Result<Option<ClientMessage>, Undecoded> {
+    let decoded = panic::catch_unwind(|| protocol::decode_untrusted(payload));
+    match decoded {
+        Ok(Ok(message)) => Ok(Some(message)),
+        Ok(Err(err @ DecodeError::UnknownKind { .. })) => {
+            debug!("Skipping message from client {}: {}", client_id, err);
+            Ok(None)
+        }
+        Ok(Err(DecodeError::Incompatible { version })) => Err(Undecoded::Incompatible { version }),
+        Ok(Err(err)) => {
+            warn!("Undecodable message from client {}: {}", client_id, err);
+            Err(Undecoded::Malformed)
+        }
+        Err(_) => {
+            warn!("Decoder panicked on a message from client {}", client_id);
+            Err(Undecoded::Malformed)
+        }
+    }
+}
+
+/// Count rejected messages, disconnecting the client past the limit. True if it was.
+fn quarantine(
+    endpoint: &mut ServerEndpoint,
+    users: &mut ResMut<Users>,
+    accounts: &Accounts,
+    rooms: &mut Rooms,
+    config: &ServerConfig,
+    client_id: ClientId,
+    rejected: u32,
+) -> bool {
+    users.anomalies.record_malformed(client_id, rejected);
+    let count = users.malformed.entry(client_id).or_default();
+    *count += rejected;
+    if *count <= config.max_malformed_messages {
+        return false;
+    }
+    warn!(
+        "Disconnecting client {} after {} malformed messages",
+        client_id, count
+    );
+    endpoint.try_send_message(
+        client_id,
+        notice(
+            NoticeLevel::Critical,
+            LocalizedText::new(texts::TOO_MANY_MALFORMED),
+        ),
+    );
+    let _ = endpoint.disconnect_client(client_id);
+    handle_disconnect(endpoint, users, accounts, rooms, client_id);
+    true
+}
+
+/// Tell a client why it can't join and let it go, rather than keep a connection that
+/// can't play
+fn reject_join(
+    endpoint: &mut ServerEndpoint,
+    users: &mut ResMut<Users>,
+    accounts: &Accounts,
+    rooms: &mut Rooms,
+    client_id: ClientId,
+    reason: LocalizedText,
+) {
+    endpoint.try_send_message(client_id, ServerMessage::JoinRejected { reason });
+    let _ = endpoint.disconnect_client(client_id);
+    handle_disconnect(endpoint, users, accounts, rooms, client_id);
+}
+
+/// What a client may send before its Join is accepted
+fn allowed_before_join(message: &ClientMessage) -> bool {
+    matches!(
+        message,
+        ClientMessage::Join { .. }
+            | ClientMessage::Resume { .. }
+            | ClientMessage::Ping { .. }
+            | ClientMessage::Disconnect { .. }
+    )
+}
+
+/// Checks that need no server state, run off the main thread
+fn validate_client_message(
+    client_id: ClientId,
+    message: &ClientMessage,
+    max_chat_length: usize,
+) -> bool {
+    match message {
+        ClientMessage::ChatMessage { message, .. }
+            if protocol::chat_length(message) > max_chat_length =>
+        {
+            // Well-behaved clients truncate before sending
+            warn!(
+                "Rejected oversized chat message ({} bytes) from client {}",
+                message.len(),
+                client_id
+            );
+            false
+        }
+        ClientMessage::PlayerUpdate { x, y, .. } if !x.is_finite() || !y.is_finite() => {
+            warn!("Rejected non-finite position from client {}", client_id);
+            false
+        }
+        ClientMessage::PropUpdate { state, .. } if !state.is_finite() => {
+            warn!("Rejected non-finite prop state from client {}", client_id);
+            false
+        }
+        _ => true,
+    }
+}
+
+fn handle_client_messages(
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    config: Res<ServerConfig>,
+    history: Res<ChatHistory>,
+    restored: Res<RestoredFrom>,
+    shutdown: Res<PendingShutdown>,
+    mut accounts: ResMut<Accounts>,
+    mut rooms: ResMut<Rooms>,
+    environment: Res<Environment>,
+    match_seed: Res<MatchSeed>,
+    mut streams: ResMut<JoinStreams>,
+    mut replay_guard: ResMut<ReplayGuard>,
+    mut sessions: ResMut<Sessions>,
+    mut net_handlers: NetDispatch<(ClientId, ClientMessage)>,
+    mut pool: Local<PayloadPool>,
+    mut leaderboard: Local<LeaderboardCache>,
+) {
+    let endpoint = &mut server.endpoint_mut();
+    let batches = receive_client_messages(endpoint, config.max_chat_length, &mut pool);
+    for ClientBatch {
+        client_id,
+        messages,
+        rejected,
+        incompatible,
+        ..
+    } in batches
+    {
+        if let Some(version) = incompatible {
+            info!(
+                "Client {} speaks protocol version {}, refusing it",
+                client_id, version
+            );
+            reject_join(
+                endpoint,
+                &mut users,
+                &accounts,
+                &mut rooms,
+                client_id,
+                LocalizedText::new(texts::INCOMPATIBLE_VERSION)
+                    .arg(version)
+                    .arg(protocol::MIN_COMPATIBLE_VERSION),
+            );
+            continue;
+        }
+        if rejected > 0
+            && quarantine(
+                endpoint, &mut users, &accounts, &mut rooms, &config, client_id, rejected,
+            )
         {
+            continue;
+        }
+        for message in messages {
+            // Only joined players get a say in the game
+            if !users.names.contains_key(&client_id) && !allowed_before_join(&message) {
+                debug!(
+                    "Dropped a message from client {} before it joined",
+                    client_id
+                );
+                continue;
+            }
+            // Each one resends the whole world, dropped before anything answers it
+            if matches!(message, ClientMessage::RequestFullSync {})
+                && !users.take_full_sync(client_id)
+            {
+                debug!("Too many full syncs from client {}", client_id);
+                continue;
+            }
+            net_handlers.dispatch_from(client_id, &message);
             match message {
-                ClientMessage::Join { name } => {
+                ClientMessage::Join {
+                    name,
+                    identity,
+                    password,
+                } => {
                     if users.names.contains_key(&client_id) {
                         warn!(
                             "Received a Join from an already connected client: {}",
                             client_id
                         )
+                    } else if users.names.len() >= config.max_players {
+                        info!("Server full, refusing client {}", client_id);
+                        reject_join(
+                            endpoint,
+                            &mut users,
+                            &accounts,
+                            &mut rooms,
+                            client_id,
+                            LocalizedText::new(texts::SERVER_FULL),
+                        );
+                    } else if config
+                        .join_password
+                        .as_ref()
+                        .is_some_and(|expected| password.as_ref() != Some(expected))
+                    {
+                        info!("Wrong password from client {}", client_id);
+                        reject_join(
+                            endpoint,
+                            &mut users,
+                            &accounts,
+                            &mut rooms,
+                            client_id,
+                            LocalizedText::new(texts::WRONG_PASSWORD),
+                        );
                     } else {
+                        let identity_key = match &identity {
+                            Some(signed) => match identity::verify_join(&name, signed)
+                                .and_then(|()| replay_guard.check(signed, persistence::unix_now()))
+                            {
+                                Ok(()) => Some(signed.public_key),
+                                Err(err) => {
+                                    warn!("Refused signed join from {}: {}", client_id, err);
+                                    reject_join(
+                                        endpoint,
+                                        &mut users,
+                                        &accounts,
+                                        &mut rooms,
+                                        client_id,
+                                        err.text(),
+                                    );
+                                    continue;
+                                }
+                            },
+                            None if config.require_identity => {
+                                reject_join(
+                                    endpoint,
+                                    &mut users,
+                                    &accounts,
+                                    &mut rooms,
+                                    client_id,
+                                    LocalizedText::new(texts::IDENTITY_REQUIRED),
+                                );
+                                continue;
+                            }
+                            None => None,
+                        };
+                        let name = match names::sanitize_username(
+                            &name,
+                            config.min_name_length,
+                            config.max_name_length,
+                        ) {
+                            Ok(name) => name,
+                            Err(err) => {
+                                info!("Rejected name {:?}: {}", name, err);
+                                endpoint.try_send_message(
+                                    client_id,
+                                    ServerMessage::NameRejected { reason: err.text() },
+                                );
+                                continue;
+                            }
+                        };
+                        let account = accounts::account_id(&name, identity_key.as_ref());
+                        // Someone else in the game already reads the same
+                        let skeleton = names::skeleton(&name);
+                        if let Some((_, other)) =
+                            users.names.iter().chain(users.suspended.iter()).find(
+                                |(other_id, other)| {
+                                    users.account_ids.get(*other_id) != Some(&account)
+                                        && names::skeleton(other) == skeleton
+                                },
+                            )
+                        {
+                            let err = names::NameError::LooksLike(other.clone());
+                            info!("Rejected name {:?}: {}", name, err);
+                            endpoint.try_send_message(
+                                client_id,
+                                ServerMessage::NameRejected { reason: err.text() },
+                            );
+                            continue;
+                        }
+                        match (accounts.name_owner(&name), identity_key) {
+                            (Some(owner), Some(key)) if owner == key => {}
+                            (Some(_), _) => {
+                                info!("Refused {:?}: bound to another identity key", name);
+                                endpoint.try_send_message(
+                                    client_id,
+                                    ServerMessage::NameRejected {
+                                        reason: LocalizedText::new(texts::NAME_REGISTERED),
+                                    },
+                                );
+                                continue;
+                            }
+                            (None, Some(key)) => accounts.bind(&name, key),
+                            (None, None) => {}
+                        }
                         info!("{} connected", name);
                         users.names.insert(client_id, name.clone());
+                        users.account_ids.insert(client_id, account.clone());
+                        users.current_match.claim(&account, client_id);
+                        let profile = accounts.0.get(&account);
+                        let cosmetics = profile
+                            .map(|profile| profile.cosmetics.clone())
+                            .unwrap_or_default();
+                        users.cosmetics.insert(client_id, cosmetics.clone());
+                        let saved = profile
+                            .and_then(|profile| profile.last_position.clone())
+                            .filter(|_| config.restore_positions);
+                        let (x, y) = users.assign_spawn(
+                            client_id,
+                            &config,
+                            saved.as_ref().map(|saved| (saved.x, saved.y)),
+                        );
+                        // Back into the room it left, if it's still open
+                        let room = saved
+                            .and_then(|saved| saved.room)
+                            .filter(|code| rooms.join(client_id, code));
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::NameAssigned { name: name.clone() },
+                        );
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::SessionToken {
+                                token: sessions.issue(client_id),
+                            },
+                        );
 
-                        // Initialize this client with existing state
+                        // Initialize this client, the world state follows over the next ticks
                         endpoint
                             .send_message(
                                 client_id,
                                 ServerMessage::InitClient {
                                     client_id: client_id,
-                                    usernames: users.names.clone(),
+                                    rng_seed: match_seed.0,
                                 },
                             )
                             .unwrap();
+                        streams.0.insert(
+                            client_id,
+                            JoinStream::new(users.room_players(&rooms, client_id), &history),
+                        );
+                        if let Some(saved_at) = restored.0 {
+                            endpoint.try_send_message(
+                                client_id,
+                                ServerMessage::StateRestored { saved_at },
+                            );
+                        }
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::GameRules {
+                                rules: config.game_rules(),
+                            },
+                        );
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::Environment {
+                                state: environment.0,
+                            },
+                        );
+                        if let Some(motd) = &config.motd {
+                            endpoint.try_send_message(
+                                client_id,
+                                notice(NoticeLevel::Info, LocalizedText::raw(motd.clone())),
+                            );
+                        }
+                        accounts.profile_mut(&account).stats.name = name.clone();
+                        endpoint
+                            .try_send_message(client_id, friend_list(&users, &accounts, &account));
+                        notify_followers(endpoint, &users, &accounts, &account, true);
+                        // Late joiners still get the countdown
+                        if let Some(seconds) = shutdown.remaining_secs() {
+                            endpoint.try_send_message(
+                                client_id,
+                                ServerMessage::ShuttingDown { seconds },
+                            );
+                        }
                         // Broadcast the connection event
                         endpoint
                             .send_group_message(
@@ -85,50 +3459,255 @@ fn handle_client_messages(mut server: ResMut<QuinnetServer>, mut users: ResMut<U
                                 ServerMessage::ClientConnected {
                                     client_id: client_id,
                                     username: name,
+                                    x,
+                                    y,
+                                    cosmetics,
                                 },
                             )
                             .unwrap();
+                        if let Some(code) = room {
+                            info!(
+                                "Restored {:?} to room {}",
+                                users.names.get(&client_id),
+                                code
+                            );
+                            endpoint.try_send_message(
+                                client_id,
+                                ServerMessage::RoomJoined { code: Some(code) },
+                            );
+                            // Players join in the lobby, the lobby stops seeing it
+                            announce_room_change(endpoint, &users, &rooms, client_id, None);
+                        }
                     }
                 }
                 ClientMessage::Disconnect {} => {
                     // We tell the server to disconnect this user
-                    endpoint.disconnect_client(client_id).unwrap();
-                    handle_disconnect(endpoint, &mut users, client_id);
+                    let _ = endpoint.disconnect_client(client_id);
+                    sessions.forget(client_id);
+                    handle_disconnect(endpoint, &mut users, &accounts, &mut rooms, client_id);
+                }
+                ClientMessage::SetAway { away } => {
+                    if !users.names.contains_key(&client_id) {
+                        continue;
+                    }
+                    let changed = if away {
+                        users.away.insert(client_id)
+                    } else {
+                        users.away.remove(&client_id)
+                    };
+                    if changed {
+                        let peers = rooms.peers(client_id, users.names.keys());
+                        endpoint.try_send_group_message(
+                            peers.iter(),
+                            ServerMessage::PlayerAway { client_id, away },
+                        );
+                    }
+                }
+                ClientMessage::RequestFullSync {} => {
+                    if users.names.contains_key(&client_id) {
+                        // Same as on Join, the client dropped its world and rebuilds it
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::InitClient {
+                                client_id,
+                                rng_seed: match_seed.0,
+                            },
+                        );
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::GameRules {
+                                rules: config.game_rules(),
+                            },
+                        );
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::Environment {
+                                state: environment.0,
+                            },
+                        );
+                        streams.0.insert(
+                            client_id,
+                            JoinStream::new(
+                                users.room_players(&rooms, client_id),
+                                &ChatHistory::default(),
+                            ),
+                        );
+                    }
                 }
-                ClientMessage::ChatMessage { message } => {
+                ClientMessage::Resume { token } => {
+                    if users.names.contains_key(&client_id) {
+                        warn!("Received a Resume from a joined client: {}", client_id);
+                        continue;
+                    }
+                    let Some(previous) = sessions
+                        .resume(token)
+                        .filter(|previous| users.suspended.contains_key(previous))
+                    else {
+                        endpoint.try_send_message(client_id, ServerMessage::ResumeFailed {});
+                        continue;
+                    };
                     info!(
-                        "Chat message | {:?}: {}",
-                        users.names.get(&client_id),
-                        message
+                        "{:?} resumed its session ({} -> {})",
+                        users.suspended.get(&previous),
+                        previous,
+                        client_id
+                    );
+                    users.migrate(previous, client_id);
+                    rooms.migrate(previous, client_id);
+                    streams.0.remove(&previous);
+                    endpoint.try_send_message(
+                        client_id,
+                        ServerMessage::Resumed {
+                            client_id,
+                            previous_client_id: previous,
+                        },
+                    );
+                    endpoint.try_send_message(
+                        client_id,
+                        ServerMessage::SessionToken {
+                            token: sessions.issue(client_id),
+                        },
+                    );
+                    // Catch up on what happened while it was away, the chat it already has
+                    streams.0.insert(
+                        client_id,
+                        JoinStream::new(
+                            users.room_players(&rooms, client_id),
+                            &ChatHistory::default(),
+                        ),
                     );
+                    endpoint.try_send_message(
+                        client_id,
+                        ServerMessage::GameRules {
+                            rules: config.game_rules(),
+                        },
+                    );
+                    endpoint.try_send_message(
+                        client_id,
+                        ServerMessage::Environment {
+                            state: environment.0,
+                        },
+                    );
+                    let peers = users.names.keys().filter(|id| **id != client_id);
                     endpoint.try_send_group_message(
-                        users.names.keys(),
-                        ServerMessage::ChatMessage {
-                            client_id: client_id,
-                            message: message,
+                        peers,
+                        ServerMessage::ClientMigrated {
+                            from: previous,
+                            to: client_id,
                         },
                     );
                 }
-                ClientMessage::PlayerUpdate {
-                    x,
-                    y,
-                    horizontal,
-                    vertical,
-                } => {
-                    info!(
-                        "Player update | {:?}: ({}, {})",
-                        users.names.get(&client_id),
-                        x,
-                        y
-                    );
+                ClientMessage::Emote { id } => {
+                    if !users.names.contains_key(&client_id) {
+                        continue;
+                    }
+                    // Emotes share the chat budget, so they can't be spammed either
+                    if !users.take_chat_token(client_id, &config) {
+                        continue;
+                    }
+                    let peers = rooms.peers(client_id, users.names.keys());
                     endpoint.try_send_group_message(
-                        users.names.keys(),
-                        ServerMessage::PlayerUpdate {
+                        peers.iter(),
+                        ServerMessage::Emote { client_id, id },
+                    );
+                }
+                ClientMessage::AddFriend { friend_id }
+                | ClientMessage::RemoveFriend { friend_id }
+                    if !users.names.contains_key(&client_id) =>
+                {
+                    warn!(
+                        "Friend change for {} from a client that hasn't joined",
+                        friend_id
+                    );
+                }
+                ClientMessage::AddFriend { friend_id } => {
+                    let account = users.account_ids[&client_id].clone();
+                    let friend_id = accounts.resolve(&friend_id);
+                    if friend_id == account {
+                        continue;
+                    }
+                    accounts.profile_mut(&account).friends.insert(friend_id);
+                    endpoint.try_send_message(client_id, friend_list(&users, &accounts, &account));
+                }
+                ClientMessage::RemoveFriend { friend_id } => {
+                    let account = users.account_ids[&client_id].clone();
+                    let friend_id = accounts.resolve(&friend_id);
+                    accounts.profile_mut(&account).friends.remove(&friend_id);
+                    endpoint.try_send_message(client_id, friend_list(&users, &accounts, &account));
+                }
+                ClientMessage::JoinFriend { friend_id } => {
+                    let name = friend_id;
+                    let friend_id = accounts.resolve(&name);
+                    if let Some(friend_client) = online_account(&users, &friend_id) {
+                        let name = accounts.display_name(&friend_id).to_string();
+                        let account = users
+                            .account_ids
+                            .get(&client_id)
+                            .cloned()
+                            .unwrap_or_default();
+                        match rooms.room_of(friend_client) {
+                            // Only share a private room's code if the friendship is
+                            // mutual, anyone can add anyone
+                            Some(_) if !accounts.follows(&friend_id, &account) => {
+                                endpoint.try_send_message(
+                                    client_id,
+                                    notice(
+                                        NoticeLevel::Info,
+                                        LocalizedText::new(texts::FRIEND_IN_PRIVATE_ROOM).arg(name),
+                                    ),
+                                );
+                            }
+                            room => {
+                                endpoint.try_send_message(
+                                    client_id,
+                                    ServerMessage::FriendLocation {
+                                        friend_id: name,
+                                        room: room.cloned(),
+                                    },
+                                );
+                            }
+                        }
+                    } else {
+                        endpoint.try_send_message(
                             client_id,
-                            x,
-                            y,
-                            horizontal,
-                            vertical,
+                            notice(
+                                NoticeLevel::Warning,
+                                LocalizedText::new(texts::FRIEND_NOT_ONLINE).arg(name),
+                            ),
+                        );
+                    }
+                }
+                // Handled by `run_votes`, `run_admin_commands`, `handle_reports` and
+                // `run_match`, and by the systems of `ChatServerPlugin`,
+                // `MovementRelayPlugin` and `RoomsPlugin` when they are built in, from
+                // their events
+                ClientMessage::StartVote { .. }
+                | ClientMessage::CastVote { .. }
+                | ClientMessage::PlayAgain { .. }
+                | ClientMessage::Admin { .. }
+                | ClientMessage::Report { .. }
+                | ClientMessage::ChatMessage { .. }
+                | ClientMessage::Whisper { .. }
+                | ClientMessage::PlayerUpdate { .. }
+                | ClientMessage::RequestAuthority { .. }
+                | ClientMessage::PropUpdate { .. }
+                | ClientMessage::CreateRoom { .. }
+                | ClientMessage::JoinRoom { .. }
+                | ClientMessage::LeaveRoom { .. } => {}
+                ClientMessage::RequestLeaderboard {} => {
+                    endpoint.try_send_message(
+                        client_id,
+                        ServerMessage::Leaderboard {
+                            entries: leaderboard.get(&accounts),
+                        },
+                    );
+                }
+                ClientMessage::Ping {} => {
+                    endpoint.try_send_message(
+                        client_id,
+                        ServerMessage::Pong {
+                            protocol_version: protocol::PROTOCOL_VERSION,
+                            players: users.names.len(),
                         },
                     );
                 }
@@ -137,20 +3716,518 @@ fn handle_client_messages(mut server: ResMut<QuinnetServer>, mut users: ResMut<U
     }
 }
 
+/// Chat and whispers, see `ChatServerPlugin`
+#[cfg(feature = "server-chat")]
+fn handle_chat(
+    mut chats: EventReader<from_client::ChatMessage>,
+    mut whispers: EventReader<from_client::Whisper>,
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    config: Res<ServerConfig>,
+    mut history: ResMut<ChatHistory>,
+    mut accounts: ResMut<Accounts>,
+    rooms: Res<Rooms>,
+) {
+    let endpoint = &mut server.endpoint_mut();
+    for ev in chats.read() {
+        let from_client::ChatMessage {
+            sender: client_id,
+            message,
+            local_id,
+        } = ev.clone();
+        if !users.take_chat_token(client_id, &config) {
+            warn!(
+                "Rate limited chat message from {:?}",
+                users.names.get(&client_id)
+            );
+            endpoint.try_send_message(
+                client_id,
+                notice(
+                    NoticeLevel::Warning,
+                    LocalizedText::new(texts::CHAT_TOO_FAST),
+                ),
+            );
+            continue;
+        }
+        let message = config.filter_words(&message);
+        let sent_at = persistence::unix_now();
+        info!(
+            "Chat message | {:?}: {}",
+            users.names.get(&client_id),
+            message
+        );
+        if let Some(account) = users.account_ids.get(&client_id) {
+            accounts.profile_mut(account).stats.messages += 1;
+        }
+        users.current_match.record_message(client_id);
+        // The backlog is for the lobby, private rooms stay private
+        if let Some(username) = users
+            .names
+            .get(&client_id)
+            .filter(|_| rooms.room_of(client_id).is_none())
+        {
+            history.0.push_back(ChatRecord {
+                username: username.clone(),
+                message: message.clone(),
+                sent_at,
+            });
+            while history.0.len() > config.chat_history_len {
+                history.0.pop_front();
+            }
+        }
+        let peers = rooms.peers(client_id, users.names.keys());
+        endpoint.try_send_group_message(
+            peers.iter(),
+            ServerMessage::ChatMessage {
+                client_id,
+                message,
+                local_id,
+                sent_at,
+            },
+        );
+        if let Some(&(x, y)) = users.positions.get(&client_id) {
+            endpoint.try_send_group_message(
+                peers.iter(),
+                ServerMessage::PlaySoundAt {
+                    sound_id: sounds::CHAT_PING,
+                    x,
+                    y,
+                },
+            );
+        }
+    }
+    for ev in whispers.read() {
+        let from_client::Whisper {
+            sender: client_id,
+            to,
+            text,
+            local_id,
+        } = ev.clone();
+        let Some(from) = users.names.get(&client_id).cloned() else {
+            continue;
+        };
+        let failed = |reason: &str| ServerMessage::WhisperFailed {
+            local_id,
+            to: to.clone(),
+            reason: LocalizedText::new(reason),
+        };
+        if protocol::chat_length(&text) > config.max_chat_length {
+            endpoint.try_send_message(client_id, failed(texts::WHISPER_TOO_LONG));
+            continue;
+        }
+        if !users.take_chat_token(client_id, &config) {
+            endpoint.try_send_message(client_id, failed(texts::WHISPER_TOO_FAST));
+            continue;
+        }
+        let target = users
+            .names
+            .iter()
+            .find(|(_, name)| name.to_lowercase() == to.to_lowercase())
+            .map(|(id, name)| (*id, name.clone()));
+        match target {
+            None => {
+                endpoint.try_send_message(client_id, failed(texts::WHISPER_NOT_ONLINE));
+            }
+            Some((target_id, _)) if target_id == client_id => {
+                endpoint.try_send_message(client_id, failed(texts::WHISPER_SELF));
+            }
+            Some((target_id, target_name)) => {
+                info!("Whisper | {} -> {}", from, target_name);
+                endpoint.try_send_message(
+                    target_id,
+                    ServerMessage::Whisper {
+                        from,
+                        text: config.filter_words(&text),
+                        sent_at: persistence::unix_now(),
+                    },
+                );
+                endpoint.try_send_message(
+                    client_id,
+                    ServerMessage::WhisperDelivered {
+                        local_id,
+                        to: target_name,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Player movement, queued for the room ticks to relay, and prop authority, see
+/// `MovementRelayPlugin`
+#[cfg(feature = "server-movement")]
+fn handle_movement(
+    mut updates: EventReader<from_client::PlayerUpdate>,
+    mut requests: EventReader<from_client::RequestAuthority>,
+    mut prop_updates: EventReader<from_client::PropUpdate>,
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    config: Res<ServerConfig>,
+    rooms: Res<Rooms>,
+    mut props: ResMut<Props>,
+    mut room_ticks: ResMut<RoomTicks>,
+) {
+    let endpoint = &mut server.endpoint_mut();
+    for ev in updates.read() {
+        let from_client::PlayerUpdate {
+            sender: client_id,
+            seq,
+            x,
+            y,
+            horizontal,
+            vertical,
+            sent_at_ms,
+        } = ev.clone();
+        if users.paused {
+            continue;
+        }
+        if let Some(&last) = users.last_update_seq.get(&client_id) {
+            if !protocol::is_newer_seq(seq, last) {
+                // Stale or duplicate, a newer update was already relayed
+                continue;
+            }
+        }
+        users.last_update_seq.insert(client_id, seq);
+        let (x, y) = config.clamp_position(x, y);
+        if let Some((old_x, old_y)) = users.positions.insert(client_id, (x, y)) {
+            let distance = (x - old_x).hypot(y - old_y);
+            users.current_match.record_move(client_id, distance);
+        }
+        users
+            .anomalies
+            .record_update(client_id, config.max_updates_per_sec);
+        users
+            .anomalies
+            .record_move(client_id, x, y, config.max_player_speed);
+        info!(
+            "Player update | {:?}: ({}, {})",
+            users.names.get(&client_id),
+            x,
+            y
+        );
+        // Relayed on the room's next tick
+        room_ticks.queue_move(
+            rooms.room_of(client_id).cloned(),
+            client_id,
+            QueuedMove {
+                seq,
+                x,
+                y,
+                horizontal,
+                vertical,
+                sent_at_ms,
+            },
+        );
+    }
+    for ev in requests.read() {
+        let from_client::RequestAuthority {
+            sender: client_id,
+            prop_id,
+        } = ev.clone();
+        match props.request(prop_id, client_id, &users.positions) {
+            Grant::Granted { revoked } => {
+                if let Some((owner, state)) = revoked {
+                    endpoint.try_send_message(owner, ServerMessage::AuthorityRevoked { state });
+                }
+                endpoint.try_send_message(client_id, ServerMessage::AuthorityGranted { prop_id });
+            }
+            Grant::Refused => {}
+        }
+    }
+    for ev in prop_updates.read() {
+        let from_client::PropUpdate {
+            sender: client_id,
+            mut state,
+        } = ev.clone();
+        (state.x, state.y) = config.clamp_position(state.x, state.y);
+        if let Err(err) = props.update(client_id, state) {
+            warn!(
+                "Rejected prop update from {:?}: {}",
+                users.names.get(&client_id),
+                err
+            );
+            if let Some((owner, state)) = props.revoke(state.prop_id) {
+                endpoint.try_send_message(owner, ServerMessage::AuthorityRevoked { state });
+            }
+        }
+    }
+}
+
+/// Private rooms opened, joined and left, see `RoomsPlugin`
+#[cfg(feature = "server-rooms")]
+fn handle_rooms(
+    mut creates: EventReader<from_client::CreateRoom>,
+    mut joins: EventReader<from_client::JoinRoom>,
+    mut leaves: EventReader<from_client::LeaveRoom>,
+    mut server: Connections,
+    users: Res<Users>,
+    mut rooms: ResMut<Rooms>,
+) {
+    let endpoint = &mut server.endpoint_mut();
+    for ev in creates.read() {
+        let from_client::CreateRoom { sender: client_id } = ev.clone();
+        let previous = rooms.room_of(client_id).cloned();
+        let code = rooms.create(client_id);
+        info!("{:?} opened room {}", users.names.get(&client_id), code);
+        endpoint.try_send_message(client_id, ServerMessage::RoomJoined { code: Some(code) });
+        announce_room_change(endpoint, &users, &rooms, client_id, previous);
+    }
+    for ev in joins.read() {
+        let from_client::JoinRoom {
+            sender: client_id,
+            code,
+        } = ev.clone();
+        let code = rooms::normalize_code(&code);
+        let previous = rooms.room_of(client_id).cloned();
+        if rooms.join(client_id, &code) {
+            let name = users.names.get(&client_id).cloned().unwrap_or_default();
+            let peers: Vec<ClientId> = rooms
+                .peers(client_id, users.names.keys())
+                .into_iter()
+                .filter(|peer| *peer != client_id)
+                .collect();
+            endpoint.try_send_group_message(
+                peers.iter(),
+                notice(
+                    NoticeLevel::Info,
+                    LocalizedText::new(texts::ROOM_PLAYER_JOINED).arg(name),
+                ),
+            );
+            endpoint.try_send_message(client_id, ServerMessage::RoomJoined { code: Some(code) });
+            announce_room_change(endpoint, &users, &rooms, client_id, previous);
+        } else {
+            endpoint.try_send_message(
+                client_id,
+                notice(
+                    NoticeLevel::Warning,
+                    LocalizedText::new(texts::UNKNOWN_INVITE_CODE).arg(code),
+                ),
+            );
+        }
+    }
+    for ev in leaves.read() {
+        let from_client::LeaveRoom { sender: client_id } = ev.clone();
+        let previous = rooms.room_of(client_id).cloned();
+        rooms.leave(client_id);
+        endpoint.try_send_message(client_id, ServerMessage::RoomJoined { code: None });
+        announce_room_change(endpoint, &users, &rooms, client_id, previous);
+    }
+}
+
+/// `client_id` moved from `previous` to its current room: the players it left stop
+/// seeing it (and it them) with PlayerLeftRoom, the ones it joined get it with
+/// WorldPlayers (and it them). Nobody disconnected, names and chat are unaffected.
+fn announce_room_change(
+    endpoint: &mut ServerEndpoint,
+    users: &Users,
+    rooms: &Rooms,
+    client_id: ClientId,
+    previous: Option<String>,
+) {
+    let current = rooms.room_of(client_id);
+    if current == previous.as_ref() {
+        return;
+    }
+    let in_room = |room: Option<&String>| -> Vec<ClientId> {
+        users
+            .world_players()
+            .into_iter()
+            .filter(|other| *other != client_id && rooms.room_of(*other) == room)
+            .collect()
+    };
+    let connected = |players: &[ClientId]| -> Vec<ClientId> {
+        players
+            .iter()
+            .filter(|player| users.names.contains_key(player))
+            .copied()
+            .collect()
+    };
+
+    let left = in_room(previous.as_ref());
+    endpoint.try_send_group_message(
+        connected(&left).iter(),
+        ServerMessage::PlayerLeftRoom { client_id },
+    );
+    for peer in left {
+        endpoint.try_send_message(client_id, ServerMessage::PlayerLeftRoom { client_id: peer });
+    }
+
+    let joined = in_room(current);
+    if let Some(info) = users.player_info(client_id) {
+        endpoint.try_send_group_message(
+            connected(&joined).iter(),
+            ServerMessage::WorldPlayers {
+                players: vec![info],
+            },
+        );
+    }
+    let players = joined
+        .into_iter()
+        .filter_map(|peer| users.player_info(peer))
+        .collect();
+    endpoint.try_send_message(client_id, ServerMessage::WorldPlayers { players });
+}
+
+/// The client connected with this account, if any
+fn online_account(users: &Users, account: &str) -> Option<ClientId> {
+    users
+        .names
+        .keys()
+        .find(|client_id| {
+            users
+                .account_ids
+                .get(*client_id)
+                .is_some_and(|id| id == account)
+        })
+        .copied()
+}
+
+fn friend_list(users: &Users, accounts: &Accounts, account: &str) -> ServerMessage {
+    ServerMessage::FriendList {
+        friends: accounts
+            .friends_of(account)
+            .map(|friend_id| FriendStatus {
+                friend_id: accounts.display_name(friend_id).to_string(),
+                online: online_account(users, friend_id).is_some(),
+            })
+            .collect(),
+    }
+}
+
+/// Tell everyone online who has `account` as a friend that it connected or left
+fn notify_followers(
+    endpoint: &mut ServerEndpoint,
+    users: &Users,
+    accounts: &Accounts,
+    account: &str,
+    online: bool,
+) {
+    let followers: Vec<ClientId> = users
+        .names
+        .keys()
+        .filter(|client_id| {
+            users
+                .account_ids
+                .get(*client_id)
+                .is_some_and(|follower| accounts.follows(follower, account))
+        })
+        .copied()
+        .collect();
+    endpoint.try_send_group_message(
+        followers.iter(),
+        ServerMessage::FriendPresence {
+            friend_id: accounts.display_name(account).to_string(),
+            online,
+        },
+    );
+}
+
 fn handle_server_events(
     mut connection_lost_events: EventReader<ConnectionLostEvent>,
-    mut server: ResMut<QuinnetServer>,
+    mut server: Connections,
     mut users: ResMut<Users>,
+    accounts: Res<Accounts>,
+    mut rooms: ResMut<Rooms>,
+    mut sessions: ResMut<Sessions>,
+    config: Res<ServerConfig>,
 ) {
     // The server signals us about users that lost connection
     for client in connection_lost_events.read() {
-        handle_disconnect(server.endpoint_mut(), &mut users, client.id);
+        // Keep the player around for a while, the client may be changing networks
+        let resume_secs = if users.away.contains(&client.id) {
+            config.away_resume_secs
+        } else {
+            config.session_resume_secs
+        };
+        if resume_secs > 0 && users.names.contains_key(&client.id) && sessions.suspend(client.id) {
+            let name = users.names.remove(&client.id).unwrap_or_default();
+            info!("{} dropped, holding its session", name);
+            users.suspended.insert(client.id, name);
+            continue;
+        }
+        sessions.forget(client.id);
+        handle_disconnect(
+            &mut server.endpoint_mut(),
+            &mut users,
+            &accounts,
+            &mut rooms,
+            client.id,
+        );
+    }
+}
+
+fn expire_sessions(
+    mut server: Connections,
+    mut users: ResMut<Users>,
+    accounts: Res<Accounts>,
+    mut rooms: ResMut<Rooms>,
+    mut sessions: ResMut<Sessions>,
+    config: Res<ServerConfig>,
+) {
+    let expired = sessions.expire(|client_id| {
+        Duration::from_secs(if users.away.contains(&client_id) {
+            config.away_resume_secs
+        } else {
+            config.session_resume_secs
+        })
+    });
+    for client_id in expired {
+        let Some(name) = users.suspended.remove(&client_id) else {
+            continue;
+        };
+        info!("Session of {} expired", name);
+        // Back in so handle_disconnect tells everyone it left
+        users.names.insert(client_id, name);
+        handle_disconnect(
+            &mut server.endpoint_mut(),
+            &mut users,
+            &accounts,
+            &mut rooms,
+            client_id,
+        );
     }
 }
 
 /// Shared disconnection behaviour, whether the client lost connection or asked to disconnect
-fn handle_disconnect(endpoint: &mut Endpoint, users: &mut ResMut<Users>, client_id: ClientId) {
+fn handle_disconnect(
+    endpoint: &mut ServerEndpoint,
+    users: &mut ResMut<Users>,
+    accounts: &Accounts,
+    rooms: &mut Rooms,
+    client_id: ClientId,
+) {
+    let account = users.account_ids.remove(&client_id);
+    if let (Some(account), Some(&(x, y))) = (&account, users.positions.get(&client_id)) {
+        let departed = (
+            account.clone(),
+            LastPosition {
+                x,
+                y,
+                room: rooms.room_of(client_id).cloned(),
+            },
+        );
+        users.departed.push(departed);
+    }
+    rooms.leave(client_id);
     // Remove this user
+    users.last_update_seq.remove(&client_id);
+    users.chat_tokens.remove(&client_id);
+    users.positions.remove(&client_id);
+    users.last_footstep.remove(&client_id);
+    users.last_bump.remove(&client_id);
+    users.last_full_sync.remove(&client_id);
+    users.links.remove(&client_id);
+    users.malformed.remove(&client_id);
+    users.away.remove(&client_id);
+    users.cosmetics.remove(&client_id);
+    if let Some(stats) = users.anomalies.forget(client_id) {
+        warn!(
+            "Anomalies of {:?} ({}) at disconnect: {}",
+            users.names.get(&client_id),
+            client_id,
+            stats.summary()
+        );
+    }
     if let Some(username) = users.names.remove(&client_id) {
         // Broadcast its deconnection
 
@@ -162,6 +4239,9 @@ fn handle_disconnect(endpoint: &mut Endpoint, users: &mut ResMut<Users>, client_
                 },
             )
             .unwrap();
+        if let Some(account) = &account {
+            notify_followers(endpoint, users, accounts, account, false);
+        }
         info!("{} disconnected", username);
     } else {
         warn!(
@@ -0,0 +1,119 @@
+//! The dedicated server's recent log lines, kept in memory for the RCON `log` command
+//! so operators can read them without a shell on the machine.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    log::{
+        BoxedLayer,
+        tracing::{
+            Event, Subscriber,
+            field::{Field, Visit},
+        },
+        tracing_subscriber::{Layer, layer::Context},
+    },
+    prelude::*,
+};
+
+/// Lines kept, older ones are dropped
+const CAPACITY: usize = 5000;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// What the RCON `log` command asks for
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogQuery {
+    /// The last lines, oldest first
+    Tail(usize),
+    /// Every kept line containing this, oldest first
+    Grep(String),
+}
+
+/// The latest `CAPACITY` log lines, shared with the layer writing them
+#[derive(Resource, Clone, Default)]
+pub struct ServerLog(Arc<Mutex<VecDeque<String>>>);
+
+impl ServerLog {
+    fn push(&self, line: String) {
+        let mut lines = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if lines.len() == CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn query(&self, query: &LogQuery) -> Vec<String> {
+        let lines = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match query {
+            LogQuery::Tail(count) => lines
+                .iter()
+                .skip(lines.len().saturating_sub(*count))
+                .cloned()
+                .collect(),
+            LogQuery::Grep(term) => lines
+                .iter()
+                .filter(|line| line.contains(term.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// `LogPlugin::custom_layer`: keep every log line in a `ServerLog` resource too
+pub fn capture(app: &mut App) -> Option<BoxedLayer> {
+    let log = ServerLog::default();
+    app.insert_resource(log.clone());
+    Some(Box::new(CaptureLayer(log)))
+}
+
+struct CaptureLayer(ServerLog);
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = FieldsVisitor::default();
+        event.record(&mut fields);
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs())
+            % SECS_PER_DAY;
+        let metadata = event.metadata();
+        self.0.push(format!(
+            "{:02}:{:02}:{:02} {} {}: {}{}",
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            metadata.level(),
+            metadata.target(),
+            fields.message,
+            fields.rest
+        ));
+    }
+}
+
+/// The event's message, then its other fields as ` name=value`
+#[derive(Default)]
+struct FieldsVisitor {
+    message: String,
+    rest: String,
+}
+
+impl Visit for FieldsVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+
+/// Session tokens, so a client whose connection dropped (e.g. a phone switching from
+/// Wi-Fi to LTE) can resume as the same player instead of leaving and rejoining
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Sessions {
+    tokens: HashMap<ClientId, u64>,
+    /// Dropped connections waiting to be resumed: token to the old client id and when it dropped
+    suspended: HashMap<u64, (ClientId, Instant)>,
+}
+
+impl Sessions {
+    /// New token for `client_id`, replacing any previous one
+    pub fn issue(&mut self, client_id: ClientId) -> u64 {
+        let token = rand::random();
+        self.tokens.insert(client_id, token);
+        token
+    }
+
+    /// Hold the session of a dropped client, false if it has none
+    pub fn suspend(&mut self, client_id: ClientId) -> bool {
+        match self.tokens.remove(&client_id) {
+            Some(token) => {
+                self.suspended.insert(token, (client_id, Instant::now()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The old client id of a held session, which is then gone; tokens are single use
+    pub fn resume(&mut self, token: u64) -> Option<ClientId> {
+        self.suspended
+            .remove(&token)
+            .map(|(client_id, _)| client_id)
+    }
+
+    /// The client left for good
+    pub fn forget(&mut self, client_id: ClientId) {
+        self.tokens.remove(&client_id);
+    }
+
+    /// Remove and return the held sessions older than their `max_age`
+    pub fn expire(&mut self, max_age: impl Fn(ClientId) -> Duration) -> Vec<ClientId> {
+        let mut expired = Vec::new();
+        self.suspended.retain(|_, (client_id, since)| {
+            let keep = since.elapsed() < max_age(*client_id);
+            if !keep {
+                expired.push(*client_id);
+            }
+            keep
+        });
+        expired
+    }
+}
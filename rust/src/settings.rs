@@ -0,0 +1,323 @@
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use bevy::prelude::*;
+use godot::{
+    classes::{Control, DisplayServer, Os, ProjectSettings},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::console::{ConsoleAppExt, ConsoleOutput};
+use crate::{ChatMessage, Users, frame_budget};
+
+/// Where the player's settings are kept, written whenever a console command changes them
+const SETTINGS_PATH: &str = "user://settings.json";
+
+/// Servers kept in the join dialog's history
+const MAX_RECENT_SERVERS: usize = 8;
+
+const MIN_FONT_SCALE: f32 = 0.5;
+const MAX_FONT_SCALE: f32 = 3.0;
+
+/// Font size theme items of a RichTextLabel, one per style
+const RICH_TEXT_FONT_SIZES: &[&str] = &[
+    "normal_font_size",
+    "bold_font_size",
+    "italics_font_size",
+    "bold_italics_font_size",
+    "mono_font_size",
+];
+
+/// Player preferences, read from `user://settings.json` at startup
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientSettings {
+    /// Read chat from other players aloud with the OS text-to-speech (the project must
+    /// enable `audio/general/text_to_speech`)
+    pub chat_tts: bool,
+    /// One of `DisplayServer.tts_get_voices()`, the first voice for the OS language when None
+    pub tts_voice: Option<String>,
+    /// 0 to 100
+    pub tts_volume: i32,
+    /// 0.1 to 10, 1 is the voice's normal speed
+    pub tts_rate: f32,
+    /// Multiplies the font sizes of every bridged UI node (chat, toasts, votes, ...)
+    /// and the controls under it
+    pub font_scale: f32,
+    /// Shake the camera on hits, explosions and collisions
+    pub screen_shake: bool,
+    /// Vibrate connected controllers on the same events
+    pub rumble: bool,
+    /// Draw our own player between its last two physics positions on every frame, so it
+    /// moves smoothly on displays faster than the physics tick; costs up to a tick of lag
+    pub interpolate_local_player: bool,
+    /// Append chat to `user://chatlogs` every few seconds, as `/savechat` does
+    pub autosave_chat: bool,
+    /// "host:port" of the servers last joined from the join dialog, most recent first
+    pub recent_servers: Vec<String>,
+    /// Where the settings were read from, None before loading
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            chat_tts: false,
+            tts_voice: None,
+            tts_volume: 50,
+            tts_rate: 1.0,
+            font_scale: 1.0,
+            screen_shake: true,
+            rumble: true,
+            interpolate_local_player: false,
+            autosave_chat: false,
+            recent_servers: Vec::new(),
+            path: None,
+        }
+    }
+}
+
+impl ClientSettings {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut settings: Self = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(err),
+        };
+        settings.path = Some(path.to_path_buf());
+        Ok(settings)
+    }
+
+    /// Move `address` to the top of `recent_servers`
+    pub fn remember_server(&mut self, address: &str) {
+        self.recent_servers.retain(|recent| recent != address);
+        self.recent_servers.insert(0, address.to_string());
+        self.recent_servers.truncate(MAX_RECENT_SERVERS);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, data)
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ClientSettings>()
+            .add_systems(Startup, load_settings)
+            .add_systems(Update, (apply_font_scale, speak_chat))
+            .add_console_command(
+                "tts",
+                "tts on|off: read chat from other players aloud",
+                tts_command,
+            )
+            .add_console_command(
+                "font_scale",
+                "font_scale <factor>: scale UI font sizes, 1 for the theme's",
+                font_scale_command,
+            )
+            .add_console_command(
+                "interpolation",
+                "interpolation on|off: smooth our own player between physics ticks",
+                interpolation_command,
+            )
+            .add_console_command(
+                "chatlog",
+                "chatlog on|off: save chat to user://chatlogs as it comes",
+                chatlog_command,
+            );
+    }
+}
+
+#[main_thread_system]
+fn load_settings(mut settings: ResMut<ClientSettings>) {
+    let path = ProjectSettings::singleton()
+        .globalize_path(SETTINGS_PATH)
+        .to_string();
+    match ClientSettings::load(Path::new(&path)) {
+        Ok(loaded) => *settings = loaded,
+        Err(err) => godot_print!("Could not read {} ({}), using defaults", path, err),
+    }
+}
+
+/// Scale fonts everywhere when the setting changes, and on nodes as they are bridged
+#[main_thread_system]
+fn apply_font_scale(settings: Res<ClientSettings>, mut query: Query<&mut GodotNodeHandle>) {
+    let _timing = frame_budget::scope("apply_font_scale");
+    let scale = settings.font_scale.clamp(MIN_FONT_SCALE, MAX_FONT_SCALE);
+    for mut handle in query.iter_mut() {
+        if !settings.is_changed() && !handle.is_added() {
+            continue;
+        }
+        if let Some(mut control) = handle.try_get::<Control>() {
+            scale_fonts(&mut control, scale);
+        }
+    }
+}
+
+/// Set this control's font sizes and its descendants' to their original size times `scale`.
+/// The original (theme or scene override) is kept as metadata, so scaling never compounds.
+fn scale_fonts(control: &mut Gd<Control>, scale: f32) {
+    let names: &[&str] = if control.is_class("RichTextLabel") {
+        RICH_TEXT_FONT_SIZES
+    } else {
+        &["font_size"]
+    };
+    for name in names {
+        let meta = format!("base_{}", name);
+        let base = if control.has_meta(&meta) {
+            control.get_meta(&meta).to::<i32>()
+        } else {
+            let base = control.get_theme_font_size(*name);
+            control.set_meta(&meta, &base.to_variant());
+            base
+        };
+        let size = (base as f32 * scale).round() as i32;
+        control.add_theme_font_size_override(*name, size.max(1));
+    }
+    for child in control.get_children().iter_shared() {
+        if let Ok(mut child) = child.try_cast::<Control>() {
+            scale_fonts(&mut child, scale);
+        }
+    }
+}
+
+#[main_thread_system]
+fn speak_chat(
+    mut events: EventReader<ChatMessage>,
+    settings: Res<ClientSettings>,
+    users: Res<Users>,
+    mut default_voice: Local<Option<String>>,
+) {
+    let _timing = frame_budget::scope("speak_chat");
+    if !settings.chat_tts {
+        events.clear();
+        return;
+    }
+    let mut display = DisplayServer::singleton();
+    for ev in events.read() {
+        // Only what other players say, not our own echo or system lines
+        if ev
+            .client_id
+            .is_none_or(|client_id| client_id == users.self_id)
+        {
+            continue;
+        }
+        let voice = match &settings.tts_voice {
+            Some(voice) => voice.clone(),
+            None => {
+                if default_voice.is_none() {
+                    let language = Os::singleton().get_locale_language();
+                    *default_voice = display
+                        .tts_get_voices_for_language(&language)
+                        .as_slice()
+                        .first()
+                        .map(GString::to_string);
+                }
+                let Some(voice) = default_voice.clone() else {
+                    godot_print!("No text-to-speech voice for this language");
+                    return;
+                };
+                voice
+            }
+        };
+        display
+            .tts_speak_ex(&format!("{}: {}", ev.username, ev.message), &voice)
+            .volume(settings.tts_volume.clamp(0, 100))
+            .rate(settings.tts_rate.clamp(0.1, 10.0))
+            .done();
+    }
+}
+
+fn tts_command(
+    In(args): In<Vec<String>>,
+    mut settings: ResMut<ClientSettings>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    settings.chat_tts = match args.first().map(String::as_str) {
+        Some("on") => true,
+        Some("off") => false,
+        _ => {
+            output.write(ConsoleOutput("usage: tts on|off".to_string()));
+            return;
+        }
+    };
+    save(&settings, &mut output);
+    output.write(ConsoleOutput(format!(
+        "chat text-to-speech {}",
+        if settings.chat_tts { "on" } else { "off" }
+    )));
+}
+
+fn interpolation_command(
+    In(args): In<Vec<String>>,
+    mut settings: ResMut<ClientSettings>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    settings.interpolate_local_player = match args.first().map(String::as_str) {
+        Some("on") => true,
+        Some("off") => false,
+        _ => {
+            output.write(ConsoleOutput("usage: interpolation on|off".to_string()));
+            return;
+        }
+    };
+    save(&settings, &mut output);
+    output.write(ConsoleOutput(format!(
+        "local player interpolation {}",
+        if settings.interpolate_local_player {
+            "on"
+        } else {
+            "off"
+        }
+    )));
+}
+
+fn chatlog_command(
+    In(args): In<Vec<String>>,
+    mut settings: ResMut<ClientSettings>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    settings.autosave_chat = match args.first().map(String::as_str) {
+        Some("on") => true,
+        Some("off") => false,
+        _ => {
+            output.write(ConsoleOutput("usage: chatlog on|off".to_string()));
+            return;
+        }
+    };
+    save(&settings, &mut output);
+    output.write(ConsoleOutput(format!(
+        "chat autosave {}",
+        if settings.autosave_chat { "on" } else { "off" }
+    )));
+}
+
+fn font_scale_command(
+    In(args): In<Vec<String>>,
+    mut settings: ResMut<ClientSettings>,
+    mut output: EventWriter<ConsoleOutput>,
+) {
+    let Some(scale) = args.first().and_then(|scale| scale.parse::<f32>().ok()) else {
+        output.write(ConsoleOutput("usage: font_scale <factor>".to_string()));
+        return;
+    };
+    settings.font_scale = scale.clamp(MIN_FONT_SCALE, MAX_FONT_SCALE);
+    save(&settings, &mut output);
+    output.write(ConsoleOutput(format!("font scale {}", settings.font_scale)));
+}
+
+fn save(settings: &ClientSettings, output: &mut EventWriter<ConsoleOutput>) {
+    if let Err(err) = settings.save() {
+        output.write(ConsoleOutput(format!("could not save settings: {}", err)));
+    }
+}
@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+/// What a random stream is used for, so unrelated draws never share a sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum RngStream {
+    SpawnOffset = 1,
+}
+
+/// Randomness every machine agrees on, seeded by the server in InitClient.
+/// Draws come from per-purpose, per-key generators instead of one shared
+/// sequence, so the result doesn't depend on what else a client rolled before.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SharedRng {
+    pub seed: u64,
+}
+
+impl SharedRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Generator for `stream`, e.g. keyed by client id for per-player values
+    pub fn rng_for(&self, stream: RngStream, key: u64) -> StdRng {
+        let mixed = splitmix64(self.seed ^ splitmix64(stream as u64 ^ splitmix64(key)));
+        StdRng::seed_from_u64(mixed)
+    }
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,165 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use godot::{
+    classes::{Camera2D, ICamera2D, Input, InputMap},
+    prelude::*,
+};
+use godot_bevy::prelude::*;
+
+use crate::flow::in_game;
+use crate::frame_budget;
+use crate::player::{Player, PlayerNode};
+
+/// Input actions cycling through players, used only if the project defines them
+const NEXT_ACTION: &str = "spectate_next";
+const PREVIOUS_ACTION: &str = "spectate_previous";
+/// No player to follow
+const NO_TARGET: i64 = -1;
+
+#[derive(Component, Default)]
+pub struct SpectatorCameraComp;
+
+/// Observer camera following any player, moved by `drive_spectator_cameras`. Cycle with
+/// the `spectate_next`/`spectate_previous` actions (e.g. Tab and Shift+Tab) or
+/// `follow_next()`, or pick one with `follow(client_id)`, e.g. from a scoreboard.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Camera2D)]
+#[bevy_bundle((SpectatorCameraComp))]
+pub struct SpectatorCameraNode {
+    base: Base<Camera2D>,
+    /// Client id of the followed player, -1 for none; the first player is picked when
+    /// it's none or the player left
+    #[var]
+    target_client_id: i64,
+    /// How fast the camera catches up, higher is snappier
+    #[var]
+    follow_speed: f32,
+    /// Players to move the selection by on the next frame
+    cycle: i32,
+}
+
+#[godot_api]
+impl ICamera2D for SpectatorCameraNode {
+    fn init(base: Base<Camera2D>) -> Self {
+        Self {
+            base,
+            target_client_id: NO_TARGET,
+            follow_speed: 5.0,
+            cycle: 0,
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        let input_map = InputMap::singleton();
+        let input = Input::singleton();
+        if input_map.has_action(NEXT_ACTION) && input.is_action_just_pressed(NEXT_ACTION) {
+            self.cycle += 1;
+        }
+        if input_map.has_action(PREVIOUS_ACTION) && input.is_action_just_pressed(PREVIOUS_ACTION) {
+            self.cycle -= 1;
+        }
+    }
+}
+
+#[godot_api]
+impl SpectatorCameraNode {
+    #[signal]
+    fn target_changed(client_id: i64);
+
+    #[func]
+    pub fn follow(&mut self, client_id: i64) {
+        if self.target_client_id != client_id {
+            self.set_target(client_id);
+        }
+    }
+
+    #[func]
+    pub fn follow_next(&mut self) {
+        self.cycle += 1;
+    }
+
+    #[func]
+    pub fn follow_previous(&mut self) {
+        self.cycle -= 1;
+    }
+
+    fn set_target(&mut self, client_id: i64) {
+        self.target_client_id = client_id;
+        self.base_mut()
+            .emit_signal("target_changed", &[client_id.to_variant()]);
+    }
+}
+
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, drive_spectator_cameras.run_if(in_game));
+    }
+}
+
+/// Apply the selection changes and ease every enabled spectator camera towards its player
+#[main_thread_system]
+fn drive_spectator_cameras(
+    mut cameras: Query<&mut GodotNodeHandle, With<SpectatorCameraComp>>,
+    mut players: Query<(&Player, &mut GodotNodeHandle), Without<SpectatorCameraComp>>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    let _timing = frame_budget::scope("drive_spectator_cameras");
+    let now = Instant::now();
+    let elapsed = now.duration_since(last_frame.replace(now).unwrap_or(now));
+    if cameras.is_empty() {
+        return;
+    }
+
+    // Cycled through in client id order, the same on every client
+    let mut positions: Vec<(ClientId, Vector2)> = players
+        .iter_mut()
+        .filter_map(|(player, mut handle)| {
+            let node = handle.try_get::<PlayerNode>()?;
+            Some((player.0, node.get_global_position()))
+        })
+        .collect();
+    positions.sort_by_key(|(client_id, _)| *client_id);
+
+    for mut handle in cameras.iter_mut() {
+        let Some(mut camera) = handle.try_get::<SpectatorCameraNode>() else {
+            continue;
+        };
+        if !camera.is_enabled() {
+            continue;
+        }
+        let (target, cycle, follow_speed) = {
+            let camera = camera.bind();
+            (camera.target_client_id, camera.cycle, camera.follow_speed)
+        };
+        let current = positions
+            .iter()
+            .position(|(client_id, _)| *client_id as i64 == target);
+        let selected = match (current, positions.len()) {
+            (_, 0) => None,
+            (Some(index), len) => Some((index as i64 + cycle as i64).rem_euclid(len as i64)),
+            // The followed player left, or nobody was followed yet
+            (None, len) => Some((cycle.max(0) as i64).rem_euclid(len as i64)),
+        };
+        let selected = selected.map(|index| positions[index as usize]);
+        {
+            let mut camera = camera.bind_mut();
+            camera.cycle = 0;
+            let selected_id = selected.map_or(NO_TARGET, |(client_id, _)| client_id as i64);
+            if selected_id != target {
+                camera.set_target(selected_id);
+            }
+        }
+
+        let Some((_, player_position)) = selected else {
+            continue;
+        };
+        // Frame-rate independent exponential ease
+        let t = 1.0 - (-follow_speed.max(0.0) * elapsed.as_secs_f32()).exp();
+        let position = camera.get_global_position();
+        camera.set_global_position(position.lerp(player_position, t));
+    }
+}
@@ -0,0 +1,163 @@
+//! Desync detection and recovery: the server sends the `protocol::state_checksum` of our
+//! room every few seconds, and we compare it with what we have. Future gameplay code
+//! that forgets to replicate something shows up here instead of as a silently diverging
+//! world, and is repaired with a full resync.
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use protocol::ClientMessage;
+
+use crate::leave;
+use crate::net_events::from_server;
+use crate::player::PlayerIndex;
+use crate::prop_sync::PropIndex;
+use crate::transport::{SendToServer, connection_ready};
+use crate::{SyncState, Users};
+
+/// A spawn or despawn may still be in flight when a checksum arrives, so only a
+/// mismatch that persists over this many checksums in a row counts
+const MISMATCHES_BEFORE_RESYNC: u32 = 2;
+/// A frame taking this long (a GC pause, a debugger, a suspended tab) may have
+/// overflowed or dropped what the server sent meanwhile
+const STALL_THRESHOLD: Duration = Duration::from_secs(3);
+/// Wait before a full sync that follows another one, doubled each time it happens again
+/// until `MAX_FULL_SYNC_BACKOFF`: something that keeps diverging isn't fixed by
+/// rebuilding the world every other second
+const FULL_SYNC_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_FULL_SYNC_BACKOFF: Duration = Duration::from_secs(30);
+/// A full sync this long after the previous one starts the backoff over
+const FULL_SYNC_BACKOFF_RESET: Duration = Duration::from_secs(60);
+
+/// Drop our players and props and have the server send everything again, as if we had
+/// just joined. Written after a checksum mismatch, a long stall, coming back from the
+/// background, or a `WorldReset`.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RequestFullSync;
+
+pub struct StateCheckPlugin;
+
+impl Plugin for StateCheckPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RequestFullSync>().add_systems(
+            Update,
+            (
+                verify_state_checksum,
+                detect_stalls,
+                rebuild_on_world_reset,
+                request_full_sync
+                    .after(verify_state_checksum)
+                    .after(detect_stalls)
+                    .after(rebuild_on_world_reset)
+                    .run_if(connection_ready),
+            ),
+        );
+    }
+}
+
+fn verify_state_checksum(
+    mut events: EventReader<from_server::StateChecksum>,
+    sync: Res<SyncState>,
+    users: Res<Users>,
+    players: Res<PlayerIndex>,
+    props: Res<PropIndex>,
+    mut full_sync: EventWriter<RequestFullSync>,
+    mut mismatches: Local<u32>,
+) {
+    let Some(ev) = events.read().last() else {
+        return;
+    };
+    if *sync != SyncState::Ready {
+        *mismatches = 0;
+        return;
+    }
+    let local = protocol::state_checksum(
+        players.0.keys().map(|&client_id| {
+            let name = users.names.get(&client_id).map_or("", String::as_str);
+            (client_id, name)
+        }),
+        props.0.keys().copied(),
+    );
+    if local == ev.checksum {
+        *mismatches = 0;
+        return;
+    }
+    *mismatches += 1;
+    if *mismatches >= MISMATCHES_BEFORE_RESYNC {
+        warn!(
+            "World state differs from the server's ({:016x} != {:016x}), resyncing",
+            local, ev.checksum
+        );
+        *mismatches = 0;
+        full_sync.write(RequestFullSync);
+    }
+}
+
+fn detect_stalls(
+    sync: Res<SyncState>,
+    mut full_sync: EventWriter<RequestFullSync>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    let now = Instant::now();
+    if let Some(last) = last_frame.replace(now) {
+        let stalled = now.duration_since(last);
+        if stalled >= STALL_THRESHOLD && *sync == SyncState::Ready {
+            warn!("Frame stalled for {:?}, resyncing", stalled);
+            full_sync.write(RequestFullSync);
+        }
+    }
+}
+
+/// Rebuild the players and props from scratch rather than patching them one by one
+fn rebuild_on_world_reset(
+    mut events: EventReader<from_server::WorldReset>,
+    mut full_sync: EventWriter<RequestFullSync>,
+) {
+    if events.read().count() > 0 {
+        full_sync.write(RequestFullSync);
+    }
+}
+
+#[derive(Debug, Default)]
+struct FullSyncBackoff {
+    last: Option<Instant>,
+    wait: Duration,
+    /// Requested while waiting, sent once the wait is over
+    pending: bool,
+}
+
+fn request_full_sync(
+    mut events: EventReader<RequestFullSync>,
+    mut sync: ResMut<SyncState>,
+    mut to_server: EventWriter<SendToServer>,
+    mut commands: Commands,
+    mut backoff: Local<FullSyncBackoff>,
+) {
+    if events.read().count() > 0 {
+        backoff.pending = true;
+    }
+    // A resync already underway is enough
+    if *sync != SyncState::Ready {
+        backoff.pending = false;
+        return;
+    }
+    if !backoff.pending
+        || backoff
+            .last
+            .is_some_and(|last| last.elapsed() < backoff.wait)
+    {
+        return;
+    }
+    backoff.wait = match backoff.last {
+        Some(last) if last.elapsed() < FULL_SYNC_BACKOFF_RESET => {
+            (backoff.wait * 2).clamp(FULL_SYNC_BACKOFF, MAX_FULL_SYNC_BACKOFF)
+        }
+        _ => FULL_SYNC_BACKOFF,
+    };
+    backoff.last = Some(Instant::now());
+    backoff.pending = false;
+    // Until the answer's InitClient, as on joining
+    *sync = SyncState::Syncing;
+    commands.queue(leave::despawn_world);
+    to_server.write(SendToServer(ClientMessage::RequestFullSync {}));
+}
@@ -0,0 +1,497 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_quinnet::client::{
+    QuinnetClient, certificate::CertificateVerificationMode,
+    connection::ClientEndpointConfiguration,
+};
+use protocol::{ClientMessage, DecodeError, ServerMessage};
+
+use crate::loopback::{self, LOOPBACK_ADDRESS, LoopbackTransport};
+use crate::msg_trace::{Direction, MessageTrace};
+use crate::net_stats::LinkStats;
+
+#[derive(Debug)]
+pub enum TransportError {
+    NotConnected,
+    InvalidAddress(String),
+    Send(String),
+}
+
+/// Log a received message that could not be decoded. Messages added by a newer server
+/// are expected and skipped quietly.
+pub fn skip_undecodable(transport: &str, err: DecodeError) {
+    match err {
+        DecodeError::UnknownKind { .. } => debug!("Skipping {} message: {}", transport, err),
+        DecodeError::Incompatible { .. } => {
+            warn!(
+                "Dropping {} message from an outdated server: {}",
+                transport, err
+            )
+        }
+        DecodeError::Invalid(_) => warn!("Dropping undecodable {} message: {}", transport, err),
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::NotConnected => write!(f, "not connected"),
+            TransportError::InvalidAddress(addr) => write!(f, "invalid address: {}", addr),
+            TransportError::Send(err) => write!(f, "send failed: {}", err),
+        }
+    }
+}
+
+/// Backend-agnostic connection events, so gameplay code doesn't depend on quinnet events
+#[derive(Event, Debug, Clone)]
+pub enum TransportEvent {
+    Connected,
+    ConnectionFailed(String),
+    Disconnected,
+}
+
+/// The client side of the ClientMessage/ServerMessage flow.
+/// QUIC (quinnet) is used on native builds; browser builds use the `websocket` feature.
+/// Either way, `loopback::LOOPBACK_ADDRESS` connects to a server in this process.
+pub trait ClientTransport {
+    fn open(&mut self, server_addr: &str) -> Result<(), TransportError>;
+    fn close(&mut self);
+    fn is_connected(&self) -> bool;
+    fn send(&mut self, message: ClientMessage) -> Result<(), TransportError>;
+    fn receive(&mut self) -> Option<ServerMessage>;
+
+    /// Round trip and loss counters, when the backend exposes them
+    fn link_stats(&self) -> Option<LinkStats> {
+        None
+    }
+
+    fn try_send(&mut self, message: ClientMessage) {
+        if let Err(err) = self.send(message) {
+            warn!("Failed to send message: {}", err);
+        }
+    }
+}
+
+#[cfg(not(feature = "websocket"))]
+type Network = QuinnetClient;
+#[cfg(feature = "websocket")]
+type Network = websocket::WebSocketClient;
+
+/// What systems send and receive through: the network backend, or the loopback while
+/// it's open
+#[derive(SystemParam)]
+pub struct Transport<'w> {
+    network: ResMut<'w, Network>,
+    loopback: ResMut<'w, LoopbackTransport>,
+}
+
+impl Transport<'_> {
+    fn active(&mut self) -> &mut dyn ClientTransport {
+        if self.loopback.is_open() {
+            &mut *self.loopback
+        } else {
+            &mut *self.network
+        }
+    }
+
+    fn active_ref(&self) -> &dyn ClientTransport {
+        if self.loopback.is_open() {
+            &*self.loopback
+        } else {
+            &*self.network
+        }
+    }
+}
+
+impl ClientTransport for Transport<'_> {
+    fn open(&mut self, server_addr: &str) -> Result<(), TransportError> {
+        if server_addr == LOOPBACK_ADDRESS {
+            self.loopback.open(server_addr)
+        } else {
+            self.network.open(server_addr)
+        }
+    }
+
+    fn close(&mut self) {
+        self.loopback.close();
+        self.network.close();
+    }
+
+    fn is_connected(&self) -> bool {
+        self.active_ref().is_connected()
+    }
+
+    fn send(&mut self, message: ClientMessage) -> Result<(), TransportError> {
+        self.active().send(message)
+    }
+
+    fn receive(&mut self) -> Option<ServerMessage> {
+        self.active().receive()
+    }
+
+    fn link_stats(&self) -> Option<LinkStats> {
+        self.active_ref().link_stats()
+    }
+}
+
+/// Where the client is in the connection lifecycle.
+/// Messages are only written to the transport once `Ready`.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Disconnected,
+    Connecting,
+    Ready,
+}
+
+/// Run condition: the connection is established and its channels are open
+pub fn connection_ready(state: Res<ConnectionState>) -> bool {
+    *state == ConnectionState::Ready
+}
+
+/// A message could not be delivered after all retries
+#[derive(Event, Debug, Clone)]
+pub struct NetworkError {
+    pub message: ClientMessage,
+    pub error: String,
+}
+
+/// Queue a message for the server. Systems that only send write this instead of
+/// taking the transport or the `Outbox`.
+#[derive(Event, Debug, Clone)]
+pub struct SendToServer(pub ClientMessage);
+
+#[derive(Debug)]
+struct PendingMessage {
+    message: ClientMessage,
+    attempts: u32,
+    queued_at: Instant,
+}
+
+/// Outgoing messages, held until the connection is ready and then flushed in order.
+/// A failed send stays at the head of the queue and is retried on the next frame.
+#[derive(Resource, Debug)]
+pub struct Outbox {
+    queue: VecDeque<PendingMessage>,
+    pub max_attempts: u32,
+    /// Debugging aid: hold every message this long before sending it
+    pub simulated_lag: Duration,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            max_attempts: 5,
+            simulated_lag: Duration::ZERO,
+        }
+    }
+}
+
+impl Outbox {
+    pub fn push(&mut self, message: ClientMessage) {
+        self.queue.push_back(PendingMessage {
+            message,
+            attempts: 0,
+            queued_at: Instant::now(),
+        });
+    }
+
+    /// Drop what wasn't sent, e.g. when leaving the server on purpose
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+impl ClientTransport for QuinnetClient {
+    fn open(&mut self, server_addr: &str) -> Result<(), TransportError> {
+        let config = ClientEndpointConfiguration::from_strings(server_addr, "0.0.0.0:0")
+            .map_err(|_| TransportError::InvalidAddress(server_addr.to_string()))?;
+        self.open_connection(
+            config,
+            CertificateVerificationMode::SkipVerification,
+            protocol::channels::configuration(),
+        )
+        .map(|_| ())
+        .map_err(|err| TransportError::Send(err.to_string()))
+    }
+
+    fn close(&mut self) {
+        let _ = self.close_all_connections();
+    }
+
+    fn is_connected(&self) -> bool {
+        QuinnetClient::is_connected(self)
+    }
+
+    fn send(&mut self, message: ClientMessage) -> Result<(), TransportError> {
+        let connection = self
+            .get_connection_mut()
+            .ok_or(TransportError::NotConnected)?;
+        connection
+            .send_payload(protocol::encode(&message))
+            .map_err(|err| TransportError::Send(err.to_string()))
+    }
+
+    fn receive(&mut self) -> Option<ServerMessage> {
+        let connection = self.get_connection_mut()?;
+        loop {
+            let (_, payload) = connection.try_receive_payload()?;
+            match protocol::decode::<ServerMessage>(&payload) {
+                Ok(message) => return Some(message),
+                Err(err) => skip_undecodable("QUIC", err),
+            }
+        }
+    }
+
+    fn link_stats(&self) -> Option<LinkStats> {
+        let stats = self.get_connection()?.connection_stats()?;
+        Some(LinkStats {
+            rtt: stats.path.rtt,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+            congestion_events: stats.path.congestion_events,
+        })
+    }
+}
+
+pub struct TransportPlugin;
+
+impl Plugin for TransportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TransportEvent>()
+            .add_event::<NetworkError>()
+            .add_event::<SendToServer>()
+            .init_resource::<ConnectionState>()
+            .init_resource::<Outbox>()
+            .init_resource::<MessageTrace>()
+            .init_resource::<LoopbackTransport>()
+            .add_systems(
+                PreUpdate,
+                (
+                    loopback::poll_loopback.before(track_connection_state),
+                    track_connection_state,
+                ),
+            )
+            .add_systems(PostUpdate, (queue_sent_messages, flush_outbox).chain());
+
+        #[cfg(not(feature = "websocket"))]
+        app.add_plugins(bevy_quinnet::client::QuinnetClientPlugin::default())
+            .add_systems(
+                PreUpdate,
+                forward_quinnet_events.before(track_connection_state),
+            );
+
+        #[cfg(feature = "websocket")]
+        app.init_resource::<websocket::WebSocketClient>()
+            .add_systems(
+                PreUpdate,
+                websocket::poll_websocket.before(track_connection_state),
+            );
+    }
+}
+
+fn track_connection_state(
+    mut events: EventReader<TransportEvent>,
+    transport: Transport,
+    mut state: ResMut<ConnectionState>,
+) {
+    for ev in events.read() {
+        *state = match ev {
+            TransportEvent::Connected => ConnectionState::Connecting,
+            TransportEvent::ConnectionFailed(_) | TransportEvent::Disconnected => {
+                ConnectionState::Disconnected
+            }
+        };
+    }
+    // The connected event can precede the connection being usable for sends
+    if *state == ConnectionState::Connecting && transport.is_connected() {
+        *state = ConnectionState::Ready;
+    }
+}
+
+fn queue_sent_messages(mut events: EventReader<SendToServer>, mut outbox: ResMut<Outbox>) {
+    for SendToServer(message) in events.read() {
+        outbox.push(message.clone());
+    }
+}
+
+fn flush_outbox(
+    state: Res<ConnectionState>,
+    mut outbox: ResMut<Outbox>,
+    mut transport: Transport,
+    mut errors: EventWriter<NetworkError>,
+    mut trace: ResMut<MessageTrace>,
+) {
+    if *state != ConnectionState::Ready {
+        return;
+    }
+    let max_attempts = outbox.max_attempts;
+    let lag = outbox.simulated_lag;
+    while let Some(pending) = outbox.queue.front_mut() {
+        if pending.queued_at.elapsed() < lag {
+            break;
+        }
+        match transport.send(pending.message.clone()) {
+            Ok(()) => {
+                trace.record(Direction::Sent, &pending.message);
+                outbox.queue.pop_front();
+            }
+            Err(err) => {
+                pending.attempts += 1;
+                if pending.attempts < max_attempts {
+                    // Keep ordering: retry this one first next frame
+                    break;
+                }
+                warn!(
+                    "Giving up on {:?} after {} attempts: {}",
+                    pending.message, pending.attempts, err
+                );
+                let pending = outbox.queue.pop_front().unwrap();
+                errors.write(NetworkError {
+                    message: pending.message,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "websocket"))]
+fn forward_quinnet_events(
+    mut connected: EventReader<bevy_quinnet::client::connection::ConnectionEvent>,
+    mut failed: EventReader<bevy_quinnet::client::connection::ConnectionFailedEvent>,
+    mut lost: EventReader<bevy_quinnet::client::connection::ConnectionLostEvent>,
+    mut events: EventWriter<TransportEvent>,
+) {
+    for _ in connected.read() {
+        events.write(TransportEvent::Connected);
+    }
+    for ev in failed.read() {
+        events.write(TransportEvent::ConnectionFailed(format!("{:?}", ev.err)));
+    }
+    for _ in lost.read() {
+        events.write(TransportEvent::Disconnected);
+    }
+}
+
+#[cfg(feature = "websocket")]
+pub mod websocket {
+    use std::cell::RefCell;
+
+    use bevy::prelude::*;
+    use godot::{
+        classes::{WebSocketPeer, web_socket_peer::State},
+        global::Error,
+        prelude::*,
+    };
+    use godot_bevy::prelude::*;
+
+    use super::{ClientTransport, TransportError, TransportEvent, skip_undecodable};
+    use crate::frame_budget;
+    use protocol::{self, ClientMessage, ServerMessage};
+
+    thread_local! {
+        // Godot objects are not Send, the peer only ever lives on the main thread
+        static PEER: RefCell<Option<Gd<WebSocketPeer>>> = const { RefCell::new(None) };
+    }
+
+    fn with_peer<R>(f: impl FnOnce(&mut Gd<WebSocketPeer>) -> R) -> Option<R> {
+        PEER.with_borrow_mut(|peer| peer.as_mut().map(f))
+    }
+
+    /// WebSocket backend for HTML5 exports, built on Godot's `WebSocketPeer`.
+    /// Messages are encoded with `protocol::encode`, like on every transport. The
+    /// bundled server has no WebSocket endpoint, so this needs a server of its own.
+    #[derive(Resource, Default)]
+    pub struct WebSocketClient {
+        state: Option<State>,
+        inbox: std::collections::VecDeque<ServerMessage>,
+    }
+
+    impl ClientTransport for WebSocketClient {
+        fn open(&mut self, server_addr: &str) -> Result<(), TransportError> {
+            let mut peer = WebSocketPeer::new_gd();
+            let url = format!("ws://{}", server_addr);
+            if peer.connect_to_url(&url) != Error::OK {
+                return Err(TransportError::InvalidAddress(url));
+            }
+            PEER.with_borrow_mut(|p| *p = Some(peer));
+            self.state = Some(State::CONNECTING);
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            with_peer(|peer| peer.close());
+        }
+
+        fn is_connected(&self) -> bool {
+            self.state == Some(State::OPEN)
+        }
+
+        fn send(&mut self, message: ClientMessage) -> Result<(), TransportError> {
+            if !self.is_connected() {
+                return Err(TransportError::NotConnected);
+            }
+            let bytes = protocol::encode(&message);
+            let result = with_peer(|peer| peer.put_packet(&PackedByteArray::from(bytes)))
+                .ok_or(TransportError::NotConnected)?;
+            if result == Error::OK {
+                Ok(())
+            } else {
+                Err(TransportError::Send(format!("{:?}", result)))
+            }
+        }
+
+        fn receive(&mut self) -> Option<ServerMessage> {
+            self.inbox.pop_front()
+        }
+    }
+
+    #[main_thread_system]
+    pub fn poll_websocket(
+        mut client: ResMut<WebSocketClient>,
+        mut events: EventWriter<TransportEvent>,
+    ) {
+        let _timing = frame_budget::scope("poll_websocket");
+        let Some(state) = with_peer(|peer| {
+            peer.poll();
+            peer.get_ready_state()
+        }) else {
+            return;
+        };
+
+        if client.state != Some(state) {
+            match (client.state, state) {
+                (_, State::OPEN) => {
+                    events.write(TransportEvent::Connected);
+                }
+                (Some(State::CONNECTING), State::CLOSED) => {
+                    events.write(TransportEvent::ConnectionFailed(
+                        "websocket handshake failed".to_string(),
+                    ));
+                }
+                (Some(State::OPEN), State::CLOSED) => {
+                    events.write(TransportEvent::Disconnected);
+                }
+                _ => {}
+            }
+            client.state = Some(state);
+        }
+
+        while let Some(packet) =
+            with_peer(|peer| (peer.get_available_packet_count() > 0).then(|| peer.get_packet()))
+                .flatten()
+        {
+            match protocol::decode::<ServerMessage>(packet.as_slice()) {
+                Ok(message) => client.inbox.push_back(message),
+                Err(err) => skip_undecodable("websocket", err),
+            }
+        }
+    }
+}
@@ -1,23 +1,189 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
 use bevy::prelude::*;
 use godot::{
-    classes::{Button, IButton},
+    classes::{
+        Button, ColorRect, ConfirmationDialog, Control, GridContainer, HBoxContainer, IButton,
+        IColorRect, ILabel, ILineEdit, INode, IVBoxContainer, Ip, Label, LineEdit, OptionButton,
+        ProgressBar, SpinBox, VBoxContainer,
+        control::{LayoutPreset, SizeFlags},
+    },
+    global::HorizontalAlignment,
     prelude::*,
 };
 use godot_bevy::prelude::*;
+use protocol::{ClientMessage, LocalizedText, NoticeLevel, VoteKind, texts};
 use tokio::sync::mpsc::Sender;
 
+use crate::frame_budget;
+use crate::leave::LeaveGame;
+use crate::localization::Translations;
+use crate::loopback::LOOPBACK_ADDRESS;
+use crate::net_events::from_server;
+use crate::presence::CurrentServer;
+use crate::results::ResultsNode;
+use crate::settings::ClientSettings;
+use crate::transport::{ClientTransport, ConnectionState, SendToServer, Transport, TransportEvent};
+use crate::{ServerPassword, SyncState};
+
 #[derive(Clone, Debug)]
 pub enum UiCommand {
-    Host { server_path: Option<String> },
-    Connect,
+    /// Start a server with `settings` and connect to it: the executable at
+    /// `server_path` if there is one, in-process otherwise
+    Host {
+        server_path: Option<String>,
+        settings: HostSettings,
+    },
+    /// Look up `address` ("host:port") and connect to it, see `JoinAttempt`
+    Join {
+        address: String,
+        password: Option<String>,
+    },
+    /// Stop the join dialog's connection attempt
+    CancelJoin,
+    /// Start a server in this process without opening its QUIC endpoint, and connect
+    /// to it through the loopback
+    PlayOffline,
+    /// Leave the game for the main menu, see `LeaveGame`
+    Leave,
+    /// Join the private room behind an invite code, connecting first if needed
+    JoinCode(String),
+    StartVote(VoteKind),
+    CastVote(bool),
+    /// Whether we want the next match to start now, see `ClientMessage::PlayAgain`
+    PlayAgain(bool),
+}
+
+/// What the host dialog asked for, passed to the server as `ServerConfig::apply_args`
+/// overrides
+#[derive(Clone, Debug)]
+pub struct HostSettings {
+    pub port: u16,
+    pub max_players: usize,
+    pub password: Option<String>,
+    /// "0.0.0.0" for public, the LAN address for LAN only
+    pub listen_address: String,
+}
+
+impl HostSettings {
+    pub fn server_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--port".to_string(),
+            self.port.to_string(),
+            "--max-players".to_string(),
+            self.max_players.to_string(),
+            "--listen".to_string(),
+            self.listen_address.clone(),
+        ];
+        if let Some(password) = &self.password {
+            args.extend(["--password".to_string(), password.clone()]);
+        }
+        args
+    }
+
+    /// Where our own client connects to the server
+    pub fn connect_address(&self) -> String {
+        let host = if self.listen_address == "0.0.0.0" {
+            "127.0.0.1"
+        } else {
+            &self.listen_address
+        };
+        format!("{}:{}", host, self.port)
+    }
 }
 
+/// A connection started from the join dialog, from the host name lookup until the
+/// connection is ready or fails
+#[derive(Resource, Debug, Default)]
+pub enum JoinAttempt {
+    #[default]
+    Idle,
+    /// Looking up the host on a background thread, so a slow DNS doesn't stall the game
+    Resolving {
+        address: String,
+        lookup: Receiver<Result<SocketAddr, String>>,
+    },
+    Connecting {
+        address: String,
+    },
+    Failed(String),
+}
+
+impl JoinAttempt {
+    fn in_progress(&self) -> bool {
+        matches!(
+            self,
+            JoinAttempt::Resolving { .. } | JoinAttempt::Connecting { .. }
+        )
+    }
+}
+
+/// Invite code entered before we were connected, joined right after Join
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PendingInvite(pub Option<String>);
+
 #[derive(Component, Default)]
 pub struct HostButtonComp;
 
 #[derive(Component, Default)]
 pub struct JoinButtonComp;
 
+#[derive(Component, Default)]
+pub struct LeaveButtonComp;
+
+#[derive(Component, Default)]
+pub struct OfflineButtonComp;
+
+#[derive(Component, Default)]
+pub struct InviteCodeComp;
+
+#[derive(Component, Default)]
+pub struct ShutdownBannerComp;
+
+#[derive(Component, Default)]
+pub struct ToastComp;
+
+#[derive(Component, Default)]
+pub struct LoadingBarComp;
+
+#[derive(Component, Default)]
+pub struct LoadingOverlayComp;
+
+#[derive(Component, Default)]
+pub struct VoteComp;
+
+#[derive(Component, Default)]
+pub struct NetworkSessionComp;
+
+/// A `SystemNotice` arrived, for toast popups
+#[derive(Event, Debug, Clone)]
+pub struct NoticeReceived {
+    pub level: NoticeLevel,
+    pub text: String,
+}
+
+/// How much of the world state stream has arrived after joining
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SyncProgress {
+    pub done: u32,
+    pub total: u32,
+}
+
+/// Set when the server announces it is going down
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ServerShutdown {
+    pub deadline: Option<Instant>,
+}
+
+/// The vote running on the server, as of its last VoteProgress
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ActiveVote(pub Option<(from_server::VoteProgress, Instant)>);
+
+/// Opens the host dialog (port, max players, password, public or LAN only); confirming
+/// starts the server and connects to it
 #[derive(GodotClass, BevyBundle)]
 #[class(base=Button)]
 #[bevy_bundle((HostButtonComp))]
@@ -25,56 +191,831 @@ pub struct HostButtonNode {
     base: Base<Button>,
     #[export]
     pub server_path: GString,
+    /// Prefilled in the dialog
+    #[export]
+    port: i32,
+    #[export]
+    max_players: i32,
     #[bevy_bundle]
     pub sender: Option<Sender<UiCommand>>,
+    dialog: Option<HostDialog>,
 }
 
+/// The host dialog's fields, built in `ready`
+struct HostDialog {
+    window: Gd<ConfirmationDialog>,
+    port: Gd<SpinBox>,
+    max_players: Gd<SpinBox>,
+    password: Gd<LineEdit>,
+    visibility: Gd<OptionButton>,
+}
+
+/// `HostDialog.visibility` item for LAN only, after Public
+const VISIBILITY_LAN: i32 = 1;
+
+/// Opens the join dialog: an address and port (or a recent server), checked before
+/// connecting, and a spinner with a Cancel button while the connection is attempted
 #[derive(GodotClass, BevyBundle)]
 #[class(base=Button)]
 #[bevy_bundle((JoinButtonComp))]
 pub struct JoinButtonNode {
+    base: Base<Button>,
+    /// Prefilled in the dialog until a server was joined
+    #[export]
+    default_address: GString,
+    #[export]
+    default_port: i32,
+    #[bevy_bundle]
+    pub sender: Option<Sender<UiCommand>>,
+    dialog: Option<JoinDialog>,
+    recent_servers: Vec<String>,
+}
+
+/// The join dialog's fields, built in `ready`
+struct JoinDialog {
+    window: Gd<ConfirmationDialog>,
+    recent: Gd<OptionButton>,
+    address: Gd<LineEdit>,
+    port: Gd<SpinBox>,
+    password: Gd<LineEdit>,
+    error: Gd<Label>,
+    progress: Gd<HBoxContainer>,
+    status: Gd<Label>,
+}
+
+/// Disconnects from the game and returns to the main menu, e.g. in the pause menu
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Button)]
+#[bevy_bundle((LeaveButtonComp))]
+pub struct LeaveButtonNode {
     base: Base<Button>,
     #[bevy_bundle]
     pub sender: Option<Sender<UiCommand>>,
 }
 
+#[godot_api]
+impl IButton for LeaveButtonNode {
+    fn init(base: Base<Button>) -> Self {
+        Self { base, sender: None }
+    }
+
+    fn pressed(&mut self) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(UiCommand::Leave);
+        } else {
+            godot_print!("Leave button pressed, but sender not set yet");
+        }
+    }
+}
+
+/// Starts a game only this process can join, without networking
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Button)]
+#[bevy_bundle((OfflineButtonComp))]
+pub struct OfflineButtonNode {
+    base: Base<Button>,
+    #[bevy_bundle]
+    pub sender: Option<Sender<UiCommand>>,
+}
+
+#[godot_api]
+impl IButton for OfflineButtonNode {
+    fn init(base: Base<Button>) -> Self {
+        Self { base, sender: None }
+    }
+
+    fn pressed(&mut self) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(UiCommand::PlayOffline);
+        } else {
+            godot_print!("Offline button pressed, but sender not set yet");
+        }
+    }
+}
+
+/// Port `NetworkSessionNode.join` connects to when the address has none
+const DEFAULT_PORT: u16 = 6000;
+
+/// Host, join and leave from GDScript, e.g. as an autoload, for menus built without
+/// the buttons above: the same commands they send, with signals saying how it went
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Node)]
+#[bevy_bundle((NetworkSessionComp))]
+pub struct NetworkSessionNode {
+    base: Base<Node>,
+    /// Server executable `host` starts; in-process when empty or missing
+    #[export]
+    server_path: GString,
+    #[export]
+    max_players: i32,
+    /// Required from players joining a game we host, and sent to servers we join
+    #[export]
+    password: GString,
+    #[bevy_bundle]
+    pub sender: Option<Sender<UiCommand>>,
+}
+
+#[godot_api]
+impl NetworkSessionNode {
+    /// The world is synced and the game can be shown
+    #[signal]
+    fn joined();
+
+    /// Out of the game, because we left or the connection dropped
+    #[signal]
+    fn left();
+
+    /// `host` or `join` didn't get a connection
+    #[signal]
+    fn connection_failed(reason: GString);
+
+    /// Start a server on `port`, open to everyone, and join it
+    #[func]
+    fn host(&mut self, port: i32) {
+        let Ok(port) = u16::try_from(port) else {
+            self.fail(format!("Invalid port {}", port));
+            return;
+        };
+        let server_path = (!self.server_path.is_empty()).then(|| self.server_path.to_string());
+        self.send(UiCommand::Host {
+            server_path,
+            settings: HostSettings {
+                port,
+                max_players: self.max_players.max(1) as usize,
+                password: self.password(),
+                listen_address: "0.0.0.0".to_string(),
+            },
+        });
+    }
+
+    /// Join the server at `address`, "host" or "host:port"
+    #[func]
+    fn join(&mut self, address: GString) {
+        let (host, port) = split_address(address.to_string().trim(), DEFAULT_PORT);
+        match normalize_host(&host) {
+            Ok(host) => self.send(UiCommand::Join {
+                address: format!("{}:{}", host, port),
+                password: self.password(),
+            }),
+            Err(err) => self.fail(err.to_string()),
+        }
+    }
+
+    /// Leave the game for the main menu, or give up on a join still connecting
+    #[func]
+    fn leave(&mut self) {
+        self.send(UiCommand::CancelJoin);
+        self.send(UiCommand::Leave);
+    }
+
+    fn password(&self) -> Option<String> {
+        (!self.password.is_empty()).then(|| self.password.to_string())
+    }
+
+    fn fail(&mut self, reason: String) {
+        self.base_mut()
+            .emit_signal("connection_failed", &[GString::from(reason).to_variant()]);
+    }
+
+    fn send(&self, command: UiCommand) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(command);
+        } else {
+            godot_print!("NetworkSessionNode used before the Bevy app was ready");
+        }
+    }
+}
+
+#[godot_api]
+impl INode for NetworkSessionNode {
+    fn init(base: Base<Node>) -> Self {
+        Self {
+            base,
+            server_path: GString::new(),
+            max_players: 8,
+            password: GString::new(),
+            sender: None,
+        }
+    }
+}
+
+/// Text field for invite codes, submitting joins the matching private room
+#[derive(GodotClass, BevyBundle)]
+#[class(base=LineEdit)]
+#[bevy_bundle((InviteCodeComp))]
+pub struct InviteCodeNode {
+    base: Base<LineEdit>,
+    #[bevy_bundle]
+    pub sender: Option<Sender<UiCommand>>,
+}
+
+#[godot_api]
+impl ILineEdit for InviteCodeNode {
+    fn init(base: Base<LineEdit>) -> Self {
+        Self { base, sender: None }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_placeholder("Invite code");
+        let callable = Callable::from_object_method(&self.to_gd(), "submit_code");
+        self.base_mut().connect("text_submitted", &callable);
+    }
+}
+
+#[godot_api]
+impl InviteCodeNode {
+    #[func]
+    fn submit_code(&mut self, code: GString) {
+        let code = code.to_string();
+        if code.trim().is_empty() {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(UiCommand::JoinCode(code));
+            self.base_mut().clear();
+        } else {
+            godot_print!("Invite code submitted, but sender not set yet");
+        }
+    }
+}
+
+/// Hidden until the server announces a shutdown, then shows the countdown
+#[derive(GodotClass, BevyBundle)]
+#[class(init, base=Label)]
+#[bevy_bundle((ShutdownBannerComp))]
+pub struct ShutdownBannerNode {
+    base: Base<Label>,
+}
+
+/// Shows the world state download after joining, hidden once it completes
+#[derive(GodotClass, BevyBundle)]
+#[class(init, base=ProgressBar)]
+#[bevy_bundle((LoadingBarComp))]
+pub struct LoadingBarNode {
+    base: Base<ProgressBar>,
+}
+
+/// Opaque cover over the game from connecting until the initial sync is done,
+/// with a status line. Hidden while disconnected so menus stay usable.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=ColorRect)]
+#[bevy_bundle((LoadingOverlayComp))]
+pub struct LoadingOverlayNode {
+    base: Base<ColorRect>,
+    status: Option<Gd<Label>>,
+}
+
+#[godot_api]
+impl IColorRect for LoadingOverlayNode {
+    fn init(base: Base<ColorRect>) -> Self {
+        Self { base, status: None }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_color(Color::BLACK);
+        self.base_mut().set_anchors_preset(LayoutPreset::FULL_RECT);
+        let mut status = Label::new_alloc();
+        status.set_anchors_preset(LayoutPreset::CENTER);
+        status.set_horizontal_alignment(HorizontalAlignment::CENTER);
+        self.base_mut().add_child(&status);
+        self.status = Some(status);
+    }
+}
+
+impl LoadingOverlayNode {
+    fn show_state(&mut self, state: SyncState, connection: ConnectionState) {
+        let text = match state {
+            SyncState::Connecting => "Connecting...",
+            SyncState::Syncing => "Loading world...",
+            SyncState::Ready => "",
+        };
+        if let Some(status) = &mut self.status {
+            status.set_text(text);
+        }
+        let visible = connection != ConnectionState::Disconnected && state != SyncState::Ready;
+        self.base_mut().set_visible(visible);
+    }
+}
+
+/// Pops up server notices for a few seconds, on top of the chat line
+#[derive(GodotClass, BevyBundle)]
+#[class(base=Label)]
+#[bevy_bundle((ToastComp))]
+pub struct ToastNode {
+    base: Base<Label>,
+    /// Lower levels only go to the chat: 0 info, 1 warning, 2 critical
+    #[export]
+    min_level: i32,
+    #[export]
+    duration_secs: f64,
+    remaining: f64,
+}
+
+#[godot_api]
+impl ILabel for ToastNode {
+    fn init(base: Base<Label>) -> Self {
+        Self {
+            base,
+            min_level: 1,
+            duration_secs: 4.0,
+            remaining: 0.0,
+        }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_visible(false);
+    }
+
+    fn process(&mut self, delta: f64) {
+        if self.remaining <= 0.0 {
+            return;
+        }
+        self.remaining -= delta;
+        if self.remaining <= 0.0 {
+            self.base_mut().set_visible(false);
+        }
+    }
+}
+
+impl ToastNode {
+    pub fn show(&mut self, level: NoticeLevel, text: &str) {
+        if (level as i32) < self.min_level {
+            return;
+        }
+        let color = match level {
+            NoticeLevel::Info => Color::LIGHT_BLUE,
+            NoticeLevel::Warning => Color::YELLOW,
+            NoticeLevel::Critical => Color::RED,
+        };
+        self.base_mut().set_text(text);
+        self.base_mut().set_modulate(color);
+        self.base_mut().set_visible(true);
+        self.remaining = self.duration_secs;
+    }
+}
+
+/// Shows the running vote with Yes/No buttons, hidden when there is none. Scripts
+/// start votes with `start_kick_vote(name)` and `start_level_vote(level)`.
+#[derive(GodotClass, BevyBundle)]
+#[class(base=VBoxContainer)]
+#[bevy_bundle((VoteComp))]
+pub struct VoteNode {
+    base: Base<VBoxContainer>,
+    #[bevy_bundle]
+    pub sender: Option<Sender<UiCommand>>,
+    status: Option<Gd<Label>>,
+}
+
+#[godot_api]
+impl IVBoxContainer for VoteNode {
+    fn init(base: Base<VBoxContainer>) -> Self {
+        Self {
+            base,
+            sender: None,
+            status: None,
+        }
+    }
+
+    fn ready(&mut self) {
+        self.base_mut().set_visible(false);
+        let status = Label::new_alloc();
+        self.base_mut().add_child(&status);
+        self.status = Some(status);
+
+        let mut buttons = HBoxContainer::new_alloc();
+        for (text, yes) in [("Yes", true), ("No", false)] {
+            let mut button = Button::new_alloc();
+            button.set_text(text);
+            let callable =
+                Callable::from_object_method(&self.to_gd(), "cast").bind(&[yes.to_variant()]);
+            button.connect("pressed", &callable);
+            buttons.add_child(&button);
+        }
+        self.base_mut().add_child(&buttons);
+    }
+}
+
+#[godot_api]
+impl VoteNode {
+    #[func]
+    fn start_kick_vote(&mut self, name: GString) {
+        self.send(UiCommand::StartVote(VoteKind::Kick {
+            name: name.to_string(),
+        }));
+    }
+
+    #[func]
+    fn start_level_vote(&mut self, level: GString) {
+        self.send(UiCommand::StartVote(VoteKind::ChangeLevel {
+            level: level.to_string(),
+        }));
+    }
+
+    #[func]
+    fn cast(&mut self, yes: bool) {
+        self.send(UiCommand::CastVote(yes));
+    }
+
+    fn send(&self, command: UiCommand) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(command);
+        } else {
+            godot_print!("Vote requested, but sender not set yet");
+        }
+    }
+
+    fn show_vote(
+        &mut self,
+        vote: Option<&(from_server::VoteProgress, Instant)>,
+        translations: &Translations,
+    ) {
+        let Some((progress, deadline)) = vote else {
+            self.base_mut().set_visible(false);
+            return;
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let text = translations.localize(
+            &LocalizedText::new(texts::VOTE_STATUS)
+                .arg(&progress.started_by)
+                .arg(translations.localize(&progress.kind.text()))
+                .arg(remaining.as_secs_f32().ceil())
+                .arg(progress.yes)
+                .arg(progress.no)
+                .arg(progress.quorum)
+                .arg(progress.eligible),
+        );
+        if let Some(status) = &mut self.status {
+            status.set_text(&text);
+        }
+        self.base_mut().set_visible(true);
+    }
+}
+
 #[godot_api]
 impl IButton for HostButtonNode {
     fn init(base: Base<Button>) -> Self {
         Self {
             base,
             server_path: GString::from("server.exe"),
+            port: 6000,
+            max_players: 16,
             sender: None,
+            dialog: None,
+        }
+    }
+
+    fn ready(&mut self) {
+        let mut window = ConfirmationDialog::new_alloc();
+        window.set_title("Host a game");
+        window.set_ok_button_text("Host");
+        let mut grid = GridContainer::new_alloc();
+        grid.set_columns(2);
+
+        let mut port = SpinBox::new_alloc();
+        port.set_min(1024.0);
+        port.set_max(65535.0);
+        port.set_value(self.port as f64);
+        let mut max_players = SpinBox::new_alloc();
+        max_players.set_min(1.0);
+        max_players.set_max(256.0);
+        max_players.set_value(self.max_players as f64);
+        let mut password = LineEdit::new_alloc();
+        password.set_secret(true);
+        password.set_placeholder("None");
+        let mut visibility = OptionButton::new_alloc();
+        visibility.add_item("Public");
+        visibility.add_item("LAN only");
+
+        for (label, field) in [
+            ("Port", port.clone().upcast::<Control>()),
+            ("Max players", max_players.clone().upcast()),
+            ("Password", password.clone().upcast()),
+            ("Visibility", visibility.clone().upcast()),
+        ] {
+            let mut text = Label::new_alloc();
+            text.set_text(label);
+            grid.add_child(&text);
+            grid.add_child(&field);
         }
+        window.add_child(&grid);
+        let callable = Callable::from_object_method(&self.to_gd(), "host_confirmed");
+        window.connect("confirmed", &callable);
+        self.base_mut().add_child(&window);
+        self.dialog = Some(HostDialog {
+            window,
+            port,
+            max_players,
+            password,
+            visibility,
+        });
     }
 
     fn pressed(&mut self) {
+        if let Some(dialog) = &mut self.dialog {
+            dialog.window.popup_centered();
+        }
+    }
+}
+
+#[godot_api]
+impl HostButtonNode {
+    #[func]
+    fn host_confirmed(&mut self) {
+        let Some(dialog) = &self.dialog else {
+            return;
+        };
+        let password = dialog.password.get_text().to_string();
+        let listen_address = if dialog.visibility.get_selected() == VISIBILITY_LAN {
+            lan_address().unwrap_or_else(|| {
+                godot_print!("No LAN address found, hosting on this machine only");
+                "127.0.0.1".to_string()
+            })
+        } else {
+            "0.0.0.0".to_string()
+        };
+        let settings = HostSettings {
+            port: dialog.port.get_value() as u16,
+            max_players: dialog.max_players.get_value() as usize,
+            password: (!password.is_empty()).then_some(password),
+            listen_address,
+        };
+        self.port = settings.port as i32;
+        self.max_players = settings.max_players as i32;
+
         if let Some(sender) = &self.sender {
             let path = if self.server_path.is_empty() {
                 None
             } else {
                 Some(self.server_path.to_string())
             };
-            let _ = sender.try_send(UiCommand::Host { server_path: path });
+            let _ = sender.try_send(UiCommand::Host {
+                server_path: path,
+                settings,
+            });
         } else {
             godot_print!("Host button pressed, but sender not set yet");
         }
     }
 }
 
+/// This machine's private IPv4 address on the local network, if it has one
+fn lan_address() -> Option<String> {
+    Ip::singleton()
+        .get_local_addresses()
+        .as_slice()
+        .iter()
+        .map(GString::to_string)
+        .find(|address| {
+            address
+                .parse::<Ipv4Addr>()
+                .is_ok_and(|address| address.is_private())
+        })
+}
+
 #[godot_api]
 impl IButton for JoinButtonNode {
     fn init(base: Base<Button>) -> Self {
-        Self { base, sender: None }
+        Self {
+            base,
+            default_address: GString::from("127.0.0.1"),
+            default_port: 6000,
+            sender: None,
+            dialog: None,
+            recent_servers: Vec::new(),
+        }
+    }
+
+    fn ready(&mut self) {
+        let mut window = ConfirmationDialog::new_alloc();
+        window.set_title("Join a game");
+        window.set_ok_button_text("Connect");
+        // Closed by us once the address checks out
+        window.set_hide_on_ok(false);
+        let mut content = VBoxContainer::new_alloc();
+
+        let mut recent = OptionButton::new_alloc();
+        recent.set_visible(false);
+        content.add_child(&recent);
+        let mut grid = GridContainer::new_alloc();
+        grid.set_columns(2);
+        let mut address = LineEdit::new_alloc();
+        address.set_placeholder("Host name or IP address");
+        address.set_custom_minimum_size(Vector2::new(220.0, 0.0));
+        let mut port = SpinBox::new_alloc();
+        port.set_min(1.0);
+        port.set_max(65535.0);
+        let mut password = LineEdit::new_alloc();
+        password.set_secret(true);
+        password.set_placeholder("None");
+        for (label, field) in [
+            ("Address", address.clone().upcast::<Control>()),
+            ("Port", port.clone().upcast()),
+            ("Password", password.clone().upcast()),
+        ] {
+            let mut text = Label::new_alloc();
+            text.set_text(label);
+            grid.add_child(&text);
+            grid.add_child(&field);
+        }
+        content.add_child(&grid);
+
+        let mut error = Label::new_alloc();
+        error.set_modulate(Color::from_rgb(1.0, 0.4, 0.4));
+        error.set_visible(false);
+        content.add_child(&error);
+        let mut progress = HBoxContainer::new_alloc();
+        let mut spinner = ProgressBar::new_alloc();
+        spinner.set_indeterminate(true);
+        spinner.set_show_percentage(false);
+        spinner.set_h_size_flags(SizeFlags::EXPAND_FILL);
+        progress.add_child(&spinner);
+        let status = Label::new_alloc();
+        progress.add_child(&status);
+        progress.set_visible(false);
+        content.add_child(&progress);
+        window.add_child(&content);
+
+        let this = self.to_gd();
+        recent.connect(
+            "item_selected",
+            &Callable::from_object_method(&this, "recent_selected"),
+        );
+        address.connect(
+            "text_submitted",
+            &Callable::from_object_method(&this, "address_submitted"),
+        );
+        window.connect(
+            "confirmed",
+            &Callable::from_object_method(&this, "join_confirmed"),
+        );
+        window.connect(
+            "canceled",
+            &Callable::from_object_method(&this, "join_canceled"),
+        );
+        self.base_mut().add_child(&window);
+        self.dialog = Some(JoinDialog {
+            window,
+            recent,
+            address,
+            port,
+            password,
+            error,
+            progress,
+            status,
+        });
     }
 
     fn pressed(&mut self) {
+        let (address, port) = match self.recent_servers.first() {
+            Some(recent) => split_address(recent, self.default_port as u16),
+            None => (self.default_address.to_string(), self.default_port as u16),
+        };
+        if let Some(dialog) = &mut self.dialog {
+            dialog.address.set_text(&address);
+            dialog.port.set_value(port as f64);
+            dialog.error.set_visible(false);
+            dialog.window.popup_centered();
+        }
+    }
+}
+
+#[godot_api]
+impl JoinButtonNode {
+    #[func]
+    fn recent_selected(&mut self, index: i32) {
+        let Some(recent) = self.recent_servers.get(index as usize) else {
+            return;
+        };
+        let (address, port) = split_address(recent, self.default_port as u16);
+        if let Some(dialog) = &mut self.dialog {
+            dialog.address.set_text(&address);
+            dialog.port.set_value(port as f64);
+        }
+    }
+
+    #[func]
+    fn address_submitted(&mut self, _text: GString) {
+        self.join_confirmed();
+    }
+
+    #[func]
+    fn join_confirmed(&mut self) {
+        let Some(dialog) = &mut self.dialog else {
+            return;
+        };
+        let host = dialog.address.get_text().to_string();
+        let address = match normalize_host(host.trim()) {
+            Ok(host) => format!("{}:{}", host, dialog.port.get_value() as u16),
+            Err(err) => {
+                dialog.error.set_text(err);
+                dialog.error.set_visible(true);
+                return;
+            }
+        };
+        let password = dialog.password.get_text().to_string();
         if let Some(sender) = &self.sender {
-            let _ = sender.try_send(UiCommand::Connect);
+            let _ = sender.try_send(UiCommand::Join {
+                address,
+                password: (!password.is_empty()).then_some(password),
+            });
         } else {
             godot_print!("Join button pressed, but sender not set yet");
         }
     }
+
+    /// Closing the dialog while connecting gives up on the connection
+    #[func]
+    fn join_canceled(&mut self) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(UiCommand::CancelJoin);
+        }
+    }
+
+    fn set_recent_servers(&mut self, servers: &[String]) {
+        self.recent_servers = servers.to_vec();
+        let Some(dialog) = &mut self.dialog else {
+            return;
+        };
+        dialog.recent.clear();
+        for server in servers {
+            dialog.recent.add_item(server);
+        }
+        dialog.recent.set_visible(!servers.is_empty());
+    }
+
+    fn show_attempt(&mut self, attempt: &JoinAttempt) {
+        let Some(dialog) = &mut self.dialog else {
+            return;
+        };
+        let status = match attempt {
+            JoinAttempt::Resolving { address, .. } => Some(format!("Looking up {}...", address)),
+            JoinAttempt::Connecting { address } => Some(format!("Connecting to {}...", address)),
+            JoinAttempt::Idle | JoinAttempt::Failed(_) => None,
+        };
+        dialog.progress.set_visible(status.is_some());
+        dialog
+            .status
+            .set_text(status.as_deref().unwrap_or_default());
+        if let Some(mut ok) = dialog.window.get_ok_button() {
+            ok.set_disabled(attempt.in_progress());
+        }
+        match attempt {
+            JoinAttempt::Failed(err) => {
+                dialog
+                    .error
+                    .set_text(&format!("Could not connect: {}", err));
+                dialog.error.set_visible(true);
+            }
+            _ => dialog.error.set_visible(false),
+        }
+    }
+
+    /// The connection is up, the loading overlay takes over
+    fn close_dialog(&mut self) {
+        if let Some(dialog) = &mut self.dialog {
+            dialog.window.hide();
+        }
+    }
+}
+
+/// "host:port" into its parts, `default_port` when there is no valid port
+fn split_address(address: &str, default_port: u16) -> (String, u16) {
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.starts_with('[') => {
+            (host.to_string(), port.parse().unwrap_or(default_port))
+        }
+        _ => (address.to_string(), default_port),
+    }
+}
+
+/// An IP address (IPv6 in brackets, ready for a port) or something that can be a host
+/// name; whether it resolves is found out in the background once connecting
+fn normalize_host(host: &str) -> Result<String, &'static str> {
+    if host.is_empty() {
+        return Err("Enter the server's address");
+    }
+    if host.parse::<Ipv4Addr>().is_ok() {
+        return Ok(host.to_string());
+    }
+    if let Ok(ip) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<Ipv6Addr>()
+    {
+        return Ok(format!("[{}]", ip));
+    }
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if host.len() <= 253 && host.split('.').all(valid_label) {
+        Ok(host.to_string())
+    } else {
+        Err("Not a valid host name or IP address")
+    }
 }
 
 #[derive(Resource, Deref, DerefMut)]
@@ -84,7 +1025,8 @@ pub struct UiReceiver(pub tokio::sync::mpsc::Receiver<UiCommand>);
 pub fn start_ui_listener(mut commands: Commands) {
     let (tx, rx) = tokio::sync::mpsc::channel::<UiCommand>(100);
 
-    // Assign the sender to any Host/Join buttons present in the scene
+    // Assign the sender to any Host/Join/Leave/Offline buttons, invite fields, vote and
+    // results panels and session nodes (autoloads included) present in the scene
     commands.queue(move |world: &mut World| {
         let mut query = world.query::<&mut GodotNodeHandle>();
         for mut handle in query.iter_mut(world) {
@@ -94,6 +1036,24 @@ pub fn start_ui_listener(mut commands: Commands) {
             if let Some(mut join_btn) = handle.try_get::<JoinButtonNode>() {
                 join_btn.bind_mut().sender = Some(tx.clone());
             }
+            if let Some(mut leave_btn) = handle.try_get::<LeaveButtonNode>() {
+                leave_btn.bind_mut().sender = Some(tx.clone());
+            }
+            if let Some(mut offline_btn) = handle.try_get::<OfflineButtonNode>() {
+                offline_btn.bind_mut().sender = Some(tx.clone());
+            }
+            if let Some(mut invite) = handle.try_get::<InviteCodeNode>() {
+                invite.bind_mut().sender = Some(tx.clone());
+            }
+            if let Some(mut vote) = handle.try_get::<VoteNode>() {
+                vote.bind_mut().sender = Some(tx.clone());
+            }
+            if let Some(mut results) = handle.try_get::<ResultsNode>() {
+                results.bind_mut().sender = Some(tx.clone());
+            }
+            if let Some(mut session) = handle.try_get::<NetworkSessionNode>() {
+                session.bind_mut().sender = Some(tx.clone());
+            }
         }
     });
 
@@ -103,35 +1063,356 @@ pub fn start_ui_listener(mut commands: Commands) {
 #[main_thread_system]
 pub fn handle_ui_commands(
     mut ui_rx: ResMut<UiReceiver>,
-    mut client: ResMut<bevy_quinnet::client::QuinnetClient>,
+    mut client: Transport,
+    mut current_server: ResMut<CurrentServer>,
+    mut connection: ResMut<ConnectionState>,
+    mut to_server: EventWriter<SendToServer>,
+    mut pending_invite: ResMut<PendingInvite>,
+    mut password: ResMut<ServerPassword>,
+    mut attempt: ResMut<JoinAttempt>,
+    mut leave: EventWriter<LeaveGame>,
 ) {
-    use bevy_quinnet::client::certificate::CertificateVerificationMode;
-    use bevy_quinnet::client::connection::ClientEndpointConfiguration;
-    use bevy_quinnet::shared::channels::ChannelsConfiguration;
-
+    let _timing = frame_budget::scope("handle_ui_commands");
     while let Ok(cmd) = ui_rx.try_recv() {
         match cmd {
-            UiCommand::Host { server_path: _ } => {
-                // Start the server in-process on a background thread
+            UiCommand::Host {
+                server_path,
+                settings,
+            } => {
+                let args = settings.server_args();
+                match server_path.filter(|path| Path::new(path).exists()) {
+                    Some(path) => {
+                        godot_print!("Starting {}...", path);
+                        if let Err(err) = std::process::Command::new(&path).args(&args).spawn() {
+                            godot_print!("Could not start {}: {}", path, err);
+                            continue;
+                        }
+                    }
+                    None => {
+                        // Start the server in-process on a background thread
+                        let _ = std::thread::spawn(move || {
+                            godot_print!("Starting in-process server...");
+                            if let Err(err) = crate::server::run_server(args) {
+                                godot_print!("Could not start the server: {}", err);
+                            }
+                        });
+                    }
+                }
+
+                // Then connect the client to the local server
+                password.0 = settings.password.clone();
+                let address = settings.connect_address();
+                current_server.address = Some(address.clone());
+                if client.open(&address).is_ok() {
+                    *connection = ConnectionState::Connecting;
+                }
+            }
+            UiCommand::Join {
+                address,
+                password: join_password,
+            } => {
+                if attempt.in_progress() || *connection != ConnectionState::Disconnected {
+                    continue;
+                }
+                password.0 = join_password;
+                let (tx, lookup) = mpsc::channel();
+                let host = address.clone();
+                std::thread::spawn(move || {
+                    let resolved = host
+                        .to_socket_addrs()
+                        .map_err(|err| err.to_string())
+                        .and_then(|mut addrs| {
+                            addrs.next().ok_or_else(|| "no address found".to_string())
+                        });
+                    let _ = tx.send(resolved);
+                });
+                *attempt = JoinAttempt::Resolving { address, lookup };
+            }
+            UiCommand::CancelJoin => match *attempt {
+                // The lookup thread's answer goes nowhere
+                JoinAttempt::Resolving { .. } => *attempt = JoinAttempt::Idle,
+                JoinAttempt::Connecting { .. } => {
+                    client.close();
+                    *connection = ConnectionState::Disconnected;
+                    current_server.address = None;
+                    *attempt = JoinAttempt::Idle;
+                }
+                JoinAttempt::Idle | JoinAttempt::Failed(_) => {}
+            },
+            UiCommand::PlayOffline => {
+                if *connection != ConnectionState::Disconnected {
+                    continue;
+                }
                 let _ = std::thread::spawn(|| {
-                    godot_print!("Starting in-process server...");
-                    crate::server::create_server();
+                    godot_print!("Starting offline server...");
+                    if let Err(err) = crate::server::run_server(vec!["--offline".to_string()]) {
+                        godot_print!("Could not start the offline server: {}", err);
+                    }
                 });
+                // Accepted once the server runs its first update
+                password.0 = None;
+                current_server.address = Some(LOOPBACK_ADDRESS.to_string());
+                if client.open(LOOPBACK_ADDRESS).is_ok() {
+                    *connection = ConnectionState::Connecting;
+                }
+            }
+            UiCommand::Leave => {
+                leave.write(LeaveGame);
+            }
+            UiCommand::JoinCode(code) => match *connection {
+                ConnectionState::Ready => {
+                    to_server.write(SendToServer(ClientMessage::JoinRoom { code }));
+                }
+                ConnectionState::Connecting => pending_invite.0 = Some(code),
+                ConnectionState::Disconnected => {
+                    pending_invite.0 = Some(code);
+                    current_server.address = Some("0.0.0.0:6000".to_string());
+                    if client.open("0.0.0.0:6000").is_ok() {
+                        *connection = ConnectionState::Connecting;
+                    }
+                }
+            },
+            UiCommand::StartVote(kind) => {
+                to_server.write(SendToServer(ClientMessage::StartVote { kind }));
+            }
+            UiCommand::CastVote(yes) => {
+                to_server.write(SendToServer(ClientMessage::CastVote { yes }));
+            }
+            UiCommand::PlayAgain(yes) => {
+                to_server.write(SendToServer(ClientMessage::PlayAgain { yes }));
+            }
+        }
+    }
+}
 
-                // Then connect the client to the local server
-                let _ = client.open_connection(
-                    ClientEndpointConfiguration::from_strings("0.0.0.0:6000", "0.0.0.0:0").unwrap(),
-                    CertificateVerificationMode::SkipVerification,
-                    ChannelsConfiguration::default(),
-                );
-            }
-            UiCommand::Connect => {
-                let _ = client.open_connection(
-                    ClientEndpointConfiguration::from_strings("0.0.0.0:6000", "0.0.0.0:0").unwrap(),
-                    CertificateVerificationMode::SkipVerification,
-                    ChannelsConfiguration::default(),
-                );
+/// Connect once the join dialog's host is looked up, and remember the server once the
+/// connection is up
+#[main_thread_system]
+pub fn advance_join_attempt(
+    mut attempt: ResMut<JoinAttempt>,
+    mut client: Transport,
+    mut current_server: ResMut<CurrentServer>,
+    mut connection: ResMut<ConnectionState>,
+    mut transport_events: EventReader<TransportEvent>,
+    mut settings: ResMut<ClientSettings>,
+) {
+    let _timing = frame_budget::scope("advance_join_attempt");
+    let failure = transport_events.read().find_map(|ev| match ev {
+        TransportEvent::ConnectionFailed(err) => Some(err.clone()),
+        TransportEvent::Disconnected => Some("connection lost".to_string()),
+        TransportEvent::Connected => None,
+    });
+    let next = match &*attempt {
+        JoinAttempt::Resolving { address, lookup } => match lookup.try_recv() {
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => JoinAttempt::Failed("lookup failed".to_string()),
+            Ok(Err(err)) => JoinAttempt::Failed(err),
+            Ok(Ok(resolved)) => match client.open(&resolved.to_string()) {
+                Ok(()) => {
+                    current_server.address = Some(address.clone());
+                    *connection = ConnectionState::Connecting;
+                    JoinAttempt::Connecting {
+                        address: address.clone(),
+                    }
+                }
+                Err(err) => JoinAttempt::Failed(err.to_string()),
+            },
+        },
+        JoinAttempt::Connecting { address } => {
+            if let Some(err) = failure {
+                current_server.address = None;
+                JoinAttempt::Failed(err)
+            } else if *connection == ConnectionState::Ready {
+                settings.remember_server(address);
+                if let Err(err) = settings.save() {
+                    godot_print!("Could not save settings: {}", err);
+                }
+                JoinAttempt::Idle
+            } else {
+                return;
+            }
+        }
+        JoinAttempt::Idle | JoinAttempt::Failed(_) => return,
+    };
+    *attempt = next;
+}
+
+/// Keep the join dialogs' recent servers and connection progress current
+#[main_thread_system]
+pub fn update_join_dialogs(
+    attempt: Res<JoinAttempt>,
+    settings: Res<ClientSettings>,
+    connection: Res<ConnectionState>,
+    mut query: Query<&mut GodotNodeHandle, With<JoinButtonComp>>,
+) {
+    let _timing = frame_budget::scope("update_join_dialogs");
+    for mut handle in query.iter_mut() {
+        let added = handle.is_added();
+        let Some(mut join_btn) = handle.try_get::<JoinButtonNode>() else {
+            continue;
+        };
+        let mut join_btn = join_btn.bind_mut();
+        if settings.is_changed() || added {
+            join_btn.set_recent_servers(&settings.recent_servers);
+        }
+        if attempt.is_changed() || added {
+            join_btn.show_attempt(&attempt);
+        }
+        if connection.is_changed() && *connection == ConnectionState::Ready {
+            join_btn.close_dialog();
+        }
+    }
+}
+
+/// `NetworkSessionNode` signals: joined once synced, left when that ends, and failed
+/// connections, from the join dialog's attempt or else the transport
+#[main_thread_system]
+pub fn emit_session_signals(
+    attempt: Res<JoinAttempt>,
+    sync: Res<SyncState>,
+    mut transport_events: EventReader<TransportEvent>,
+    mut query: Query<&mut GodotNodeHandle, With<NetworkSessionComp>>,
+    mut was_ready: Local<bool>,
+) {
+    let _timing = frame_budget::scope("emit_session_signals");
+    // A failed join ends its attempt too, report it once with the attempt's reason
+    let failures: Vec<String> = match &*attempt {
+        JoinAttempt::Failed(err) if attempt.is_changed() => {
+            transport_events.clear();
+            vec![err.clone()]
+        }
+        _ => transport_events
+            .read()
+            .filter_map(|ev| match ev {
+                TransportEvent::ConnectionFailed(err) => Some(err.clone()),
+                _ => None,
+            })
+            .collect(),
+    };
+    let ready = *sync == SyncState::Ready;
+    let change = match (*was_ready, ready) {
+        (false, true) => Some("joined"),
+        (true, false) => Some("left"),
+        _ => None,
+    };
+    *was_ready = ready;
+    if failures.is_empty() && change.is_none() {
+        return;
+    }
+    for mut handle in query.iter_mut() {
+        let Some(mut node) = handle.try_get::<NetworkSessionNode>() else {
+            continue;
+        };
+        for reason in &failures {
+            node.emit_signal("connection_failed", &[reason.to_variant()]);
+        }
+        if let Some(signal) = change {
+            node.emit_signal(signal, &[]);
+        }
+    }
+}
+
+#[main_thread_system]
+pub fn update_shutdown_banner(
+    shutdown: Res<ServerShutdown>,
+    mut query: Query<&mut GodotNodeHandle, With<ShutdownBannerComp>>,
+) {
+    let _timing = frame_budget::scope("update_shutdown_banner");
+    for mut handle in query.iter_mut() {
+        let Some(mut label) = handle.try_get::<Label>() else {
+            continue;
+        };
+        match shutdown.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                label.set_text(&format!(
+                    "Server shutting down in {}s",
+                    remaining.as_secs_f32().ceil()
+                ));
+                label.set_visible(true);
             }
+            None => label.set_visible(false),
+        }
+    }
+}
+
+#[main_thread_system]
+pub fn show_toasts(
+    mut notices: EventReader<NoticeReceived>,
+    mut query: Query<&mut GodotNodeHandle, With<ToastComp>>,
+) {
+    let _timing = frame_budget::scope("show_toasts");
+    for notice in notices.read() {
+        for mut handle in query.iter_mut() {
+            if let Some(mut toast) = handle.try_get::<ToastNode>() {
+                toast.bind_mut().show(notice.level, &notice.text);
+            }
+        }
+    }
+}
+
+#[main_thread_system]
+pub fn update_loading_bar(
+    mut progress: EventReader<SyncProgress>,
+    mut query: Query<&mut GodotNodeHandle, With<LoadingBarComp>>,
+) {
+    let _timing = frame_budget::scope("update_loading_bar");
+    let Some(progress) = progress.read().last().copied() else {
+        return;
+    };
+    for mut handle in query.iter_mut() {
+        let Some(mut bar) = handle.try_get::<ProgressBar>() else {
+            continue;
+        };
+        bar.set_max(progress.total as f64);
+        bar.set_value(progress.done as f64);
+        bar.set_visible(progress.done < progress.total);
+    }
+}
+
+#[main_thread_system]
+pub fn update_loading_overlay(
+    sync: Res<SyncState>,
+    connection: Res<ConnectionState>,
+    added: Query<(), Added<LoadingOverlayComp>>,
+    mut query: Query<&mut GodotNodeHandle, With<LoadingOverlayComp>>,
+) {
+    let _timing = frame_budget::scope("update_loading_overlay");
+    if !sync.is_changed() && !connection.is_changed() && added.is_empty() {
+        return;
+    }
+    for mut handle in query.iter_mut() {
+        if let Some(mut overlay) = handle.try_get::<LoadingOverlayNode>() {
+            overlay.bind_mut().show_state(*sync, *connection);
+        }
+    }
+}
+
+/// Follow the server's vote announcements
+pub fn track_votes(
+    mut progress: EventReader<from_server::VoteProgress>,
+    mut ended: EventReader<from_server::VoteEnded>,
+    mut active: ResMut<ActiveVote>,
+) {
+    for ev in progress.read() {
+        let deadline = Instant::now() + Duration::from_secs(ev.remaining_secs as u64);
+        active.0 = Some((ev.clone(), deadline));
+    }
+    if ended.read().last().is_some() {
+        active.0 = None;
+    }
+}
+
+#[main_thread_system]
+pub fn update_vote_nodes(
+    active: Res<ActiveVote>,
+    translations: Res<Translations>,
+    mut query: Query<&mut GodotNodeHandle, With<VoteComp>>,
+) {
+    let _timing = frame_budget::scope("update_vote_nodes");
+    for mut handle in query.iter_mut() {
+        if let Some(mut vote) = handle.try_get::<VoteNode>() {
+            vote.bind_mut().show_vote(active.0.as_ref(), &translations);
         }
     }
 }
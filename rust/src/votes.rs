@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use bevy_quinnet::shared::ClientId;
+use protocol::{LocalizedText, VoteKind, texts};
+
+/// How a vote is decided, from the server config
+#[derive(Debug, Clone, Copy)]
+pub struct VoteRules {
+    pub timeout: Duration,
+    /// Fraction of the eligible players that must vote for the result to count
+    pub quorum: f32,
+    /// Minimum time between two votes started by the same player
+    pub cooldown: Duration,
+    /// Ballots a kick needs however few players there are, so two players alone can't
+    /// kick each other
+    pub kick_min_voters: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Pending,
+    Passed,
+    Failed,
+}
+
+/// Ballots of the running vote
+#[derive(Debug, Clone)]
+pub struct Ballot {
+    pub kind: VoteKind,
+    pub started_by: ClientId,
+    /// The player a kick vote is about, who doesn't get a say
+    pub target: Option<ClientId>,
+    /// Invite code of the private room it was started in, None for the lobby. Only the
+    /// players there vote on it and hear about it.
+    pub room: Option<String>,
+    started: Instant,
+    yes: HashSet<ClientId>,
+    no: HashSet<ClientId>,
+}
+
+/// Ballots of the players still eligible
+#[derive(Debug, Clone, Copy)]
+pub struct Tally {
+    pub yes: u32,
+    pub no: u32,
+    pub eligible: u32,
+    pub quorum: u32,
+}
+
+impl Ballot {
+    /// A vote without ballots yet, see `Votes::start` for the ones players start
+    pub fn new(kind: VoteKind, started_by: ClientId, target: Option<ClientId>) -> Self {
+        Self {
+            kind,
+            started_by,
+            target,
+            room: None,
+            started: Instant::now(),
+            yes: HashSet::new(),
+            no: HashSet::new(),
+        }
+    }
+
+    /// Later ballots replace earlier ones from the same player
+    pub fn cast(&mut self, client_id: ClientId, yes: bool) {
+        if Some(client_id) == self.target {
+            return;
+        }
+        let (add, remove) = if yes {
+            (&mut self.yes, &mut self.no)
+        } else {
+            (&mut self.no, &mut self.yes)
+        };
+        remove.remove(&client_id);
+        add.insert(client_id);
+    }
+
+    /// `connected` are the players in the world; ballots of those who left don't count
+    pub fn tally<'a>(
+        &self,
+        connected: impl Iterator<Item = &'a ClientId>,
+        rules: &VoteRules,
+    ) -> Tally {
+        let (mut yes, mut no, mut eligible) = (0, 0, 0);
+        for client_id in connected.filter(|id| Some(**id) != self.target) {
+            eligible += 1;
+            yes += self.yes.contains(client_id) as u32;
+            no += self.no.contains(client_id) as u32;
+        }
+        let mut quorum = ((eligible as f32 * rules.quorum).ceil() as u32).max(1);
+        if matches!(self.kind, VoteKind::Kick { .. }) {
+            quorum = quorum.max(rules.kick_min_voters);
+        }
+        Tally {
+            yes,
+            no,
+            eligible,
+            quorum,
+        }
+    }
+
+    pub fn migrate(&mut self, from: ClientId, to: ClientId) {
+        if self.started_by == from {
+            self.started_by = to;
+        }
+        if self.target == Some(from) {
+            self.target = Some(to);
+        }
+        for set in [&mut self.yes, &mut self.no] {
+            if set.remove(&from) {
+                set.insert(to);
+            }
+        }
+    }
+
+    pub fn remaining(&self, rules: &VoteRules) -> Duration {
+        rules.timeout.saturating_sub(self.started.elapsed())
+    }
+
+    /// A vote passes with more yes than no once the quorum voted. It ends early when
+    /// everyone voted or the ballots left can't change the result.
+    pub fn outcome(&self, tally: &Tally, rules: &VoteRules) -> Outcome {
+        let passing = tally.yes + tally.no >= tally.quorum && tally.yes > tally.no;
+        let left = tally.eligible.saturating_sub(tally.yes + tally.no);
+        if passing && tally.yes > tally.no + left {
+            Outcome::Passed
+        } else if tally.yes + left <= tally.no {
+            Outcome::Failed
+        } else if left == 0 || self.remaining(rules).is_zero() {
+            if passing {
+                Outcome::Passed
+            } else {
+                Outcome::Failed
+            }
+        } else {
+            Outcome::Pending
+        }
+    }
+}
+
+/// At most one vote runs at a time
+#[derive(Debug, Clone, Default)]
+pub struct Votes {
+    pub active: Option<Ballot>,
+    last_started: HashMap<ClientId, Instant>,
+}
+
+impl Votes {
+    /// Open a vote in `room` with its starter's yes, or say why not
+    pub fn start(
+        &mut self,
+        kind: VoteKind,
+        started_by: ClientId,
+        target: Option<ClientId>,
+        room: Option<String>,
+        rules: &VoteRules,
+    ) -> Result<&Ballot, LocalizedText> {
+        if self.active.is_some() {
+            return Err(LocalizedText::new(texts::VOTE_RUNNING));
+        }
+        if let Some(last) = self.last_started.get(&started_by) {
+            let wait = rules.cooldown.saturating_sub(last.elapsed());
+            if !wait.is_zero() {
+                return Err(LocalizedText::new(texts::VOTE_COOLDOWN).arg(wait.as_secs_f32().ceil()));
+            }
+        }
+        self.last_started.insert(started_by, Instant::now());
+        let mut ballot = Ballot::new(kind, started_by, target);
+        ballot.room = room;
+        ballot.cast(started_by, true);
+        Ok(self.active.insert(ballot))
+    }
+
+    pub fn migrate(&mut self, from: ClientId, to: ClientId) {
+        if let Some(last) = self.last_started.remove(&from) {
+            self.last_started.insert(to, last);
+        }
+        if let Some(ballot) = &mut self.active {
+            ballot.migrate(from, to);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Notifications for community server operators: server start, joins and leaves, and
+//! player reports are posted to `webhook_url`, e.g. a Discord or Slack incoming
+//! webhook. Posts go out from a thread of their own, so a slow endpoint never holds up
+//! the server loop.
+
+use std::{sync::mpsc, thread};
+
+use bevy::prelude::*;
+use serde_json::json;
+
+use crate::server::ServerConfig;
+
+/// Queue of texts to post, with the URL current when they were queued
+#[derive(Resource)]
+pub struct Webhook {
+    sender: mpsc::Sender<(String, String)>,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel::<(String, String)>();
+        thread::spawn(move || {
+            for (url, text) in receiver {
+                // Discord reads `content` and Slack `text`, each ignores the other
+                let body = json!({ "content": text, "text": text }).to_string();
+                if let Err(err) = ureq::post(&url)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body)
+                {
+                    warn!("Webhook post failed: {}", err);
+                }
+            }
+        });
+        Self { sender }
+    }
+}
+
+impl Webhook {
+    /// Post `text` to the configured webhook, if there is one
+    pub fn notify(&self, config: &ServerConfig, text: impl Into<String>) {
+        if let Some(url) = &config.webhook_url {
+            let _ = self.sender.send((url.clone(), text.into()));
+        }
+    }
+}
+
+pub fn notify_server_start(webhook: Res<Webhook>, config: Res<ServerConfig>) {
+    webhook.notify(
+        &config,
+        format!(
+            "Server started on port {} ({} players max)",
+            config.port, config.max_players
+        ),
+    );
+}